@@ -0,0 +1,1764 @@
+use ndarray::Array1;
+use ndarray_npy::NpzReader;
+use num_traits::cast::AsPrimitive;
+use std::fs::File;
+use std::io::Read;
+use zip::ZipArchive;
+
+use crate::sim::simulation;
+
+/// Number of points `stream_npz_segments` reads from each array at once, e.g. 64 Ki points or 512 KiB.
+pub const DEFAULT_STREAM_CHUNK_POINTS: usize = 1 << 16;
+
+/// Number of meters in a kilometer.
+pub const METERS_PER_KILOMETER: f64 = 1000.0;
+
+/// Number of meters in a statute mile.
+pub const METERS_PER_MILE: f64 = 1609.344;
+
+/// Number of meters in an international foot.
+pub const METERS_PER_FOOT: f64 = 0.3048;
+
+/// Absolute grade above which `validate_profile` warns, e.g. 0.2 for 20%.
+const STEEP_GRADE_WARNING: f64 = 0.2;
+
+/// Absolute grade above which `validate_profile` reports an error, steeper than any paved road.
+const STEEP_GRADE_ERROR: f64 = 0.4;
+
+/// Length in meters of a shallower stretch, e.g. a false flat, that `detect_climbs` tolerates within a climb
+/// without splitting it in two.
+const CLIMB_GAP_TOLERANCE: f64 = 300.0;
+
+/// A unit of length of the distances or elevations of a course file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthUnit {
+    Meter,
+    Kilometer,
+    Foot,
+    Mile,
+}
+
+impl LengthUnit {
+    /// Returns the number of meters in one unit.
+    pub const fn meters(&self) -> f64 {
+        match self {
+            LengthUnit::Meter => 1.0,
+            LengthUnit::Kilometer => METERS_PER_KILOMETER,
+            LengthUnit::Foot => METERS_PER_FOOT,
+            LengthUnit::Mile => METERS_PER_MILE,
+        }
+    }
+}
+
+/// The units of the distances and elevations of a course file. The simulation itself is always in meters.
+///
+/// * `Metric` - Distances and elevations in meters.
+/// * `Imperial` - Distances in miles and elevations in feet.
+/// * `Mixed` - Explicit units for each, e.g. distances in miles and elevations in meters. Files mixing units are
+///   too ambiguous to guess, so they must be described this way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Units {
+    Metric,
+    Imperial,
+    Mixed { distance_unit: LengthUnit, elevation_unit: LengthUnit },
+}
+
+impl Units {
+    /// Returns the unit of the distances.
+    pub const fn distance_unit(&self) -> LengthUnit {
+        match self {
+            Units::Metric => LengthUnit::Meter,
+            Units::Imperial => LengthUnit::Mile,
+            Units::Mixed { distance_unit, .. } => *distance_unit,
+        }
+    }
+
+    /// Returns the unit of the elevations.
+    pub const fn elevation_unit(&self) -> LengthUnit {
+        match self {
+            Units::Metric => LengthUnit::Meter,
+            Units::Imperial => LengthUnit::Foot,
+            Units::Mixed { elevation_unit, .. } => *elevation_unit,
+        }
+    }
+
+    /// Converts a profile in these units to meters.
+    ///
+    /// # Arguments
+    ///
+    /// * `distance_vec` - The cumulative distances in `distance_unit`.
+    /// * `elevation_vec` - The elevations in `elevation_unit`.
+    ///
+    /// # Returns
+    ///
+    /// * `(Vec<f64>, Vec<f64>)` - The distances and elevations in meters.
+    pub fn to_meters(&self, distance_vec: &Vec<f64>, elevation_vec: &Vec<f64>) -> (Vec<f64>, Vec<f64>) {
+        let distance_scale = self.distance_unit().meters();
+        let elevation_scale = self.elevation_unit().meters();
+        (
+            distance_vec.iter().map(|distance| distance * distance_scale).collect(),
+            elevation_vec.iter().map(|elevation| elevation * elevation_scale).collect(),
+        )
+    }
+
+    /// Converts a profile in meters to these units, e.g. to display or export it, the inverse of `to_meters`.
+    ///
+    /// # Arguments
+    ///
+    /// * `distance_vec` - The cumulative distances in meters.
+    /// * `elevation_vec` - The elevations in meters.
+    ///
+    /// # Returns
+    ///
+    /// * `(Vec<f64>, Vec<f64>)` - The distances in `distance_unit` and elevations in `elevation_unit`.
+    pub fn from_meters(&self, distance_vec: &Vec<f64>, elevation_vec: &Vec<f64>) -> (Vec<f64>, Vec<f64>) {
+        let distance_scale = self.distance_unit().meters();
+        let elevation_scale = self.elevation_unit().meters();
+        (
+            distance_vec.iter().map(|distance| distance / distance_scale).collect(),
+            elevation_vec.iter().map(|elevation| elevation / elevation_scale).collect(),
+        )
+    }
+}
+
+/// Describes how a course is stored in an npz archive.
+///
+/// # Fields
+///
+/// * `distance_name` - The name of the cumulative distance array.
+/// * `elevation_name` - The name of the elevation array.
+/// * `distance_scale` - The factor converting the stored distances to meters (e.g. `METERS_PER_KILOMETER`).
+/// * `elevation_scale` - The factor converting the stored elevations to meters (e.g. `METERS_PER_FOOT`).
+pub struct NpzFormat<'a> {
+    pub distance_name: &'a str,
+    pub elevation_name: &'a str,
+    pub distance_scale: f64,
+    pub elevation_scale: f64,
+}
+
+/// Returns the npz format used by the sample data: `distance` and `elevation` arrays, both in meters.
+pub const fn default_npz_format() -> NpzFormat<'static> {
+    NpzFormat {
+        distance_name: "distance",
+        elevation_name: "elevation",
+        distance_scale: 1.0,
+        elevation_scale: 1.0,
+    }
+}
+
+/// Returns the npz format with the `distance` and `elevation` arrays of the sample data, stored in `units`.
+///
+/// # Example
+///
+/// ```
+/// let (distance_vec, elevation_vec) = load_npz("course.npz", &npz_format_in(Units::Imperial))?;
+/// ```
+pub const fn npz_format_in(units: Units) -> NpzFormat<'static> {
+    NpzFormat {
+        distance_scale: units.distance_unit().meters(),
+        elevation_scale: units.elevation_unit().meters(),
+        ..default_npz_format()
+    }
+}
+
+fn convert_array_to_float<T: AsPrimitive<f64>>(b: Array1<T>, scale: f64) -> Result<Vec<f64>, String> {
+    let (vec, offset) = b.into_raw_vec_and_offset();
+    if offset != Some(0) && !vec.is_empty() {
+        return Err(format!("unexpected array layout with offset {:?}", offset));
+    }
+    Ok(vec
+        .into_iter()
+        .map(|x: T| -> f64 {
+            scale * x.as_()
+        })
+        .collect())
+}
+
+/// Loads the distance and elevation profile of a course from an npz archive.
+///
+/// The distances may be stored as integers or floats, the elevations as floats. Both are converted to meters
+/// with the scales of `format`, see `npz_format_in` for files in imperial or mixed units.
+///
+/// # Arguments
+///
+/// * `path` - The path of the npz file.
+/// * `format` - The array names and units used in the file.
+///
+/// # Returns
+///
+/// * `Result<(Vec<f64>, Vec<f64>), Box<dyn std::error::Error>>` - The distances and elevations in meters, or an
+///   error naming the array that could not be read, e.g. because it is missing or of the wrong dtype, or saying
+///   the two arrays differ in length. Malformed files never panic.
+///
+/// # Example
+///
+/// ```
+/// let format = NpzFormat {
+///     distance_name: "dist_km",
+///     elevation_name: "alt_ft",
+///     distance_scale: METERS_PER_KILOMETER,
+///     elevation_scale: METERS_PER_FOOT,
+/// };
+/// let (distance_vec, elevation_vec) = load_npz("course.npz", &format)?;
+/// ```
+pub fn load_npz(path: &str, format: &NpzFormat) -> Result<(Vec<f64>, Vec<f64>), Box<dyn std::error::Error>> {
+    let mut npz = NpzReader::new(File::open(path)?)?;
+    // Distances are usually whole meters, but fractional in coarser units such as miles.
+    let distance_array: Result<Array1<i64>, _> = npz.by_name(format.distance_name);
+    let distance_vec = match distance_array {
+        Ok(distance_array) => convert_array_to_float(distance_array, format.distance_scale),
+        Err(_) => {
+            let distance_array: Array1<f64> = npz
+                .by_name(format.distance_name)
+                .map_err(|e| format!("cannot read distance array \"{}\": {}", format.distance_name, e))?;
+            convert_array_to_float(distance_array, format.distance_scale)
+        }
+    }
+    .map_err(|e| format!("cannot read distance array \"{}\": {}", format.distance_name, e))?;
+    let elevation_array: Array1<f64> = npz
+        .by_name(format.elevation_name)
+        .map_err(|e| format!("cannot read elevation array \"{}\": {}", format.elevation_name, e))?;
+
+    let elevation_vec = convert_array_to_float(elevation_array, format.elevation_scale)
+        .map_err(|e| format!("cannot read elevation array \"{}\": {}", format.elevation_name, e))?;
+    if distance_vec.len() != elevation_vec.len() {
+        return Err(format!(
+            "distance and elevation arrays have different lengths: {} and {}",
+            distance_vec.len(),
+            elevation_vec.len()
+        )
+        .into());
+    }
+
+    Ok((distance_vec, elevation_vec))
+}
+
+/// A one-dimensional array of 64-bit integers or floats in npy format, read a chunk at a time.
+struct NpyChunkReader<R: Read> {
+    reader: R,
+    is_integer: bool,
+    remaining_points: usize,
+    scale: f64,
+}
+
+impl<R: Read> NpyChunkReader<R> {
+    /// Reads the npy header, leaving `reader` at the start of the data.
+    fn new(mut reader: R, scale: f64) -> Result<NpyChunkReader<R>, String> {
+        let mut preamble = [0u8; 8];
+        reader.read_exact(&mut preamble).map_err(|e| e.to_string())?;
+        if &preamble[..6] != b"\x93NUMPY" {
+            return Err("not an npy array".to_string());
+        }
+        // Version 1 stores the header length on 2 bytes, later versions on 4.
+        let header_length = if preamble[6] == 1 {
+            let mut length_bytes = [0u8; 2];
+            reader.read_exact(&mut length_bytes).map_err(|e| e.to_string())?;
+            u16::from_le_bytes(length_bytes) as usize
+        } else {
+            let mut length_bytes = [0u8; 4];
+            reader.read_exact(&mut length_bytes).map_err(|e| e.to_string())?;
+            u32::from_le_bytes(length_bytes) as usize
+        };
+        let mut header_bytes = vec![0u8; header_length];
+        reader.read_exact(&mut header_bytes).map_err(|e| e.to_string())?;
+        let header = String::from_utf8_lossy(&header_bytes);
+
+        let is_integer = if header.contains("'<i8'") {
+            true
+        } else if header.contains("'<f8'") {
+            false
+        } else {
+            return Err(format!("unsupported dtype in header {}", header.trim()));
+        };
+        let shape = header
+            .split("'shape':")
+            .nth(1)
+            .and_then(|rest| rest.split(['(', ')']).nth(1))
+            .ok_or_else(|| format!("no shape in header {}", header.trim()))?;
+        let dimension_vec: Vec<&str> =
+            shape.split(',').map(str::trim).filter(|dimension| !dimension.is_empty()).collect();
+        let [length] = dimension_vec[..] else {
+            return Err(format!("expected a one-dimensional array, got shape ({})", shape));
+        };
+        let length = length.parse::<usize>().map_err(|e| e.to_string())?;
+        Ok(NpyChunkReader {
+            reader,
+            is_integer,
+            remaining_points: length,
+            scale,
+        })
+    }
+
+    /// Reads up to `max_points` more points, scaled, into `out_vec`.
+    fn read_chunk(&mut self, max_points: usize, out_vec: &mut Vec<f64>) -> Result<(), String> {
+        let n_points = usize::min(max_points, self.remaining_points);
+        let mut bytes = vec![0u8; 8 * n_points];
+        self.reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        out_vec.reserve(n_points);
+        for point_bytes in bytes.chunks_exact(8) {
+            let point_bytes: [u8; 8] =
+                point_bytes.try_into().map_err(|e: std::array::TryFromSliceError| e.to_string())?;
+            let point = if self.is_integer {
+                i64::from_le_bytes(point_bytes) as f64
+            } else {
+                f64::from_le_bytes(point_bytes)
+            };
+            out_vec.push(self.scale * point);
+        }
+        self.remaining_points -= n_points;
+        Ok(())
+    }
+}
+
+/// Streams the segments of a course stored in an npz archive, for courses too large to load at once with
+/// `load_npz`, e.g. continent-scale routes.
+///
+/// The distance and elevation arrays are read in windows of `chunk_points` points, and the segments of each
+/// window are built with `build_segment_vecs` and handed to `on_segments`, so that only one window is in memory
+/// at a time. Each window but the last leaves its final segment, and any run of duplicate points at its end, to
+/// the next one, which starts from the point before, so the segments across the boundaries get the same length
+/// and slope as with the whole arrays, and the segments are exactly those of `load_npz` followed by
+/// `build_segment_vecs`. The arrays must be one-dimensional, of little-endian 64-bit integers (distances only) or
+/// floats, as written by numpy, in a stored or deflated archive.
+///
+/// # Arguments
+///
+/// * `path` - The path of the npz file.
+/// * `format` - The array names and units used in the file.
+/// * `chunk_points` - The number of points read from each array at once, see `DEFAULT_STREAM_CHUNK_POINTS`.
+/// * `on_segments` - Called with the segments of each window, in order.
+///
+/// # Returns
+///
+/// * `Result<usize, Box<dyn std::error::Error>>` - The total number of segments, or an error naming the array
+///   that could not be read, saying the two arrays differ in length or that the distance decreases.
+///
+/// # Example
+///
+/// ```
+/// let mut total_length = 0.0;
+/// let format = default_npz_format();
+/// let n_segments = stream_npz_segments("route.npz", &format, DEFAULT_STREAM_CHUNK_POINTS, |road_segment_vec| {
+///     total_length += road_segment_vec.iter().map(|road_segment| road_segment.length).sum::<f64>();
+/// })?;
+/// ```
+pub fn stream_npz_segments(
+    path: &str,
+    format: &NpzFormat,
+    chunk_points: usize,
+    mut on_segments: impl FnMut(Vec<simulation::RoadSegment>),
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // Each array is read through its own handle on the file, so that both can be read alongside.
+    let mut distance_archive = ZipArchive::new(File::open(path)?)?;
+    let mut elevation_archive = ZipArchive::new(File::open(path)?)?;
+    let distance_file = distance_archive
+        .by_name(&format!("{}.npy", format.distance_name))
+        .map_err(|e| format!("cannot read distance array \"{}\": {}", format.distance_name, e))?;
+    let elevation_file = elevation_archive
+        .by_name(&format!("{}.npy", format.elevation_name))
+        .map_err(|e| format!("cannot read elevation array \"{}\": {}", format.elevation_name, e))?;
+    let mut distance_reader = NpyChunkReader::new(distance_file, format.distance_scale)
+        .map_err(|e| format!("cannot read distance array \"{}\": {}", format.distance_name, e))?;
+    let mut elevation_reader = NpyChunkReader::new(elevation_file, format.elevation_scale)
+        .map_err(|e| format!("cannot read elevation array \"{}\": {}", format.elevation_name, e))?;
+    if distance_reader.remaining_points != elevation_reader.remaining_points {
+        return Err(format!(
+            "distance and elevation arrays have different lengths: {} and {}",
+            distance_reader.remaining_points, elevation_reader.remaining_points
+        )
+        .into());
+    }
+
+    let chunk_points = usize::max(1, chunk_points);
+    let mut distance_vec = Vec::with_capacity(chunk_points + 1);
+    let mut elevation_vec = Vec::with_capacity(chunk_points + 1);
+    let mut n_segments = 0;
+    while distance_reader.remaining_points > 0 {
+        distance_reader
+            .read_chunk(chunk_points, &mut distance_vec)
+            .map_err(|e| format!("cannot read distance array \"{}\": {}", format.distance_name, e))?;
+        elevation_reader
+            .read_chunk(chunk_points, &mut elevation_vec)
+            .map_err(|e| format!("cannot read elevation array \"{}\": {}", format.elevation_name, e))?;
+        // A trailing run of duplicate points may go on in the next window, and the segment up to the run must end
+        // at its last point, so the segments of this window stop at the point before the run.
+        let n_points = distance_vec.len();
+        let is_last_window = distance_reader.remaining_points == 0;
+        let end = if is_last_window {
+            n_points
+        } else {
+            let last_distance = distance_vec[n_points - 1];
+            distance_vec.iter().rposition(|&distance| distance != last_distance).map_or(0, |i| i + 1)
+        };
+        let road_segment_vec = build_segment_vecs(&distance_vec[..end].to_vec(), &elevation_vec[..end].to_vec())?;
+        n_segments += road_segment_vec.len();
+        if !road_segment_vec.is_empty() {
+            on_segments(road_segment_vec);
+        }
+        if is_last_window {
+            break;
+        }
+        // The point before the run starts the first segment of the next window, followed by the last point of the
+        // run, the others being merged anyway.
+        for point_vec in [&mut distance_vec, &mut elevation_vec] {
+            point_vec.drain(end..n_points - 1);
+            point_vec.drain(..end.saturating_sub(1));
+        }
+    }
+    Ok(n_segments)
+}
+
+/// Merges the duplicate points of a profile, a common export artifact anywhere in the series, which would give
+/// zero-length segments with an undefined slope. Of each run of points at the same distance, the last is kept, so
+/// the next segment starts from it.
+///
+/// # Returns
+///
+/// * `Result<Vec<usize>, String>` - The indices of the points kept, in order, to pick the matching elevations,
+///   coordinates or any other per-point data, or an error if the distance decreases between two points.
+///
+/// # Example
+///
+/// ```
+/// let kept_index_vec = merge_duplicate_points(&distance_vec)?;
+/// let kept_distance_vec: Vec<f64> = kept_index_vec.iter().map(|&i| distance_vec[i]).collect();
+/// let label_vec = segment_labels(&kept_distance_vec, &waypoint_vec);
+/// ```
+pub fn merge_duplicate_points(distance_vec: &Vec<f64>) -> Result<Vec<usize>, String> {
+    let mut kept_index_vec: Vec<usize> = Vec::with_capacity(distance_vec.len());
+    for (i, &distance) in distance_vec.iter().enumerate() {
+        let Some(&last_index) = kept_index_vec.last() else {
+            kept_index_vec.push(i);
+            continue;
+        };
+        let step = distance - distance_vec[last_index];
+        if step < 0.0 {
+            return Err(format!(
+                "distance decreases from {} m to {} m at point {}",
+                distance_vec[last_index], distance, i
+            ));
+        }
+        if step == 0.0 {
+            kept_index_vec.pop();
+        }
+        kept_index_vec.push(i);
+    }
+    Ok(kept_index_vec)
+}
+
+/// Builds the segments between consecutive points of a profile, after merging its duplicate points with
+/// `merge_duplicate_points`. Segment `k` runs between the kept points `k` and `k + 1`, so per-point data such as
+/// the coordinates or the distances given to `segment_labels` must be picked with the same kept indices.
+///
+/// # Returns
+///
+/// * `Result<Vec<simulation::RoadSegment>, String>` - The segments, or an error if the distance decreases between
+///   two points.
+pub fn build_segment_vecs(
+    distance_vec: &Vec<f64>,
+    elevation_vec: &Vec<f64>,
+) -> Result<Vec<simulation::RoadSegment>, String> {
+    let kept_index_vec = merge_duplicate_points(distance_vec)?;
+    Ok(build_kept_segment_vecs(distance_vec, elevation_vec, &kept_index_vec))
+}
+
+/// Same as `build_segment_vecs`, with the indices of the points kept by `merge_duplicate_points` already computed.
+fn build_kept_segment_vecs(
+    distance_vec: &[f64],
+    elevation_vec: &[f64],
+    kept_index_vec: &[usize],
+) -> Vec<simulation::RoadSegment> {
+    let mut road_segment_vec: Vec<simulation::RoadSegment> = Vec::with_capacity(kept_index_vec.len());
+
+    for pair in kept_index_vec.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let segment_length = distance_vec[end] - distance_vec[start];
+        let slope = (elevation_vec[end] - elevation_vec[start]) / segment_length;
+        let temperature = 20.0;
+        road_segment_vec.push(simulation::RoadSegment {
+            length: segment_length,
+            slope,
+            temperature,
+            altitude: elevation_vec[start],
+            relative_wind_speed: 0.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        });
+    }
+    road_segment_vec
+}
+
+/// A named point of a course, e.g. a GPX waypoint such as the top of a climb.
+///
+/// # Fields
+///
+/// * `distance` - The distance from the start in meters.
+/// * `name` - The name of the point.
+pub struct Waypoint {
+    pub distance: f64,
+    pub name: String,
+}
+
+/// Attaches the waypoint names to the segments between consecutive distances of `distance_vec`, which should be
+/// the distances of the points kept by `merge_duplicate_points` for the labels to match the segments.
+///
+/// Segment `i` covers the distances from `distance_vec[i]` (included) to `distance_vec[i + 1]` (excluded, except
+/// for the last segment). When several waypoints fall within one segment, their names are joined with `" / "`
+/// in order of distance. Waypoints outside the course, or at a NaN distance, are ignored.
+///
+/// # Returns
+///
+/// * `Vec<Option<String>>` - The label of each segment, `None` for segments without waypoints.
+pub fn segment_labels(distance_vec: &Vec<f64>, waypoint_vec: &Vec<Waypoint>) -> Vec<Option<String>> {
+    let n_segments = distance_vec.len().saturating_sub(1);
+    let mut label_vec: Vec<Option<String>> = vec![None; n_segments];
+    if n_segments == 0 {
+        return label_vec;
+    }
+    let mut sorted_waypoint_vec: Vec<&Waypoint> = waypoint_vec.iter().collect();
+    sorted_waypoint_vec.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+    for waypoint in sorted_waypoint_vec {
+        if waypoint.distance < distance_vec[0] || waypoint.distance > distance_vec[n_segments] {
+            continue;
+        }
+        // No point is at or before a NaN distance, which the range check above lets through.
+        let n_points_before = distance_vec.partition_point(|&distance| distance <= waypoint.distance);
+        let Some(point_index) = n_points_before.checked_sub(1) else {
+            continue;
+        };
+        let segment_index = usize::min(point_index, n_segments - 1);
+        label_vec[segment_index] = match label_vec[segment_index].take() {
+            Some(label) => Some(label + " / " + &waypoint.name),
+            None => Some(waypoint.name.clone()),
+        };
+    }
+    label_vec
+}
+
+/// Converts a grade in percent, as on road signs, into a slope, e.g. 8% into 0.08. Slopes and grades are positive
+/// uphill and negative downhill throughout the crate, and are the elevation change over the distance travelled.
+pub fn grade_percent_to_slope(grade_percent: f64) -> f64 {
+    grade_percent / 100.0
+}
+
+/// Converts an elevation profile into the slope of each segment between consecutive points, positive uphill.
+///
+/// # Arguments
+///
+/// * `distance_vec` - The cumulative distances of the points in meters, increasing.
+/// * `elevation_vec` - The elevations of the points in meters.
+///
+/// # Returns
+///
+/// * `Vec<f64>` - The slope of each segment, one fewer than the points, e.g. 0.08 for 8%.
+pub fn elevation_to_slopes(distance_vec: &Vec<f64>, elevation_vec: &Vec<f64>) -> Vec<f64> {
+    (1..usize::min(distance_vec.len(), elevation_vec.len()))
+        .map(|i| (elevation_vec[i] - elevation_vec[i - 1]) / (distance_vec[i] - distance_vec[i - 1]))
+        .collect()
+}
+
+/// Converts the slopes of the segments between consecutive points into an elevation profile, the inverse of
+/// `elevation_to_slopes`: the slopes only give the elevation relative to the start, so it is set to
+/// `start_elevation`.
+///
+/// # Arguments
+///
+/// * `distance_vec` - The cumulative distances of the points in meters, increasing.
+/// * `slope_vec` - The slope of each segment, one fewer than the points.
+/// * `start_elevation` - The elevation of the first point in meters.
+///
+/// # Returns
+///
+/// * `Vec<f64>` - The elevation of each point in meters.
+///
+/// # Example
+///
+/// ```
+/// let slope_vec = elevation_to_slopes(&distance_vec, &elevation_vec);
+/// let round_trip_vec = slopes_to_elevation(&distance_vec, &slope_vec, elevation_vec[0]);
+/// ```
+pub fn slopes_to_elevation(distance_vec: &Vec<f64>, slope_vec: &Vec<f64>, start_elevation: f64) -> Vec<f64> {
+    let mut elevation_vec = vec![start_elevation];
+    let mut elevation = start_elevation;
+    for (i, slope) in slope_vec.iter().enumerate().take(distance_vec.len().saturating_sub(1)) {
+        elevation += slope * (distance_vec[i + 1] - distance_vec[i]);
+        elevation_vec.push(elevation);
+    }
+    elevation_vec
+}
+
+/// When the elevation profile is smoothed relative to its resampling onto a uniform grid.
+///
+/// * `BeforeResampling` - The raw points are smoothed, so the window averages over the recorded points.
+/// * `AfterResampling` - The resampled points are smoothed, so every point weighs the same whatever the recording
+///   density. Dense recordings, e.g. when stopped, then no longer dominate the average.
+pub enum SmoothingOrder {
+    BeforeResampling,
+    AfterResampling,
+}
+
+/// Settings of the course preprocessing pipeline, see `preprocess`.
+///
+/// # Fields
+///
+/// * `sample_spacing` - The length of the resampled segments in meters.
+/// * `smoothing_window` - The width in meters of the centered moving average applied to the elevations, zero to
+///   disable smoothing.
+/// * `smoothing_order` - Whether the smoothing is applied before or after resampling.
+/// * `min_segment_length` - The length in meters below which a segment is merged into its shorter neighbour.
+/// * `min_crest_height` - How far in meters a crest must rise above (or a trough sink below) both ends of a
+///   resampled segment for the segment to be split there, so small oscillations of a flat road are ignored.
+///   `f64::INFINITY` disables the splitting.
+/// * `base_altitude` - The altitude in meters added to the elevations, for sources giving them relative to the
+///   start or to the ground. Only the air density depends on it; the slopes come from the relative profile.
+pub struct PreprocessOptions {
+    pub sample_spacing: f64,
+    pub smoothing_window: f64,
+    pub smoothing_order: SmoothingOrder,
+    pub min_segment_length: f64,
+    pub min_crest_height: f64,
+    pub base_altitude: f64,
+}
+
+pub const fn default_preprocess_options() -> PreprocessOptions {
+    PreprocessOptions {
+        sample_spacing: 100.0,
+        smoothing_window: 200.0,
+        smoothing_order: SmoothingOrder::BeforeResampling,
+        min_segment_length: 20.0,
+        min_crest_height: 1.0,
+        base_altitude: 0.0,
+    }
+}
+
+/// Keeps the points of increasing distance and fills the missing (non-finite) elevations by linear interpolation
+/// between their neighbours, or with the nearest elevation at the ends of the course.
+fn fill_gaps(raw_distance_vec: &Vec<f64>, raw_elevation_vec: &Vec<f64>) -> (Vec<f64>, Vec<f64>) {
+    let mut distance_vec: Vec<f64> = Vec::with_capacity(raw_distance_vec.len());
+    let mut elevation_vec: Vec<f64> = Vec::with_capacity(raw_distance_vec.len());
+    for (&distance, &elevation) in raw_distance_vec.iter().zip(raw_elevation_vec.iter()) {
+        if distance.is_finite() && distance_vec.last().is_none_or(|&last_distance| distance > last_distance) {
+            distance_vec.push(distance);
+            elevation_vec.push(elevation);
+        }
+    }
+    let known_index_vec: Vec<usize> = (0..elevation_vec.len()).filter(|&i| elevation_vec[i].is_finite()).collect();
+    for i in 0..elevation_vec.len() {
+        if elevation_vec[i].is_finite() {
+            continue;
+        }
+        let next = known_index_vec.partition_point(|&index| index < i);
+        elevation_vec[i] = match (next.checked_sub(1).map(|k| known_index_vec[k]), known_index_vec.get(next)) {
+            (Some(before), Some(&after)) => {
+                let weight = (distance_vec[i] - distance_vec[before]) / (distance_vec[after] - distance_vec[before]);
+                elevation_vec[before] + weight * (elevation_vec[after] - elevation_vec[before])
+            }
+            (Some(before), None) => elevation_vec[before],
+            (None, Some(&after)) => elevation_vec[after],
+            (None, None) => f64::NAN,
+        };
+    }
+    (distance_vec, elevation_vec)
+}
+
+/// Centered moving average of the elevations over `smoothing_window` meters.
+fn smooth(distance_vec: &Vec<f64>, elevation_vec: &Vec<f64>, smoothing_window: f64) -> Vec<f64> {
+    if smoothing_window <= 0.0 {
+        return elevation_vec.to_owned();
+    }
+    let half_window = 0.5 * smoothing_window;
+    distance_vec
+        .iter()
+        .map(|&distance| {
+            let start = distance_vec.partition_point(|&d| d < distance - half_window);
+            let end = distance_vec.partition_point(|&d| d <= distance + half_window);
+            elevation_vec[start..end].iter().sum::<f64>() / (end - start) as f64
+        })
+        .collect()
+}
+
+/// Linearly interpolates the elevations every `sample_spacing` meters from the start to the end of the course. The
+/// last point is always the end of the course, so the last segment can be shorter.
+fn resample(distance_vec: &Vec<f64>, elevation_vec: &Vec<f64>, sample_spacing: f64) -> (Vec<f64>, Vec<f64>) {
+    let start = distance_vec[0];
+    let end = distance_vec[distance_vec.len() - 1];
+    let mut resampled_distance_vec: Vec<f64> = Vec::new();
+    let mut k = 0;
+    while start + k as f64 * sample_spacing < end {
+        resampled_distance_vec.push(start + k as f64 * sample_spacing);
+        k += 1;
+    }
+    resampled_distance_vec.push(end);
+    let resampled_elevation_vec = resampled_distance_vec
+        .iter()
+        .map(|&distance| {
+            let i = usize::min(distance_vec.partition_point(|&d| d <= distance), distance_vec.len() - 1);
+            let weight = (distance - distance_vec[i - 1]) / (distance_vec[i] - distance_vec[i - 1]);
+            elevation_vec[i - 1] + weight * (elevation_vec[i] - elevation_vec[i - 1])
+        })
+        .collect();
+    (resampled_distance_vec, resampled_elevation_vec)
+}
+
+/// Pushes the crests and troughs of the profile strictly between `start` and `end`, both `(distance, elevation)`
+/// points, so that the grade keeps its sign between consecutive points. Only extrema rising above (or sinking
+/// below) both ends by more than `min_crest_height` count.
+fn push_extrema(
+    distance_vec: &Vec<f64>,
+    elevation_vec: &Vec<f64>,
+    start: (f64, f64),
+    end: (f64, f64),
+    min_crest_height: f64,
+    out_distance_vec: &mut Vec<f64>,
+    out_elevation_vec: &mut Vec<f64>,
+) {
+    let first = distance_vec.partition_point(|&d| d <= start.0);
+    let last = distance_vec.partition_point(|&d| d < end.0);
+    let mut extremum: Option<(usize, f64)> = None;
+    for (i, &elevation) in elevation_vec.iter().enumerate().take(last).skip(first) {
+        let height = f64::max(elevation - f64::max(start.1, end.1), f64::min(start.1, end.1) - elevation);
+        if height > min_crest_height && extremum.is_none_or(|(_, best_height)| height > best_height) {
+            extremum = Some((i, height));
+        }
+    }
+    let Some((i, _)) = extremum else {
+        return;
+    };
+    let point = (distance_vec[i], elevation_vec[i]);
+    push_extrema(distance_vec, elevation_vec, start, point, min_crest_height, out_distance_vec, out_elevation_vec);
+    out_distance_vec.push(point.0);
+    out_elevation_vec.push(point.1);
+    push_extrema(distance_vec, elevation_vec, point, end, min_crest_height, out_distance_vec, out_elevation_vec);
+}
+
+/// Adds to the resampled profile the crests and troughs of the underlying profile found between two resampled
+/// points, see `push_extrema`. A segment straddling a crest would otherwise average the climb and the descent into
+/// a single, near-flat grade.
+fn split_at_extrema(
+    distance_vec: &Vec<f64>,
+    elevation_vec: &Vec<f64>,
+    resampled_distance_vec: &Vec<f64>,
+    resampled_elevation_vec: &Vec<f64>,
+    min_crest_height: f64,
+) -> (Vec<f64>, Vec<f64>) {
+    let mut split_distance_vec: Vec<f64> = vec![resampled_distance_vec[0]];
+    let mut split_elevation_vec: Vec<f64> = vec![resampled_elevation_vec[0]];
+    for i in 1..resampled_distance_vec.len() {
+        push_extrema(
+            distance_vec,
+            elevation_vec,
+            (resampled_distance_vec[i - 1], resampled_elevation_vec[i - 1]),
+            (resampled_distance_vec[i], resampled_elevation_vec[i]),
+            min_crest_height,
+            &mut split_distance_vec,
+            &mut split_elevation_vec,
+        );
+        split_distance_vec.push(resampled_distance_vec[i]);
+        split_elevation_vec.push(resampled_elevation_vec[i]);
+    }
+    (split_distance_vec, split_elevation_vec)
+}
+
+/// Merges every segment shorter than `min_segment_length` into its shorter neighbour, shortest first, by removing
+/// the point between them. The slope of the merged segment is the length-weighted mean of the two slopes, since
+/// the elevations of the remaining points are unchanged. A course shorter than `min_segment_length` is left as a
+/// single segment.
+fn merge_short_segments(distance_vec: &mut Vec<f64>, elevation_vec: &mut Vec<f64>, min_segment_length: f64) {
+    while distance_vec.len() > 2 {
+        let shortest = (0..distance_vec.len() - 1)
+            .map(|i| (i, distance_vec[i + 1] - distance_vec[i]))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((i, length)) = shortest else {
+            return;
+        };
+        if length >= min_segment_length {
+            return;
+        }
+        // Segment `i` goes from point `i` to point `i + 1`; removing one of them merges it with a neighbour.
+        let n_segments = distance_vec.len() - 1;
+        let previous_length = if i > 0 { distance_vec[i] - distance_vec[i - 1] } else { f64::INFINITY };
+        let next_length = if i + 1 < n_segments { distance_vec[i + 2] - distance_vec[i + 1] } else { f64::INFINITY };
+        let removed_point = if previous_length <= next_length { i } else { i + 1 };
+        distance_vec.remove(removed_point);
+        elevation_vec.remove(removed_point);
+    }
+}
+
+/// Turns a raw recorded profile into segments ready to simulate.
+///
+/// The stages run in this order:
+///
+/// 1. Fill gaps: points whose distance does not increase are dropped, and missing (non-finite) elevations are
+///    interpolated from their neighbours.
+/// 2. Smooth, if `options.smoothing_order` is `BeforeResampling`.
+/// 3. Resample every `options.sample_spacing` meters.
+/// 4. Split at crests: where the profile between two resampled points rises above both (or sinks below both) by
+///    more than `options.min_crest_height`, its highest (or lowest) point is added, so no segment straddles a
+///    change of grade sign.
+/// 5. Smooth, if `options.smoothing_order` is `AfterResampling`.
+/// 6. Merge every segment shorter than `options.min_segment_length`, e.g. the last, partial one or a sliver next
+///    to a crest, into its shorter neighbour.
+///
+/// The order of smoothing and resampling changes the result, which is why it is an option rather than fixed.
+/// The segments are at 20°C, without wind, on smooth road. The recordings hold no positions, so no bearing is
+/// computed.
+///
+/// # Arguments
+///
+/// * `raw_distance_vec` - The recorded cumulative distances in meters.
+/// * `raw_elevation_vec` - The recorded elevations in meters, non-finite where missing.
+/// * `options` - The settings of the stages.
+///
+/// # Returns
+///
+/// * `Result<Vec<simulation::RoadSegment>, Box<dyn std::error::Error>>` - The segments, or an error when the
+///   options are invalid or fewer than two points have a distance and an elevation.
+///
+/// # Example
+///
+/// ```
+/// let (distance_vec, elevation_vec) = load_sample_data()?;
+/// let road_segment_vec = preprocess(&distance_vec, &elevation_vec, &default_preprocess_options())?;
+/// ```
+pub fn preprocess(
+    raw_distance_vec: &Vec<f64>,
+    raw_elevation_vec: &Vec<f64>,
+    options: &PreprocessOptions,
+) -> Result<Vec<simulation::RoadSegment>, Box<dyn std::error::Error>> {
+    if raw_distance_vec.len() != raw_elevation_vec.len() {
+        return Err(format!(
+            "distance and elevation lengths differ: {} and {}",
+            raw_distance_vec.len(),
+            raw_elevation_vec.len()
+        )
+        .into());
+    }
+    if options.sample_spacing.is_nan() || options.sample_spacing <= 0.0 {
+        return Err(format!("sample spacing must be positive, got {}", options.sample_spacing).into());
+    }
+    let (distance_vec, mut elevation_vec) = fill_gaps(raw_distance_vec, raw_elevation_vec);
+    if distance_vec.len() < 2 || elevation_vec.iter().any(|elevation| !elevation.is_finite()) {
+        return Err("fewer than two points with a distance and an elevation".into());
+    }
+
+    if let SmoothingOrder::BeforeResampling = options.smoothing_order {
+        elevation_vec = smooth(&distance_vec, &elevation_vec, options.smoothing_window);
+    }
+    let (resampled_distance_vec, resampled_elevation_vec) =
+        resample(&distance_vec, &elevation_vec, options.sample_spacing);
+    let (mut distance_vec, mut elevation_vec) = split_at_extrema(
+        &distance_vec,
+        &elevation_vec,
+        &resampled_distance_vec,
+        &resampled_elevation_vec,
+        options.min_crest_height,
+    );
+    if let SmoothingOrder::AfterResampling = options.smoothing_order {
+        elevation_vec = smooth(&distance_vec, &elevation_vec, options.smoothing_window);
+    }
+    merge_short_segments(&mut distance_vec, &mut elevation_vec, options.min_segment_length);
+
+    let road_segment_vec = (0..distance_vec.len() - 1)
+        .map(|i| {
+            let length = distance_vec[i + 1] - distance_vec[i];
+            simulation::RoadSegment {
+                length,
+                altitude: options.base_altitude + elevation_vec[i],
+                slope: (elevation_vec[i + 1] - elevation_vec[i]) / length,
+                temperature: 20.0,
+                relative_wind_speed: 0.0,
+                roughness: 1.0,
+                rolling_resistance: None,
+                drivetrain_efficiency: None,
+            }
+        })
+        .collect();
+    Ok(road_segment_vec)
+}
+
+/// A problem found in a course profile.
+///
+/// * `NonFinite` - The distance or elevation of the point is NaN or infinite.
+/// * `NonMonotonicDistance` - The distance decreases over the segment.
+/// * `ZeroLength` - The segment has the same distance at both ends, so its slope is undefined.
+/// * `SteepGrade` - The absolute grade of the segment is above `STEEP_GRADE_WARNING`, e.g. 0.25 for 25%.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IssueKind {
+    NonFinite,
+    NonMonotonicDistance,
+    ZeroLength,
+    SteepGrade { grade: f64 },
+}
+
+/// Whether an issue prevents a meaningful simulation.
+///
+/// * `Warning` - The data is plausible but worth checking, e.g. a borderline-steep grade.
+/// * `Error` - The data is invalid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// An issue found in a course profile.
+///
+/// # Fields
+///
+/// * `index` - The index of the point for `NonFinite` issues, and of the segment (from point `index` to point
+///   `index + 1`) otherwise.
+/// * `kind` - The kind of issue.
+/// * `severity` - Whether it is a warning or an error.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValidationIssue {
+    pub index: usize,
+    pub kind: IssueKind,
+    pub severity: Severity,
+}
+
+/// The issues found in a course profile, in order of index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationReport {
+    pub issue_vec: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns whether any issue is an error.
+    pub fn has_errors(&self) -> bool {
+        self.issue_vec.iter().any(|issue| issue.severity == Severity::Error)
+    }
+}
+
+/// A climb found by `detect_climbs`.
+///
+/// # Fields
+///
+/// * `start_index` - The index of the first segment of the climb.
+/// * `end_index` - The index after the last segment of the climb.
+/// * `start_distance` - The distance from the start of the course to the foot of the climb in meters.
+/// * `length` - The length of the climb in meters.
+/// * `elevation_gain` - The elevation gained from the foot to the top in meters, net of any dip within the climb.
+/// * `average_gradient` - The elevation gain over the length, e.g. 0.07 for 7%.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClimbSpan {
+    pub start_index: usize,
+    pub end_index: usize,
+    pub start_distance: f64,
+    pub length: f64,
+    pub elevation_gain: f64,
+    pub average_gradient: f64,
+}
+
+/// Finds the climbs of a course: runs of segments with a grade of at least `min_grade`, at least `min_length`
+/// meters long.
+///
+/// Shallower stretches of up to `CLIMB_GAP_TOLERANCE` meters between climbing segments, e.g. a false flat or a
+/// short dip, belong to the climb rather than splitting it. A climb always starts and ends with a climbing
+/// segment.
+///
+/// # Example
+///
+/// ```
+/// let road_segment_vec = preprocess(&distance_vec, &elevation_vec, &default_preprocess_options())?;
+/// for climb in detect_climbs(&road_segment_vec, 0.03, 1000.0).iter() {
+///     println!("{:.0}m at {:.1}%", climb.length, 100.0 * climb.average_gradient);
+/// }
+/// ```
+pub fn detect_climbs(
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    min_grade: f64,
+    min_length: f64,
+) -> Vec<ClimbSpan> {
+    let mut climb_vec = Vec::new();
+    let mut distance = 0.0;
+    let mut i = 0;
+    while i < road_segment_vec.len() {
+        if road_segment_vec[i].slope < min_grade {
+            distance += road_segment_vec[i].length;
+            i += 1;
+            continue;
+        }
+        let mut end_index = i + 1;
+        let mut gap_length = 0.0;
+        for (j, road_segment) in road_segment_vec.iter().enumerate().skip(i + 1) {
+            if road_segment.slope >= min_grade {
+                end_index = j + 1;
+                gap_length = 0.0;
+            } else {
+                gap_length += road_segment.length;
+                if gap_length > CLIMB_GAP_TOLERANCE {
+                    break;
+                }
+            }
+        }
+        let climb_segment_vec = &road_segment_vec[i..end_index];
+        let length: f64 = climb_segment_vec.iter().map(|road_segment| road_segment.length).sum();
+        let elevation_gain: f64 =
+            climb_segment_vec.iter().map(|road_segment| road_segment.slope * road_segment.length).sum();
+        if length >= min_length {
+            climb_vec.push(ClimbSpan {
+                start_index: i,
+                end_index,
+                start_distance: distance,
+                length,
+                elevation_gain,
+                average_gradient: elevation_gain / length,
+            });
+        }
+        distance += length;
+        i = end_index;
+    }
+    climb_vec
+}
+
+/// Checks an imported course profile before simulating it, without modifying it.
+///
+/// Points with a non-finite distance or elevation, decreasing distances, zero-length segments and grades
+/// steeper than `STEEP_GRADE_ERROR` are errors. Grades steeper than `STEEP_GRADE_WARNING` are only warnings,
+/// since such roads exist. Segments next to a non-finite point are not checked further.
+///
+/// # Arguments
+///
+/// * `distance_vec` - The cumulative distances in meters.
+/// * `elevation_vec` - The elevations in meters.
+///
+/// # Returns
+///
+/// * `ValidationReport` - The issues found, empty for a clean profile.
+///
+/// # Example
+///
+/// ```
+/// let (distance_vec, elevation_vec) = load_sample_data()?;
+/// let report = validate_profile(&distance_vec, &elevation_vec);
+/// for issue in report.issue_vec.iter() {
+///     println!("{:?}", issue);
+/// }
+/// ```
+pub fn validate_profile(distance_vec: &Vec<f64>, elevation_vec: &Vec<f64>) -> ValidationReport {
+    let mut issue_vec = Vec::new();
+    let is_finite = |i: usize| distance_vec[i].is_finite() && elevation_vec[i].is_finite();
+    let n_points = usize::min(distance_vec.len(), elevation_vec.len());
+    for i in 0..n_points {
+        if !is_finite(i) {
+            issue_vec.push(ValidationIssue {
+                index: i,
+                kind: IssueKind::NonFinite,
+                severity: Severity::Error,
+            });
+        }
+        if i + 1 >= n_points || !is_finite(i) || !is_finite(i + 1) {
+            continue;
+        }
+        let length = distance_vec[i + 1] - distance_vec[i];
+        if length < 0.0 {
+            issue_vec.push(ValidationIssue {
+                index: i,
+                kind: IssueKind::NonMonotonicDistance,
+                severity: Severity::Error,
+            });
+        } else if length == 0.0 {
+            issue_vec.push(ValidationIssue {
+                index: i,
+                kind: IssueKind::ZeroLength,
+                severity: Severity::Error,
+            });
+        } else {
+            let grade = (elevation_vec[i + 1] - elevation_vec[i]) / length;
+            if f64::abs(grade) > STEEP_GRADE_WARNING {
+                let severity = if f64::abs(grade) > STEEP_GRADE_ERROR { Severity::Error } else { Severity::Warning };
+                issue_vec.push(ValidationIssue {
+                    index: i,
+                    kind: IssueKind::SteepGrade { grade },
+                    severity,
+                });
+            }
+        }
+    }
+    ValidationReport { issue_vec }
+}
+
+/// A course with the positions of its points, for mapping.
+///
+/// # Fields
+///
+/// * `road_segment_vec` - The segments of the course.
+/// * `coordinate_vec` - The `(longitude, latitude)` in degrees of each point, one more than the segments, or
+///   `None` when the source has no positions, e.g. an npz archive from `load_npz`.
+pub struct GeoCourse {
+    pub road_segment_vec: Vec<simulation::RoadSegment>,
+    pub coordinate_vec: Option<Vec<(f64, f64)>>,
+}
+
+/// Builds a course with the positions of its points, keeping the coordinates of the points kept by
+/// `merge_duplicate_points` so that they stay paired with the segments.
+///
+/// # Returns
+///
+/// * `Result<GeoCourse, String>` - The course, or an error if the distance decreases between two points or the
+///   number of coordinates differs from the number of points.
+pub fn build_geo_course(
+    distance_vec: &Vec<f64>,
+    elevation_vec: &Vec<f64>,
+    coordinate_vec: Option<&Vec<(f64, f64)>>,
+) -> Result<GeoCourse, String> {
+    if let Some(coordinate_vec) = coordinate_vec {
+        if coordinate_vec.len() != distance_vec.len() {
+            return Err(format!(
+                "expected one coordinate per point, got {} coordinates for {} points",
+                coordinate_vec.len(),
+                distance_vec.len()
+            ));
+        }
+    }
+    let kept_index_vec = merge_duplicate_points(distance_vec)?;
+    let kept_coordinate_vec =
+        coordinate_vec.map(|coordinate_vec| kept_index_vec.iter().map(|&i| coordinate_vec[i]).collect());
+    Ok(GeoCourse {
+        road_segment_vec: build_kept_segment_vecs(distance_vec, elevation_vec, &kept_index_vec),
+        coordinate_vec: kept_coordinate_vec,
+    })
+}
+
+/// Converts a simulation result into a GeoJSON `FeatureCollection` for web mapping: one `LineString` feature per
+/// segment, from its start point to its end point, with the segment outputs as properties (`segment_index`,
+/// `distance`, `duration`, `speed`, `power` and `anaerobic_reserve`, in the units of `SimulationResult`).
+///
+/// # Returns
+///
+/// * `Result<String, Box<dyn std::error::Error>>` - The GeoJSON, or an error when the course has no coordinates,
+///   or when the number of coordinates or of result entries does not match the segments.
+///
+/// # Example
+///
+/// ```
+/// let geojson = result_to_geojson(&geo_course, &result)?;
+/// std::fs::write("ride.geojson", geojson)?;
+/// ```
+#[cfg(feature = "serde")]
+pub fn result_to_geojson(
+    course: &GeoCourse,
+    result: &simulation::SimulationResult,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(coordinate_vec) = &course.coordinate_vec else {
+        return Err("the course has no coordinates, e.g. it was loaded from an npz archive".into());
+    };
+    let n_segments = course.road_segment_vec.len();
+    if coordinate_vec.len() != n_segments + 1 {
+        return Err(format!(
+            "expected {} coordinates for {} segments, got {}",
+            n_segments + 1,
+            n_segments,
+            coordinate_vec.len()
+        )
+        .into());
+    }
+    if result.duration_vec.len() != n_segments {
+        return Err(
+            format!("the result has {} segments but the course {}", result.duration_vec.len(), n_segments).into()
+        );
+    }
+    let feature_vec: Vec<serde_json::Value> = (0..n_segments)
+        .map(|i| {
+            let (start_longitude, start_latitude) = coordinate_vec[i];
+            let (end_longitude, end_latitude) = coordinate_vec[i + 1];
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": [[start_longitude, start_latitude], [end_longitude, end_latitude]],
+                },
+                "properties": {
+                    "segment_index": i,
+                    "distance": result.distance_vec[i],
+                    "duration": result.duration_vec[i],
+                    "speed": result.velocity_vec[i],
+                    "power": result.power_vec[i],
+                    "anaerobic_reserve": result.anaerobic_reserve_vec[i],
+                },
+            })
+        })
+        .collect();
+    let feature_collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": feature_vec,
+    });
+    Ok(serde_json::to_string(&feature_collection)?)
+}
+
+/// Formats a simulation as a plain-text pacing sheet, e.g. to tape to the top tube.
+///
+/// Consecutive segments with the same target power, rounded to whole watts, are merged into a single row, ending
+/// early at the segments where checkpoints fall. Each row gives the distance in kilometers and the cumulative time,
+/// rounded to whole seconds, at its end, the target power of the stretch it ends and the anaerobic reserve left in
+/// kilojoules. A checkpoint is listed on the row ending at or just after it, several checkpoints on the same row
+/// being joined with `" / "`. The last row is at the end of the last completed segment, so for a feasible
+/// simulation its time is the total time.
+///
+/// # Arguments
+///
+/// * `result` - The simulation, of which the completed segments are listed.
+/// * `checkpoint_vec` - The landmarks to list, e.g. the feed zones and the foot and top of the climbs. Checkpoints
+///   past the last completed segment are ignored.
+///
+/// # Returns
+///
+/// * `String` - The sheet, a header line then one line per row.
+///
+/// # Example
+///
+/// ```
+/// let checkpoint_vec = vec![Waypoint { distance: 4200.0, name: "Summit".to_string() }];
+/// print!("{}", pacing_sheet(&result, &checkpoint_vec));
+/// ```
+pub fn pacing_sheet(result: &simulation::SimulationResult, checkpoint_vec: &Vec<Waypoint>) -> String {
+    let n_segments = result.completed_segments();
+    let mut label_vec: Vec<Option<String>> = vec![None; n_segments];
+    let mut sorted_checkpoint_vec: Vec<&Waypoint> = checkpoint_vec.iter().collect();
+    sorted_checkpoint_vec.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    for checkpoint in sorted_checkpoint_vec {
+        let segment_index =
+            result.distance_vec[..n_segments].partition_point(|&distance| distance < checkpoint.distance);
+        if segment_index == n_segments {
+            continue;
+        }
+        label_vec[segment_index] = match label_vec[segment_index].take() {
+            Some(label) => Some(label + " / " + &checkpoint.name),
+            None => Some(checkpoint.name.clone()),
+        };
+    }
+
+    let mut sheet = format!("{:>7}  {:>8}  {:>5}  {:>6}  checkpoint\n", "km", "time", "watts", "W' kJ");
+    let mut elapsed_time = 0.0;
+    for (i, label) in label_vec.iter().enumerate() {
+        elapsed_time += result.duration_vec[i];
+        let target_power = result.power_vec[i].round();
+        let is_row_end =
+            i + 1 == n_segments || label.is_some() || result.power_vec[i + 1].round() != target_power;
+        if !is_row_end {
+            continue;
+        }
+        let seconds = elapsed_time.round() as u64;
+        let row = format!(
+            "{:>7.1}  {:>2}:{:02}:{:02}  {:>5}  {:>6.1}  {}",
+            result.distance_vec[i] / METERS_PER_KILOMETER,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            target_power,
+            result.anaerobic_reserve_vec[i] / 1000.0,
+            label.as_deref().unwrap_or(""),
+        );
+        sheet += row.trim_end();
+        sheet += "\n";
+    }
+    sheet
+}
+
+pub fn load_sample_data() -> Result<(Vec<f64>, Vec<f64>), Box<dyn std::error::Error>> {
+    load_npz("resources/murianette.npz", &default_npz_format())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    use ndarray_npy::NpzWriter;
+    use std::path::PathBuf;
+
+    /// Returns a path in the temporary directory, unique to the test process and `name`.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusty_bike_{}_{}.npz", std::process::id(), name))
+    }
+
+    #[test]
+    fn load_npz_reads_custom_names_and_converts_feet() {
+        let path = temp_path("custom_names");
+        let mut npz = NpzWriter::new(File::create(&path).unwrap());
+        npz.add_array("dist_km", &array![0.0, 0.5, 1.25]).unwrap();
+        npz.add_array("alt_ft", &array![100.0, 200.0, 150.0]).unwrap();
+        npz.finish().unwrap();
+        let format = NpzFormat {
+            distance_name: "dist_km",
+            elevation_name: "alt_ft",
+            distance_scale: METERS_PER_KILOMETER,
+            elevation_scale: METERS_PER_FOOT,
+        };
+
+        let (distance_vec, elevation_vec) = load_npz(path.to_str().unwrap(), &format).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(distance_vec, vec![0.0, 500.0, 1250.0]);
+        for (elevation, feet) in elevation_vec.iter().zip([100.0, 200.0, 150.0]) {
+            assert!((elevation - feet * 0.3048).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn preprocess_yields_a_finite_monotone_uniform_course_from_noisy_data() {
+        // A rolling profile every ~7 m with deterministic noise, a backwards point and missing elevations.
+        let mut raw_distance_vec = Vec::new();
+        let mut raw_elevation_vec = Vec::new();
+        for k in 0..1500 {
+            let distance = 7.0 * k as f64 + 2.0 * f64::sin(1.3 * k as f64);
+            let noise = 0.8 * f64::sin(17.0 * k as f64);
+            raw_distance_vec.push(distance);
+            raw_elevation_vec.push(40.0 * f64::sin(distance / 1500.0) + noise);
+        }
+        raw_distance_vec[200] = raw_distance_vec[199] - 3.0;
+        raw_elevation_vec[500] = f64::NAN;
+        raw_elevation_vec[501] = f64::NAN;
+        let options = PreprocessOptions {
+            min_crest_height: f64::INFINITY,
+            ..default_preprocess_options()
+        };
+
+        let road_segment_vec = preprocess(&raw_distance_vec, &raw_elevation_vec, &options).unwrap();
+        assert!(road_segment_vec.len() > 50);
+        for (i, road_segment) in road_segment_vec.iter().enumerate() {
+            assert!(road_segment.slope.is_finite() && road_segment.altitude.is_finite());
+            assert!(road_segment.length >= options.min_segment_length);
+            if i + 1 < road_segment_vec.len() {
+                assert!((road_segment.length - options.sample_spacing).abs() < 1e-9);
+            }
+        }
+        let last_length = road_segment_vec.last().unwrap().length;
+        assert!(last_length < options.sample_spacing + options.min_segment_length);
+        let total_length: f64 = road_segment_vec.iter().map(|road_segment| road_segment.length).sum();
+        assert!((total_length - (raw_distance_vec[1499] - raw_distance_vec[0])).abs() < 1e-6);
+    }
+
+    #[test]
+    fn short_segments_merge_into_their_shorter_neighbour_anywhere() {
+        let mut distance_vec = vec![0.0, 100.0, 105.0, 200.0, 300.0, 302.0, 400.0, 410.0];
+        let mut elevation_vec = vec![0.0, 5.0, 6.0, 3.0, 8.0, 8.5, 2.0, 1.0];
+        merge_short_segments(&mut distance_vec, &mut elevation_vec, 20.0);
+
+        // The 5 m sliver joins the 95 m segment after it, the 2 m one the 98 m one after it and the last 10 m the
+        // segment before it. The remaining points keep their elevations, so the slopes are length-weighted.
+        assert_eq!(distance_vec, vec![0.0, 100.0, 200.0, 300.0, 410.0]);
+        assert_eq!(elevation_vec, vec![0.0, 5.0, 3.0, 8.0, 1.0]);
+
+        let mut short_distance_vec = vec![0.0, 4.0, 9.0];
+        let mut short_elevation_vec = vec![0.0, 1.0, 1.5];
+        merge_short_segments(&mut short_distance_vec, &mut short_elevation_vec, 20.0);
+        assert_eq!(short_distance_vec, vec![0.0, 9.0]);
+        assert_eq!(short_elevation_vec, vec![0.0, 1.5]);
+    }
+
+    #[test]
+    fn load_npz_returns_errors_on_malformed_files() {
+        let load = |name: &str, write: &dyn Fn(&PathBuf)| {
+            let path = temp_path(name);
+            write(&path);
+            let result = load_npz(path.to_str().unwrap(), &default_npz_format());
+            std::fs::remove_file(&path).unwrap();
+            result
+        };
+        let write_npz = |path: &PathBuf, distance: bool, elevation_vec: Vec<f64>| {
+            let mut npz = NpzWriter::new(File::create(path).unwrap());
+            if distance {
+                npz.add_array("distance", &array![0.0, 100.0, 200.0]).unwrap();
+            }
+            npz.add_array("elevation", &ndarray::Array1::from(elevation_vec)).unwrap();
+            npz.finish().unwrap();
+        };
+
+        let not_an_archive = load("not_an_archive", &|path| std::fs::write(path, b"not a zip archive").unwrap());
+        assert!(not_an_archive.is_err());
+        let missing_distance = load("missing_distance", &|path| write_npz(path, false, vec![0.0, 1.0, 2.0]));
+        assert!(missing_distance.unwrap_err().to_string().contains("distance"));
+        let length_mismatch = load("length_mismatch", &|path| write_npz(path, true, vec![0.0, 1.0]));
+        assert!(length_mismatch.unwrap_err().to_string().contains("different lengths"));
+        let wrong_dtype = load("wrong_dtype", &|path| {
+            let mut npz = NpzWriter::new(File::create(path).unwrap());
+            npz.add_array("distance", &array![0.0, 100.0]).unwrap();
+            npz.add_array("elevation", &array![1_i32, 2]).unwrap();
+            npz.finish().unwrap();
+        });
+        assert!(wrong_dtype.unwrap_err().to_string().contains("elevation"));
+    }
+
+    #[test]
+    fn high_base_altitude_reduces_drag_across_the_course() {
+        let raw_distance_vec: Vec<f64> = (0..=50).map(|k| 100.0 * k as f64).collect();
+        let raw_elevation_vec: Vec<f64> =
+            raw_distance_vec.iter().map(|distance| 30.0 * f64::sin(distance / 800.0)).collect();
+        let course = |base_altitude: f64| {
+            let options = PreprocessOptions {
+                base_altitude,
+                ..default_preprocess_options()
+            };
+            preprocess(&raw_distance_vec, &raw_elevation_vec, &options).unwrap()
+        };
+        let sea_level_segment_vec = course(0.0);
+        let mountain_segment_vec = course(2000.0);
+        let rider_model = crate::sim::morton::default_rider_model();
+        let ride = |road_segment_vec: &Vec<simulation::RoadSegment>| {
+            simulation::simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![250.0; road_segment_vec.len()],
+                road_segment_vec,
+                &simulation::default_resistance_model(),
+                &rider_model,
+            )
+        };
+
+        let sea_level_result = ride(&sea_level_segment_vec);
+        let mountain_result = ride(&mountain_segment_vec);
+        for (sea_level_segment, mountain_segment) in sea_level_segment_vec.iter().zip(mountain_segment_vec.iter()) {
+            assert_eq!(mountain_segment.slope, sea_level_segment.slope);
+            assert!((mountain_segment.altitude - sea_level_segment.altitude - 2000.0).abs() < 1e-9);
+        }
+        let sea_level_aero_energy: f64 = sea_level_result.aero_energy_vec.iter().sum();
+        let mountain_aero_energy: f64 = mountain_result.aero_energy_vec.iter().sum();
+        assert!(mountain_aero_energy < 0.95 * sea_level_aero_energy);
+        assert!(mountain_result.total_time < sea_level_result.total_time);
+    }
+
+    #[test]
+    fn validate_profile_flags_seeded_problems() {
+        let distance_vec = vec![0.0, 100.0, 200.0, 200.0, 300.0, 250.0, 400.0, 500.0, f64::NAN, 700.0];
+        let elevation_vec = vec![0.0, 1.0, 26.0, 26.0, 27.0, 27.0, 30.0, 80.0, 80.0, 80.0];
+        let report = validate_profile(&distance_vec, &elevation_vec);
+        let summary: Vec<(usize, Severity)> =
+            report.issue_vec.iter().map(|issue| (issue.index, issue.severity)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, Severity::Warning),
+                (2, Severity::Error),
+                (4, Severity::Error),
+                (6, Severity::Error),
+                (8, Severity::Error),
+            ]
+        );
+        assert!(matches!(report.issue_vec[0].kind, IssueKind::SteepGrade { grade } if (grade - 0.25).abs() < 1e-12));
+        assert_eq!(report.issue_vec[1].kind, IssueKind::ZeroLength);
+        assert_eq!(report.issue_vec[2].kind, IssueKind::NonMonotonicDistance);
+        assert!(matches!(report.issue_vec[3].kind, IssueKind::SteepGrade { grade } if grade > STEEP_GRADE_ERROR));
+        assert_eq!(report.issue_vec[4].kind, IssueKind::NonFinite);
+        assert!(report.has_errors());
+
+        let clean = validate_profile(&vec![0.0, 100.0, 200.0], &vec![0.0, 5.0, 3.0]);
+        assert!(clean.issue_vec.is_empty());
+        assert!(!clean.has_errors());
+    }
+
+    #[test]
+    fn crest_spanning_segment_is_split_at_the_top() {
+        // A climb at 10% to a crest at 430 m, then a descent at 10%, recorded every 10 m.
+        let distance_vec: Vec<f64> = (0..=100).map(|k| 10.0 * k as f64).collect();
+        let elevation_vec: Vec<f64> = distance_vec.iter().map(|&d| 43.0 - 0.1 * f64::abs(d - 430.0)).collect();
+        let (resampled_distance_vec, resampled_elevation_vec) = resample(&distance_vec, &elevation_vec, 500.0);
+        assert_eq!(resampled_distance_vec, vec![0.0, 500.0, 1000.0]);
+
+        let (split_distance_vec, split_elevation_vec) =
+            split_at_extrema(&distance_vec, &elevation_vec, &resampled_distance_vec, &resampled_elevation_vec, 1.0);
+        assert_eq!(split_distance_vec, vec![0.0, 430.0, 500.0, 1000.0]);
+        assert_eq!(split_elevation_vec[1], 43.0);
+        assert!(split_elevation_vec[1] > split_elevation_vec[0]);
+        assert!(split_elevation_vec.windows(2).skip(1).all(|pair| pair[1] < pair[0]));
+
+        // Small oscillations of a flat road are not split.
+        let wavy_elevation_vec: Vec<f64> = (0..=100).map(|k| 0.4 * f64::sin(k as f64)).collect();
+        let (resampled_distance_vec, resampled_elevation_vec) = resample(&distance_vec, &wavy_elevation_vec, 500.0);
+        let (split_distance_vec, _) = split_at_extrema(
+            &distance_vec,
+            &wavy_elevation_vec,
+            &resampled_distance_vec,
+            &resampled_elevation_vec,
+            1.0,
+        );
+        assert_eq!(split_distance_vec, resampled_distance_vec);
+    }
+
+    #[test]
+    fn climb_with_a_false_flat_is_detected_as_a_single_span() {
+        // 1 km of flat, 800 m at 7%, a 200 m false flat at 1%, 1200 m at 7%, 1 km of flat and a 200 m bump.
+        let slope_vec: Vec<f64> = [(10, 0.0), (8, 0.07), (2, 0.01), (12, 0.07), (10, 0.0), (2, 0.08)]
+            .iter()
+            .flat_map(|&(n_segments, slope)| std::iter::repeat_n(slope, n_segments))
+            .collect();
+        let road_segment_vec: Vec<simulation::RoadSegment> = slope_vec
+            .iter()
+            .map(|&slope| simulation::RoadSegment {
+                length: 100.0,
+                altitude: 0.0,
+                slope,
+                temperature: 20.0,
+                relative_wind_speed: 0.0,
+                roughness: 1.0,
+                rolling_resistance: None,
+                drivetrain_efficiency: None,
+            })
+            .collect();
+
+        let climb_vec = detect_climbs(&road_segment_vec, 0.03, 1000.0);
+        assert_eq!(climb_vec.len(), 1);
+        let climb = climb_vec[0];
+        assert_eq!((climb.start_index, climb.end_index), (10, 32));
+        assert!((climb.start_distance - 1000.0).abs() < 1e-9);
+        assert!((climb.length - 2200.0).abs() < 1e-9);
+        assert!((climb.elevation_gain - 142.0).abs() < 1e-9);
+        assert!((climb.average_gradient - 142.0 / 2200.0).abs() < 1e-12);
+
+        // With a lower minimum length, the bump counts too.
+        assert_eq!(detect_climbs(&road_segment_vec, 0.03, 100.0).len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn geojson_has_one_populated_feature_per_segment() {
+        let road_segment_vec = crate::testutil::make_course(crate::testutil::CourseProfile::RollingSine {
+            n_segments: 6,
+            segment_length: 200.0,
+            max_slope: 0.04,
+            period: 6,
+        });
+        let rider_model = crate::sim::morton::default_rider_model();
+        let result = simulation::simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![300.0; 6],
+            &road_segment_vec,
+            &simulation::default_resistance_model(),
+            &rider_model,
+        );
+        let coordinate_vec: Vec<(f64, f64)> = (0..7).map(|k| (6.0 + 0.002 * k as f64, 45.0)).collect();
+        let geo_course = GeoCourse {
+            road_segment_vec,
+            coordinate_vec: Some(coordinate_vec),
+        };
+
+        let geojson = result_to_geojson(&geo_course, &result).unwrap();
+        let geojson: serde_json::Value = serde_json::from_str(&geojson).unwrap();
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let feature_vec = geojson["features"].as_array().unwrap();
+        assert_eq!(feature_vec.len(), 6);
+        for (i, feature) in feature_vec.iter().enumerate() {
+            assert_eq!(feature["geometry"]["coordinates"].as_array().unwrap().len(), 2);
+            let properties = &feature["properties"];
+            assert_eq!(properties["segment_index"], i);
+            assert_eq!(properties["speed"].as_f64().unwrap(), result.velocity_vec[i]);
+            assert_eq!(properties["power"].as_f64().unwrap(), result.power_vec[i]);
+            assert_eq!(properties["anaerobic_reserve"].as_f64().unwrap(), result.anaerobic_reserve_vec[i]);
+        }
+
+        // A course without coordinates, e.g. from an npz archive, is rejected.
+        let npz_course = GeoCourse {
+            coordinate_vec: None,
+            ..geo_course
+        };
+        assert!(result_to_geojson(&npz_course, &result).is_err());
+    }
+
+    #[test]
+    fn elevation_round_trips_through_slopes_up_to_the_start_altitude() {
+        let distance_vec: Vec<f64> = (0..50).map(|k| 37.0 * k as f64 + 5.0 * f64::sin(k as f64)).collect();
+        let elevation_vec: Vec<f64> = distance_vec.iter().map(|&d| 800.0 + 30.0 * f64::sin(d / 400.0)).collect();
+        let slope_vec = elevation_to_slopes(&distance_vec, &elevation_vec);
+        assert_eq!(slope_vec.len(), distance_vec.len() - 1);
+
+        let round_trip_vec = slopes_to_elevation(&distance_vec, &slope_vec, elevation_vec[0]);
+        assert_eq!(round_trip_vec.len(), elevation_vec.len());
+        for (round_trip, elevation) in round_trip_vec.iter().zip(elevation_vec.iter()) {
+            assert!((round_trip - elevation).abs() < 1e-9);
+        }
+        // Another start altitude shifts the whole profile.
+        let shifted_vec = slopes_to_elevation(&distance_vec, &slope_vec, 0.0);
+        for (shifted, elevation) in shifted_vec.iter().zip(elevation_vec.iter()) {
+            assert!((shifted - (elevation - elevation_vec[0])).abs() < 1e-9);
+        }
+
+        assert_eq!(grade_percent_to_slope(8.0), 0.08);
+        assert_eq!(grade_percent_to_slope(-5.0), -0.05);
+        assert!(elevation_to_slopes(&vec![0.0, 100.0], &vec![10.0, 2.0])[0] < 0.0);
+    }
+
+    #[test]
+    fn imperial_and_metric_files_of_the_same_route_give_the_same_segments() {
+        let miles = [0.0, 0.5, 1.25, 2.0];
+        let feet = [300.0, 520.0, 410.0, 700.0];
+        let write_npz = |name: &str, distance_vec: Vec<f64>, elevation_vec: Vec<f64>| {
+            let path = temp_path(name);
+            let mut npz = NpzWriter::new(File::create(&path).unwrap());
+            npz.add_array("distance", &Array1::from(distance_vec)).unwrap();
+            npz.add_array("elevation", &Array1::from(elevation_vec)).unwrap();
+            npz.finish().unwrap();
+            path
+        };
+        let load_segments = |path: &PathBuf, units: Units| {
+            let (distance_vec, elevation_vec) = load_npz(path.to_str().unwrap(), &npz_format_in(units)).unwrap();
+            std::fs::remove_file(path).unwrap();
+            build_segment_vecs(&distance_vec, &elevation_vec).unwrap()
+        };
+
+        let imperial_path = write_npz("imperial", miles.to_vec(), feet.to_vec());
+        let metric_path = write_npz(
+            "metric",
+            miles.iter().map(|mile| mile * METERS_PER_MILE).collect(),
+            feet.iter().map(|foot| foot * METERS_PER_FOOT).collect(),
+        );
+        let mixed_path = write_npz("mixed", miles.to_vec(), feet.iter().map(|foot| foot * METERS_PER_FOOT).collect());
+        let imperial = load_segments(&imperial_path, Units::Imperial);
+        let metric = load_segments(&metric_path, Units::Metric);
+        let mixed = load_segments(
+            &mixed_path,
+            Units::Mixed {
+                distance_unit: LengthUnit::Mile,
+                elevation_unit: LengthUnit::Meter,
+            },
+        );
+
+        assert_eq!(metric.len(), 3);
+        for other in [&imperial, &mixed] {
+            assert_eq!(other.len(), metric.len());
+            for (segment, metric_segment) in other.iter().zip(metric.iter()) {
+                assert!((segment.length - metric_segment.length).abs() < 1e-9);
+                assert!((segment.altitude - metric_segment.altitude).abs() < 1e-9);
+                assert!((segment.slope - metric_segment.slope).abs() < 1e-12);
+            }
+        }
+        assert!((metric[0].length - 0.5 * METERS_PER_MILE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pacing_sheet_ends_at_the_total_time_with_rounded_targets() {
+        let road_segment_vec = crate::testutil::make_course(crate::testutil::CourseProfile::RollingSine {
+            n_segments: 6,
+            segment_length: 400.0,
+            max_slope: 0.04,
+            period: 6,
+        });
+        let rider_model = crate::sim::morton::default_rider_model();
+        let input_power_vec = vec![250.4, 249.6, 250.0, 300.2, 299.7, 300.0];
+        let result = simulation::simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &input_power_vec,
+            &road_segment_vec,
+            &simulation::default_resistance_model(),
+            &rider_model,
+        );
+        assert!(result.is_feasible());
+        let checkpoint_vec = vec![
+            Waypoint { distance: 500.0, name: "Bridge".to_string() },
+            Waypoint { distance: 50000.0, name: "Beyond the finish".to_string() },
+        ];
+        let sheet = pacing_sheet(&result, &checkpoint_vec);
+        let row_vec: Vec<Vec<&str>> = sheet.lines().skip(1).map(|line| line.split_whitespace().collect()).collect();
+
+        // The 250 W stretch ends early at the checkpoint, then the 300 W stretch is merged into the last row.
+        assert_eq!(row_vec.len(), 3);
+        assert_eq!(row_vec[0][4..], ["Bridge"]);
+        assert_eq!(row_vec.iter().map(|row| row[2]).collect::<Vec<_>>(), ["250", "250", "300"]);
+        assert!(!sheet.contains("Beyond the finish"));
+
+        let seconds = result.total_time.round() as u64;
+        let total_time = format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+        assert_eq!(row_vec[2][..2], ["2.4", total_time.as_str()]);
+    }
+
+    #[test]
+    fn segment_labels_skip_waypoints_that_cannot_be_placed() {
+        let distance_vec = vec![0.0, 100.0, 200.0, 300.0];
+        let waypoint_vec = vec![
+            Waypoint { distance: f64::NAN, name: "Nowhere".to_string() },
+            Waypoint { distance: 150.0, name: "Church".to_string() },
+            Waypoint { distance: 300.0, name: "Finish".to_string() },
+            Waypoint { distance: 350.0, name: "Car park".to_string() },
+        ];
+        let label_vec = segment_labels(&distance_vec, &waypoint_vec);
+        assert_eq!(label_vec, [None, Some("Church".to_string()), Some("Finish".to_string())]);
+        assert!(segment_labels(&vec![0.0], &waypoint_vec).is_empty());
+    }
+
+    #[test]
+    fn duplicate_points_are_merged_without_nan_slopes() {
+        // Duplicates at the start, in the middle (three times the same point) and at the end.
+        let distance_vec = vec![0.0, 0.0, 100.0, 250.0, 250.0, 250.0, 400.0, 500.0, 500.0];
+        let elevation_vec = vec![10.0, 11.0, 15.0, 20.0, 21.0, 22.0, 25.0, 24.0, 23.0];
+        let kept_index_vec = merge_duplicate_points(&distance_vec).unwrap();
+        assert_eq!(kept_index_vec, [1, 2, 5, 6, 8]);
+
+        let road_segment_vec = build_segment_vecs(&distance_vec, &elevation_vec).unwrap();
+        assert_eq!(road_segment_vec.len(), 4);
+        assert!(road_segment_vec.iter().all(|road_segment| road_segment.slope.is_finite()));
+        let length_vec: Vec<f64> = road_segment_vec.iter().map(|road_segment| road_segment.length).collect();
+        assert_eq!(length_vec, [100.0, 150.0, 150.0, 100.0]);
+        // Each segment starts from the last of the duplicates.
+        assert_eq!(road_segment_vec[1].altitude, 15.0);
+        assert_eq!(road_segment_vec[2].altitude, 22.0);
+        assert_eq!(road_segment_vec[3].slope, (23.0 - 25.0) / 100.0);
+
+        // The labels and coordinates picked with the kept indices stay paired with the segments.
+        let kept_distance_vec: Vec<f64> = kept_index_vec.iter().map(|&i| distance_vec[i]).collect();
+        let waypoint_vec = vec![Waypoint { distance: 260.0, name: "Bridge".to_string() }];
+        let label_vec = segment_labels(&kept_distance_vec, &waypoint_vec);
+        assert_eq!(label_vec.len(), road_segment_vec.len());
+        assert_eq!(label_vec[2].as_deref(), Some("Bridge"));
+        let coordinate_vec: Vec<(f64, f64)> = (0..distance_vec.len()).map(|k| (6.0 + k as f64, 45.0)).collect();
+        let geo_course = build_geo_course(&distance_vec, &elevation_vec, Some(&coordinate_vec)).unwrap();
+        let kept_coordinate_vec = geo_course.coordinate_vec.unwrap();
+        assert_eq!(kept_coordinate_vec.len(), geo_course.road_segment_vec.len() + 1);
+        assert_eq!(kept_coordinate_vec[2], coordinate_vec[5]);
+        assert!(build_geo_course(&distance_vec, &elevation_vec, Some(&coordinate_vec[1..].to_vec())).is_err());
+
+        // A distance going backwards is an error rather than a negative length.
+        assert!(merge_duplicate_points(&vec![0.0, 100.0, 90.0, 200.0]).is_err());
+        assert!(build_segment_vecs(&vec![0.0, 100.0, 90.0, 200.0], &vec![0.0; 4]).is_err());
+        assert!(build_segment_vecs(&vec![], &vec![]).unwrap().is_empty());
+        assert!(build_segment_vecs(&vec![5.0, 5.0], &vec![1.0, 2.0]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn streaming_a_large_course_gives_the_segments_of_the_in_memory_loader() {
+        let n_points = 5000;
+        let mut distance_vec: Vec<f64> = (0..n_points).map(|k| 10.0 * k as f64 + 3.0 * f64::sin(k as f64)).collect();
+        let elevation_vec: Vec<f64> = (0..n_points).map(|k| 500.0 + 40.0 * f64::sin(k as f64 / 150.0)).collect();
+        // Single duplicates, runs of three and a run longer than the small windows, each with its own elevation.
+        for i in (5..n_points).step_by(97) {
+            distance_vec[i] = distance_vec[i - 1];
+        }
+        for i in (300..n_points).step_by(413) {
+            distance_vec[i] = distance_vec[i - 2];
+            distance_vec[i - 1] = distance_vec[i - 2];
+        }
+        for i in 2001..2025 {
+            distance_vec[i] = distance_vec[2000];
+        }
+        let path = temp_path("stream");
+        let mut npz = NpzWriter::new(File::create(&path).unwrap());
+        npz.add_array("distance", &Array1::from(distance_vec)).unwrap();
+        npz.add_array("elevation", &Array1::from(elevation_vec)).unwrap();
+        // A course with whole-meter distances, stored as integers, with a duplicate point every 50.
+        let integer_distance_vec: Vec<i64> = (0..n_points as i64).map(|k| 10 * (k - k / 50)).collect();
+        npz.add_array("integer_distance", &Array1::from(integer_distance_vec)).unwrap();
+        npz.finish().unwrap();
+
+        let path = path.to_str().unwrap();
+        let key = |road_segment: &simulation::RoadSegment| {
+            (road_segment.length, road_segment.slope, road_segment.altitude)
+        };
+        let integer_format = NpzFormat { distance_name: "integer_distance", ..default_npz_format() };
+        for format in [default_npz_format(), integer_format] {
+            let (distance_vec, elevation_vec) = load_npz(path, &format).unwrap();
+            let road_segment_vec = build_segment_vecs(&distance_vec, &elevation_vec).unwrap();
+            let expected_vec: Vec<_> = road_segment_vec.iter().map(key).collect();
+            assert!(expected_vec.len() < n_points - 1);
+            for chunk_points in [1, 2, 7, 64, 4096, 2 * n_points] {
+                let mut streamed_vec = Vec::new();
+                let n_segments = stream_npz_segments(path, &format, chunk_points, |road_segment_vec| {
+                    streamed_vec.extend(road_segment_vec.iter().map(key));
+                })
+                .unwrap();
+                assert_eq!(n_segments, expected_vec.len());
+                assert_eq!(streamed_vec, expected_vec);
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
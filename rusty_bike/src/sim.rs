@@ -1,3 +1,18 @@
+pub mod altitude;
+pub mod field_test;
+pub mod gearing;
+pub mod heart_rate;
 pub mod kinematics;
+pub mod monte_carlo;
 pub mod morton;
-pub mod simulation;
\ No newline at end of file
+pub mod omni_domain;
+pub mod pacing;
+pub mod power_duration;
+pub mod replay;
+pub mod root_finding;
+pub mod simulation;
+pub mod team;
+pub mod thermal;
+pub mod two_compartment;
+pub mod weather;
+pub mod workout;
\ No newline at end of file
@@ -0,0 +1,129 @@
+// Altitude
+// Loss of aerobic power in hypoxia and its recovery with acclimatization over the days of a stage race
+
+/// The number of meters in a kilometer, the unit of the altitude fits.
+const METERS_PER_KILOMETER: f64 = 1000.0;
+
+/// The acclimatization of a rider to altitude.
+///
+/// Above sea level, the rider delivers only a fraction of their power, see `power_factor`. On arrival (day 0)
+/// the loss is the one of an unacclimatized rider, and it relaxes exponentially towards the one of a fully
+/// acclimatized rider over the following days.
+///
+/// # Fields
+///
+/// * `day` - The number of days the rider has spent at altitude, 0 on arrival.
+/// * `time_constant` - The number of days over which the gap to full acclimatization shrinks by a factor e.
+#[derive(Clone, Copy, Debug)]
+pub struct AltitudeAcclimatization {
+    pub day: usize,
+    pub time_constant: f64,
+}
+
+/// Returns the acclimatization of a rider arriving at altitude, with a time constant of 7 days, so that most of
+/// the acclimatization happens over the three weeks of a grand tour.
+pub const fn default_altitude_acclimatization() -> AltitudeAcclimatization {
+    AltitudeAcclimatization {
+        day: 0,
+        time_constant: 7.0,
+    }
+}
+
+/// Returns the fraction of their sea level power an unacclimatized rider delivers at the given altitude, from
+/// the fit of Bassett et al. (1999). Below sea level it is 1.
+///
+/// # Arguments
+///
+/// * `altitude` - The altitude in meters.
+///
+/// # Example
+///
+/// ```
+/// let factor = unacclimatized_power_factor(2000.0);
+/// println!("Power at 2000m: {:.1}%", 100.0 * factor);
+/// ```
+pub fn unacclimatized_power_factor(altitude: f64) -> f64 {
+    let x = f64::max(0.0, altitude / METERS_PER_KILOMETER);
+    (0.178 * x * x * x - 1.43 * x * x - 4.07 * x + 100.0) / 100.0
+}
+
+/// Returns the fraction of their sea level power a fully acclimatized rider delivers at the given altitude, from
+/// the fit of Bassett et al. (1999). Below sea level it is 1.
+///
+/// # Arguments
+///
+/// * `altitude` - The altitude in meters.
+pub fn acclimatized_power_factor(altitude: f64) -> f64 {
+    let x = f64::max(0.0, altitude / METERS_PER_KILOMETER);
+    (99.921 - 1.8991 * x - 1.1219 * x * x) / 99.921
+}
+
+impl AltitudeAcclimatization {
+    /// Returns the fraction of their sea level power the rider delivers at the given altitude in meters on
+    /// `day`, between the unacclimatized and the acclimatized factors.
+    pub fn power_factor(&self, altitude: f64) -> f64 {
+        let acclimatized = acclimatized_power_factor(altitude);
+        let unacclimatized = unacclimatized_power_factor(altitude);
+        acclimatized + (unacclimatized - acclimatized) * f64::exp(-(self.day as f64) / self.time_constant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+    use crate::sim::simulation::{self, RoadSegment, SimulationOptions};
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn high_climb_is_faster_once_acclimatized() {
+        let road_segment_vec: Vec<RoadSegment> = make_course(CourseProfile::ConstantClimb {
+            n_segments: 20,
+            segment_length: 500.0,
+            slope: 0.07,
+        })
+        .iter()
+        .map(|road_segment| RoadSegment {
+            altitude: road_segment.altitude + 2000.0,
+            ..*road_segment
+        })
+        .collect();
+        let rider_model = morton::default_rider_model();
+        let ride = |day: usize| {
+            let options = SimulationOptions {
+                altitude_acclimatization: Some(AltitudeAcclimatization {
+                    day,
+                    ..default_altitude_acclimatization()
+                }),
+                ..simulation::default_simulation_options()
+            };
+            simulation::simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![300.0; 20],
+                &road_segment_vec,
+                &simulation::default_resistance_model(),
+                &rider_model,
+                &options,
+            )
+            .total_time
+        };
+
+        let day_0 = ride(0);
+        let day_7 = ride(7);
+        let day_21 = ride(21);
+        assert!(day_7 < day_0);
+        assert!(day_21 < day_7);
+
+        // On arrival the full derate applies, and after many days only the acclimatized one remains.
+        let arrival = default_altitude_acclimatization();
+        let settled = AltitudeAcclimatization {
+            day: 1000,
+            ..arrival
+        };
+        assert_eq!(arrival.power_factor(2500.0), unacclimatized_power_factor(2500.0));
+        assert!((settled.power_factor(2500.0) - acclimatized_power_factor(2500.0)).abs() < 1e-12);
+        assert!(acclimatized_power_factor(2500.0) > unacclimatized_power_factor(2500.0));
+        assert_eq!(arrival.power_factor(0.0), 1.0);
+    }
+}
@@ -0,0 +1,160 @@
+// Field test estimation
+// Fits the CdA and the rolling resistance coefficient to runs on a flat road
+
+use crate::sim::kinematics;
+use crate::sim::simulation;
+
+/// Condition number above which the CdA and rolling resistance are considered poorly separable.
+const MAX_FIT_CONDITION_NUMBER: f64 = 100.0;
+
+/// A measurement on a flat road without wind, either at steady power or during a coast-down.
+///
+/// # Fields
+///
+/// * `velocity` - The velocity in meters per second.
+/// * `power` - The input power in watts, zero during a coast-down.
+/// * `acceleration` - The acceleration in meters per second squared, zero at steady speed and negative during
+///   a coast-down.
+pub struct FieldTestRun {
+    pub velocity: f64,
+    pub power: f64,
+    pub acceleration: f64,
+}
+
+/// The CdA and rolling resistance fitted to field test runs.
+///
+/// # Fields
+///
+/// * `cda_surface` - The coefficient of drag area (CdA) in square meters.
+/// * `rolling_resistance` - The coefficient of rolling resistance.
+/// * `condition_number` - The condition number of the least squares problem, 1 when the runs are at very
+///   different speeds and growing without bound as their speeds get closer.
+pub struct AeroFit {
+    pub cda_surface: f64,
+    pub rolling_resistance: f64,
+    pub condition_number: f64,
+}
+
+impl AeroFit {
+    /// Returns whether the runs spread over enough speeds for the CdA and rolling resistance to be told apart.
+    /// When they do not, only their combined resistance at the tested speed is reliable.
+    pub fn is_well_conditioned(&self) -> bool {
+        self.condition_number <= MAX_FIT_CONDITION_NUMBER
+    }
+}
+
+/// Fits both the CdA and the rolling resistance coefficient to runs on a flat road by least squares.
+///
+/// On every run the force at the wheel, `wheel_power(power) / velocity - total_mass * acceleration`,
+/// balances the air resistance `0.5 * air_density * cda * velocity^2` and the rolling resistance
+/// `crr * total_mass * g`. The air resistance is quadratic in the velocity while the rolling resistance is
+/// constant, so they separate when the runs are at different speeds.
+///
+/// # Arguments
+///
+/// * `run_vec` - The runs, at least two at different speeds.
+/// * `resistance_model` - The resistance model, of which the total mass and drivetrain losses are used.
+/// * `air_density` - The air density during the test in kg/m^3, see `kinematics::air_density`.
+///
+/// # Returns
+///
+/// * `Option<AeroFit>` - The fitted values, or `None` if all the runs are at the same speed.
+///
+/// # Example
+///
+/// ```
+/// let fit = fit_aero_and_crr(&run_vec, &default_resistance_model(), air_density(0.0, 20.0)).unwrap();
+/// if !fit.is_well_conditioned() {
+///     println!("Warning: runs too close in speed to separate CdA and Crr");
+/// }
+/// ```
+pub fn fit_aero_and_crr(
+    run_vec: &Vec<FieldTestRun>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    air_density: f64,
+) -> Option<AeroFit> {
+    let total_mass = resistance_model.total_mass();
+    let rolling_force_per_crr = kinematics::get_rolling_resistance_force(1.0, total_mass);
+
+    // Normal equations of `force = cda * aero_term + crr * rolling_force_per_crr`.
+    let mut aero_aero = 0.0;
+    let mut aero_rolling = 0.0;
+    let mut rolling_rolling = 0.0;
+    let mut aero_force = 0.0;
+    let mut rolling_force = 0.0;
+    for run in run_vec.iter() {
+        let aero_term = 0.5 * air_density * run.velocity * run.velocity;
+        let force =
+            simulation::wheel_power(run.power, resistance_model) / run.velocity - total_mass * run.acceleration;
+        aero_aero += aero_term * aero_term;
+        aero_rolling += aero_term * rolling_force_per_crr;
+        rolling_rolling += rolling_force_per_crr * rolling_force_per_crr;
+        aero_force += aero_term * force;
+        rolling_force += rolling_force_per_crr * force;
+    }
+    let determinant = aero_aero * rolling_rolling - aero_rolling * aero_rolling;
+    if determinant <= 1e-12 * aero_aero * rolling_rolling {
+        return None;
+    }
+    // Cosine between the aero and rolling columns, which sets the condition number of the normalized problem.
+    let cosine = aero_rolling / f64::sqrt(aero_aero * rolling_rolling);
+
+    let fit = AeroFit {
+        cda_surface: (rolling_rolling * aero_force - aero_rolling * rolling_force) / determinant,
+        rolling_resistance: (aero_aero * rolling_force - aero_rolling * aero_force) / determinant,
+        condition_number: (1.0 + cosine) / (1.0 - cosine),
+    };
+    Some(fit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::root_finding;
+
+    /// Runs at the given velocities on a flat road for a bicycle of the given CdA and rolling resistance: a
+    /// steady power run and a coast-down at each velocity.
+    fn synthetic_runs(
+        velocity_vec: &[f64],
+        cda_surface: f64,
+        rolling_resistance: f64,
+        resistance_model: &simulation::BicycleResistanceModel,
+        air_density: f64,
+    ) -> Vec<FieldTestRun> {
+        let total_mass = resistance_model.total_mass();
+        let mut run_vec = Vec::new();
+        for &velocity in velocity_vec {
+            let force = 0.5 * air_density * cda_surface * velocity * velocity
+                + kinematics::get_rolling_resistance_force(rolling_resistance, total_mass);
+            let solution = root_finding::bisect(
+                |power| simulation::wheel_power(power, resistance_model) - force * velocity,
+                0.0,
+                2000.0,
+                &root_finding::default_tolerance(),
+            );
+            assert!(solution.converged);
+            run_vec.push(FieldTestRun { velocity, power: solution.value, acceleration: 0.0 });
+            run_vec.push(FieldTestRun { velocity, power: 0.0, acceleration: -force / total_mass });
+        }
+        run_vec
+    }
+
+    #[test]
+    fn fit_recovers_cda_and_rolling_resistance_from_runs_at_several_speeds() {
+        let resistance_model = simulation::default_resistance_model();
+        let air_density = kinematics::air_density(0.0, 20.0);
+        let run_vec = synthetic_runs(&[6.0, 9.0, 12.0, 15.0], 0.28, 0.005, &resistance_model, air_density);
+
+        let fit = fit_aero_and_crr(&run_vec, &resistance_model, air_density).unwrap();
+        assert!((fit.cda_surface - 0.28).abs() < 1e-4, "CdA {}", fit.cda_surface);
+        assert!((fit.rolling_resistance - 0.005).abs() < 1e-5, "Crr {}", fit.rolling_resistance);
+        assert!(fit.is_well_conditioned());
+
+        // Runs at nearly the same speed cannot tell the two apart.
+        let close_run_vec = synthetic_runs(&[10.0, 10.05], 0.28, 0.005, &resistance_model, air_density);
+        let close_fit = fit_aero_and_crr(&close_run_vec, &resistance_model, air_density).unwrap();
+        assert!(!close_fit.is_well_conditioned());
+        let same_speed_run_vec = synthetic_runs(&[10.0], 0.28, 0.005, &resistance_model, air_density);
+        assert!(fit_aero_and_crr(&same_speed_run_vec, &resistance_model, air_density).is_none());
+    }
+}
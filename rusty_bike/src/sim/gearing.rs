@@ -0,0 +1,223 @@
+// Gearing
+// Recommends the gear keeping the cadence in the rider's efficient band
+
+use crate::sim::simulation;
+
+/// The gears of a bicycle.
+///
+/// # Fields
+///
+/// * `chainring_teeth_vec` - The number of teeth of each chainring.
+/// * `cog_teeth_vec` - The number of teeth of each cog of the cassette.
+/// * `wheel_circumference` - The circumference of the rear wheel in meters, e.g. 2.1 for a 700x25c tyre.
+pub struct Gearing {
+    pub chainring_teeth_vec: Vec<u32>,
+    pub cog_teeth_vec: Vec<u32>,
+    pub wheel_circumference: f64,
+}
+
+impl Gearing {
+    /// Returns the cadence in revolutions per minute at `velocity` meters per second in the given gear.
+    pub fn cadence(&self, velocity: f64, chainring_teeth: u32, cog_teeth: u32) -> f64 {
+        let development = self.wheel_circumference * chainring_teeth as f64 / cog_teeth as f64;
+        60.0 * velocity / development
+    }
+
+    /// Returns the velocity in meters per second at `max_cadence` revolutions per minute in the highest gear,
+    /// above which the rider spins out and pedaling no longer makes them faster.
+    pub fn spin_out_velocity(&self, max_cadence: f64) -> f64 {
+        let max_chainring_teeth = self.chainring_teeth_vec.iter().copied().max().unwrap_or(0);
+        let min_cog_teeth = self.cog_teeth_vec.iter().copied().min().unwrap_or(1);
+        let development = self.wheel_circumference * max_chainring_teeth as f64 / min_cog_teeth as f64;
+        max_cadence * development / 60.0
+    }
+}
+
+/// The range of cadences the rider pedals efficiently at.
+///
+/// # Fields
+///
+/// * `min_cadence` - The lowest cadence in revolutions per minute.
+/// * `max_cadence` - The highest cadence in revolutions per minute.
+pub struct CadenceBand {
+    pub min_cadence: f64,
+    pub max_cadence: f64,
+}
+
+/// How the cadence of a recommended gear relates to the cadence band.
+///
+/// * `InBand` - The cadence is within the band.
+/// * `Grinding` - Even the lowest gear gives a cadence below the band, e.g. on a very steep climb.
+/// * `SpinningOut` - Even the highest gear gives a cadence above the band, e.g. on a fast descent.
+/// * `BetweenGears` - The band falls between two consecutive gears, and the closest one is recommended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CadenceFit {
+    InBand,
+    Grinding,
+    SpinningOut,
+    BetweenGears,
+}
+
+/// The gear recommended on a segment.
+///
+/// # Fields
+///
+/// * `chainring_teeth` - The number of teeth of the chainring.
+/// * `cog_teeth` - The number of teeth of the cog.
+/// * `cadence` - The cadence in revolutions per minute at the mean speed of the segment.
+/// * `fit` - Whether the cadence is within the band.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GearRecommendation {
+    pub chainring_teeth: u32,
+    pub cog_teeth: u32,
+    pub cadence: f64,
+    pub fit: CadenceFit,
+}
+
+/// Recommends a gear on every segment ridden in a simulation, keeping the cadence at the mean speed of the
+/// segment within the cadence band.
+///
+/// Among the gears within the band, the one closest to the middle of the band is recommended. When no gear fits,
+/// the gear giving the cadence closest to the band is recommended and flagged, see `CadenceFit`.
+///
+/// # Arguments
+///
+/// * `result` - The simulation, of which the completed segments are used.
+/// * `gearing` - The gears of the bicycle, with at least one chainring and one cog.
+/// * `cadence_band` - The efficient cadence band of the rider.
+///
+/// # Returns
+///
+/// * `Vec<GearRecommendation>` - The recommended gear on each completed segment.
+///
+/// # Example
+///
+/// ```
+/// let gearing = Gearing {
+///     chainring_teeth_vec: vec![50, 34],
+///     cog_teeth_vec: vec![11, 12, 13, 14, 15, 17, 19, 21, 24, 28],
+///     wheel_circumference: 2.1,
+/// };
+/// let cadence_band = CadenceBand { min_cadence: 80.0, max_cadence: 100.0 };
+/// for gear in recommend_gears(&result, &gearing, &cadence_band) {
+///     println!("{}x{} at {:.0} rpm", gear.chainring_teeth, gear.cog_teeth, gear.cadence);
+/// }
+/// ```
+pub fn recommend_gears(
+    result: &simulation::SimulationResult,
+    gearing: &Gearing,
+    cadence_band: &CadenceBand,
+) -> Vec<GearRecommendation> {
+    let target_cadence = 0.5 * (cadence_band.min_cadence + cadence_band.max_cadence);
+    let mut recommendation_vec = Vec::with_capacity(result.completed_segments());
+    let mut start_distance = 0.0;
+    for i in 0..result.completed_segments() {
+        let velocity = (result.distance_vec[i] - start_distance) / result.duration_vec[i];
+        start_distance = result.distance_vec[i];
+
+        // Distance of a cadence to the band, zero within it. Ties are broken by the distance to the middle.
+        let band_gap = |cadence: f64| {
+            f64::max(0.0, f64::max(cadence_band.min_cadence - cadence, cadence - cadence_band.max_cadence))
+        };
+        let mut best: Option<GearRecommendation> = None;
+        let mut min_cadence = f64::INFINITY;
+        let mut max_cadence = f64::NEG_INFINITY;
+        for &chainring_teeth in gearing.chainring_teeth_vec.iter() {
+            for &cog_teeth in gearing.cog_teeth_vec.iter() {
+                let cadence = gearing.cadence(velocity, chainring_teeth, cog_teeth);
+                min_cadence = f64::min(min_cadence, cadence);
+                max_cadence = f64::max(max_cadence, cadence);
+                let is_better = best.is_none_or(|best| {
+                    let (gap, best_gap) = (band_gap(cadence), band_gap(best.cadence));
+                    gap < best_gap
+                        || (gap == best_gap
+                            && f64::abs(cadence - target_cadence) < f64::abs(best.cadence - target_cadence))
+                });
+                if is_better {
+                    best = Some(GearRecommendation {
+                        chainring_teeth,
+                        cog_teeth,
+                        cadence,
+                        fit: CadenceFit::InBand,
+                    });
+                }
+            }
+        }
+        let Some(mut recommendation) = best else {
+            break;
+        };
+        recommendation.fit = if min_cadence > cadence_band.max_cadence {
+            CadenceFit::SpinningOut
+        } else if max_cadence < cadence_band.min_cadence {
+            CadenceFit::Grinding
+        } else if band_gap(recommendation.cadence) > 0.0 {
+            CadenceFit::BetweenGears
+        } else {
+            CadenceFit::InBand
+        };
+        recommendation_vec.push(recommendation);
+    }
+    recommendation_vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+    use crate::testutil::{make_course, CourseProfile};
+
+    fn road_gearing() -> Gearing {
+        Gearing {
+            chainring_teeth_vec: vec![50, 34],
+            cog_teeth_vec: vec![11, 12, 13, 14, 15, 17, 19, 21, 24, 28],
+            wheel_circumference: 2.1,
+        }
+    }
+
+    #[test]
+    fn recommended_gear_on_a_flat_steady_segment_is_within_the_band() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 3, segment_length: 1000.0 });
+        let rider_model = morton::default_rider_model();
+        // Starting at about the steady speed of 250 W, so every segment is ridden at a steady pace.
+        let result = simulation::simulate(
+            10.3,
+            rider_model.anaerobic_work_capacity,
+            &vec![250.0; 3],
+            &road_segment_vec,
+            &simulation::default_resistance_model(),
+            &rider_model,
+        );
+        let cadence_band = CadenceBand { min_cadence: 80.0, max_cadence: 100.0 };
+
+        let recommendation_vec = recommend_gears(&result, &road_gearing(), &cadence_band);
+        assert_eq!(recommendation_vec.len(), 3);
+        for recommendation in recommendation_vec.iter() {
+            assert_eq!(recommendation.fit, CadenceFit::InBand);
+            assert!(recommendation.cadence >= cadence_band.min_cadence);
+            assert!(recommendation.cadence <= cadence_band.max_cadence);
+        }
+    }
+
+    #[test]
+    fn steep_climbs_grind_and_fast_descents_spin_out() {
+        let rider_model = morton::default_rider_model();
+        let cadence_band = CadenceBand { min_cadence: 80.0, max_cadence: 100.0 };
+        let fit = |profile: CourseProfile, input_power: f64| {
+            let road_segment_vec = make_course(profile);
+            let result = simulation::simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![input_power; road_segment_vec.len()],
+                &road_segment_vec,
+                &simulation::default_resistance_model(),
+                &rider_model,
+            );
+            recommend_gears(&result, &road_gearing(), &cadence_band).last().unwrap().fit
+        };
+
+        let wall = CourseProfile::ConstantClimb { n_segments: 2, segment_length: 300.0, slope: 0.2 };
+        assert_eq!(fit(wall, 250.0), CadenceFit::Grinding);
+        let descent = CourseProfile::Descent { n_segments: 4, segment_length: 500.0, slope: 0.1 };
+        assert_eq!(fit(descent, 100.0), CadenceFit::SpinningOut);
+    }
+}
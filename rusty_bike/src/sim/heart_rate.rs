@@ -0,0 +1,89 @@
+// Heart rate model
+// First order response of the heart rate to power
+
+/// A first order heart rate model: at constant power, the heart rate relaxes exponentially towards a steady
+/// state that grows linearly with power.
+///
+/// # Fields
+///
+/// * `resting_heart_rate` - The heart rate without effort in beats per minute.
+/// * `max_heart_rate` - The maximal heart rate in beats per minute, which caps the steady state.
+/// * `heart_rate_per_watt` - The rise of the steady-state heart rate per watt of power.
+/// * `time_constant` - The time constant of the heart rate response in seconds.
+pub struct HeartRateModel {
+    pub resting_heart_rate: f64,
+    pub max_heart_rate: f64,
+    pub heart_rate_per_watt: f64,
+    pub time_constant: f64,
+}
+
+/// Returns a heart rate model for a trained rider: 60 bpm at rest, up to 190 bpm, 0.35 bpm per watt, so a
+/// steady state of 165 bpm at 300 W, and a time constant of one minute.
+pub const fn default_heart_rate_model() -> HeartRateModel {
+    HeartRateModel {
+        resting_heart_rate: 60.0,
+        max_heart_rate: 190.0,
+        heart_rate_per_watt: 0.35,
+        time_constant: 60.0,
+    }
+}
+
+/// The heart rate in beats per minute reached after holding `input_power` long enough.
+pub fn steady_state_heart_rate(model: &HeartRateModel, input_power: f64) -> f64 {
+    f64::min(
+        model.max_heart_rate,
+        model.resting_heart_rate + model.heart_rate_per_watt * f64::max(0.0, input_power),
+    )
+}
+
+/// The heart rate in beats per minute after riding at `input_power` for `duration` seconds, starting from
+/// `current_heart_rate`. The heart rate moves monotonically towards its steady state, so it stays between the
+/// two over the whole duration.
+pub fn update_heart_rate(model: &HeartRateModel, input_power: f64, duration: f64, current_heart_rate: f64) -> f64 {
+    let steady_state = steady_state_heart_rate(model, input_power);
+    steady_state + (current_heart_rate - steady_state) * f64::exp(-duration / model.time_constant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heart_rate_lags_behind_a_step_in_power() {
+        let model = default_heart_rate_model();
+        let resting_heart_rate = model.resting_heart_rate;
+        let steady_state = steady_state_heart_rate(&model, 300.0);
+        assert!((steady_state - 165.0).abs() < 1e-9);
+
+        // After one time constant, the heart rate has covered 1 - 1/e of the way to its steady state.
+        let heart_rate = update_heart_rate(&model, 300.0, model.time_constant, resting_heart_rate);
+        let expected = steady_state - (steady_state - resting_heart_rate) / std::f64::consts::E;
+        assert!((heart_rate - expected).abs() < 1e-9, "{} vs {}", heart_rate, expected);
+
+        // Riding in two halves gives the same heart rate as in one go, rising monotonically.
+        let halfway = update_heart_rate(&model, 300.0, 0.5 * model.time_constant, resting_heart_rate);
+        assert!(resting_heart_rate < halfway && halfway < heart_rate);
+        let second_half = update_heart_rate(&model, 300.0, 0.5 * model.time_constant, halfway);
+        assert!((second_half - heart_rate).abs() < 1e-9);
+
+        // Easing off, the heart rate comes down, still lagging.
+        let recovering = update_heart_rate(&model, 100.0, model.time_constant, heart_rate);
+        assert!(steady_state_heart_rate(&model, 100.0) < recovering && recovering < heart_rate);
+    }
+
+    #[test]
+    fn heart_rate_settles_at_its_steady_state() {
+        let model = default_heart_rate_model();
+        for input_power in [0.0, 150.0, 300.0] {
+            let steady_state = steady_state_heart_rate(&model, input_power);
+            let heart_rate = update_heart_rate(&model, input_power, 60.0 * model.time_constant, 120.0);
+            assert!((heart_rate - steady_state).abs() < 1e-9, "{} W", input_power);
+            // Once there, it stays.
+            assert_eq!(update_heart_rate(&model, input_power, 30.0, steady_state), steady_state);
+        }
+
+        // The steady state is capped at the max heart rate, and does not drop below rest when braking.
+        assert_eq!(steady_state_heart_rate(&model, 1000.0), model.max_heart_rate);
+        assert_eq!(steady_state_heart_rate(&model, -50.0), model.resting_heart_rate);
+    }
+}
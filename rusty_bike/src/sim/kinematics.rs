@@ -3,6 +3,12 @@ pub const fn gravity_acceleration() -> f64 {
     return 9.81;
 }
 
+/// The velocity in meters per second below which the propulsive force stops growing as the velocity drops.
+///
+/// From a standstill the rider is limited by the torque they can apply on the pedals rather than by their
+/// power, so the propulsive force is capped at `input_power / TORQUE_LIMIT_VELOCITY`.
+pub const TORQUE_LIMIT_VELOCITY: f64 = 1.0;
+
 /// Calculates the air density given the altitude and temperature.
 ///
 /// # Arguments
@@ -21,10 +27,306 @@ pub const fn gravity_acceleration() -> f64 {
 /// println!("Air density: {}", density);
 /// ```
 pub fn air_density(altitude: f64, temperature: f64) -> f64 {
-    let pressure_pa = 100.0 * 1013.25 * f64::powf(1.0 - 0.0065 * altitude / 288.15, 5.255);
-    let r_air = 287.0;
-    let kelvin = temperature + 273.0;
-    return pressure_pa / kelvin / r_air;
+    Atmosphere::standard().density(altitude, temperature)
+}
+
+/// The barometric air pressure in pascals at the given altitude in meters.
+fn air_pressure(altitude: f64) -> f64 {
+    Atmosphere::standard().pressure(altitude)
+}
+
+fn density_from_pressure(pressure_pa: f64, temperature: f64) -> f64 {
+    Atmosphere::standard().density_from_pressure(pressure_pa, temperature)
+}
+
+/// The parameters of the atmosphere model behind `air_density`: the barometric formula for the pressure and the
+/// ideal gas law for the density. The simulation uses `Atmosphere::standard`; other values serve e.g. to validate
+/// against a reference model or to model non-standard conditions.
+///
+/// # Fields
+///
+/// * `gas_constant` - The specific gas constant of the air in J/(kg·K).
+/// * `lapse_rate` - The drop of the temperature with altitude in the barometric formula in K/m.
+/// * `reference_pressure` - The pressure at altitude 0 in pascals.
+/// * `reference_temperature` - The temperature at altitude 0 in the barometric formula in kelvins.
+/// * `pressure_exponent` - The exponent of the barometric formula, `g / (lapse_rate * gas_constant)` for a
+///   consistent atmosphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Atmosphere {
+    pub gas_constant: f64,
+    pub lapse_rate: f64,
+    pub reference_pressure: f64,
+    pub reference_temperature: f64,
+    pub pressure_exponent: f64,
+}
+
+impl Atmosphere {
+    /// Returns the standard atmosphere used by `air_density`: 1013.25 hPa and 288.15 K at sea level, a lapse
+    /// rate of 6.5 K/km and a gas constant of 287 J/(kg·K).
+    pub const fn standard() -> Atmosphere {
+        Atmosphere {
+            gas_constant: 287.0,
+            lapse_rate: 0.0065,
+            reference_pressure: 100.0 * 1013.25,
+            reference_temperature: 288.15,
+            pressure_exponent: 5.255,
+        }
+    }
+
+    /// Returns the air pressure in pascals at the given altitude in meters.
+    pub fn pressure(&self, altitude: f64) -> f64 {
+        self.reference_pressure
+            * f64::powf(1.0 - self.lapse_rate * altitude / self.reference_temperature, self.pressure_exponent)
+    }
+
+    /// Returns the air density in kg/m^3 at the given altitude in meters and temperature in Celsius.
+    pub fn density(&self, altitude: f64, temperature: f64) -> f64 {
+        self.density_from_pressure(self.pressure(altitude), temperature)
+    }
+
+    /// Returns the air density in kg/m^3 at the given pressure in pascals and temperature in Celsius.
+    pub fn density_from_pressure(&self, pressure_pa: f64, temperature: f64) -> f64 {
+        let kelvin = temperature + 273.0;
+        pressure_pa / kelvin / self.gas_constant
+    }
+}
+
+/// A precomputed table of the air pressure over a range of altitudes, to compute the air density without
+/// the `powf` of `air_density` in hot loops.
+///
+/// Only the pressure depends on the altitude, so the density is exact in temperature and the table is one
+/// dimensional. The pressure is linearly interpolated between the evenly spaced altitudes, and linearly
+/// extrapolated from the first or last interval outside the range. With a 100 m spacing the relative error
+/// is below 2e-5 within the range, and grows outside it (about 1% at 1000 m past the end).
+///
+/// Build it with `air_density_table`, which rejects tables with fewer than two entries.
+#[derive(Clone)]
+pub struct AirDensityTable {
+    min_altitude: f64,
+    altitude_step: f64,
+    pressure_vec: Vec<f64>,
+}
+
+/// Precomputes an air density table over `[min_altitude, max_altitude]` with `n_points` evenly spaced altitudes.
+///
+/// # Returns
+///
+/// * `Result<AirDensityTable, String>` - The table, or an error if `n_points` is below 2 or the altitude range is
+///   empty or not finite.
+///
+/// # Example
+///
+/// ```
+/// let table = air_density_table(-500.0, 5000.0, 56).unwrap();
+/// let density = table.density(1000.0, 15.0);
+/// ```
+pub fn air_density_table(min_altitude: f64, max_altitude: f64, n_points: usize) -> Result<AirDensityTable, String> {
+    if n_points < 2 {
+        return Err(format!("an air density table needs at least 2 points, got {}", n_points));
+    }
+    if !(min_altitude.is_finite() && max_altitude.is_finite()) || min_altitude >= max_altitude {
+        return Err(format!("invalid air density table altitude range [{}, {}] m", min_altitude, max_altitude));
+    }
+    let altitude_step = (max_altitude - min_altitude) / (n_points - 1) as f64;
+    Ok(AirDensityTable {
+        min_altitude,
+        altitude_step,
+        pressure_vec: (0..n_points)
+            .map(|k| air_pressure(min_altitude + k as f64 * altitude_step))
+            .collect(),
+    })
+}
+
+impl AirDensityTable {
+    /// Interpolates the air density in kg/m^3 at the given altitude in meters and temperature in Celsius.
+    pub fn density(&self, altitude: f64, temperature: f64) -> f64 {
+        let position = (altitude - self.min_altitude) / self.altitude_step;
+        let index = f64::clamp(f64::floor(position), 0.0, (self.pressure_vec.len() - 2) as f64) as usize;
+        let fraction = position - index as f64;
+        let pressure_pa =
+            self.pressure_vec[index] + fraction * (self.pressure_vec[index + 1] - self.pressure_vec[index]);
+        density_from_pressure(pressure_pa, temperature)
+    }
+}
+
+/// Calculates the dynamic viscosity of air given the temperature, using Sutherland's formula.
+///
+/// # Arguments
+///
+/// * `temperature` - The temperature in Celsius.
+///
+/// # Returns
+///
+/// * `f64` - The dynamic viscosity in Pa·s.
+///
+/// # Example
+///
+/// ```
+/// let viscosity = air_viscosity(15.0);
+/// println!("Air viscosity: {}", viscosity);
+/// ```
+pub fn air_viscosity(temperature: f64) -> f64 {
+    let reference_viscosity = 1.716e-5;
+    let reference_kelvin = 273.15;
+    let sutherland_constant = 110.4;
+    let kelvin = temperature + 273.15;
+    reference_viscosity * f64::powf(kelvin / reference_kelvin, 1.5) * (reference_kelvin + sutherland_constant)
+        / (kelvin + sutherland_constant)
+}
+
+/// Calculates the Reynolds number of the flow around the rider.
+///
+/// # Arguments
+///
+/// * `air_speed` - The speed of the air relative to the rider in meters per second.
+/// * `density` - The air density in kg/m^3.
+/// * `viscosity` - The dynamic viscosity of air in Pa·s.
+/// * `characteristic_length` - The characteristic length of the rider in meters.
+///
+/// # Returns
+///
+/// * `f64` - The Reynolds number (dimensionless).
+///
+/// # Example
+///
+/// ```
+/// let reynolds = reynolds_number(10.0, 1.225, air_viscosity(15.0), 0.5);
+/// println!("Reynolds number: {}", reynolds);
+/// ```
+pub fn reynolds_number(air_speed: f64, density: f64, viscosity: f64, characteristic_length: f64) -> f64 {
+    density * f64::abs(air_speed) * characteristic_length / viscosity
+}
+
+/// Calculates the effective CdA at a given Reynolds number.
+///
+/// The drag coefficient grows at low Reynolds numbers and levels off at high ones. The correction is
+/// `(1 + sensitivity * reference_reynolds / reynolds) / (1 + sensitivity)`, so the nominal CdA is recovered at
+/// `reference_reynolds` and the CdA tends to `cda / (1 + sensitivity)` at high speed.
+///
+/// # Arguments
+///
+/// * `cda` - The nominal CdA in square meters, measured at `reference_reynolds`.
+/// * `reynolds` - The Reynolds number of the flow.
+/// * `reference_reynolds` - The Reynolds number at which the nominal CdA was measured.
+/// * `sensitivity` - The strength of the correction (dimensionless).
+///
+/// # Returns
+///
+/// * `f64` - The effective CdA in square meters.
+///
+/// # Example
+///
+/// ```
+/// let cda = reynolds_corrected_cda(0.3, 2.0e5, 3.4e5, 0.1);
+/// println!("Effective CdA: {}", cda);
+/// ```
+pub fn reynolds_corrected_cda(cda: f64, reynolds: f64, reference_reynolds: f64, sensitivity: f64) -> f64 {
+    let reynolds = f64::max(reynolds, 1.0);
+    cda * (1.0 + sensitivity * reference_reynolds / reynolds) / (1.0 + sensitivity)
+}
+
+/// Interpolates a CdA-vs-speed curve, e.g. for a rider tucking more as they go faster.
+///
+/// # Arguments
+///
+/// * `cda_speed_curve` - The `(velocity, cda)` points of the curve, in increasing order of velocity. The CdA
+///   is linearly interpolated between them and held constant beyond the first and last ones.
+/// * `velocity` - The velocity in meters per second.
+///
+/// # Returns
+///
+/// * `Option<f64>` - The CdA in square meters, or `None` if the curve is empty.
+///
+/// # Example
+///
+/// ```
+/// let cda = interpolate_cda(&[(10.0, 0.3), (20.0, 0.22)], 15.0).unwrap();
+/// println!("CdA: {}", cda);
+/// ```
+pub fn interpolate_cda(cda_speed_curve: &[(f64, f64)], velocity: f64) -> Option<f64> {
+    let (&(first_velocity, first_cda), &(last_velocity, last_cda)) =
+        (cda_speed_curve.first()?, cda_speed_curve.last()?);
+    if velocity < first_velocity {
+        return Some(first_cda);
+    }
+    if velocity >= last_velocity {
+        return Some(last_cda);
+    }
+    let index = cda_speed_curve.partition_point(|&(curve_velocity, _)| curve_velocity <= velocity);
+    let (low_velocity, low_cda) = cda_speed_curve[index - 1];
+    let (high_velocity, high_cda) = cda_speed_curve[index];
+    Some(low_cda + (velocity - low_velocity) / (high_velocity - low_velocity) * (high_cda - low_cda))
+}
+
+/// A CdA measured in speed bands, e.g. in a wind tunnel or on a velodrome, rather than as a smooth curve.
+///
+/// Each band `(speed_low, speed_high, cda)` holds for speeds from `speed_low` included to `speed_high` excluded,
+/// so at the boundary between two adjacent bands the faster one applies. Below, above or between the bands, the
+/// nearest band applies. Build it with `CdaBands::new`, which rejects overlapping bands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CdaBands {
+    band_vec: Vec<(f64, f64, f64)>,
+}
+
+impl CdaBands {
+    /// Builds the bands from `(speed_low, speed_high, cda)` triples in meters per second and square meters, in
+    /// any order.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CdaBands, String>` - The bands sorted by speed, or an error if there is no band, a band is empty
+    ///   or not finite, or two bands overlap.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let cda_bands = CdaBands::new(vec![(0.0, 10.0, 0.32), (10.0, 15.0, 0.28), (15.0, 25.0, 0.25)]).unwrap();
+    /// println!("CdA at 12 m/s: {}", cda_bands.cda_at(12.0));
+    /// ```
+    pub fn new(band_vec: Vec<(f64, f64, f64)>) -> Result<CdaBands, String> {
+        if band_vec.is_empty() {
+            return Err("no CdA band".to_string());
+        }
+        let mut band_vec = band_vec;
+        for &(speed_low, speed_high, cda) in band_vec.iter() {
+            if !(speed_low.is_finite() && speed_high.is_finite() && cda.is_finite()) || speed_low >= speed_high {
+                return Err(format!("invalid CdA band [{}, {}) m/s", speed_low, speed_high));
+            }
+        }
+        band_vec.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for pair in band_vec.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                return Err(format!(
+                    "CdA bands [{}, {}) and [{}, {}) m/s overlap",
+                    pair[0].0, pair[0].1, pair[1].0, pair[1].1
+                ));
+            }
+        }
+        Ok(CdaBands { band_vec })
+    }
+
+    /// Returns the CdA in square meters of the band containing `velocity`, or of the nearest band.
+    pub fn cda_at(&self, velocity: f64) -> f64 {
+        let index = self.band_vec.partition_point(|&(speed_low, _, _)| speed_low <= velocity);
+        if index == 0 {
+            return self.band_vec[0].2;
+        }
+        let (_, speed_high, cda) = self.band_vec[index - 1];
+        if velocity < speed_high {
+            return cda;
+        }
+        // In a gap between two bands or above the last one.
+        match self.band_vec.get(index) {
+            Some(&(next_speed_low, _, next_cda)) if next_speed_low - velocity < velocity - speed_high => next_cda,
+            _ => cda,
+        }
+    }
+
+    /// Returns the same bands with every CdA multiplied by `factor`, e.g. for a rider sheltered in a draft.
+    pub fn scaled(&self, factor: f64) -> CdaBands {
+        let band_vec = self.band_vec.iter().map(|&(speed_low, speed_high, cda)| (speed_low, speed_high, factor * cda));
+        CdaBands { band_vec: band_vec.collect() }
+    }
 }
 
 /// Calculates the velocity given the kinetic energy and total mass.
@@ -69,6 +371,36 @@ pub fn kinetic_energy(velocity: f64, total_mass: f64) -> f64 {
     return 0.5 * total_mass * velocity * velocity;
 }
 
+/// Calculates the aerodynamic drag force, the single place where the air resistance is computed.
+///
+/// The drag is along the direction of travel and grows with the square of the apparent wind `velocity +
+/// apparent_wind`. When a tailwind is faster than the rider, the apparent wind is negative and so is the force,
+/// which then pushes the rider forward; it is zero when the tailwind matches the rider's speed.
+///
+/// # Arguments
+///
+/// * `velocity` - The ground speed of the rider in meters per second.
+/// * `apparent_wind` - The wind velocity along the direction of travel in meters per second, positive for a
+///   headwind.
+/// * `density` - The air density in kilograms per cubic meter, e.g. corrected for the altitude at this step.
+/// * `cda` - The drag area in square meters.
+/// * `yaw_factor` - The factor applied to the CdA for the yaw angle of the wind, 1 without crosswind.
+///
+/// # Returns
+///
+/// * `f64` - The drag force in newtons, positive against the direction of travel.
+///
+/// # Example
+///
+/// ```
+/// let drag_force = aero_force(10.0, 2.0, 1.225, 0.3, 1.0);
+/// println!("Aero Force: {}", drag_force);
+/// ```
+pub fn aero_force(velocity: f64, apparent_wind: f64, density: f64, cda: f64, yaw_factor: f64) -> f64 {
+    let air_speed = velocity + apparent_wind;
+    0.5 * density * cda * yaw_factor * f64::abs(air_speed) * air_speed
+}
+
 /// Calculates the drag force given the velocity, wind velocity, rolling resistance, and air resistance coefficient.
 ///
 /// # Arguments
@@ -95,9 +427,126 @@ pub fn get_drag_force(
     air_resistance_coef: f64,
     total_mass: f64
 ) -> f64 {
-    let air_resistance =
-        air_resistance_coef * f64::abs(velocity + wind_velocity) * (velocity + wind_velocity);
-    return air_resistance + rolling_resistance * gravity_acceleration() * total_mass;
+    let air_resistance = aero_force(velocity, wind_velocity, 2.0 * air_resistance_coef, 1.0, 1.0);
+    air_resistance + get_rolling_resistance_force(rolling_resistance, total_mass)
+}
+
+/// Calculates the air resistance force given the velocity, wind velocity, and air resistance coefficient
+/// `0.5 * density * CdA`, see `aero_force`.
+///
+/// # Arguments
+///
+/// * `velocity` - The velocity in meters per second.
+/// * `wind_velocity` - The wind velocity in meters per second, positive for a headwind.
+/// * `air_resistance_coef` - The air resistance coefficient.
+///
+/// # Returns
+///
+/// * `f64` - The air resistance force in newtons.
+///
+/// # Example
+///
+/// ```
+/// let air_force = get_air_resistance_force(10.0, 2.0, 0.18);
+/// println!("Air Resistance Force: {}", air_force);
+/// ```
+pub fn get_air_resistance_force(velocity: f64, wind_velocity: f64, air_resistance_coef: f64) -> f64 {
+    aero_force(velocity, wind_velocity, 2.0 * air_resistance_coef, 1.0, 1.0)
+}
+
+/// Calculates the rolling resistance force given the rolling resistance coefficient and total mass.
+///
+/// # Arguments
+///
+/// * `rolling_resistance` - The rolling resistance coefficient.
+/// * `total_mass` - The total mass in kilograms.
+///
+/// # Returns
+///
+/// * `f64` - The rolling resistance force in newtons.
+///
+/// # Example
+///
+/// ```
+/// let rolling_force = get_rolling_resistance_force(0.004, 80.0);
+/// println!("Rolling Resistance Force: {}", rolling_force);
+/// ```
+pub fn get_rolling_resistance_force(rolling_resistance: f64, total_mass: f64) -> f64 {
+    rolling_resistance * gravity_acceleration() * total_mass
+}
+
+/// Calculates the speed-dependent loss force of rough surfaces (impedance losses), on top of rolling resistance.
+///
+/// The force grows with the square of the velocity, so fast riding on cobbles costs disproportionately more
+/// than the rolling resistance scaled by `roughness` predicts. It is zero on smooth road, of roughness 1 or
+/// less, whatever the velocity.
+///
+/// # Arguments
+///
+/// * `velocity` - The velocity in meters per second.
+/// * `roughness` - The roughness of the road, 1 for smooth asphalt.
+/// * `roughness_speed_loss` - The loss coefficient in s^2/m^2 per unit of roughness above 1.
+/// * `total_mass` - The total mass in kilograms.
+///
+/// # Returns
+///
+/// * `f64` - The roughness loss force in newtons.
+///
+/// # Example
+///
+/// ```
+/// let roughness_force = get_roughness_loss_force(10.0, 3.0, 1e-4, 80.0);
+/// println!("Roughness Loss Force: {}", roughness_force);
+/// ```
+pub fn get_roughness_loss_force(velocity: f64, roughness: f64, roughness_speed_loss: f64, total_mass: f64) -> f64 {
+    roughness_speed_loss * f64::max(0.0, roughness - 1.0) * gravity_acceleration() * total_mass
+        * velocity
+        * velocity
+}
+
+/// Calculates the gravity force along the road given the slope and total mass.
+///
+/// # Arguments
+///
+/// * `slope` - The slope of the surface (dimensionless).
+/// * `total_mass` - The total mass in kilograms.
+///
+/// # Returns
+///
+/// * `f64` - The gravity force in newtons, positive when it opposes the motion.
+///
+/// # Example
+///
+/// ```
+/// let gravity_force = get_gravity_force(0.05, 80.0);
+/// println!("Gravity Force: {}", gravity_force);
+/// ```
+pub fn get_gravity_force(slope: f64, total_mass: f64) -> f64 {
+    gravity_acceleration() * slope * total_mass
+}
+
+/// Calculates the propulsive force produced by the rider given the input power and velocity.
+///
+/// Below `TORQUE_LIMIT_VELOCITY` the force is held constant, which keeps it finite at a standing start where
+/// `input_power / velocity` would diverge.
+///
+/// # Arguments
+///
+/// * `input_power` - The input power in watts.
+/// * `velocity` - The velocity in meters per second.
+///
+/// # Returns
+///
+/// * `f64` - The propulsive force in newtons.
+///
+/// # Example
+///
+/// ```
+/// let force = get_propulsive_force(300.0, 0.0);
+/// println!("Propulsive Force: {}", force);
+/// ```
+pub fn get_propulsive_force(input_power: f64, velocity: f64) -> f64 {
+    input_power / f64::max(velocity, TORQUE_LIMIT_VELOCITY)
 }
 
 /// Calculates the total force acting on the object given the kinetic energy, input power, rolling resistance, air resistance coefficient, wind velocity, slope, and total mass.
@@ -130,20 +579,284 @@ pub fn get_total_force(
     wind_velocity: f64,
     slope: f64,
     total_mass: f64,
+) -> f64 {
+    get_total_force_with_constant_forces(
+        kinetic_energy,
+        input_power,
+        air_resistance_coef,
+        wind_velocity,
+        get_rolling_resistance_force(rolling_resistance, total_mass),
+        get_gravity_force(slope, total_mass),
+        total_mass,
+    )
+}
+
+/// Calculates the total force acting on the object given the kinetic energy, input power, air resistance coefficient, wind velocity, and the precomputed rolling resistance and gravity forces.
+///
+/// The rolling resistance and gravity forces do not depend on the velocity, so they can be computed once per
+/// segment and reused for every integration step.
+///
+/// # Arguments
+///
+/// * `kinetic_energy` - The kinetic energy in joules.
+/// * `input_power` - The input power in watts.
+/// * `air_resistance_coef` - The air resistance coefficient.
+/// * `wind_velocity` - The wind velocity in meters per second.
+/// * `rolling_resistance_force` - The rolling resistance force in newtons.
+/// * `gravity_force` - The gravity force in newtons.
+/// * `total_mass` - The total mass in kilograms.
+///
+/// # Returns
+///
+/// * `f64` - The total force in newtons.
+///
+/// # Example
+///
+/// ```
+/// let rolling_force = get_rolling_resistance_force(0.005, 70.0);
+/// let gravity_force = get_gravity_force(0.05, 70.0);
+/// let total_force = get_total_force_with_constant_forces(500.0, 250.0, 0.3, 2.0, rolling_force, gravity_force, 70.0);
+/// println!("Total Force: {}", total_force);
+/// ```
+pub fn get_total_force_with_constant_forces(
+    kinetic_energy: f64,
+    input_power: f64,
+    air_resistance_coef: f64,
+    wind_velocity: f64,
+    rolling_resistance_force: f64,
+    gravity_force: f64,
+    total_mass: f64,
 ) -> f64 {
     let velocity = velocity(kinetic_energy, total_mass);
 
-    let drag_force = f64::signum(kinetic_energy)
-        * get_drag_force(
-            velocity,
-            wind_velocity,
-            rolling_resistance,
-            air_resistance_coef,
-            total_mass
-        );
+    let air_resistance = get_air_resistance_force(velocity, wind_velocity, air_resistance_coef);
+    let drag_force = f64::signum(kinetic_energy) * (air_resistance + rolling_resistance_force);
 
-    let gravity_force = gravity_acceleration() * slope * total_mass;
-    let total_force = input_power / velocity - drag_force - gravity_force;
-    return total_force;
+    get_propulsive_force(input_power, velocity) - drag_force - gravity_force
 }
 
+/// Calculates the highest speed at which a rider can take a corner before the tyres slide.
+///
+/// # Arguments
+///
+/// * `radius` - The radius of the line through the corner in meters.
+/// * `cornering_friction` - The lateral friction coefficient of the tyres on the road, e.g. 0.8 on dry asphalt.
+///
+/// # Returns
+///
+/// * `f64` - The corner speed limit in meters per second.
+///
+/// # Example
+///
+/// ```
+/// let corner_speed = corner_speed_limit(20.0, 0.8);
+/// println!("Corner speed limit: {}", corner_speed);
+/// ```
+pub fn corner_speed_limit(radius: f64, cornering_friction: f64) -> f64 {
+    f64::sqrt(cornering_friction * gravity_acceleration() * radius)
+}
+
+/// Calculates the radius of the racing line through a corner, cutting from the outside to the apex and back out
+/// over the whole road width.
+///
+/// The widening grows as the corner gets gentler: a hairpin only gains the road width, while a sweeping bend
+/// gains many times the road width.
+///
+/// # Arguments
+///
+/// * `radius` - The radius of the corner followed without a racing line in meters.
+/// * `turn_angle` - The change of direction through the corner in degrees, e.g. 180 for a hairpin.
+/// * `road_width` - The width of road available for the racing line in meters.
+///
+/// # Returns
+///
+/// * `f64` - The radius of the racing line in meters.
+///
+/// # Example
+///
+/// ```
+/// let radius = racing_line_radius(20.0, 45.0, 4.0);
+/// println!("Racing line radius: {}", radius);
+/// ```
+pub fn racing_line_radius(radius: f64, turn_angle: f64, road_width: f64) -> f64 {
+    radius + road_width / (1.0 - f64::cos(0.5 * f64::to_radians(turn_angle)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::simulation;
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn standing_start_accelerates_smoothly() {
+        assert_eq!(get_propulsive_force(300.0, 0.0), 300.0 / TORQUE_LIMIT_VELOCITY);
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 1, segment_length: 200.0 });
+        let mut trajectory_vec = Vec::new();
+        let (time, final_velocity) = simulation::compute_time_and_final_velocity_with_trajectory(
+            0.0,
+            300.0,
+            &road_segment_vec[0],
+            &simulation::default_resistance_model(),
+            &mut trajectory_vec,
+        );
+
+        assert!(time.is_finite() && final_velocity.is_finite());
+        assert!(trajectory_vec.len() > 2);
+        for pair in trajectory_vec.windows(2) {
+            let (_, velocity, _) = pair[0];
+            let (_, next_velocity, _) = pair[1];
+            assert!(next_velocity.is_finite());
+            assert!(next_velocity > velocity, "{} after {}", next_velocity, velocity);
+            assert!(next_velocity - velocity < 1.0, "jump from {} to {}", velocity, next_velocity);
+        }
+    }
+
+    #[test]
+    fn hoisted_constant_forces_give_the_same_total_force() {
+        let total_mass = 80.0;
+        let rolling_resistance = 0.004;
+        let air_resistance_coef = 0.18;
+        for velocity in [2.0, 8.0, 15.0, 25.0] {
+            for (wind_velocity, slope) in [(0.0, 0.0), (3.0, 0.05), (-4.0, -0.08)] {
+                let kinetic_energy = kinetic_energy(velocity, total_mass);
+                // The force as computed inside the integrator loop before the constant forces were hoisted.
+                let air_speed = velocity + wind_velocity;
+                let expected = 250.0 / velocity
+                    - (air_resistance_coef * f64::abs(air_speed) * air_speed
+                        + rolling_resistance * gravity_acceleration() * total_mass)
+                    - gravity_acceleration() * slope * total_mass;
+                let hoisted = get_total_force_with_constant_forces(
+                    kinetic_energy,
+                    250.0,
+                    air_resistance_coef,
+                    wind_velocity,
+                    get_rolling_resistance_force(rolling_resistance, total_mass),
+                    get_gravity_force(slope, total_mass),
+                    total_mass,
+                );
+                let total = get_total_force(
+                    kinetic_energy,
+                    250.0,
+                    rolling_resistance,
+                    air_resistance_coef,
+                    wind_velocity,
+                    slope,
+                    total_mass,
+                );
+                assert_eq!(hoisted, total);
+                let tolerance = 1e-9 * f64::max(1.0, expected.abs());
+                assert!((hoisted - expected).abs() < tolerance, "{} vs {}", hoisted, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn reynolds_corrected_cda_differs_from_nominal_in_the_cold() {
+        let cda = 0.3;
+        let characteristic_length = 1.7;
+        let reynolds_at = |temperature: f64| {
+            reynolds_number(10.0, air_density(0.0, temperature), air_viscosity(temperature), characteristic_length)
+        };
+        let reference_reynolds = reynolds_at(20.0);
+
+        assert!(air_viscosity(-10.0) < air_viscosity(20.0));
+        assert!((reynolds_corrected_cda(cda, reference_reynolds, reference_reynolds, 0.2) - cda).abs() < 1e-12);
+        let cold_cda = reynolds_corrected_cda(cda, reynolds_at(-10.0), reference_reynolds, 0.2);
+        assert!(reynolds_at(-10.0) > reference_reynolds);
+        assert!(cold_cda < cda - 1e-4, "{}", cold_cda);
+    }
+
+    #[test]
+    fn air_density_table_matches_the_exact_formula() {
+        let table = air_density_table(-500.0, 5000.0, 56).unwrap();
+        for altitude in (-400..=4800).step_by(37).map(f64::from) {
+            for temperature in [-10.0, 0.0, 15.0, 35.0] {
+                let exact = air_density(altitude, temperature);
+                let relative_error = (table.density(altitude, temperature) - exact).abs() / exact;
+                assert!(relative_error < 2e-5, "{} m, {} C: relative error {}", altitude, temperature, relative_error);
+            }
+        }
+        assert!(air_density_table(0.0, 1000.0, 0).is_err());
+        assert!(air_density_table(0.0, 1000.0, 1).is_err());
+        assert!(air_density_table(1000.0, 1000.0, 10).is_err());
+    }
+
+    #[test]
+    fn doubling_speed_more_than_doubles_the_roughness_resistance_on_cobbles() {
+        let rolling_resistance = 0.004;
+        let roughness_speed_loss = 1e-4;
+        let total_mass = 80.0;
+        // Resistance in excess of the rolling resistance on smooth road.
+        let roughness_resistance = |velocity: f64, roughness: f64| {
+            get_rolling_resistance_force(roughness * rolling_resistance, total_mass)
+                - get_rolling_resistance_force(rolling_resistance, total_mass)
+                + get_roughness_loss_force(velocity, roughness, roughness_speed_loss, total_mass)
+        };
+
+        for velocity in [5.0, 8.0, 12.0] {
+            assert!(roughness_resistance(2.0 * velocity, 3.0) > 2.0 * roughness_resistance(velocity, 3.0));
+            assert_eq!(get_roughness_loss_force(velocity, 1.0, roughness_speed_loss, total_mass), 0.0);
+            assert_eq!(roughness_resistance(velocity, 1.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn banded_cda_selects_the_band_or_the_nearest_one() {
+        let cda_bands = CdaBands::new(vec![(10.0, 15.0, 0.28), (0.0, 10.0, 0.32), (16.0, 25.0, 0.25)]).unwrap();
+        assert_eq!(cda_bands.cda_at(5.0), 0.32);
+        assert_eq!(cda_bands.cda_at(12.0), 0.28);
+        assert_eq!(cda_bands.cda_at(20.0), 0.25);
+        // A shared boundary belongs to the faster band.
+        assert_eq!(cda_bands.cda_at(10.0), 0.28);
+        // Out of range, and in the gap between 15 and 16 m/s, the nearest band applies.
+        assert_eq!(cda_bands.cda_at(-1.0), 0.32);
+        assert_eq!(cda_bands.cda_at(40.0), 0.25);
+        assert_eq!(cda_bands.cda_at(15.2), 0.28);
+        assert_eq!(cda_bands.cda_at(15.8), 0.25);
+
+        assert!(CdaBands::new(Vec::new()).is_err());
+        assert!(CdaBands::new(vec![(0.0, 10.0, 0.32), (9.0, 15.0, 0.28)]).is_err());
+        assert!(CdaBands::new(vec![(10.0, 10.0, 0.32)]).is_err());
+        assert!(CdaBands::new(vec![(0.0, f64::NAN, 0.32)]).is_err());
+    }
+
+    #[test]
+    fn aero_force_vanishes_at_matching_tailwind_and_flips_beyond() {
+        let (density, cda) = (1.225, 0.3);
+        assert_eq!(aero_force(10.0, -10.0, density, cda, 1.0), 0.0);
+        assert!(aero_force(10.0, -5.0, density, cda, 1.0) > 0.0);
+        let pushing = aero_force(10.0, -15.0, density, cda, 1.0);
+        assert!(pushing < 0.0);
+        // The push of a tailwind 5 m/s faster than the rider mirrors the drag of riding 5 m/s into still air.
+        assert!((pushing + aero_force(5.0, 0.0, density, cda, 1.0)).abs() < 1e-12);
+        assert!((aero_force(10.0, 2.0, density, cda, 1.1) - 0.5 * density * cda * 1.1 * 144.0).abs() < 1e-9);
+
+        // The drag force adds rolling resistance to the aero force with the coefficient `0.5 * density * CdA`.
+        let air_resistance_coef = 0.5 * density * cda;
+        let drag_force = get_drag_force(10.0, -15.0, 0.005, air_resistance_coef, 80.0);
+        assert!((drag_force - pushing - get_rolling_resistance_force(0.005, 80.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn standard_atmosphere_reproduces_the_original_air_density() {
+        // The formula `air_density` hardcoded before the atmosphere parameters were exposed.
+        let original_air_density = |altitude: f64, temperature: f64| {
+            let pressure_pa = 100.0 * 1013.25 * f64::powf(1.0 - 0.0065 * altitude / 288.15, 5.255);
+            pressure_pa / (temperature + 273.0) / 287.0
+        };
+        for altitude in [-400.0, 0.0, 500.0, 1800.0, 2800.0] {
+            for temperature in [-10.0, 0.0, 20.0, 38.0] {
+                let expected = original_air_density(altitude, temperature);
+                assert!((air_density(altitude, temperature) - expected).abs() <= 1e-15 * expected);
+                assert!((Atmosphere::standard().density(altitude, temperature) - expected).abs() <= 1e-15 * expected);
+            }
+        }
+
+        let thin_gas = Atmosphere {
+            gas_constant: 2.0 * 287.0,
+            ..Atmosphere::standard()
+        };
+        assert!((thin_gas.density(1000.0, 20.0) - 0.5 * air_density(1000.0, 20.0)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,299 @@
+// Monte Carlo
+// Repeated simulations under random perturbations of the course, e.g. the uncertainty of a wind forecast, or of
+// the power the rider produces, e.g. the errors of their power meter
+//
+// Randomness is always injected: stochastic functions take a `Rng`, and the drivers take a master seed from which
+// every trial derives its own stream, so results are reproducible and independent of the threading.
+
+use crate::sim::power_duration::PowerDurationModel;
+use crate::sim::simulation;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Derives the seed of a trial from the master seed, with the SplitMix64 finalizer, so that the trials get
+/// independent streams and any trial can be replayed on its own.
+pub fn trial_seed(master_seed: u64, trial_index: usize) -> u64 {
+    let mut z = master_seed.wrapping_add((trial_index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Draws a sample of the standard normal distribution with the Box-Muller transform.
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen::<f64>();
+    f64::sqrt(-2.0 * f64::ln(u1)) * f64::cos(2.0 * std::f64::consts::PI * u2)
+}
+
+/// Returns a copy of the course with a normally distributed error of standard deviation `wind_speed_std` meters
+/// per second added to the relative wind speed of each segment, independently.
+///
+/// # Example
+///
+/// ```
+/// let mut rng = StdRng::seed_from_u64(42);
+/// let perturbed_segment_vec = perturb_wind(&road_segment_vec, 1.5, &mut rng);
+/// ```
+pub fn perturb_wind<R: Rng>(
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    wind_speed_std: f64,
+    rng: &mut R,
+) -> Vec<simulation::RoadSegment> {
+    road_segment_vec
+        .iter()
+        .map(|road_segment| simulation::RoadSegment {
+            relative_wind_speed: road_segment.relative_wind_speed + wind_speed_std * standard_normal(rng),
+            ..*road_segment
+        })
+        .collect()
+}
+
+/// How many Monte Carlo trials to run, and how.
+///
+/// # Fields
+///
+/// * `n_trials` - The number of trials.
+/// * `master_seed` - The seed from which every trial derives its own, see `trial_seed`.
+/// * `parallel` - Whether to spread the trials over the available threads.
+pub struct MonteCarloSettings {
+    pub n_trials: usize,
+    pub master_seed: u64,
+    pub parallel: bool,
+}
+
+/// Returns settings running 1000 trials in parallel from the master seed 0.
+pub const fn default_monte_carlo_settings() -> MonteCarloSettings {
+    MonteCarloSettings {
+        n_trials: 1000,
+        master_seed: 0,
+        parallel: true,
+    }
+}
+
+/// Simulates a power plan over `settings.n_trials` perturbations of the course wind, see `perturb_wind`.
+///
+/// Trial `k` draws its perturbation from a `StdRng` seeded with `trial_seed(settings.master_seed, k)`, so the same
+/// master seed gives the same times whether the trials run serially or in parallel, on any number of threads.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `input_power_vec` - The power on each segment in watts.
+/// * `road_segment_vec` - The segments of the course, with the forecast wind.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model, starting with a full anaerobic reserve.
+/// * `wind_speed_std` - The standard deviation of the wind error in meters per second.
+/// * `settings` - The number of trials, the master seed and whether to run them in parallel.
+///
+/// # Returns
+///
+/// * `Vec<f64>` - The total time of each trial in seconds, in trial order.
+pub fn monte_carlo_wind_times<M: PowerDurationModel + Sync>(
+    initial_velocity: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &M,
+    wind_speed_std: f64,
+    settings: &MonteCarloSettings,
+) -> Vec<f64> {
+    let MonteCarloSettings { n_trials, master_seed, parallel } = *settings;
+    let run_trial = |trial_index: usize| {
+        let mut rng = StdRng::seed_from_u64(trial_seed(master_seed, trial_index));
+        let perturbed_segment_vec = perturb_wind(road_segment_vec, wind_speed_std, &mut rng);
+        simulation::simulate(
+            initial_velocity,
+            rider_model.anaerobic_work_capacity(),
+            input_power_vec,
+            &perturbed_segment_vec,
+            resistance_model,
+            rider_model,
+        )
+        .total_time
+    };
+    if !parallel {
+        return (0..n_trials).map(run_trial).collect();
+    }
+    let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = usize::max(1, n_trials.div_ceil(n_threads));
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..n_trials)
+            .step_by(chunk_size)
+            .map(|start| {
+                let run_trial = &run_trial;
+                let end = usize::min(start + chunk_size, n_trials);
+                scope.spawn(move || (start..end).map(run_trial).collect::<Vec<f64>>())
+            })
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// The errors of the power meter a rider paces by, so that the power they produce differs from the plan.
+///
+/// # Fields
+///
+/// * `calibration_offset` - The systematic error as a fraction of the power: the rider produces
+///   `1 + calibration_offset` times the power they see, e.g. 0.03 for a meter reading 3% low, which makes them
+///   over-pace.
+/// * `dropout_probability` - The probability that the meter drops out on a segment, independently.
+/// * `feel_std` - The standard deviation, as a fraction of the planned power, of the error of a rider pacing by
+///   feel during a dropout.
+pub struct PowerMeterModel {
+    pub calibration_offset: f64,
+    pub dropout_probability: f64,
+    pub feel_std: f64,
+}
+
+/// Returns an exact power meter that never drops out, the rider producing the planned power, and pacing within
+/// 10% by feel should it drop out.
+pub const fn default_power_meter_model() -> PowerMeterModel {
+    PowerMeterModel {
+        calibration_offset: 0.0,
+        dropout_probability: 0.0,
+        feel_std: 0.1,
+    }
+}
+
+/// Returns the power the rider produces on each segment when pacing a plan by the power meter.
+///
+/// On a segment where the meter works, the rider produces the planned power scaled by the calibration offset. On
+/// a segment where it drops out, they pace by feel instead, producing the planned power with a normally
+/// distributed error of relative standard deviation `feel_std`, never below zero.
+///
+/// # Example
+///
+/// ```
+/// let mut rng = StdRng::seed_from_u64(42);
+/// let produced_power_vec = perturb_power(&input_power_vec, &default_power_meter_model(), &mut rng);
+/// ```
+pub fn perturb_power<R: Rng>(input_power_vec: &Vec<f64>, power_meter_model: &PowerMeterModel, rng: &mut R) -> Vec<f64> {
+    input_power_vec
+        .iter()
+        .map(|&input_power| {
+            if rng.gen::<f64>() < power_meter_model.dropout_probability {
+                return f64::max(0.0, input_power * (1.0 + power_meter_model.feel_std * standard_normal(rng)));
+            }
+            input_power * (1.0 + power_meter_model.calibration_offset)
+        })
+        .collect()
+}
+
+/// Simulates a power plan paced by an imperfect power meter, starting with a full anaerobic reserve: the planned
+/// powers are perturbed by `perturb_power` before being ridden, e.g. to see whether a meter reading a few percent
+/// low makes the rider run out of anaerobic reserve before the finish.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `input_power_vec` - The planned power on each segment in watts, as read on the meter.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `power_meter_model` - The errors of the power meter.
+/// * `rng` - The source of the dropouts and of the errors of pacing by feel.
+///
+/// # Returns
+///
+/// * `SimulationResult` - The simulation of the powers actually produced.
+pub fn simulate_with_power_meter<M: PowerDurationModel, R: Rng>(
+    initial_velocity: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &M,
+    power_meter_model: &PowerMeterModel,
+    rng: &mut R,
+) -> simulation::SimulationResult {
+    let produced_power_vec = perturb_power(input_power_vec, power_meter_model, rng);
+    simulation::simulate(
+        initial_velocity,
+        rider_model.anaerobic_work_capacity(),
+        &produced_power_vec,
+        road_segment_vec,
+        resistance_model,
+        rider_model,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn same_master_seed_gives_the_same_times_serially_and_in_parallel() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 10,
+            segment_length: 300.0,
+            max_slope: 0.04,
+            period: 5,
+        });
+        let rider_model = morton::default_rider_model();
+        let times = |master_seed: u64, parallel: bool| {
+            let settings = MonteCarloSettings {
+                n_trials: 37,
+                master_seed,
+                parallel,
+            };
+            monte_carlo_wind_times(
+                5.0,
+                &vec![280.0; 10],
+                &road_segment_vec,
+                &simulation::default_resistance_model(),
+                &rider_model,
+                2.0,
+                &settings,
+            )
+        };
+
+        let serial_time_vec = times(7, false);
+        assert_eq!(serial_time_vec.len(), 37);
+        assert_eq!(times(7, true), serial_time_vec);
+        assert_eq!(times(7, false), serial_time_vec);
+        assert_ne!(times(8, false), serial_time_vec);
+        // The trials draw independent streams.
+        assert!(serial_time_vec.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn positive_calibration_offset_depletes_the_reserve_earlier() {
+        let road_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 20,
+            segment_length: 500.0,
+            slope: 0.05,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let input_power_vec = vec![rider_model.critical_power * 1.04; road_segment_vec.len()];
+        let ride = |calibration_offset: f64| {
+            let power_meter_model = PowerMeterModel { calibration_offset, ..default_power_meter_model() };
+            simulate_with_power_meter(
+                5.0,
+                &input_power_vec,
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                &power_meter_model,
+                &mut StdRng::seed_from_u64(42),
+            )
+        };
+        let exact = ride(0.0);
+        let over_paced = ride(0.03);
+        // Without an offset the rider holds the plan to the finish, with reserve to spare.
+        assert!(exact.power_vec.iter().zip(&input_power_vec).all(|(power, input_power)| power == input_power));
+        assert!(*exact.anaerobic_reserve_vec.last().unwrap() > 0.0);
+
+        // Reading 3% low, they over-pace and run short of reserve before the finish, falling back to critical power.
+        let exhausted_index =
+            over_paced.power_vec.iter().position(|&power| power == rider_model.critical_power).unwrap();
+        assert!(exhausted_index < road_segment_vec.len() - 1);
+        assert!(over_paced.power_vec[..exhausted_index].iter().all(|&power| power > input_power_vec[0]));
+        let reserve_pair_vec = over_paced.anaerobic_reserve_vec.iter().zip(&exact.anaerobic_reserve_vec);
+        assert!(reserve_pair_vec.take(exhausted_index).all(|(over_paced_reserve, exact_reserve)| {
+            over_paced_reserve < exact_reserve
+        }));
+    }
+}
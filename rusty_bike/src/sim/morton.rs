@@ -4,10 +4,15 @@
 // A 3-parameter critical power model
 // R. HUGH MORTON
 
+use crate::sim::power_duration::PowerDurationModel;
+
 pub struct RiderModel {
     pub critical_power: f64,
     pub anaerobic_work_capacity: f64,
     pub max_power: f64,
+    /// Extra anaerobic reserve drawdown at high power, see `anaerobic_depletion_factor`. Zero gives the
+    /// linear Morton drawdown.
+    pub fatigue_coefficient: f64,
 }
 
 pub const fn default_rider_model() -> RiderModel {
@@ -15,16 +20,59 @@ pub const fn default_rider_model() -> RiderModel {
         critical_power: 300.0,
         anaerobic_work_capacity: 20000.0,
         max_power: 1000.0,
+        fatigue_coefficient: 0.0,
     };
     return model;
 }
 
+/// Calibrated rider profiles, from a sedentary adult to a professional road racer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RiderPreset {
+    Untrained,
+    Amateur,
+    Elite,
+    WorldTour,
+}
+
+/// Returns the rider model of a preset. Every preset has `max_power > critical_power` and a positive
+/// `anaerobic_work_capacity`, and the linear drawdown of the Morton model (`fatigue_coefficient` of 0).
+///
+/// # Example
+///
+/// ```
+/// let rider_model = rider_preset(RiderPreset::Elite);
+/// ```
+pub const fn rider_preset(preset: RiderPreset) -> RiderModel {
+    let (critical_power, anaerobic_work_capacity, max_power) = match preset {
+        RiderPreset::Untrained => (150.0, 12000.0, 600.0),
+        RiderPreset::Amateur => (230.0, 16000.0, 900.0),
+        RiderPreset::Elite => (330.0, 22000.0, 1300.0),
+        RiderPreset::WorldTour => (420.0, 25000.0, 1600.0),
+    };
+    RiderModel {
+        critical_power,
+        anaerobic_work_capacity,
+        max_power,
+        fatigue_coefficient: 0.0,
+    }
+}
+
 pub fn max_power(rider_model: &RiderModel, current_anaerobic_reserve: f64) -> f64 {
     return rider_model.critical_power
         + (rider_model.max_power - rider_model.critical_power) * current_anaerobic_reserve
             / rider_model.anaerobic_work_capacity;
 }
 
+/// Factor applied to the linear anaerobic reserve drawdown `delta_p * duration` above critical power.
+///
+/// The factor is `1 + fatigue_coefficient * x^2` where `x` is the fraction of the way from critical power to
+/// max power, so efforts just above CP deplete the reserve linearly while sprint-level efforts deplete it up
+/// to `1 + fatigue_coefficient` times faster.
+pub fn anaerobic_depletion_factor(rider_model: &RiderModel, input_power: f64) -> f64 {
+    let intensity = (input_power - rider_model.critical_power) / (rider_model.max_power - rider_model.critical_power);
+    1.0 + rider_model.fatigue_coefficient * intensity * intensity
+}
+
 pub fn time_to_exhaustion(
     rider_model: &RiderModel,
     input_power: f64,
@@ -34,9 +82,10 @@ pub fn time_to_exhaustion(
         return f64::MAX;
     }
     let delta_p = input_power - rider_model.critical_power;
-    return current_anaerobic_reserve / delta_p
+    (current_anaerobic_reserve / delta_p
         + rider_model.anaerobic_work_capacity
-            / (rider_model.critical_power - rider_model.max_power);
+            / (rider_model.critical_power - rider_model.max_power))
+        / anaerobic_depletion_factor(rider_model, input_power)
 }
 
 pub fn update_anaerobic_reserve(rider_model: &RiderModel,
@@ -45,9 +94,162 @@ pub fn update_anaerobic_reserve(rider_model: &RiderModel,
     current_anaerobic_reserve: f64) -> f64 {
         let delta_p = input_power - rider_model.critical_power;
         if delta_p > 0.0 {
-            return current_anaerobic_reserve - delta_p * duration;
+            current_anaerobic_reserve
+                - delta_p * duration * anaerobic_depletion_factor(rider_model, input_power)
         }
         else {
             return current_anaerobic_reserve + (rider_model.anaerobic_work_capacity - current_anaerobic_reserve) * (1.0 - f64::exp(delta_p * duration / rider_model.anaerobic_work_capacity));
         }
-}
\ No newline at end of file
+}
+
+/// Fraction of the anaerobic work capacity at which the reserve counts as fully recovered. The recovery is
+/// exponential, so the reserve only tends to the full capacity.
+const RECOVERED_RESERVE_FRACTION: f64 = 0.99;
+
+/// Time in seconds to recover the anaerobic reserve after the finish, riding at `recovery_power` below critical
+/// power, until it reaches `RECOVERED_RESERVE_FRACTION` of the anaerobic work capacity.
+///
+/// Inverts the recovery branch of `update_anaerobic_reserve`. A finish with the reserve already recovered takes
+/// no time, and the time is longest for a finish at zero reserve. At or above critical power the reserve does not
+/// recover, so the time is `f64::INFINITY`.
+///
+/// # Example
+///
+/// ```
+/// let time = recovery_time_to_full(&default_rider_model(), 5000.0, 100.0);
+/// println!("Recovered after {:.0}s", time);
+/// ```
+pub fn recovery_time_to_full(rider_model: &RiderModel, final_reserve: f64, recovery_power: f64) -> f64 {
+    let recovered_reserve = RECOVERED_RESERVE_FRACTION * rider_model.anaerobic_work_capacity;
+    if final_reserve >= recovered_reserve {
+        return 0.0;
+    }
+    if recovery_power >= rider_model.critical_power {
+        return f64::INFINITY;
+    }
+    let delta_p = rider_model.critical_power - recovery_power;
+    rider_model.anaerobic_work_capacity / delta_p
+        * f64::ln(
+            (rider_model.anaerobic_work_capacity - final_reserve)
+                / (rider_model.anaerobic_work_capacity - recovered_reserve),
+        )
+}
+
+impl PowerDurationModel for RiderModel {
+    fn critical_power(&self) -> f64 {
+        self.critical_power
+    }
+
+    fn anaerobic_work_capacity(&self) -> f64 {
+        self.anaerobic_work_capacity
+    }
+
+    fn max_power(&self, current_anaerobic_reserve: f64) -> f64 {
+        max_power(self, current_anaerobic_reserve)
+    }
+
+    fn time_to_exhaustion(&self, input_power: f64, current_anaerobic_reserve: f64) -> f64 {
+        time_to_exhaustion(self, input_power, current_anaerobic_reserve)
+    }
+
+    fn update_anaerobic_reserve(&self, input_power: f64, duration: f64, current_anaerobic_reserve: f64) -> f64 {
+        update_anaerobic_reserve(self, input_power, duration, current_anaerobic_reserve)
+    }
+
+    fn power_duration(&self, duration: f64) -> f64 {
+        self.critical_power
+            + self.anaerobic_work_capacity
+                / (duration + self.anaerobic_work_capacity / (self.max_power - self.critical_power))
+    }
+
+    fn with_critical_power(&self, critical_power: f64) -> Self {
+        RiderModel { critical_power, ..*self }
+    }
+}
+
+/// Intensity zones relative to the critical power.
+///
+/// * `Endurance` - Below 75% of CP.
+/// * `Tempo` - From 75% to 90% of CP.
+/// * `Threshold` - From 90% of CP up to and including CP. Riding exactly at CP does not draw on the
+///   anaerobic reserve, so it is not counted as above CP.
+/// * `AboveCriticalPower` - Strictly above CP, depleting the anaerobic reserve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffortZone {
+    Endurance,
+    Tempo,
+    Threshold,
+    AboveCriticalPower,
+}
+
+pub fn effort_zone(rider_model: &RiderModel, input_power: f64) -> EffortZone {
+    if input_power > rider_model.critical_power {
+        return EffortZone::AboveCriticalPower;
+    }
+    if input_power >= 0.9 * rider_model.critical_power {
+        return EffortZone::Threshold;
+    }
+    if input_power >= 0.75 * rider_model.critical_power {
+        return EffortZone::Tempo;
+    }
+    EffortZone::Endurance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fatigue_depletes_hard_efforts_more_than_the_linear_model() {
+        let rider_model = RiderModel {
+            fatigue_coefficient: 0.5,
+            ..default_rider_model()
+        };
+        let depletion = |input_power: f64, duration: f64| {
+            let linear_depletion = (input_power - rider_model.critical_power) * duration;
+            let reserve = update_anaerobic_reserve(
+                &rider_model,
+                input_power,
+                duration,
+                rider_model.anaerobic_work_capacity,
+            );
+            (rider_model.anaerobic_work_capacity - reserve, linear_depletion)
+        };
+
+        let (hard_depletion, hard_linear_depletion) = depletion(900.0, 10.0);
+        assert!(hard_depletion > 1.2 * hard_linear_depletion);
+        let (mild_depletion, mild_linear_depletion) = depletion(310.0, 120.0);
+        assert!((mild_depletion - mild_linear_depletion).abs() < 1e-3 * mild_linear_depletion);
+    }
+
+    #[test]
+    fn rider_presets_keep_the_model_valid() {
+        for preset in [RiderPreset::Untrained, RiderPreset::Amateur, RiderPreset::Elite, RiderPreset::WorldTour] {
+            let rider_model = rider_preset(preset);
+            assert!(rider_model.max_power > rider_model.critical_power, "{:?}", preset);
+            assert!(rider_model.anaerobic_work_capacity > 0.0, "{:?}", preset);
+        }
+        assert!(rider_preset(RiderPreset::Elite).critical_power > rider_preset(RiderPreset::Amateur).critical_power);
+    }
+
+    #[test]
+    fn deeper_finish_takes_longer_to_recover() {
+        let rider_model = default_rider_model();
+        let capacity = rider_model.anaerobic_work_capacity;
+        let recovery_time = |final_reserve: f64| recovery_time_to_full(&rider_model, final_reserve, 100.0);
+
+        assert_eq!(recovery_time(capacity), 0.0);
+        let shallow = recovery_time(0.75 * capacity);
+        let deep = recovery_time(0.25 * capacity);
+        let empty = recovery_time(0.0);
+        assert!(shallow > 0.0);
+        assert!(deep > shallow);
+        assert!(empty > deep);
+        assert!(empty.is_finite());
+
+        // Recovering for that time brings the reserve back to the recovered fraction.
+        let recovered = update_anaerobic_reserve(&rider_model, 100.0, empty, 0.0);
+        assert!((recovered - RECOVERED_RESERVE_FRACTION * capacity).abs() < 1e-6 * capacity);
+        assert_eq!(recovery_time_to_full(&rider_model, 0.0, rider_model.critical_power), f64::INFINITY);
+    }
+}
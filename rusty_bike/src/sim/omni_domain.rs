@@ -0,0 +1,158 @@
+// Omni-domain power-duration model
+// A power-duration model fitting both sprint and long durations
+// M. J. PUCHOWICZ, J. BAKER, D. C. CLARKE
+
+use crate::sim::power_duration::PowerDurationModel;
+use roots::{find_root_brent, SimpleConvergency};
+
+/// Longest duration in seconds considered when solving for the time to exhaustion.
+const MAX_TIME_TO_EXHAUSTION: f64 = 1e6;
+
+pub struct OmniDomainModel {
+    pub critical_power: f64,
+    pub anaerobic_work_capacity: f64,
+    pub max_power: f64,
+    /// The decline in watts per unit of `ln(duration / critical_power_max_duration)` beyond that duration.
+    pub long_duration_decline: f64,
+    /// The longest duration in seconds for which critical power can be held.
+    pub critical_power_max_duration: f64,
+}
+
+pub const fn default_omni_domain_model() -> OmniDomainModel {
+    OmniDomainModel {
+        critical_power: 300.0,
+        anaerobic_work_capacity: 20000.0,
+        max_power: 1000.0,
+        long_duration_decline: 25.0,
+        critical_power_max_duration: 1800.0,
+    }
+}
+
+/// Maximal power held for `duration` seconds drawing on `anaerobic_reserve` joules.
+///
+/// Up to `critical_power_max_duration` the curve is the exponential severe/extreme domain term above CP. Beyond
+/// it, power keeps declining logarithmically below CP. The log term is zero at the switch, so the curve is
+/// continuous there.
+fn power_with_reserve(model: &OmniDomainModel, duration: f64, anaerobic_reserve: f64) -> f64 {
+    let delta_p_max = model.max_power - model.critical_power;
+    let mut power = model.critical_power
+        + anaerobic_reserve / duration
+            * (1.0 - f64::exp(-duration * delta_p_max / model.anaerobic_work_capacity));
+    if duration > model.critical_power_max_duration {
+        power -= model.long_duration_decline * f64::ln(duration / model.critical_power_max_duration);
+    }
+    power
+}
+
+pub fn max_power(model: &OmniDomainModel, current_anaerobic_reserve: f64) -> f64 {
+    model.critical_power
+        + (model.max_power - model.critical_power) * current_anaerobic_reserve / model.anaerobic_work_capacity
+}
+
+pub fn time_to_exhaustion(model: &OmniDomainModel, input_power: f64, current_anaerobic_reserve: f64) -> f64 {
+    if input_power >= max_power(model, current_anaerobic_reserve) {
+        return 0.0;
+    }
+    let excess_power = |duration: f64| power_with_reserve(model, duration, current_anaerobic_reserve) - input_power;
+    if excess_power(MAX_TIME_TO_EXHAUSTION) >= 0.0 {
+        return f64::MAX;
+    }
+    let mut convergency = SimpleConvergency {
+        eps: 1e-9,
+        max_iter: 100,
+    };
+    find_root_brent(0.0, MAX_TIME_TO_EXHAUSTION, |duration: f64| {
+        if duration <= 0.0 {
+            return max_power(model, current_anaerobic_reserve) - input_power;
+        }
+        excess_power(duration)
+    }, &mut convergency)
+    .unwrap_or(0.0)
+}
+
+pub fn update_anaerobic_reserve(
+    model: &OmniDomainModel,
+    input_power: f64,
+    duration: f64,
+    current_anaerobic_reserve: f64,
+) -> f64 {
+    let delta_p = input_power - model.critical_power;
+    if delta_p > 0.0 {
+        return current_anaerobic_reserve - delta_p * duration;
+    }
+    current_anaerobic_reserve
+        + (model.anaerobic_work_capacity - current_anaerobic_reserve)
+            * (1.0 - f64::exp(delta_p * duration / model.anaerobic_work_capacity))
+}
+
+impl PowerDurationModel for OmniDomainModel {
+    fn critical_power(&self) -> f64 {
+        self.critical_power
+    }
+
+    fn anaerobic_work_capacity(&self) -> f64 {
+        self.anaerobic_work_capacity
+    }
+
+    fn max_power(&self, current_anaerobic_reserve: f64) -> f64 {
+        max_power(self, current_anaerobic_reserve)
+    }
+
+    fn time_to_exhaustion(&self, input_power: f64, current_anaerobic_reserve: f64) -> f64 {
+        time_to_exhaustion(self, input_power, current_anaerobic_reserve)
+    }
+
+    fn update_anaerobic_reserve(&self, input_power: f64, duration: f64, current_anaerobic_reserve: f64) -> f64 {
+        update_anaerobic_reserve(self, input_power, duration, current_anaerobic_reserve)
+    }
+
+    fn power_duration(&self, duration: f64) -> f64 {
+        power_with_reserve(self, duration, self.anaerobic_work_capacity)
+    }
+
+    fn with_critical_power(&self, critical_power: f64) -> Self {
+        OmniDomainModel { critical_power, ..*self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+    use crate::sim::root_finding;
+
+    #[test]
+    fn power_duration_is_below_morton_at_sprint_durations() {
+        // With the same parameters the omni-domain curve is above Morton's everywhere, so compare the shapes of
+        // curves agreeing on critical power, W' and the one-minute power, as fitted to the same rider.
+        let rider_model = morton::default_rider_model();
+        let one_minute_power = rider_model.power_duration(60.0);
+        let model_with_max_power = |max_power: f64| OmniDomainModel {
+            max_power,
+            ..default_omni_domain_model()
+        };
+        let solution = root_finding::bisect(
+            |max_power| model_with_max_power(max_power).power_duration(60.0) - one_minute_power,
+            rider_model.critical_power + 1.0,
+            rider_model.max_power,
+            &root_finding::default_tolerance(),
+        );
+        assert!(solution.converged);
+        let omni_domain_model = model_with_max_power(solution.value);
+
+        let durations = [1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 1200.0, 3600.0];
+        for pair in durations.windows(2) {
+            assert!(omni_domain_model.power_duration(pair[1]) < omni_domain_model.power_duration(pair[0]));
+            assert!(rider_model.power_duration(pair[1]) < rider_model.power_duration(pair[0]));
+        }
+        for duration in [1.0, 5.0, 10.0, 15.0] {
+            assert!(
+                omni_domain_model.power_duration(duration) < rider_model.power_duration(duration),
+                "at {}s",
+                duration
+            );
+        }
+        // Past `critical_power_max_duration` the omni-domain power also falls below critical power.
+        assert!(omni_domain_model.power_duration(3600.0) < rider_model.critical_power);
+    }
+}
@@ -0,0 +1,1592 @@
+use crate::sim::heart_rate;
+use crate::sim::morton;
+use crate::sim::root_finding;
+use crate::sim::simulation;
+use crate::sim::workout;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Number of times the constant depletion pacer re-plans the course with the updated total time estimate.
+const CONSTANT_DEPLETION_ITERATIONS: usize = 10;
+
+/// Settings for the dynamic programming pacer.
+///
+/// # Fields
+///
+/// * `reserve_buckets` - The number of buckets the anaerobic reserve is discretized into. States whose reserve
+///   and velocity fall into the same buckets are merged, so a coarse resolution can make the pacing suboptimal.
+/// * `velocity_bucket_width` - The width in meters per second of the buckets the velocity is discretized into,
+///   so that a state keeping more speed into the next segment is not merged with a slower one of lower cost.
+/// * `power_levels` - The number of candidate power levels tried on each segment, evenly spaced between
+///   `min_power` and the rider's max power.
+/// * `min_power` - The lowest candidate power in watts.
+/// * `smoothness_weight` - The penalty in seconds per squared watt of power change between consecutive
+///   segments. Zero gives the raw time-optimal plan; larger values give smoother, more rideable plans. The
+///   penalty is soft, so a large power jump is still taken where it saves enough time (e.g. at the foot of a
+///   steep climb).
+pub struct DynamicPacerSettings {
+    pub reserve_buckets: usize,
+    pub velocity_bucket_width: f64,
+    pub power_levels: usize,
+    pub min_power: f64,
+    pub smoothness_weight: f64,
+}
+
+/// Returns default settings for the dynamic programming pacer.
+///
+/// # Example
+///
+/// ```
+/// let settings = default_dynamic_pacer_settings();
+/// ```
+pub const fn default_dynamic_pacer_settings() -> DynamicPacerSettings {
+    DynamicPacerSettings {
+        reserve_buckets: 20,
+        velocity_bucket_width: 0.5,
+        power_levels: 10,
+        min_power: 100.0,
+        smoothness_weight: 0.0,
+    }
+}
+
+/// The (reserve bucket, velocity bucket) a pacer state is merged into.
+type PacerBucket = (usize, usize);
+
+/// Best known way of reaching a given (segment, reserve bucket, velocity bucket) state.
+#[derive(Clone, Copy)]
+struct PacerState {
+    cost: f64,
+    time: f64,
+    velocity: f64,
+    anaerobic_reserve: f64,
+    previous_bucket: PacerBucket,
+    power: f64,
+}
+
+fn pacer_bucket(
+    anaerobic_reserve: f64,
+    velocity: f64,
+    rider_model: &morton::RiderModel,
+    settings: &DynamicPacerSettings,
+) -> PacerBucket {
+    let ratio = f64::clamp(anaerobic_reserve / rider_model.anaerobic_work_capacity, 0.0, 1.0);
+    let velocity_bucket = f64::floor(f64::max(0.0, velocity) / settings.velocity_bucket_width) as usize;
+    (f64::floor(ratio * settings.reserve_buckets as f64) as usize, velocity_bucket)
+}
+
+fn candidate_powers(rider_model: &morton::RiderModel, settings: &DynamicPacerSettings) -> Vec<f64> {
+    let mut powers: Vec<f64> = (0..settings.power_levels)
+        .map(|k| {
+            let ratio = k as f64 / f64::max(1.0, (settings.power_levels - 1) as f64);
+            settings.min_power + ratio * (rider_model.max_power - settings.min_power)
+        })
+        .collect();
+    // Riding at CP must always be an option so the pacer never does worse than the constant-CP plan.
+    powers.push(rider_model.critical_power);
+    powers
+}
+
+/// Finds the minimum-time power plan by dynamic programming over (segment, anaerobic reserve bucket, velocity
+/// bucket) states.
+///
+/// Each transition rides one segment at a candidate power, using `compute_time_and_final_velocity` for its
+/// duration and the Morton model for the reserve update. Powers the rider cannot hold until the end of the
+/// segment, as given by `time_to_exhaustion`, are rejected. When `smoothness_weight` is set, the minimized cost is the
+/// total time plus the weighted sum of squared power changes between consecutive segments.
+///
+/// Merging the states of a bucket is an approximation, so the plan found is checked with `simulation::simulate`:
+/// if the rider cannot ride it as planned, or if it is slower than riding at critical power throughout, the
+/// constant critical power plan is returned instead. The pacer thus never does worse than the constant-CP plan.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `initial_anaerobic_reserve` - The anaerobic reserve at the start of the course in joules.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `settings` - The discretization settings.
+/// * `out_power_vec` - Filled with the optimal power of each segment.
+///
+/// # Returns
+///
+/// * `f64` - The total time of the returned plan in seconds as simulated (excluding the smoothness penalty), or
+///   `f64::INFINITY` if neither the optimal plan nor the constant-CP plan is feasible.
+pub fn optimize_dynamic_programming(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    settings: &DynamicPacerSettings,
+    out_power_vec: &mut Vec<f64>,
+) -> f64 {
+    let n_segments = road_segment_vec.len();
+    let powers = candidate_powers(rider_model, settings);
+
+    let mut states: Vec<BTreeMap<PacerBucket, PacerState>> = Vec::with_capacity(n_segments + 1);
+    let initial_bucket = pacer_bucket(initial_anaerobic_reserve, initial_velocity, rider_model, settings);
+    states.push(BTreeMap::from([(
+        initial_bucket,
+        PacerState {
+            cost: 0.0,
+            time: 0.0,
+            velocity: initial_velocity,
+            anaerobic_reserve: initial_anaerobic_reserve,
+            previous_bucket: initial_bucket,
+            power: 0.0,
+        },
+    )]));
+
+    for i in 0..n_segments {
+        let mut next_states: BTreeMap<PacerBucket, PacerState> = BTreeMap::new();
+        for (&bucket, state) in states[i].iter() {
+            for &power in powers.iter() {
+                let tau = morton::time_to_exhaustion(rider_model, power, state.anaerobic_reserve);
+                if tau <= 0.0 {
+                    continue;
+                }
+                let (duration, velocity) = simulation::compute_time_and_final_velocity(
+                    state.velocity,
+                    power,
+                    &road_segment_vec[i],
+                    resistance_model,
+                );
+                if tau < duration {
+                    continue;
+                }
+                let anaerobic_reserve =
+                    morton::update_anaerobic_reserve(rider_model, power, duration, state.anaerobic_reserve);
+                let time = state.time + duration;
+                let mut cost = state.cost + duration;
+                if i > 0 {
+                    cost += settings.smoothness_weight * f64::powi(power - state.power, 2);
+                }
+                let next_bucket = pacer_bucket(anaerobic_reserve, velocity, rider_model, settings);
+                let is_better = match next_states.get(&next_bucket) {
+                    Some(best) => cost < best.cost,
+                    None => true,
+                };
+                if is_better {
+                    next_states.insert(
+                        next_bucket,
+                        PacerState {
+                            cost,
+                            time,
+                            velocity,
+                            anaerobic_reserve,
+                            previous_bucket: bucket,
+                            power,
+                        },
+                    );
+                }
+            }
+        }
+        states.push(next_states);
+    }
+
+    // Total time of a plan as simulated, infinite if the rider cannot ride it as planned.
+    let simulated_time = |power_vec: &Vec<f64>| {
+        let result = simulation::simulate(
+            initial_velocity,
+            initial_anaerobic_reserve,
+            power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+        );
+        let is_exhausted = result.power_vec.iter().zip(power_vec.iter()).any(|(ridden, planned)| ridden < planned);
+        if !result.is_feasible() || is_exhausted {
+            return f64::INFINITY;
+        }
+        result.total_time
+    };
+
+    let constant_power_vec = vec![rider_model.critical_power; n_segments];
+    let constant_time = simulated_time(&constant_power_vec);
+    let best_final = states[n_segments].values().min_by(|a, b| a.cost.total_cmp(&b.cost));
+    out_power_vec.clear();
+    out_power_vec.resize(n_segments, 0.0);
+    if let Some(final_state) = best_final {
+        let mut state = *final_state;
+        for i in (0..n_segments).rev() {
+            out_power_vec[i] = state.power;
+            if i > 0 {
+                state = states[i][&state.previous_bucket];
+            }
+        }
+        let optimized_time = simulated_time(out_power_vec);
+        if optimized_time <= constant_time {
+            return optimized_time;
+        }
+    }
+    out_power_vec.copy_from_slice(&constant_power_vec);
+    constant_time
+}
+
+/// Finds a power plan that depletes the anaerobic reserve (W'bal) at a constant rate over time, so the rider
+/// reaches the finish with an empty reserve and an even stress.
+///
+/// On each segment, the pacer picks the highest power that leaves at least the reserve budgeted for the end of
+/// the segment, i.e. the current reserve drawn down linearly to zero over the estimated remaining time. The
+/// budget is recomputed from the actual reserve on every segment, so the reserve regained on recovery segments
+/// (where even the lowest power is below critical power) is spread over the rest of the course. The total
+/// time estimate starts from riding at critical power and is refined by re-planning the course a few times.
+/// When even the lowest power exhausts the rider on some segment, the course cannot be ridden and the plan is
+/// only a placeholder.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `initial_anaerobic_reserve` - The anaerobic reserve at the start of the course in joules.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `settings` - The pacer settings, of which only `min_power` is used as the lowest power tried.
+/// * `tolerance` - The stopping criterion of the bisection on the power of each segment.
+/// * `out_power_vec` - Filled with the power of each segment.
+///
+/// # Returns
+///
+/// * `f64` - The total time of the plan in seconds, `f64::INFINITY` if the course cannot be ridden.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize_constant_depletion(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    settings: &DynamicPacerSettings,
+    tolerance: &root_finding::Tolerance,
+    out_power_vec: &mut Vec<f64>,
+) -> f64 {
+    let n_segments = road_segment_vec.len();
+    out_power_vec.clear();
+    out_power_vec.resize(n_segments, rider_model.critical_power);
+
+    let mut total_time = 0.0;
+    let mut velocity = initial_velocity;
+    for road_segment in road_segment_vec.iter() {
+        let (duration, final_velocity) = simulation::compute_time_and_final_velocity(
+            velocity,
+            rider_model.critical_power,
+            road_segment,
+            resistance_model,
+        );
+        total_time += duration;
+        velocity = final_velocity;
+    }
+
+    let mut is_feasible = true;
+    for _ in 0..CONSTANT_DEPLETION_ITERATIONS {
+        let estimated_total_time = total_time;
+        is_feasible = true;
+        let mut time = 0.0;
+        let mut velocity = initial_velocity;
+        let mut anaerobic_reserve = initial_anaerobic_reserve;
+        for i in 0..n_segments {
+            let ride = |power: f64| {
+                let (duration, final_velocity) = simulation::compute_time_and_final_velocity(
+                    velocity,
+                    power,
+                    &road_segment_vec[i],
+                    resistance_model,
+                );
+                let remaining_time = estimated_total_time - time;
+                let budget = if remaining_time > duration {
+                    anaerobic_reserve * (1.0 - duration / remaining_time)
+                } else {
+                    0.0
+                };
+                let final_reserve = morton::update_anaerobic_reserve(rider_model, power, duration, anaerobic_reserve);
+                let is_exhausted = morton::time_to_exhaustion(rider_model, power, anaerobic_reserve) < duration;
+                let is_within_budget = final_reserve >= budget && !is_exhausted;
+                (is_within_budget, is_exhausted, duration, final_velocity, final_reserve)
+            };
+
+            let min_power = f64::max(
+                settings.min_power,
+                simulation::minimum_power_to_move(&road_segment_vec[i], resistance_model),
+            );
+            let max_power = f64::max(min_power, rider_model.max_power);
+            let power = root_finding::bisect_threshold(|power| ride(power).0, min_power, max_power, tolerance).value;
+            let (_, is_exhausted, duration, final_velocity, final_reserve) = ride(power);
+            is_feasible &= !is_exhausted;
+            out_power_vec[i] = power;
+            time += duration;
+            velocity = final_velocity;
+            anaerobic_reserve = final_reserve;
+        }
+        total_time = time;
+    }
+    if !is_feasible {
+        return f64::INFINITY;
+    }
+    total_time
+}
+
+/// Rides the segments of the course in order at the given powers.
+///
+/// # Returns
+///
+/// * `Option<(f64, f64, f64)>` - The time, final velocity and final anaerobic reserve, or `None` if the rider
+///   is exhausted before the end of a segment.
+fn ride_segments(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    road_segment_vec: &[simulation::RoadSegment],
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    power_of: impl Fn(usize) -> f64,
+) -> Option<(f64, f64, f64)> {
+    let mut time = 0.0;
+    let mut velocity = initial_velocity;
+    let mut anaerobic_reserve = initial_anaerobic_reserve;
+    for (i, road_segment) in road_segment_vec.iter().enumerate() {
+        let power = power_of(i);
+        let (duration, final_velocity) =
+            simulation::compute_time_and_final_velocity(velocity, power, road_segment, resistance_model);
+        if morton::time_to_exhaustion(rider_model, power, anaerobic_reserve) < duration {
+            return None;
+        }
+        time += duration;
+        velocity = final_velocity;
+        anaerobic_reserve = morton::update_anaerobic_reserve(rider_model, power, duration, anaerobic_reserve);
+    }
+    Some((time, velocity, anaerobic_reserve))
+}
+
+/// Finds how to split the anaerobic reserve between two climbs to minimize the total time.
+///
+/// This is the classic two-climb sub-problem of pacing, small enough to be solved by search and so useful to
+/// validate the general optimizer. Each climb is ridden at a constant power above critical power and the rest
+/// of the course at critical power, which neither depletes nor recovers the reserve. For a given fraction of the
+/// reserve spent on the first climb, the power of each climb is found by bisection so that the climb spends
+/// its budget (or as much of it as the rider can while holding the power to the end of the climb). The
+/// fraction is then optimized by golden-section search. Steeper climbs, where the rider is slower and aero
+/// losses are smaller, get proportionally more of the reserve.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `initial_anaerobic_reserve` - The anaerobic reserve at the start of the course in joules.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `climb_ranges` - The segment indices of the first and second climbs, the first before the second.
+/// * `tolerance` - The stopping criterion of the bisection on the power of each climb and of the search on the
+///   split.
+/// * `out_power_vec` - Filled with the power of each segment.
+///
+/// # Returns
+///
+/// * `Result<f64, String>` - The total time of the best split in seconds, or an error if a climb is empty, past
+///   the end of the course or not before the second one, or if the rider cannot ride the course at critical
+///   power outside the climbs.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize_two_climbs(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    climb_ranges: [Range<usize>; 2],
+    tolerance: &root_finding::Tolerance,
+    out_power_vec: &mut Vec<f64>,
+) -> Result<f64, String> {
+    let critical_power = rider_model.critical_power;
+    let [first_climb, second_climb] = climb_ranges;
+    if first_climb.is_empty() || second_climb.is_empty() {
+        return Err(format!("empty climb in {:?} and {:?}", first_climb, second_climb));
+    }
+    if first_climb.end > second_climb.start {
+        return Err(format!("climb {:?} is not before climb {:?}", first_climb, second_climb));
+    }
+    if second_climb.end > road_segment_vec.len() {
+        return Err(format!(
+            "climb {:?} is past the end of the course of {} segments",
+            second_climb,
+            road_segment_vec.len()
+        ));
+    }
+    let plan_power = |i: usize, first_power: f64, second_power: f64| {
+        if first_climb.contains(&i) {
+            return first_power;
+        }
+        if second_climb.contains(&i) {
+            return second_power;
+        }
+        critical_power
+    };
+    // Highest power on the climb at `climb_range` leaving at least `target_reserve` at its end, given the
+    // velocity and reserve at its start.
+    let climb_power = |velocity: f64, anaerobic_reserve: f64, climb_range: &Range<usize>, target_reserve: f64| {
+        let is_within_budget = |power: f64| {
+            let ride = ride_segments(
+                velocity,
+                anaerobic_reserve,
+                &road_segment_vec[climb_range.clone()],
+                resistance_model,
+                rider_model,
+                |_| power,
+            );
+            matches!(ride, Some((_, _, final_reserve)) if final_reserve >= target_reserve)
+        };
+        root_finding::bisect_threshold(is_within_budget, critical_power, rider_model.max_power, tolerance).value
+    };
+    // Powers of both climbs when spending `split` of the reserve on the first one.
+    let climb_powers = |split: f64| {
+        let (_, velocity, anaerobic_reserve) = ride_segments(
+            initial_velocity,
+            initial_anaerobic_reserve,
+            &road_segment_vec[..first_climb.start],
+            resistance_model,
+            rider_model,
+            |_| critical_power,
+        )?;
+        let first_power =
+            climb_power(velocity, anaerobic_reserve, &first_climb, (1.0 - split) * initial_anaerobic_reserve);
+        let (_, velocity, anaerobic_reserve) = ride_segments(
+            velocity,
+            anaerobic_reserve,
+            &road_segment_vec[first_climb.start..second_climb.start],
+            resistance_model,
+            rider_model,
+            |i| plan_power(first_climb.start + i, first_power, critical_power),
+        )?;
+        let second_power = climb_power(velocity, anaerobic_reserve, &second_climb, 0.0);
+        Some((first_power, second_power))
+    };
+    let total_time = |split: f64| {
+        let (first_power, second_power) = climb_powers(split)?;
+        let (time, _, _) = ride_segments(
+            initial_velocity,
+            initial_anaerobic_reserve,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+            |i| plan_power(i, first_power, second_power),
+        )?;
+        Some(time)
+    };
+    let infeasible = || "the rider cannot ride the course at critical power outside the climbs".to_string();
+
+    let split = root_finding::golden_section_minimize(
+        |split| total_time(split).unwrap_or(f64::INFINITY),
+        0.0,
+        1.0,
+        tolerance,
+    )
+    .value;
+    let (first_power, second_power) = climb_powers(split).ok_or_else(infeasible)?;
+    out_power_vec.clear();
+    out_power_vec.extend((0..road_segment_vec.len()).map(|i| plan_power(i, first_power, second_power)));
+    total_time(split).ok_or_else(infeasible)
+}
+
+/// Finds the opening surge that minimizes the time of a short prologue from a standing start.
+///
+/// From a standstill the rider spends a large share of a short effort accelerating, so a surge over the opening
+/// meters pays off, but the anaerobic reserve it spends is missing at the line. The plan has two powers: one
+/// over the opening segments, the ones starting before `opening_length` meters, and one over the rest of the
+/// course. For a given opening power, the power of the rest is the highest one that still reaches the line
+/// with a non-negative reserve, found by bisection; the opening power is then optimized by golden-section
+/// search between critical power and max power.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start in meters per second, 0 for a standing start.
+/// * `initial_anaerobic_reserve` - The anaerobic reserve at the start in joules.
+/// * `road_segment_vec` - The segments of the prologue, short enough at the start to resolve the opening.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `opening_length` - The length of the opening in meters, e.g. 200.
+/// * `tolerance` - The stopping criterion of the bisection on the power after the opening and of the search on
+///   the opening power.
+/// * `out_power_vec` - Filled with the power of each segment.
+///
+/// # Returns
+///
+/// * `f64` - The total time of the best plan in seconds.
+///
+/// # Example
+///
+/// ```
+/// let mut power_vec = Vec::new();
+/// let time = optimize_prologue_start(
+///     0.0,
+///     20000.0,
+///     &road_segment_vec,
+///     &resistance_model,
+///     &rider_model,
+///     300.0,
+///     &root_finding::default_tolerance(),
+///     &mut power_vec,
+/// );
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn optimize_prologue_start(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    opening_length: f64,
+    tolerance: &root_finding::Tolerance,
+    out_power_vec: &mut Vec<f64>,
+) -> f64 {
+    let mut opening_end = 0;
+    let mut opening_start_distance = 0.0;
+    while opening_end < road_segment_vec.len() && opening_start_distance < opening_length {
+        opening_start_distance += road_segment_vec[opening_end].length;
+        opening_end += 1;
+    }
+    let (opening_segment_vec, rest_segment_vec) = road_segment_vec.split_at(opening_end);
+    // Highest power over the rest of the course reaching the line with a non-negative reserve, and the time.
+    let rest_plan = |velocity: f64, anaerobic_reserve: f64| {
+        let ride_rest = |power: f64| {
+            ride_segments(
+                velocity,
+                anaerobic_reserve,
+                rest_segment_vec,
+                resistance_model,
+                rider_model,
+                |_| power,
+            )
+        };
+        let reaches_the_line =
+            |power: f64| matches!(ride_rest(power), Some((_, _, final_reserve)) if final_reserve >= 0.0);
+        let critical_power = rider_model.critical_power;
+        let rest_power =
+            root_finding::bisect_threshold(reaches_the_line, critical_power, rider_model.max_power, tolerance).value;
+        let rest_time = ride_rest(rest_power).map_or(f64::INFINITY, |(time, _, _)| time);
+        (rest_power, rest_time)
+    };
+    // Rest power and total time when opening at `opening_power`, infinite if the rider cannot hold it.
+    let plan = |opening_power: f64| {
+        let opening = ride_segments(
+            initial_velocity,
+            initial_anaerobic_reserve,
+            opening_segment_vec,
+            resistance_model,
+            rider_model,
+            |_| opening_power,
+        );
+        let Some((opening_time, velocity, anaerobic_reserve)) = opening else {
+            return (rider_model.critical_power, f64::INFINITY);
+        };
+        let (rest_power, rest_time) = rest_plan(velocity, anaerobic_reserve);
+        (rest_power, opening_time + rest_time)
+    };
+
+    let opening_power = root_finding::golden_section_minimize(
+        |opening_power| plan(opening_power).1,
+        rider_model.critical_power,
+        rider_model.max_power,
+        tolerance,
+    )
+    .value;
+    let (rest_power, total_time) = plan(opening_power);
+    out_power_vec.clear();
+    out_power_vec.extend((0..road_segment_vec.len()).map(|i| if i < opening_end { opening_power } else { rest_power }));
+    total_time
+}
+
+/// The pacing of a mountain pass, see `optimize_pass`.
+///
+/// # Fields
+///
+/// * `climb_power` - The power up to the summit in watts.
+/// * `descent_power` - The power from the summit to the finish in watts.
+/// * `total_time` - The total time in seconds, `f64::INFINITY` if the rider cannot even hold critical power up
+///   the climb.
+/// * `power_vec` - The power of each segment in watts.
+pub struct PassPlan {
+    pub climb_power: f64,
+    pub descent_power: f64,
+    pub total_time: f64,
+    pub power_vec: Vec<f64>,
+}
+
+/// Finds how to pace a mountain pass: a climb followed by a descent.
+///
+/// The anaerobic reserve spent climbing is hardly recovered on the way down, where the rider is fast, so extra
+/// power buys little time, and often held back by the corners. The plan has two powers: one up to the summit and
+/// one from it to the finish. For a given climbing power, the descending power is the highest one the rider can
+/// hold to the finish, found by bisection: below critical power on a short descent, so the reserve recovers
+/// while they spin, and possibly above it on a descent long enough to recover. The climbing power is then
+/// optimized by golden-section search between critical power and max power. The segments are ridden with
+/// `simulation::simulate_with_options`, so the corners and finish speed of `options` cap the descent speed; the
+/// powers stay within the floor and cap of `options`, and the bisection and search stop at its tolerance.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the foot of the climb in meters per second.
+/// * `initial_anaerobic_reserve` - The anaerobic reserve at the foot of the climb in joules.
+/// * `road_segment_vec` - The segments of the pass.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `summit_index` - The index of the first segment of the descent.
+/// * `options` - The simulation options, e.g. the corners of the descent.
+///
+/// # Returns
+///
+/// * `PassPlan` - The best plan.
+///
+/// # Example
+///
+/// ```
+/// let plan = optimize_pass(
+///     5.0,
+///     20000.0,
+///     &road_segment_vec,
+///     &resistance_model,
+///     &rider_model,
+///     summit_index,
+///     &simulation::default_simulation_options(),
+/// );
+/// println!("Climb at {:.0}W, descend at {:.0}W", plan.climb_power, plan.descent_power);
+/// ```
+pub fn optimize_pass(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    summit_index: usize,
+    options: &simulation::SimulationOptions,
+) -> PassPlan {
+    let max_power =
+        options.power_cap.map_or(rider_model.max_power, |power_cap| f64::min(power_cap, rider_model.max_power));
+    let plan_power_vec = |climb_power: f64, descent_power: f64| {
+        (0..road_segment_vec.len())
+            .map(|i| if i < summit_index { climb_power } else { descent_power })
+            .collect::<Vec<f64>>()
+    };
+    // Total time of the plan, infinite if the rider cannot hold it to the finish.
+    let ride = |climb_power: f64, descent_power: f64| {
+        let power_vec = plan_power_vec(climb_power, descent_power);
+        let result = simulation::simulate_with_options(
+            initial_velocity,
+            initial_anaerobic_reserve,
+            &power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+            options,
+        );
+        let is_exhausted = result.power_vec.iter().zip(power_vec.iter()).any(|(ridden, planned)| ridden < planned);
+        if !result.is_feasible() || is_exhausted {
+            return f64::INFINITY;
+        }
+        result.total_time
+    };
+    // Highest descending power the rider can hold after climbing at `climb_power`, and the total time.
+    let plan = |climb_power: f64| {
+        if ride(climb_power, options.power_floor) == f64::INFINITY {
+            return (options.power_floor, f64::INFINITY);
+        }
+        let is_holdable = |descent_power: f64| ride(climb_power, descent_power) < f64::INFINITY;
+        let descent_power =
+            root_finding::bisect_threshold(is_holdable, options.power_floor, max_power, &options.tolerance).value;
+        (descent_power, ride(climb_power, descent_power))
+    };
+
+    // Too high a climbing power cannot be held to the finish at all, so ties move towards lower powers.
+    let climb_power = root_finding::golden_section_minimize(
+        |climb_power| plan(climb_power).1,
+        rider_model.critical_power,
+        max_power,
+        &options.tolerance,
+    )
+    .value;
+    let (descent_power, total_time) = plan(climb_power);
+    PassPlan {
+        climb_power,
+        descent_power,
+        total_time,
+        power_vec: plan_power_vec(climb_power, descent_power),
+    }
+}
+
+/// The pacing of a downhill finish, see `optimize_downhill_finish`.
+///
+/// # Fields
+///
+/// * `total_time` - The time to the finish line in seconds.
+/// * `power_vec` - The power of each segment in watts, zero where the rider coasts.
+pub struct DownhillFinishPlan {
+    pub total_time: f64,
+    pub power_vec: Vec<f64>,
+}
+
+/// Finds where to pedal on a downhill finish, given the top speed of the gearing.
+///
+/// Pedaling only helps below the spin-out speed: above it the cadence would exceed what the rider can turn, so
+/// extra power does nothing and the rider tucks and coasts. The target speed is thus the spin-out speed, or the
+/// speed the rider reaches at `power_budget` if lower. The segments are ridden in order, and on each one the
+/// rider pedals at the highest power up to `power_budget` that they can hold to the end of the segment and that
+/// does not take them above the spin-out speed, found by bisection. On a segment where even coasting ends above
+/// the spin-out speed the power is zero, so the plan pedals out of the corners and on the flatter run-in, and
+/// coasts where the descent is steep. Splitting the descent into short segments places the point where the
+/// rider stops pedaling more precisely.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the top of the descent in meters per second.
+/// * `initial_anaerobic_reserve` - The anaerobic reserve at the top of the descent in joules.
+/// * `power_budget` - The highest power the rider is willing to pedal at in watts.
+/// * `road_segment_vec` - The segments of the descent, up to the finish line.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `spin_out_velocity` - The velocity in meters per second above which pedaling does not help, see
+///   `gearing::Gearing::spin_out_velocity`.
+/// * `tolerance` - The stopping criterion of the bisection on the power of each segment.
+///
+/// # Returns
+///
+/// * `DownhillFinishPlan` - Where to pedal and the time to the finish line.
+///
+/// # Example
+///
+/// ```
+/// let spin_out_velocity = gearing.spin_out_velocity(120.0);
+/// let plan = optimize_downhill_finish(
+///     15.0,
+///     10000.0,
+///     500.0,
+///     &road_segment_vec,
+///     &resistance_model,
+///     &rider_model,
+///     spin_out_velocity,
+///     &root_finding::default_tolerance(),
+/// );
+/// println!("{:.1}s to the line", plan.total_time);
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn optimize_downhill_finish(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    power_budget: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    spin_out_velocity: f64,
+    tolerance: &root_finding::Tolerance,
+) -> DownhillFinishPlan {
+    let mut power_vec = Vec::with_capacity(road_segment_vec.len());
+    let mut total_time = 0.0;
+    let mut velocity = initial_velocity;
+    let mut anaerobic_reserve = initial_anaerobic_reserve;
+    for road_segment in road_segment_vec.iter() {
+        let ride = |power: f64| {
+            simulation::compute_time_and_final_velocity(velocity, power, road_segment, resistance_model)
+        };
+        // Pedaling helps as long as it does not end the segment faster than both the spin-out speed and coasting.
+        let velocity_limit = f64::max(spin_out_velocity, ride(0.0).1);
+        let is_useful = |power: f64| {
+            let (duration, final_velocity) = ride(power);
+            final_velocity <= velocity_limit
+                && morton::time_to_exhaustion(rider_model, power, anaerobic_reserve) >= duration
+        };
+        let mut power = f64::max(0.0, power_budget);
+        if !is_useful(power) {
+            power = root_finding::bisect_threshold(is_useful, 0.0, power, tolerance).value;
+        }
+        let (duration, final_velocity) = ride(power);
+        power_vec.push(power);
+        total_time += duration;
+        velocity = final_velocity;
+        anaerobic_reserve = morton::update_anaerobic_reserve(rider_model, power, duration, anaerobic_reserve);
+    }
+    DownhillFinishPlan { total_time, power_vec }
+}
+
+/// Lowers a power plan where needed to keep the modeled heart rate below a cap, for riders pacing by a
+/// physiological ceiling (e.g. their threshold heart rate) rather than by their anaerobic reserve.
+///
+/// The segments are ridden in order from the resting heart rate. When the planned power of a segment would
+/// take the heart rate above the cap by its end, the power is lowered by bisection to the highest power that
+/// does not, so on long climbs the plan settles at the power whose steady-state heart rate is the cap, even if
+/// the anaerobic reserve would allow more. The power is never lowered below `minimum_power_to_move`, so the
+/// cap can still be exceeded on segments too steep to ride more easily.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `heart_rate_model` - The heart rate model of the rider.
+/// * `heart_rate_cap` - The heart rate not to exceed in beats per minute.
+/// * `tolerance` - The stopping criterion of the bisection on the power of each segment.
+/// * `power_vec` - The power plan, lowered in place.
+///
+/// # Returns
+///
+/// * `Vec<f64>` - The heart rate at the end of each segment in beats per minute.
+pub fn apply_heart_rate_cap(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    heart_rate_model: &heart_rate::HeartRateModel,
+    heart_rate_cap: f64,
+    tolerance: &root_finding::Tolerance,
+    power_vec: &mut [f64],
+) -> Vec<f64> {
+    let mut velocity = initial_velocity;
+    let mut current_heart_rate = heart_rate_model.resting_heart_rate;
+    let mut heart_rate_vec = Vec::with_capacity(road_segment_vec.len());
+    for (i, road_segment) in road_segment_vec.iter().enumerate() {
+        let ride = |power: f64| {
+            let (duration, final_velocity) =
+                simulation::compute_time_and_final_velocity(velocity, power, road_segment, resistance_model);
+            let heart_rate = heart_rate::update_heart_rate(heart_rate_model, power, duration, current_heart_rate);
+            (final_velocity, heart_rate)
+        };
+
+        let mut outcome = ride(power_vec[i]);
+        if outcome.1 > heart_rate_cap {
+            let min_power = f64::min(
+                power_vec[i],
+                f64::max(0.0, simulation::minimum_power_to_move(road_segment, resistance_model)),
+            );
+            let is_under_cap = |power: f64| ride(power).1 <= heart_rate_cap;
+            power_vec[i] = root_finding::bisect_threshold(is_under_cap, min_power, power_vec[i], tolerance).value;
+            outcome = ride(power_vec[i]);
+        }
+        velocity = outcome.0;
+        current_heart_rate = outcome.1;
+        heart_rate_vec.push(current_heart_rate);
+    }
+    heart_rate_vec
+}
+
+/// The pacing of a hill repeat session, see `optimize_hill_repeats`.
+///
+/// # Fields
+///
+/// * `rep_power` - The power held on every rep in watts.
+/// * `rep_time_vec` - The time of each rep in seconds.
+/// * `anaerobic_reserve_vec` - The anaerobic reserve at the top of each rep in joules, before the recovery.
+pub struct HillRepeatPlan {
+    pub rep_power: f64,
+    pub rep_time_vec: Vec<f64>,
+    pub anaerobic_reserve_vec: Vec<f64>,
+}
+
+/// Finds the highest power a rider can hold on every rep of a hill repeat session and still finish the last rep.
+///
+/// Every rep starts at the foot of the hill at `initial_velocity` and is simulated with
+/// `simulation::compute_all_times`, and the anaerobic reserve recovers between reps at the power of `recovery`.
+/// Since the reserve left for a rep shrinks from one rep to the next, going as hard on the early reps as the
+/// reserve allows would blow up on a later one: the power is found by bisection so that no rep is ridden out of
+/// reserve. The same power on every rep evens out the effort, and since the time of a rep is convex in its power,
+/// it is close to the fastest split of the reserve between identical reps.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the foot of the hill in meters per second.
+/// * `hill_segment_vec` - The segments of the hill.
+/// * `repetitions` - The number of reps.
+/// * `recovery` - The recovery between two reps, e.g. rolling back down the hill.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model, starting the session with a full anaerobic reserve.
+/// * `tolerance` - The stopping criterion of the bisection on the power of the reps.
+///
+/// # Returns
+///
+/// * `Option<HillRepeatPlan>` - The pacing of the session, or `None` if the rider cannot get up the hill
+///   `repetitions` times even at the lowest power that moves them up every segment.
+///
+/// # Example
+///
+/// ```
+/// let recovery = workout::Interval { duration: 240.0, power: 100.0 };
+/// let plan = optimize_hill_repeats(
+///     4.0,
+///     &hill_segment_vec,
+///     6,
+///     recovery,
+///     &resistance_model,
+///     &rider_model,
+///     &root_finding::default_tolerance(),
+/// );
+/// ```
+pub fn optimize_hill_repeats(
+    initial_velocity: f64,
+    hill_segment_vec: &Vec<simulation::RoadSegment>,
+    repetitions: usize,
+    recovery: workout::Interval,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    tolerance: &root_finding::Tolerance,
+) -> Option<HillRepeatPlan> {
+    let n_segments = hill_segment_vec.len();
+    let mut outputs = simulation::SimulationOutputs::new();
+    let mut ride_session = |rep_power: f64| {
+        let mut plan = HillRepeatPlan {
+            rep_power,
+            rep_time_vec: Vec::with_capacity(repetitions),
+            anaerobic_reserve_vec: Vec::with_capacity(repetitions),
+        };
+        let mut anaerobic_reserve = rider_model.anaerobic_work_capacity;
+        for rep in 0..repetitions {
+            if rep > 0 {
+                anaerobic_reserve = morton::update_anaerobic_reserve(
+                    rider_model,
+                    recovery.power,
+                    recovery.duration,
+                    anaerobic_reserve,
+                );
+            }
+            let rep_time = simulation::compute_all_times(
+                initial_velocity,
+                anaerobic_reserve,
+                &vec![rep_power; n_segments],
+                hill_segment_vec,
+                resistance_model,
+                rider_model,
+                &mut outputs,
+            );
+            // The simulation drops the power to critical power where the rider is exhausted.
+            let is_exhausted = outputs.power_vec.iter().any(|&power| power < rep_power)
+                || outputs.anaerobic_reserve_vec.last().is_some_and(|&reserve| reserve < 0.0);
+            if is_exhausted {
+                return None;
+            }
+            anaerobic_reserve = outputs.anaerobic_reserve_vec.last().copied().unwrap_or(anaerobic_reserve);
+            plan.rep_time_vec.push(rep_time);
+            plan.anaerobic_reserve_vec.push(anaerobic_reserve);
+        }
+        Some(plan)
+    };
+
+    let min_power = hill_segment_vec
+        .iter()
+        .map(|road_segment| simulation::minimum_power_to_move(road_segment, resistance_model))
+        .fold(0.0, f64::max);
+    ride_session(min_power)?;
+    let max_power = f64::max(min_power, rider_model.max_power);
+    let rep_power =
+        root_finding::bisect_threshold(|power| ride_session(power).is_some(), min_power, max_power, tolerance).value;
+    ride_session(rep_power)
+}
+
+/// The pacing strategies compared by `compare_strategies`.
+///
+/// * `ConstantCriticalPower` - Critical power on every segment, which neither depletes nor recovers the reserve.
+/// * `ConstantDepletion` - An even effort, see `optimize_constant_depletion`.
+/// * `DynamicProgramming` - The time-optimal plan, see `optimize_dynamic_programming`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacingStrategy {
+    ConstantCriticalPower,
+    ConstantDepletion,
+    DynamicProgramming,
+}
+
+/// One row of the comparison of pacing strategies on a course.
+///
+/// # Fields
+///
+/// * `strategy` - The pacing strategy.
+/// * `power_vec` - The power plan of the strategy, one entry per segment, in watts.
+/// * `is_feasible` - Whether the rider completes the course with the plan.
+/// * `total_time` - The total time of the plan in seconds, `f64::INFINITY` when it is not feasible.
+/// * `min_anaerobic_reserve` - The lowest anaerobic reserve at a segment end in joules, over the segments the
+///   rider completed.
+/// * `normalized_power` - The normalized power in watts over the segments the rider completed, see
+///   `SimulationResult::normalized_power`.
+pub struct StrategyComparison {
+    pub strategy: PacingStrategy,
+    pub power_vec: Vec<f64>,
+    pub is_feasible: bool,
+    pub total_time: f64,
+    pub min_anaerobic_reserve: f64,
+    pub normalized_power: f64,
+}
+
+/// Runs every pacing strategy on a course with the default pacer settings and simulates the resulting plans, to
+/// compare them side by side. Strategies that are not feasible on the course are kept in the comparison, flagged
+/// by `is_feasible`.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model, starting with a full anaerobic reserve.
+/// * `tolerance` - The stopping criterion of the bisections of the pacers.
+///
+/// # Returns
+///
+/// * `Vec<StrategyComparison>` - One row per strategy, in the order of `PacingStrategy`.
+///
+/// # Example
+///
+/// ```
+/// let tolerance = root_finding::default_tolerance();
+/// for row in compare_strategies(0.0, &road_segment_vec, &resistance_model, &rider_model, &tolerance).iter() {
+///     println!("{:?}: {:.1}s, NP {:.0}W", row.strategy, row.total_time, row.normalized_power);
+/// }
+/// ```
+pub fn compare_strategies(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &morton::RiderModel,
+    tolerance: &root_finding::Tolerance,
+) -> Vec<StrategyComparison> {
+    let settings = default_dynamic_pacer_settings();
+    let initial_anaerobic_reserve = rider_model.anaerobic_work_capacity;
+    let strategy_vec = [
+        PacingStrategy::ConstantCriticalPower,
+        PacingStrategy::ConstantDepletion,
+        PacingStrategy::DynamicProgramming,
+    ];
+    let mut comparison_vec = Vec::with_capacity(strategy_vec.len());
+    for &strategy in strategy_vec.iter() {
+        let mut power_vec = vec![rider_model.critical_power; road_segment_vec.len()];
+        let planned_time = match strategy {
+            PacingStrategy::ConstantCriticalPower => 0.0,
+            PacingStrategy::ConstantDepletion => optimize_constant_depletion(
+                initial_velocity,
+                initial_anaerobic_reserve,
+                road_segment_vec,
+                resistance_model,
+                rider_model,
+                &settings,
+                tolerance,
+                &mut power_vec,
+            ),
+            PacingStrategy::DynamicProgramming => optimize_dynamic_programming(
+                initial_velocity,
+                initial_anaerobic_reserve,
+                road_segment_vec,
+                resistance_model,
+                rider_model,
+                &settings,
+                &mut power_vec,
+            ),
+        };
+        let result = simulation::simulate(
+            initial_velocity,
+            initial_anaerobic_reserve,
+            &power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+        );
+        // The optimizers return an infinite time when they find no plan, leaving a placeholder plan, and a plan the
+        // rider is exhausted on is not ridden as planned, as in `optimize_dynamic_programming`.
+        let is_exhausted = result.power_vec.iter().zip(power_vec.iter()).any(|(ridden, planned)| ridden < planned);
+        let is_feasible = planned_time.is_finite() && result.is_feasible() && !is_exhausted;
+        let min_anaerobic_reserve = result.anaerobic_reserve_vec[..result.completed_segments()]
+            .iter()
+            .fold(initial_anaerobic_reserve, |min_reserve, &reserve| f64::min(min_reserve, reserve));
+        comparison_vec.push(StrategyComparison {
+            strategy,
+            power_vec,
+            is_feasible,
+            total_time: if is_feasible { result.total_time } else { f64::INFINITY },
+            min_anaerobic_reserve,
+            normalized_power: result.normalized_power(),
+        });
+    }
+    comparison_vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::gearing;
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn dynamic_programming_is_no_slower_than_constant_critical_power() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 250.0,
+            max_slope: 0.06,
+            period: 8,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let constant_power_vec = vec![rider_model.critical_power; road_segment_vec.len()];
+        let constant_time = simulation::simulate(
+            0.0,
+            rider_model.anaerobic_work_capacity,
+            &constant_power_vec,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        )
+        .total_time;
+
+        let mut power_vec = Vec::new();
+        let optimized_time = optimize_dynamic_programming(
+            0.0,
+            rider_model.anaerobic_work_capacity,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &default_dynamic_pacer_settings(),
+            &mut power_vec,
+        );
+
+        assert!(optimized_time <= constant_time, "{} > {}", optimized_time, constant_time);
+        assert_eq!(power_vec.len(), road_segment_vec.len());
+    }
+
+    /// Sum of the absolute power changes between consecutive segments.
+    fn total_variation(power_vec: &[f64]) -> f64 {
+        power_vec.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+    }
+
+    #[test]
+    fn smoothness_weight_reduces_power_variation_at_small_time_cost() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 250.0,
+            max_slope: 0.06,
+            period: 8,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let optimize = |smoothness_weight: f64| {
+            let settings = DynamicPacerSettings {
+                smoothness_weight,
+                ..default_dynamic_pacer_settings()
+            };
+            let mut power_vec = Vec::new();
+            let time = optimize_dynamic_programming(
+                0.0,
+                rider_model.anaerobic_work_capacity,
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                &settings,
+                &mut power_vec,
+            );
+            (time, total_variation(&power_vec))
+        };
+
+        let (raw_time, raw_variation) = optimize(0.0);
+        let (smooth_time, smooth_variation) = optimize(2e-4);
+
+        assert!(smooth_variation < raw_variation);
+        assert!(smooth_time >= raw_time);
+        assert!(smooth_time < 1.02 * raw_time);
+    }
+
+    #[test]
+    fn constant_depletion_drains_the_reserve_linearly() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 20,
+            segment_length: 250.0,
+            max_slope: 0.04,
+            period: 10,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let mut power_vec = Vec::new();
+        optimize_constant_depletion(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &default_dynamic_pacer_settings(),
+            &root_finding::default_tolerance(),
+            &mut power_vec,
+        );
+        let result = simulation::simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &power_vec,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+
+        let mut elapsed_time = 0.0;
+        let mut max_deviation: f64 = 0.0;
+        for (duration, reserve) in result.duration_vec.iter().zip(result.anaerobic_reserve_vec.iter()) {
+            elapsed_time += duration;
+            let linear_reserve = rider_model.anaerobic_work_capacity * (1.0 - elapsed_time / result.total_time);
+            max_deviation = f64::max(max_deviation, (reserve - linear_reserve).abs());
+        }
+        assert!(max_deviation < 0.05 * rider_model.anaerobic_work_capacity, "{}", max_deviation);
+    }
+
+    #[test]
+    fn constant_depletion_reports_a_course_that_cannot_be_ridden() {
+        let wall = make_course(CourseProfile::ConstantClimb {
+            n_segments: 3,
+            segment_length: 100.0,
+            slope: 0.5,
+        });
+        let rider_model = morton::default_rider_model();
+        let mut power_vec = Vec::new();
+        let total_time = optimize_constant_depletion(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &wall,
+            &simulation::default_resistance_model(),
+            &rider_model,
+            &default_dynamic_pacer_settings(),
+            &root_finding::default_tolerance(),
+            &mut power_vec,
+        );
+        assert_eq!(total_time, f64::INFINITY);
+        assert_eq!(power_vec.len(), wall.len());
+    }
+
+    #[test]
+    fn two_climb_solver_agrees_with_the_general_optimizer() {
+        // Flat, a steep climb, flat, a shallow climb, flat.
+        let mut road_segment_vec = Vec::new();
+        for (n_segments, slope) in [(2, 0.0), (3, 0.08), (3, 0.0), (3, 0.03), (1, 0.0)] {
+            road_segment_vec.extend(make_course(CourseProfile::ConstantClimb {
+                n_segments,
+                segment_length: 250.0,
+                slope,
+            }));
+        }
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let initial_anaerobic_reserve = rider_model.anaerobic_work_capacity;
+
+        let mut two_climb_power_vec = Vec::new();
+        let two_climb_time = optimize_two_climbs(
+            8.0,
+            initial_anaerobic_reserve,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            [2..5, 8..11],
+            &root_finding::default_tolerance(),
+            &mut two_climb_power_vec,
+        )
+        .unwrap();
+        let mut optimized_power_vec = Vec::new();
+        let optimized_time = optimize_dynamic_programming(
+            8.0,
+            initial_anaerobic_reserve,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &default_dynamic_pacer_settings(),
+            &mut optimized_power_vec,
+        );
+
+        let relative_difference = (two_climb_time - optimized_time).abs() / optimized_time;
+        assert!(relative_difference < 0.02, "{} s vs {} s", two_climb_time, optimized_time);
+        // The steeper climb gets more of the reserve.
+        let result = simulation::simulate(
+            8.0,
+            initial_anaerobic_reserve,
+            &two_climb_power_vec,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+        let first_climb_spent = initial_anaerobic_reserve - result.anaerobic_reserve_vec[4];
+        let second_climb_spent = result.anaerobic_reserve_vec[7] - result.anaerobic_reserve_vec[10];
+        assert!(first_climb_spent > second_climb_spent, "{} J vs {} J", first_climb_spent, second_climb_spent);
+    }
+
+    #[test]
+    fn two_climb_solver_rejects_invalid_climbs() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 6, segment_length: 250.0 });
+        let rider_model = morton::default_rider_model();
+        let optimize = |climb_ranges: [Range<usize>; 2]| {
+            optimize_two_climbs(
+                8.0,
+                rider_model.anaerobic_work_capacity,
+                &road_segment_vec,
+                &simulation::default_resistance_model(),
+                &rider_model,
+                climb_ranges,
+                &root_finding::default_tolerance(),
+                &mut Vec::new(),
+            )
+        };
+        assert!(optimize([1..1, 3..4]).is_err());
+        assert!(optimize([3..5, 1..2]).is_err());
+        assert!(optimize([1..3, 2..4]).is_err());
+        assert!(optimize([1..3, 4..7]).is_err());
+        assert!(optimize([1..3, 4..6]).is_ok());
+    }
+
+    #[test]
+    fn heart_rate_cap_lowers_power_on_a_sustained_climb() {
+        let road_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 8,
+            segment_length: 500.0,
+            slope: 0.06,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let mut unconstrained_power_vec = Vec::new();
+        optimize_dynamic_programming(
+            4.0,
+            rider_model.anaerobic_work_capacity,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &default_dynamic_pacer_settings(),
+            &mut unconstrained_power_vec,
+        );
+
+        let heart_rate_model = heart_rate::default_heart_rate_model();
+        let heart_rate_cap = 160.0;
+        let mut capped_power_vec = unconstrained_power_vec.clone();
+        let heart_rate_vec = apply_heart_rate_cap(
+            4.0,
+            &road_segment_vec,
+            &resistance_model,
+            &heart_rate_model,
+            heart_rate_cap,
+            &root_finding::default_tolerance(),
+            &mut capped_power_vec,
+        );
+
+        assert!(heart_rate_vec.iter().all(|&heart_rate| heart_rate <= heart_rate_cap + 1e-6));
+        for (capped_power, unconstrained_power) in capped_power_vec.iter().zip(unconstrained_power_vec.iter()) {
+            assert!(capped_power <= unconstrained_power);
+        }
+        // Once the heart rate has caught up, the climb is ridden below the W'-optimal pace, at about the power
+        // whose steady-state heart rate is the cap.
+        let cap_power = (heart_rate_cap - heart_rate_model.resting_heart_rate) / heart_rate_model.heart_rate_per_watt;
+        let last_power = *capped_power_vec.last().unwrap();
+        assert!(last_power < *unconstrained_power_vec.last().unwrap());
+        assert!((last_power - cap_power).abs() < 5.0, "{} W vs {} W", last_power, cap_power);
+    }
+
+    #[test]
+    fn hill_repeats_leave_a_non_negative_reserve_after_every_rep() {
+        let hill_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 10,
+            segment_length: 100.0,
+            slope: 0.08,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let session = |repetitions: usize, recovery_duration: f64| {
+            let recovery = workout::Interval {
+                duration: recovery_duration,
+                power: 100.0,
+            };
+            optimize_hill_repeats(
+                4.0,
+                &hill_segment_vec,
+                repetitions,
+                recovery,
+                &resistance_model,
+                &rider_model,
+                &root_finding::default_tolerance(),
+            )
+            .unwrap()
+        };
+
+        let single = session(1, 240.0);
+        let long_recovery = session(6, 240.0);
+        let short_recovery = session(6, 60.0);
+        for plan in [&single, &long_recovery, &short_recovery] {
+            assert!(plan.rep_power > rider_model.critical_power);
+            assert!(plan.anaerobic_reserve_vec.iter().all(|&reserve| reserve >= 0.0));
+        }
+        assert_eq!(short_recovery.rep_time_vec.len(), 6);
+        assert_eq!(short_recovery.anaerobic_reserve_vec.len(), 6);
+        // The early reps hold back for the last one, all the more as the recoveries get shorter.
+        assert!(long_recovery.rep_power < single.rep_power);
+        assert!(short_recovery.rep_power < long_recovery.rep_power);
+    }
+
+    #[test]
+    fn time_optimal_strategy_is_no_slower_than_constant_cp() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 20,
+            segment_length: 500.0,
+            max_slope: 0.06,
+            period: 10,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let comparison_vec = compare_strategies(
+            5.0,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &root_finding::default_tolerance(),
+        );
+        let time_of = |strategy: PacingStrategy| {
+            let row = comparison_vec.iter().find(|row| row.strategy == strategy).unwrap();
+            assert!(row.is_feasible);
+            assert!(row.min_anaerobic_reserve >= 0.0);
+            row.total_time
+        };
+        let constant_cp = time_of(PacingStrategy::ConstantCriticalPower);
+        let even_effort = time_of(PacingStrategy::ConstantDepletion);
+        let optimal = time_of(PacingStrategy::DynamicProgramming);
+        assert!(optimal <= constant_cp);
+        assert!(even_effort <= constant_cp);
+
+        // On a wall nobody can ride up, every strategy is still reported.
+        let wall = make_course(CourseProfile::ConstantClimb {
+            n_segments: 3,
+            segment_length: 100.0,
+            slope: 0.5,
+        });
+        let comparison_vec = compare_strategies(
+            5.0,
+            &wall,
+            &resistance_model,
+            &rider_model,
+            &root_finding::default_tolerance(),
+        );
+        assert_eq!(comparison_vec.len(), 3);
+        assert!(comparison_vec.iter().all(|row| !row.is_feasible && row.total_time == f64::INFINITY));
+    }
+
+    #[test]
+    fn measured_opening_surge_beats_constant_cp_on_a_flat_prologue() {
+        let road_segment_vec = make_course(CourseProfile::Flat {
+            n_segments: 80,
+            segment_length: 50.0,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let mut power_vec = Vec::new();
+        let surge_time = optimize_prologue_start(
+            0.0,
+            rider_model.anaerobic_work_capacity,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            300.0,
+            &root_finding::default_tolerance(),
+            &mut power_vec,
+        );
+        let result = simulation::simulate(
+            0.0,
+            rider_model.anaerobic_work_capacity,
+            &power_vec,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+        assert!((result.total_time - surge_time).abs() < 1e-9);
+        assert!(result.anaerobic_reserve_vec.iter().all(|&reserve| reserve >= 0.0));
+        // The surge stays measured: it leaves the rider above critical power after the opening.
+        assert!(power_vec[0] > power_vec[79]);
+        assert!(power_vec[0] <= rider_model.max_power);
+        assert!(power_vec[79] >= rider_model.critical_power);
+
+        let constant_cp = simulation::simulate(
+            0.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![rider_model.critical_power; 80],
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+        assert!(surge_time < constant_cp.total_time);
+    }
+
+    #[test]
+    fn pass_plan_over_invests_on_the_climb_relative_to_even_power() {
+        let climb = make_course(CourseProfile::ConstantClimb {
+            n_segments: 10,
+            segment_length: 500.0,
+            slope: 0.07,
+        });
+        let descent = make_course(CourseProfile::Descent {
+            n_segments: 10,
+            segment_length: 500.0,
+            slope: 0.07,
+        });
+        let road_segment_vec: Vec<simulation::RoadSegment> = climb
+            .iter()
+            .chain(descent.iter())
+            .map(|road_segment| simulation::RoadSegment { ..*road_segment })
+            .collect();
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let options = simulation::default_simulation_options();
+        let plan = optimize_pass(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            10,
+            &options,
+        );
+
+        // The highest even power the rider holds to the finish.
+        let ride_even = |power: f64| {
+            simulation::simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![power; 20],
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+            )
+        };
+        let is_holdable = |power: f64| ride_even(power).power_vec.iter().all(|&ridden| ridden == power);
+        let even_power = root_finding::bisect_threshold(
+            is_holdable,
+            rider_model.critical_power,
+            rider_model.max_power,
+            &root_finding::default_tolerance(),
+        )
+        .value;
+        let even_time = ride_even(even_power).total_time;
+
+        assert!(plan.climb_power > even_power);
+        assert!(plan.descent_power < plan.climb_power);
+        assert_eq!(plan.power_vec[0], plan.climb_power);
+        assert_eq!(plan.power_vec[19], plan.descent_power);
+        assert!(plan.total_time < even_time);
+    }
+
+    #[test]
+    fn extra_power_beyond_the_spin_out_speed_does_not_shorten_a_descent() {
+        let gearing = gearing::Gearing {
+            chainring_teeth_vec: vec![50, 34],
+            cog_teeth_vec: vec![11, 12, 13, 14, 15, 17, 19, 21, 24, 28],
+            wheel_circumference: 2.1,
+        };
+        let spin_out_velocity = gearing.spin_out_velocity(90.0);
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let finish = |slope: f64, power_budget: f64| {
+            let road_segment_vec = make_course(CourseProfile::Descent {
+                n_segments: 20,
+                segment_length: 100.0,
+                slope,
+            });
+            optimize_downhill_finish(
+                20.0,
+                rider_model.anaerobic_work_capacity,
+                power_budget,
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                spin_out_velocity,
+                &root_finding::default_tolerance(),
+            )
+        };
+
+        // Down 10%, the rider coasts above the spin-out speed, so the power budget makes no difference.
+        let moderate = finish(0.1, 300.0);
+        let all_out = finish(0.1, 1000.0);
+        assert!(all_out.power_vec.iter().all(|&power| power < 1e-6));
+        assert!((all_out.total_time - moderate.total_time).abs() < 1e-6);
+
+        // Down 2%, below the spin-out speed, more power still pays.
+        assert!(finish(0.02, 600.0).total_time < finish(0.02, 300.0).total_time);
+    }
+}
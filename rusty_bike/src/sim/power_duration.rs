@@ -0,0 +1,29 @@
+/// A rider power-duration model with an anaerobic reserve (W') balance.
+///
+/// Implementations describe the maximal power a rider can hold, how long they can hold a power above critical
+/// power before exhaustion, and how the anaerobic reserve depletes and recovers.
+pub trait PowerDurationModel {
+    /// The critical power in watts.
+    fn critical_power(&self) -> f64;
+
+    /// The anaerobic work capacity (W') in joules.
+    fn anaerobic_work_capacity(&self) -> f64;
+
+    /// The maximal instantaneous power in watts given the current anaerobic reserve.
+    fn max_power(&self, current_anaerobic_reserve: f64) -> f64;
+
+    /// The time in seconds the rider can hold `input_power` before exhaustion, starting from the current
+    /// anaerobic reserve. Returns `f64::MAX` if the power can be held indefinitely.
+    fn time_to_exhaustion(&self, input_power: f64, current_anaerobic_reserve: f64) -> f64;
+
+    /// The anaerobic reserve in joules after riding at `input_power` for `duration` seconds.
+    fn update_anaerobic_reserve(&self, input_power: f64, duration: f64, current_anaerobic_reserve: f64) -> f64;
+
+    /// The maximal power in watts the rider can hold for `duration` seconds starting from a full reserve.
+    fn power_duration(&self, duration: f64) -> f64;
+
+    /// A copy of the model with its critical power replaced by `critical_power` watts, the other parameters kept.
+    fn with_critical_power(&self, critical_power: f64) -> Self
+    where
+        Self: Sized;
+}
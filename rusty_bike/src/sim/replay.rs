@@ -0,0 +1,296 @@
+// Power meter replay
+// Reconstructs a ride by feeding a recorded power trace through the physics
+
+use crate::sim::kinematics;
+use crate::sim::power_duration::PowerDurationModel;
+use crate::sim::root_finding;
+use crate::sim::simulation;
+
+/// Maximal number of fixed-point iterations matching the power of a segment to the time spent on it.
+const REPLAY_ITERATIONS: usize = 10;
+
+/// Power difference in watts below which the fixed-point iteration has converged.
+const REPLAY_POWER_TOL: f64 = 1e-6;
+
+/// Highest plausible power in watts inferred from a speed trace, above which it is clamped.
+const MAX_INFERRED_POWER: f64 = 2500.0;
+
+/// Tolerance in watts on the power inferred from a speed trace.
+const INFER_POWER_TOL: f64 = 1e-3;
+
+/// Mean power of a trace sampled every `sample_interval` seconds between `start_time` and `end_time`.
+///
+/// Each sample holds over its interval. Past the end of the trace, the last sample is held.
+fn average_power(power_trace: &Vec<f64>, sample_interval: f64, start_time: f64, end_time: f64) -> f64 {
+    let Some(&last_power) = power_trace.last() else {
+        return 0.0;
+    };
+    let sample_at = |time: f64| {
+        let index = f64::max(0.0, f64::floor(time / sample_interval)) as usize;
+        power_trace.get(index).copied().unwrap_or(last_power)
+    };
+    if end_time <= start_time {
+        return sample_at(start_time);
+    }
+    let mut energy = 0.0;
+    let mut time = start_time;
+    while time < end_time {
+        let next_time = f64::min(end_time, (f64::floor(time / sample_interval) + 1.0) * sample_interval);
+        if time >= power_trace.len() as f64 * sample_interval {
+            energy += last_power * (end_time - time);
+            break;
+        }
+        energy += sample_at(time) * (next_time - time);
+        time = next_time;
+    }
+    energy / (end_time - start_time)
+}
+
+/// Replays a recorded power trace over a course to reconstruct the speed and anaerobic reserve (W'bal).
+///
+/// Each segment is ridden at the mean power of the trace over the time the rider spends on it, found by
+/// iterating since that time depends on the power. Unlike `simulation::simulate`, the powers are not clamped to
+/// what the model says the rider can sustain: the anaerobic reserve can go negative when the recording exceeds
+/// the model, and the result is always flagged feasible. A trace shorter than the ride is padded with its last
+/// sample, and the samples recorded after the finish are ignored.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `power_trace` - The recorded power in watts.
+/// * `sample_interval` - The time between two samples of the trace in seconds, e.g. 1 for a 1 Hz recording.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+///
+/// # Returns
+///
+/// * `Result<SimulationResult, String>` - The reconstructed ride, with the power of each segment as resampled
+///   from the trace, or an error if `sample_interval` is not a positive number.
+pub fn replay<M: PowerDurationModel>(
+    initial_velocity: f64,
+    power_trace: &Vec<f64>,
+    sample_interval: f64,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_model: &M,
+) -> Result<simulation::SimulationResult, String> {
+    if !(sample_interval.is_finite() && sample_interval > 0.0) {
+        return Err(format!("sample interval must be a positive number of seconds, got {}", sample_interval));
+    }
+    let n_segments = road_segment_vec.len();
+    let mut velocity = initial_velocity;
+    let mut anaerobic_reserve = rider_model.anaerobic_work_capacity();
+    let mut battery_energy = simulation::full_battery_energy(resistance_model);
+    // The recovered energy stays zero, the trace giving no braking to recover from.
+    let mut scratch = simulation::SimulationScratch::new();
+    scratch.resize(n_segments);
+
+    let mut total_duration = 0.0;
+    let mut total_distance = 0.0;
+    for (i, road_segment) in road_segment_vec.iter().enumerate() {
+        let ride = |input_power: f64| {
+            simulation::ride_segment(
+                velocity,
+                input_power,
+                road_segment,
+                resistance_model,
+                battery_energy,
+                f64::INFINITY,
+            )
+        };
+        let mut power = average_power(power_trace, sample_interval, total_duration, total_duration);
+        let mut outcome = ride(power);
+        for _ in 0..REPLAY_ITERATIONS {
+            let mean_power =
+                average_power(power_trace, sample_interval, total_duration, total_duration + outcome.time);
+            if f64::abs(mean_power - power) < REPLAY_POWER_TOL {
+                break;
+            }
+            power = mean_power;
+            outcome = ride(power);
+        }
+
+        anaerobic_reserve = rider_model.update_anaerobic_reserve(power, outcome.time, anaerobic_reserve);
+        total_duration += outcome.time;
+        total_distance += road_segment.length;
+        scratch.distance_vec[i] = total_distance;
+        scratch.duration_vec[i] = outcome.time;
+        scratch.velocity_vec[i] = outcome.final_velocity;
+        scratch.air_speed_vec[i] = outcome.final_velocity + road_segment.relative_wind_speed;
+        scratch.power_vec[i] = power;
+        scratch.anaerobic_reserve_vec[i] = anaerobic_reserve;
+        scratch.battery_energy_vec[i] = outcome.battery_energy;
+        scratch.aero_energy_vec[i] = outcome.aero_energy;
+        scratch.wind_energy_vec[i] = outcome.wind_energy;
+        scratch.braking_energy_vec[i] = outcome.braking_energy;
+        scratch.gravity_energy_vec[i] = kinematics::get_gravity_force(road_segment.slope, resistance_model.total_mass())
+            * road_segment.length;
+        scratch.step_count_vec[i] = outcome.step_count;
+        velocity = outcome.final_velocity;
+        battery_energy = outcome.battery_energy;
+    }
+    Ok(scratch.into_result(total_duration, simulation::Feasibility::Feasible))
+}
+
+/// The power inferred from a speed trace.
+///
+/// # Fields
+///
+/// * `power_vec` - The input power on each segment in watts, clamped between 0 and `MAX_INFERRED_POWER`.
+/// * `is_clamped_vec` - Whether the power of each segment was clamped, e.g. because noise in the speeds implied
+///   a negative or huge power. A power clamped to 0 can also mean the rider braked.
+pub struct InferredPower {
+    pub power_vec: Vec<f64>,
+    pub is_clamped_vec: Vec<bool>,
+}
+
+/// Infers the power a rider produced on each segment from their recorded speeds, the inverse of `replay`.
+///
+/// On each segment, the power is the constant power with which `simulation::ride_segment` goes from the recorded
+/// speed at its start to the recorded speed at its end, found by bisection. When no power between 0 and
+/// `MAX_INFERRED_POWER` fits, e.g. because of noise in the speeds or because the rider braked, the closest bound
+/// is taken and the segment is flagged. Motor assist is not accounted for.
+///
+/// # Arguments
+///
+/// * `road_segment_vec` - The segments of the course.
+/// * `speed_vec` - The recorded speed in meters per second at the start of the course and at the end of each
+///   segment, i.e. one more entry than segments.
+/// * `resistance_model` - The resistance model of the bicycle.
+///
+/// # Returns
+///
+/// * `Result<InferredPower, String>` - The power of each segment and whether it was clamped, or an error if
+///   `speed_vec` does not have one more entry than `road_segment_vec`.
+pub fn infer_power(
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    speed_vec: &Vec<f64>,
+    resistance_model: &simulation::BicycleResistanceModel,
+) -> Result<InferredPower, String> {
+    if speed_vec.len() != road_segment_vec.len() + 1 {
+        return Err(format!(
+            "expected {} speeds for {} segments, got {}",
+            road_segment_vec.len() + 1,
+            road_segment_vec.len(),
+            speed_vec.len()
+        ));
+    }
+    let tolerance = root_finding::Tolerance {
+        abs: INFER_POWER_TOL,
+        rel: 0.0,
+        max_iter: 100,
+    };
+    let mut power_vec = Vec::with_capacity(road_segment_vec.len());
+    let mut is_clamped_vec = Vec::with_capacity(road_segment_vec.len());
+    for (i, road_segment) in road_segment_vec.iter().enumerate() {
+        let velocity_gap = |input_power: f64| {
+            let (_, final_velocity) =
+                simulation::compute_time_and_final_velocity(speed_vec[i], input_power, road_segment, resistance_model);
+            final_velocity - speed_vec[i + 1]
+        };
+        let solution = root_finding::bisect(velocity_gap, 0.0, MAX_INFERRED_POWER, &tolerance);
+        power_vec.push(solution.value);
+        is_clamped_vec.push(!solution.converged);
+    }
+    Ok(InferredPower {
+        power_vec,
+        is_clamped_vec,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn constant_power_replay_matches_a_constant_power_simulation() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 250.0,
+            max_slope: 0.04,
+            period: 8,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let simulated = simulation::simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![250.0; road_segment_vec.len()],
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+
+        // A trace ending well before the finish is padded, one going on after it is truncated.
+        for trace_length in [10, 100_000] {
+            let replayed = replay(
+                5.0,
+                &vec![250.0; trace_length],
+                1.0,
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+            )
+            .unwrap();
+            assert!((replayed.total_time - simulated.total_time).abs() < 1e-9);
+            for i in 0..road_segment_vec.len() {
+                assert_eq!(replayed.power_vec[i], 250.0);
+                assert!((replayed.velocity_vec[i] - simulated.velocity_vec[i]).abs() < 1e-9);
+                assert!((replayed.anaerobic_reserve_vec[i] - simulated.anaerobic_reserve_vec[i]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn inferring_power_from_a_constant_power_trace_recovers_it() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 250.0,
+            max_slope: 0.05,
+            period: 8,
+        });
+        let resistance_model = simulation::default_resistance_model();
+        let mut speed_vec = vec![5.0];
+        for road_segment in road_segment_vec.iter() {
+            let (_, final_velocity) = simulation::compute_time_and_final_velocity(
+                *speed_vec.last().unwrap(),
+                280.0,
+                road_segment,
+                &resistance_model,
+            );
+            speed_vec.push(final_velocity);
+        }
+
+        let inferred = infer_power(&road_segment_vec, &speed_vec, &resistance_model).unwrap();
+        for (power, is_clamped) in inferred.power_vec.iter().zip(inferred.is_clamped_vec.iter()) {
+            assert!((power - 280.0).abs() < 0.01, "{} W", power);
+            assert!(!is_clamped);
+        }
+
+        // A noise spike needs an implausible power, which is clamped and flagged.
+        speed_vec[4] += 15.0;
+        let noisy = infer_power(&road_segment_vec, &speed_vec, &resistance_model).unwrap();
+        assert!(noisy.is_clamped_vec[3] && noisy.power_vec[3] == MAX_INFERRED_POWER);
+        assert!(noisy.is_clamped_vec[4] && noisy.power_vec[4] == 0.0);
+    }
+    #[test]
+    fn malformed_inputs_are_rejected() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 3, segment_length: 100.0 });
+        let resistance_model = simulation::default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        for sample_interval in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let replayed =
+                replay(5.0, &vec![250.0; 60], sample_interval, &road_segment_vec, &resistance_model, &rider_model);
+            assert!(replayed.is_err(), "{}", sample_interval);
+        }
+
+        // One speed per segment end plus the starting speed, no more, no less.
+        assert!(infer_power(&road_segment_vec, &vec![8.0; 4], &resistance_model).is_ok());
+        for n_speeds in [0, 3, 5] {
+            assert!(infer_power(&road_segment_vec, &vec![8.0; n_speeds], &resistance_model).is_err());
+        }
+    }
+}
@@ -0,0 +1,362 @@
+// Root finding
+// Bisection and golden-section search with a shared tolerance and convergence report
+
+/// The stopping criterion of a solver.
+///
+/// # Fields
+///
+/// * `abs` - The absolute tolerance on the solution.
+/// * `rel` - The tolerance on the solution relative to its magnitude. The solver stops when the uncertainty on
+///   the solution is below `abs + rel * |solution|`.
+/// * `max_iter` - The maximal number of iterations, after which the solver gives up.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub abs: f64,
+    pub rel: f64,
+    pub max_iter: usize,
+}
+
+pub const fn default_tolerance() -> Tolerance {
+    Tolerance {
+        abs: 1e-6,
+        rel: 1e-9,
+        max_iter: 100,
+    }
+}
+
+/// The outcome of a solver.
+///
+/// # Fields
+///
+/// * `value` - The solution, or the best estimate when the solver did not converge.
+/// * `converged` - Whether the tolerance was reached. It is false when the root could not be bracketed or the
+///   solver ran out of iterations.
+/// * `iterations` - The number of iterations done.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Solution {
+    pub value: f64,
+    pub converged: bool,
+    pub iterations: usize,
+}
+
+/// Finds a root of `f` between `low` and `high` by bisection.
+///
+/// When `f` has the same sign at both ends, e.g. on a flat objective, no root can be bracketed: the solver
+/// returns at once the end where `|f|` is smallest, flagged as not converged.
+///
+/// # Arguments
+///
+/// * `f` - The function, continuous between `low` and `high`.
+/// * `low` - The lower end of the search interval.
+/// * `high` - The upper end of the search interval.
+/// * `tolerance` - The stopping criterion.
+///
+/// # Returns
+///
+/// * `Solution` - The root and whether it was found within the tolerance.
+///
+/// # Example
+///
+/// ```
+/// let solution = bisect(|x| x * x - 2.0, 0.0, 2.0, &default_tolerance());
+/// assert!(solution.converged);
+/// ```
+pub fn bisect<F: Fn(f64) -> f64>(f: F, low: f64, high: f64, tolerance: &Tolerance) -> Solution {
+    let mut low = low;
+    let mut high = high;
+    let mut f_low = f(low);
+    let f_high = f(high);
+    if f_low == 0.0 || f_high == 0.0 {
+        let value = if f_low == 0.0 { low } else { high };
+        return Solution {
+            value,
+            converged: true,
+            iterations: 0,
+        };
+    }
+    if f64::signum(f_low) == f64::signum(f_high) {
+        let value = if f64::abs(f_low) <= f64::abs(f_high) { low } else { high };
+        return Solution {
+            value,
+            converged: false,
+            iterations: 0,
+        };
+    }
+    for iteration in 1..=tolerance.max_iter {
+        let middle = 0.5 * (low + high);
+        let f_middle = f(middle);
+        if f_middle == 0.0 {
+            return Solution {
+                value: middle,
+                converged: true,
+                iterations: iteration,
+            };
+        }
+        if f64::signum(f_middle) == f64::signum(f_low) {
+            low = middle;
+            f_low = f_middle;
+        } else {
+            high = middle;
+        }
+        if f64::abs(high - low) <= tolerance.abs + tolerance.rel * f64::abs(middle) {
+            return Solution {
+                value: 0.5 * (low + high),
+                converged: true,
+                iterations: iteration,
+            };
+        }
+    }
+    Solution {
+        value: 0.5 * (low + high),
+        converged: false,
+        iterations: tolerance.max_iter,
+    }
+}
+
+/// Finds by bisection the end of the values of `[low, high]` for which `holds` is true, e.g. the highest power a
+/// rider can sustain, when `holds` is true from `low` up to some value and false above.
+///
+/// `holds` is assumed to be true at `low`, which is returned when it is true nowhere. When it is true at `high`,
+/// `high` is returned at once.
+///
+/// # Arguments
+///
+/// * `holds` - The predicate, true below the end looked for and false above.
+/// * `low` - The lower end of the search interval.
+/// * `high` - The upper end of the search interval.
+/// * `tolerance` - The stopping criterion.
+///
+/// # Returns
+///
+/// * `Solution` - The highest value found for which `holds` is true, and whether the end was bracketed within the
+///   tolerance.
+///
+/// # Example
+///
+/// ```
+/// let solution = bisect_threshold(|x| x * x <= 2.0, 0.0, 2.0, &default_tolerance());
+/// assert!(solution.value <= f64::sqrt(2.0));
+/// ```
+pub fn bisect_threshold<P: FnMut(f64) -> bool>(
+    mut holds: P,
+    low: f64,
+    high: f64,
+    tolerance: &Tolerance,
+) -> Solution {
+    if holds(high) {
+        return Solution {
+            value: high,
+            converged: true,
+            iterations: 0,
+        };
+    }
+    let mut low = low;
+    let mut high = high;
+    for iteration in 1..=tolerance.max_iter {
+        let middle = 0.5 * (low + high);
+        if holds(middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+        if f64::abs(high - low) <= tolerance.abs + tolerance.rel * f64::abs(middle) {
+            return Solution {
+                value: low,
+                converged: true,
+                iterations: iteration,
+            };
+        }
+    }
+    Solution {
+        value: low,
+        converged: false,
+        iterations: tolerance.max_iter,
+    }
+}
+
+/// Finds a minimum of `f` between `low` and `high` by golden-section search, e.g. the best split of an effort.
+///
+/// `f` is assumed unimodal between `low` and `high`. Where it takes the same value at both probes, e.g. infinite
+/// where a plan cannot be held, the search moves towards `low`.
+///
+/// # Arguments
+///
+/// * `f` - The function to minimize.
+/// * `low` - The lower end of the search interval.
+/// * `high` - The upper end of the search interval.
+/// * `tolerance` - The stopping criterion.
+///
+/// # Returns
+///
+/// * `Solution` - The minimizer, and whether it was bracketed within the tolerance.
+///
+/// # Example
+///
+/// ```
+/// let solution = golden_section_minimize(|x| (x - 1.0) * (x - 1.0), 0.0, 3.0, &default_tolerance());
+/// assert!((solution.value - 1.0).abs() < 1e-5);
+/// ```
+pub fn golden_section_minimize<F: FnMut(f64) -> f64>(
+    mut f: F,
+    low: f64,
+    high: f64,
+    tolerance: &Tolerance,
+) -> Solution {
+    let inverse_golden_ratio = 0.5 * (f64::sqrt(5.0) - 1.0);
+    let mut low = low;
+    let mut high = high;
+    let mut left = high - inverse_golden_ratio * (high - low);
+    let mut right = low + inverse_golden_ratio * (high - low);
+    let mut f_left = f(left);
+    let mut f_right = f(right);
+    for iteration in 1..=tolerance.max_iter {
+        // The probe kept is at the golden ratio of the new interval, so only one new evaluation is needed.
+        if f_left <= f_right {
+            high = right;
+            right = left;
+            f_right = f_left;
+            left = high - inverse_golden_ratio * (high - low);
+            f_left = f(left);
+        } else {
+            low = left;
+            left = right;
+            f_left = f_right;
+            right = low + inverse_golden_ratio * (high - low);
+            f_right = f(right);
+        }
+        let middle = 0.5 * (low + high);
+        if f64::abs(high - low) <= tolerance.abs + tolerance.rel * f64::abs(middle) {
+            return Solution {
+                value: middle,
+                converged: true,
+                iterations: iteration,
+            };
+        }
+    }
+    Solution {
+        value: 0.5 * (low + high),
+        converged: false,
+        iterations: tolerance.max_iter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bisection_converges_to_the_root_within_the_tolerance() {
+        let tolerance = Tolerance {
+            abs: 1e-9,
+            rel: 0.0,
+            max_iter: 100,
+        };
+        let solution = bisect(|x| x * x - 2.0, 0.0, 2.0, &tolerance);
+        assert!(solution.converged);
+        assert!((solution.value - f64::sqrt(2.0)).abs() <= 1e-9, "{}", solution.value);
+        // Each iteration halves the interval, from 2 down to the tolerance.
+        assert_eq!(solution.iterations, 31);
+
+        // A root at an end of the interval is returned at once.
+        assert_eq!(bisect(|x| x - 2.0, 0.0, 2.0, &tolerance), Solution { value: 2.0, converged: true, iterations: 0 });
+    }
+
+    #[test]
+    fn bisection_without_a_bracketed_root_returns_the_closest_end() {
+        let solution = bisect(|x| x * x + 1.0, -1.0, 3.0, &default_tolerance());
+        assert_eq!(solution, Solution { value: -1.0, converged: false, iterations: 0 });
+        let solution = bisect(|x| x - 10.0, 0.0, 3.0, &default_tolerance());
+        assert_eq!(solution, Solution { value: 3.0, converged: false, iterations: 0 });
+    }
+
+    #[test]
+    fn bisection_stops_at_max_iter() {
+        let tolerance = Tolerance {
+            max_iter: 10,
+            ..default_tolerance()
+        };
+        let solution = bisect(|x| x * x - 2.0, 0.0, 2.0, &tolerance);
+        assert!(!solution.converged);
+        assert_eq!(solution.iterations, 10);
+        assert!((solution.value - f64::sqrt(2.0)).abs() <= 2.0 / 1024.0, "{}", solution.value);
+    }
+
+    #[test]
+    fn threshold_bisection_converges_to_the_end_of_the_holding_values() {
+        let tolerance = Tolerance {
+            abs: 1e-9,
+            rel: 0.0,
+            max_iter: 100,
+        };
+        let solution = bisect_threshold(|x| x * x <= 2.0, 0.0, 2.0, &tolerance);
+        assert!(solution.converged);
+        // The value returned always holds.
+        assert!(solution.value * solution.value <= 2.0);
+        assert!(f64::sqrt(2.0) - solution.value <= 1e-9, "{}", solution.value);
+    }
+
+    #[test]
+    fn threshold_bisection_returns_an_end_when_the_predicate_does_not_switch() {
+        let holds_everywhere = bisect_threshold(|_| true, 0.0, 2.0, &default_tolerance());
+        assert_eq!(holds_everywhere, Solution { value: 2.0, converged: true, iterations: 0 });
+        // Holding nowhere, the search shrinks onto `low`.
+        let holds_nowhere = bisect_threshold(|_| false, 0.0, 2.0, &default_tolerance());
+        assert_eq!(holds_nowhere.value, 0.0);
+    }
+
+    #[test]
+    fn threshold_bisection_stops_at_max_iter() {
+        let tolerance = Tolerance {
+            max_iter: 10,
+            ..default_tolerance()
+        };
+        let mut n_calls = 0;
+        let solution = bisect_threshold(
+            |x| {
+                n_calls += 1;
+                x * x <= 2.0
+            },
+            0.0,
+            2.0,
+            &tolerance,
+        );
+        assert!(!solution.converged);
+        assert_eq!(solution.iterations, 10);
+        // One call on `high`, then one per iteration.
+        assert_eq!(n_calls, 11);
+        assert!(solution.value <= f64::sqrt(2.0) && f64::sqrt(2.0) - solution.value <= 2.0 / 1024.0);
+    }
+
+    #[test]
+    fn golden_section_search_converges_to_the_minimum() {
+        let solution = golden_section_minimize(|x| (x - 1.0) * (x - 1.0) + 2.0, 0.0, 3.0, &default_tolerance());
+        assert!(solution.converged);
+        assert!((solution.value - 1.0).abs() < 1e-5, "{}", solution.value);
+
+        // A minimum at an end of the interval is found there.
+        let solution = golden_section_minimize(|x| x, 2.0, 5.0, &default_tolerance());
+        assert!(solution.converged);
+        assert!((solution.value - 2.0).abs() < 1e-5, "{}", solution.value);
+    }
+
+    #[test]
+    fn golden_section_search_moves_towards_low_on_ties() {
+        // Infinite above 4, like a plan that cannot be held, and decreasing below.
+        let f = |x: f64| if x > 4.0 { f64::INFINITY } else { -x };
+        let solution = golden_section_minimize(f, 0.0, 100.0, &default_tolerance());
+        assert!(solution.converged);
+        assert!((solution.value - 4.0).abs() < 1e-5, "{}", solution.value);
+    }
+
+    #[test]
+    fn golden_section_search_stops_at_max_iter() {
+        let tolerance = Tolerance {
+            max_iter: 5,
+            ..default_tolerance()
+        };
+        let solution = golden_section_minimize(|x| (x - 1.0) * (x - 1.0), 0.0, 3.0, &tolerance);
+        assert!(!solution.converged);
+        assert_eq!(solution.iterations, 5);
+        assert!((solution.value - 1.0).abs() < 3.0 * f64::powi(0.5 * (f64::sqrt(5.0) - 1.0), 5));
+    }
+}
@@ -1,12 +1,87 @@
+use crate::sim::altitude;
 use crate::sim::kinematics;
 use crate::sim::morton;
+use crate::sim::power_duration::PowerDurationModel;
+use crate::sim::root_finding;
+use crate::sim::thermal;
+use std::cell::RefCell;
 
 /// The minimum velocity constant in meters per second.
 const MIN_VELOCITY: f64 = 0.1;
 
-/// The tolerance for kinetic energy calculations.
+/// The kinetic energy change in joules targeted by the first integration step of a segment.
 const KINETIC_ENERGY_TOL: f64 = 2.0;
 
+/// The local error in joules of kinetic energy allowed per integration step.
+const STEP_ERROR_TOL: f64 = 0.01;
+
+/// The step size in meters below which a step is accepted whatever its error.
+const MIN_STEP_SIZE: f64 = 1e-6;
+
+/// The safety factor applied to the step size proposed by the step-size controller.
+const STEP_SAFETY_FACTOR: f64 = 0.9;
+
+/// The integral and proportional gains of the PI step-size controller, for a first order method.
+const STEP_INTEGRAL_GAIN: f64 = 0.35;
+const STEP_PROPORTIONAL_GAIN: f64 = 0.2;
+
+/// The bounds on the factor by which the step size changes from one step to the next.
+const MIN_STEP_FACTOR: f64 = 0.2;
+const MAX_STEP_FACTOR: f64 = 5.0;
+
+/// The number of joules in a watt-hour.
+const JOULES_PER_WATT_HOUR: f64 = 3600.0;
+
+/// The deceleration in meters per second squared the rider brakes at to respect a speed limit.
+const BRAKING_DECELERATION: f64 = 3.0;
+
+/// The time step in seconds of the coast-downs finding the speed limits of a ride without braking.
+const COASTING_TIME_STEP: f64 = 0.1;
+
+/// The number of Simpson intervals used by the analytic segment solver.
+const ANALYTIC_QUADRATURE_INTERVALS: usize = 200;
+
+/// The relative distance to the terminal velocity below which the analytic solver considers it reached.
+const ANALYTIC_TERMINAL_VELOCITY_TOL: f64 = 1e-9;
+
+/// The number of wind speeds tried when looking for the break-even wind speed of two routes.
+const WIND_SWEEP_STEPS: usize = 50;
+
+/// Mass of the bicycle in kilograms when only the total mass is known.
+pub const DEFAULT_BIKE_MASS: f64 = 8.0;
+
+/// Mass difference in kilograms of the central difference in `seconds_per_kilogram`.
+const MASS_DIFFERENCE_STEP: f64 = 0.5;
+
+/// Critical power difference in watts of the central difference in `watts_to_seconds`.
+const CRITICAL_POWER_DIFFERENCE_STEP: f64 = 1.0;
+
+/// Highest power in watts tried by `constant_power_for_time`.
+const MAX_CONSTANT_POWER: f64 = 2000.0;
+
+/// The highest velocity in meters per second searched for a steady state, far above any rider's speed.
+const MAX_STEADY_VELOCITY: f64 = 100.0;
+
+/// The steepest gradient searched for the critical gradient, e.g. 0.5 for 50%.
+const MAX_CRITICAL_GRADIENT: f64 = 0.5;
+
+/// The length in seconds of the rolling average of the power in the normalized power.
+const NORMALIZED_POWER_WINDOW: usize = 30;
+
+/// Represents a segment of road of constant slope.
+///
+/// # Fields
+///
+/// * `length` - The length of the segment in meters.
+/// * `altitude` - The altitude at the start of the segment in meters.
+/// * `slope` - The slope of the segment, e.g. 0.08 for 8%.
+/// * `temperature` - The ambient temperature in degrees Celsius.
+/// * `relative_wind_speed` - The headwind component of the wind in meters per second, negative for a tailwind.
+/// * `roughness` - The multiplier of the rolling resistance for the road surface, 1 for smooth asphalt.
+/// * `rolling_resistance` - The coefficient of rolling resistance of the segment, e.g. from a surface map of a
+///   gravel event, overriding the one of the resistance model. `None` uses the resistance model's.
+/// * `drivetrain_efficiency` - The efficiency of the drivetrain on the segment, e.g. lower on muddy cyclocross or
+///   wet sections, overriding the one of the resistance model. `None` uses the resistance model's.
 pub struct RoadSegment {
     pub length: f64,
     pub altitude: f64,
@@ -14,22 +89,104 @@ pub struct RoadSegment {
     pub temperature: f64,
     pub relative_wind_speed: f64,
     pub roughness: f64,
+    pub rolling_resistance: Option<f64>,
+    pub drivetrain_efficiency: Option<f64>,
 }
 
 /// Represents the resistance model for a bicycle simulation.
 ///
 /// # Fields
 /// 
-/// * `total_mass` - The total mass of the bicycle and rider in kilograms.
+/// * `rider_mass` - The mass of the rider with their clothing and equipment in kilograms.
+/// * `bike_mass` - The mass of the bicycle in kilograms. The physics only depends on the total, see `total_mass`,
+///   so features concerning a single component, e.g. the rotational inertia of the bicycle, must use that one.
 /// * `cda_surface` - The coefficient of drag area (CdA) in square meters.
 /// * `rolling_resistance` - The coefficient of rolling resistance.
 /// * `temperature` - The ambient temperature in degrees Celsius.
 /// * `drivetrain_efficiency` - The efficiency of the drivetrain as a fraction (e.g., 0.95 for 95% efficiency).
+/// * `drivetrain_fixed_loss` - The drivetrain loss in watts on top of the proportional one, e.g. from chain and
+///   bearing friction. It dominates at low power, where a pure efficiency underestimates the losses. See
+///   `wheel_power`.
+/// * `motor_assist` - The electric motor assist of an e-bike, if any.
+/// * `reynolds_correction` - The Reynolds number correction of the CdA, if any.
+/// * `cda_speed_curve` - The CdA as a function of the ground speed, if any, replacing `cda_surface`. See
+///   `kinematics::interpolate_cda`. A constant curve gives the same results as `cda_surface`, and an empty one
+///   falls back to it.
+/// * `cda_speed_bands` - The CdA measured in bands of ground speed, if any, replacing `cda_surface` and
+///   `cda_speed_curve`. See `kinematics::CdaBands`.
+/// * `roughness_speed_loss` - The speed-dependent loss coefficient of rough roads in s^2/m^2, see
+///   `kinematics::get_roughness_loss_force`. Zero keeps `roughness` a plain multiplier of rolling resistance.
+/// * `can_coast` - Whether the bicycle has a freewheel. Without one (fixed gear), the pedals turn with the
+///   wheel, so the rider cannot go faster than `fixed_gear` allows and scrubs the excess speed with their legs.
+/// * `fixed_gear` - The gearing and cadence limit used when `can_coast` is false.
+/// * `air_density_table` - A precomputed air density table, if any, to interpolate the air density of each segment
+///   rather than compute it exactly, e.g. in large optimizer runs. See `kinematics::AirDensityTable`.
+#[derive(Clone)]
 pub struct BicycleResistanceModel {
-    pub total_mass: f64,
+    pub rider_mass: f64,
+    pub bike_mass: f64,
     pub cda_surface: f64,
     pub rolling_resistance: f64,
     pub drivetrain_efficiency: f64,
+    pub drivetrain_fixed_loss: f64,
+    pub motor_assist: Option<MotorAssist>,
+    pub reynolds_correction: Option<ReynoldsCorrection>,
+    pub cda_speed_curve: Option<Vec<(f64, f64)>>,
+    pub cda_speed_bands: Option<kinematics::CdaBands>,
+    pub roughness_speed_loss: f64,
+    pub can_coast: bool,
+    pub fixed_gear: FixedGear,
+    pub air_density_table: Option<kinematics::AirDensityTable>,
+}
+
+/// Represents the dependency of the CdA on the Reynolds number, which varies with the air speed, density and
+/// temperature-dependent viscosity. See `kinematics::reynolds_corrected_cda`.
+///
+/// # Fields
+///
+/// * `characteristic_length` - The characteristic length of the rider in meters.
+/// * `reference_reynolds_number` - The Reynolds number at which `cda_surface` was measured.
+/// * `sensitivity` - The strength of the correction (dimensionless).
+#[derive(Clone, Copy)]
+pub struct ReynoldsCorrection {
+    pub characteristic_length: f64,
+    pub reference_reynolds_number: f64,
+    pub sensitivity: f64,
+}
+
+/// Represents the electric motor assist of an e-bike.
+///
+/// The motor adds `max_assist_power` to the rider's power at the wheel until the battery is empty, after which
+/// the rider is on their own.
+///
+/// # Fields
+///
+/// * `max_assist_power` - The power added by the motor in watts.
+/// * `battery_capacity` - The energy stored in a full battery in watt-hours.
+#[derive(Clone, Copy)]
+pub struct MotorAssist {
+    pub max_assist_power: f64,
+    pub battery_capacity: f64,
+}
+
+/// Represents the gearing of a fixed-gear bicycle, on which the cadence is tied to the speed.
+///
+/// # Fields
+///
+/// * `gear_development` - The distance covered per crank revolution in meters, e.g. 7.5 for 48x15 on a road
+///   wheel.
+/// * `max_cadence` - The highest cadence the rider can spin in revolutions per minute.
+#[derive(Clone, Copy)]
+pub struct FixedGear {
+    pub gear_development: f64,
+    pub max_cadence: f64,
+}
+
+impl FixedGear {
+    /// Returns the velocity in meters per second at the maximal cadence.
+    pub fn max_velocity(&self) -> f64 {
+        self.max_cadence * self.gear_development / 60.0
+    }
 }
 
 /// Returns a default resistance model for a bicycle simulation.
@@ -46,113 +203,4938 @@ pub struct BicycleResistanceModel {
 /// ```
 pub const fn default_resistance_model() -> BicycleResistanceModel {
     let model = BicycleResistanceModel {
-        total_mass: 80.0,
+        rider_mass: 72.0,
+        bike_mass: DEFAULT_BIKE_MASS,
         cda_surface: 0.3,
         rolling_resistance: 0.004,
         drivetrain_efficiency: 0.98,
+        drivetrain_fixed_loss: 0.0,
+        motor_assist: None,
+        reynolds_correction: None,
+        cda_speed_curve: None,
+        cda_speed_bands: None,
+        roughness_speed_loss: 0.0,
+        can_coast: true,
+        fixed_gear: FixedGear {
+            gear_development: 7.5,
+            max_cadence: 130.0,
+        },
+        air_density_table: None,
     };
     return model;
 }
 
+/// Returns the default resistance model with the given total mass of the bicycle and rider, for callers that do
+/// not know the rider and bicycle masses apart. The bicycle is assumed to weigh `DEFAULT_BIKE_MASS`.
+///
+/// # Arguments
+///
+/// * `total_mass` - The total mass of the bicycle and rider in kilograms.
+///
+/// # Returns
+///
+/// * `Result<BicycleResistanceModel, String>` - The default resistance model, of which `total_mass()` is
+///   `total_mass`, or an error if `total_mass` does not exceed `DEFAULT_BIKE_MASS`, which would leave no mass for
+///   the rider.
+pub fn resistance_model_with_total_mass(total_mass: f64) -> Result<BicycleResistanceModel, String> {
+    if total_mass.is_nan() || total_mass <= DEFAULT_BIKE_MASS {
+        return Err(format!(
+            "total mass of {} kg, expected more than the bicycle's {} kg",
+            total_mass, DEFAULT_BIKE_MASS
+        ));
+    }
+    let mut model = default_resistance_model();
+    model.rider_mass = total_mass - DEFAULT_BIKE_MASS;
+    Ok(model)
+}
+
+impl BicycleResistanceModel {
+    /// Returns the total mass of the bicycle and rider in kilograms.
+    pub fn total_mass(&self) -> f64 {
+        self.rider_mass + self.bike_mass
+    }
+}
+
+/// Returns the battery energy in watt-hours of a fully charged motor assist, or zero without assist.
+pub fn full_battery_energy(resistance_model: &BicycleResistanceModel) -> f64 {
+    match resistance_model.motor_assist {
+        Some(motor_assist) => motor_assist.battery_capacity,
+        None => 0.0,
+    }
+}
+
+/// Returns the CdA in square meters at the given ground speed, from the CdA speed bands or the CdA-vs-speed curve
+/// if any, and `cda_surface` otherwise or if the curve is empty.
+fn cda_at(velocity: f64, resistance_model: &BicycleResistanceModel) -> f64 {
+    if let Some(cda_speed_bands) = &resistance_model.cda_speed_bands {
+        return cda_speed_bands.cda_at(velocity);
+    }
+    resistance_model
+        .cda_speed_curve
+        .as_ref()
+        .and_then(|cda_speed_curve| kinematics::interpolate_cda(cda_speed_curve, velocity))
+        .unwrap_or(resistance_model.cda_surface)
+}
+
+/// Returns the air resistance coefficient `0.5 * CdA * density` at the given ground speed and relative wind speed,
+/// with the Reynolds number correction of the CdA if any.
+fn air_resistance_coef_at(
+    velocity: f64,
+    wind_speed: f64,
+    density: f64,
+    viscosity: f64,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    let cda = cda_at(velocity, resistance_model);
+    match resistance_model.reynolds_correction {
+        Some(correction) => {
+            let reynolds = kinematics::reynolds_number(
+                velocity + wind_speed,
+                density,
+                viscosity,
+                correction.characteristic_length,
+            );
+            0.5 * density
+                * kinematics::reynolds_corrected_cda(
+                    cda,
+                    reynolds,
+                    correction.reference_reynolds_number,
+                    correction.sensitivity,
+                )
+        }
+        None => 0.5 * cda * density,
+    }
+}
+
+/// Returns the coefficient of rolling resistance on the segment, including its roughness.
+fn rolling_resistance_coef(road_segment: &RoadSegment, resistance_model: &BicycleResistanceModel) -> f64 {
+    road_segment.roughness * road_segment.rolling_resistance.unwrap_or(resistance_model.rolling_resistance)
+}
+
+/// Returns the drivetrain efficiency on the segment, from the segment if it overrides the model's.
+fn drivetrain_efficiency(road_segment: &RoadSegment, resistance_model: &BicycleResistanceModel) -> f64 {
+    road_segment.drivetrain_efficiency.unwrap_or(resistance_model.drivetrain_efficiency)
+}
+
+/// Returns the speed-dependent roughness loss force in newtons of a segment at the given velocity, see
+/// `kinematics::get_roughness_loss_force`.
+fn roughness_loss_force(velocity: f64, road_segment: &RoadSegment, resistance_model: &BicycleResistanceModel) -> f64 {
+    kinematics::get_roughness_loss_force(
+        velocity,
+        road_segment.roughness,
+        resistance_model.roughness_speed_loss,
+        resistance_model.total_mass(),
+    )
+}
+
+/// Returns the power in watts reaching the wheel when the rider pushes `input_power` on the pedals.
+///
+/// The drivetrain loss is affine: the proportional loss of `drivetrain_efficiency` plus the fixed
+/// `drivetrain_fixed_loss`, which never takes more than what is pedaled.
+pub fn wheel_power(input_power: f64, resistance_model: &BicycleResistanceModel) -> f64 {
+    wheel_power_with_efficiency(input_power, resistance_model.drivetrain_efficiency, resistance_model)
+}
+
+/// Returns the air density in kg/m^3 on the segment, interpolated in the resistance model's air density table
+/// if any.
+fn segment_air_density(road_segment: &RoadSegment, resistance_model: &BicycleResistanceModel) -> f64 {
+    match &resistance_model.air_density_table {
+        Some(table) => table.density(road_segment.altitude, road_segment.temperature),
+        None => kinematics::air_density(road_segment.altitude, road_segment.temperature),
+    }
+}
+
+/// Same as `wheel_power`, with the drivetrain efficiency of the segment.
+pub fn segment_wheel_power(
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    wheel_power_with_efficiency(
+        input_power,
+        drivetrain_efficiency(road_segment, resistance_model),
+        resistance_model,
+    )
+}
+
+/// Returns the input power in watts the rider pushes on the pedals of the segment for `wheel_power` to reach the
+/// wheel, the inverse of `segment_wheel_power`. A wheel power of zero or less needs no pedaling.
+pub fn segment_input_power(
+    wheel_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    if wheel_power <= 0.0 {
+        return 0.0;
+    }
+    (wheel_power + resistance_model.drivetrain_fixed_loss) / drivetrain_efficiency(road_segment, resistance_model)
+}
+
+/// Same as `wheel_power`, with the given proportional drivetrain efficiency.
+fn wheel_power_with_efficiency(
+    input_power: f64,
+    drivetrain_efficiency: f64,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    let proportional_power = input_power * drivetrain_efficiency;
+    proportional_power - f64::clamp(proportional_power, 0.0, resistance_model.drivetrain_fixed_loss)
+}
+
+/// Returns the input power in watts the rider needs to keep moving up a segment.
+///
+/// Below `kinematics::TORQUE_LIMIT_VELOCITY` the rider is torque-limited, so this is the power whose propulsive
+/// force at that velocity balances the rolling resistance and gravity forces. It is negative on descents steep
+/// enough to roll without pedaling. The motor assist is counted as available, with a full battery.
+pub fn minimum_power_to_move(road_segment: &RoadSegment, resistance_model: &BicycleResistanceModel) -> f64 {
+    minimum_power_to_move_with_battery(road_segment, resistance_model, full_battery_energy(resistance_model))
+}
+
+/// Same as `minimum_power_to_move`, starting the segment with `battery_energy` watt-hours in the battery. The
+/// motor assist counted is the energy left spread over the segment ridden at `kinematics::TORQUE_LIMIT_VELOCITY`,
+/// up to `MotorAssist::max_assist_power`, so none with an empty battery.
+pub fn minimum_power_to_move_with_battery(
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    battery_energy: f64,
+) -> f64 {
+    let resisting_force = kinematics::get_rolling_resistance_force(
+        rolling_resistance_coef(road_segment, resistance_model),
+        resistance_model.total_mass(),
+    ) + kinematics::get_gravity_force(road_segment.slope, resistance_model.total_mass())
+        + roughness_loss_force(kinematics::TORQUE_LIMIT_VELOCITY, road_segment, resistance_model);
+    let assist_power = match resistance_model.motor_assist {
+        Some(motor_assist) => f64::min(
+            motor_assist.max_assist_power,
+            battery_energy * JOULES_PER_WATT_HOUR * kinematics::TORQUE_LIMIT_VELOCITY / road_segment.length,
+        ),
+        None => 0.0,
+    };
+    let required_wheel_power = resisting_force * kinematics::TORQUE_LIMIT_VELOCITY - assist_power;
+    let drivetrain_efficiency = drivetrain_efficiency(road_segment, resistance_model);
+    if required_wheel_power <= 0.0 {
+        return required_wheel_power / drivetrain_efficiency;
+    }
+    (required_wheel_power + resistance_model.drivetrain_fixed_loss) / drivetrain_efficiency
+}
+
+/// Computes the net force on the rider and bicycle at the given velocity and power on a segment, from plain
+/// physical inputs rather than the coefficients the integrator precomputes, e.g. to study the physics without
+/// riding the segment.
+///
+/// The force includes the drivetrain losses, the motor assist if any, the air resistance with the segment's
+/// relative wind, the rolling and roughness resistances and gravity. At a standstill, the propulsive force is
+/// capped by the torque limit, see `kinematics::TORQUE_LIMIT_VELOCITY`, so the force stays finite.
+///
+/// # Arguments
+///
+/// * `velocity` - The ground speed in meters per second, not negative.
+/// * `input_power` - The power the rider pushes on the pedals in watts.
+/// * `road_segment` - The segment, whose length is ignored.
+/// * `resistance_model` - The resistance model of the bicycle.
+///
+/// # Returns
+///
+/// * `f64` - The net force in newtons along the road, positive when the rider accelerates.
+///
+/// # Example
+///
+/// ```
+/// let force = net_force(10.0, 250.0, &road_segment, &default_resistance_model());
+/// let acceleration = force / default_resistance_model().total_mass();
+/// ```
+pub fn net_force(
+    velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    let assist_power = match resistance_model.motor_assist {
+        Some(motor_assist) => motor_assist.max_assist_power,
+        None => 0.0,
+    };
+    net_force_with_assist(velocity, input_power, assist_power, road_segment, resistance_model)
+}
+
+/// Same as `net_force`, with the motor assist power in watts given rather than the max of the motor, e.g. zero once
+/// the battery is empty.
+fn net_force_with_assist(
+    velocity: f64,
+    input_power: f64,
+    assist_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    let total_mass = resistance_model.total_mass();
+    let density = segment_air_density(road_segment, resistance_model);
+    let viscosity = kinematics::air_viscosity(road_segment.temperature);
+    let rolling_resistance_force =
+        kinematics::get_rolling_resistance_force(rolling_resistance_coef(road_segment, resistance_model), total_mass);
+    kinematics::get_total_force_with_constant_forces(
+        kinematics::kinetic_energy(velocity, total_mass),
+        segment_wheel_power(input_power, road_segment, resistance_model) + assist_power,
+        air_resistance_coef_at(velocity, road_segment.relative_wind_speed, density, viscosity, resistance_model),
+        road_segment.relative_wind_speed,
+        rolling_resistance_force + roughness_loss_force(velocity, road_segment, resistance_model),
+        kinematics::get_gravity_force(road_segment.slope, total_mass),
+        total_mass,
+    )
+}
+
+/// Computes the instantaneous acceleration `dv/dt` of the rider at the given state, the derivative the integrator
+/// follows, e.g. for a speed controller. It is `net_force` divided by the total mass, so it stays finite near a
+/// standstill where the propulsive force is capped by the torque limit.
+///
+/// # Arguments
+///
+/// * `velocity` - The ground speed in meters per second, not negative.
+/// * `input_power` - The power the rider pushes on the pedals in watts.
+/// * `road_segment` - The segment, whose length is ignored.
+/// * `resistance_model` - The resistance model of the bicycle.
+///
+/// # Returns
+///
+/// * `f64` - The acceleration in meters per second squared, zero at the steady velocity, see `steady_velocity`,
+///   and negative above it.
+///
+/// # Example
+///
+/// ```
+/// let acceleration = instantaneous_acceleration(10.0, 250.0, &road_segment, &default_resistance_model());
+/// ```
+pub fn instantaneous_acceleration(
+    velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    net_force(velocity, input_power, road_segment, resistance_model) / resistance_model.total_mass()
+}
 
-fn compute_time_and_final_velocity(
+/// Computes the time to ride a segment and the final velocity, assuming a fully charged battery if the
+/// bicycle has motor assist.
+pub fn compute_time_and_final_velocity(
     initial_velocity: f64,
     input_power: f64,
     road_segment: &RoadSegment,
     resistance_model: &BicycleResistanceModel,
 ) -> (f64, f64) {
-    let mut time: f64 = 0.0;
-    let mut position = 0.0;
-    let mut current_velocity = initial_velocity;
-    let mut step_size;
-    let air_resistance_coef = road_segment.relative_wind_speed * resistance_model.cda_surface
-        * kinematics::air_density(road_segment.temperature, road_segment.temperature);
-    loop {
-        let kinetic_energy = kinematics::kinetic_energy(current_velocity, resistance_model.total_mass);
-        let force = kinematics::get_total_force(
-            kinetic_energy,
-            input_power * resistance_model.drivetrain_efficiency,
-            road_segment.roughness * resistance_model.rolling_resistance,
-            air_resistance_coef,
-            road_segment.relative_wind_speed,
-            road_segment.slope,
-            resistance_model.total_mass,
-        );
-        step_size = KINETIC_ENERGY_TOL / (0.001 + f64::abs(force));
-        if position + step_size > road_segment.length {
-            step_size = road_segment.length - position;
-        }
-        let new_kinetic_energy = kinetic_energy + force * step_size;
-        let new_velocity = f64::max(MIN_VELOCITY, kinematics::velocity(new_kinetic_energy, resistance_model.total_mass));
+    compute_time_and_final_velocity_with_observer(
+        initial_velocity,
+        input_power,
+        road_segment,
+        resistance_model,
+        None,
+    )
+}
 
-        if position + step_size >= road_segment.length {
-            step_size = road_segment.length - position;
-            time += step_size / (0.5 * (new_velocity + current_velocity));
-            break;
+/// The state of the integrator after an integration step, passed to step observers.
+///
+/// # Fields
+///
+/// * `position` - The distance from the start of the segment in meters.
+/// * `time` - The time since the start of the segment in seconds.
+/// * `velocity` - The velocity in meters per second.
+/// * `force` - The total force in newtons at the start of the step.
+/// * `step_size` - The length of the step in meters.
+#[derive(Clone, Copy, Debug)]
+pub struct IntegrationStep {
+    pub position: f64,
+    pub time: f64,
+    pub velocity: f64,
+    pub force: f64,
+    pub step_size: f64,
+}
+
+/// How the integrator chooses the length of its steps along a segment.
+///
+/// * `Energy` - The step size adapts to keep the local kinetic energy error near `STEP_ERROR_TOL`, starting with a
+///   step changing the kinetic energy by about `KINETIC_ENERGY_TOL`.
+/// * `Distance` - The segment is covered in fixed increments of `step_length` meters from its start, each halved
+///   into sub-steps as needed to keep the local kinetic energy error under `STEP_ERROR_TOL`. The step positions
+///   are then predictable, e.g. to compare observer logs between runs. A `step_length` that is not a positive
+///   number never reaches the end of a segment, see `InfeasibilityReason::InvalidStepLength`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepStrategy {
+    Energy,
+    Distance { step_length: f64 },
+}
+
+/// Same as `compute_time_and_final_velocity`, calling `observer` after every accepted integration step, e.g. to
+/// log the integrator state. The observer only gets a copy of the state, so it cannot alter the simulation.
+///
+/// # Example
+///
+/// ```
+/// let step_vec = std::cell::RefCell::new(Vec::new());
+/// let log_step = |step: &IntegrationStep| step_vec.borrow_mut().push(*step);
+/// let (time, final_velocity) =
+///     compute_time_and_final_velocity_with_observer(5.0, 300.0, &road_segment, &resistance_model, Some(&log_step));
+/// ```
+pub fn compute_time_and_final_velocity_with_observer(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    observer: Option<&dyn Fn(&IntegrationStep)>,
+) -> (f64, f64) {
+    let outcome = ride_segment_with_observer(
+        initial_velocity,
+        input_power,
+        road_segment,
+        resistance_model,
+        full_battery_energy(resistance_model),
+        f64::INFINITY,
+        observer,
+    );
+    (outcome.time, outcome.final_velocity)
+}
+
+/// Same as `compute_time_and_final_velocity`, appending the trajectory within the segment to `trajectory_vec`,
+/// e.g. to plot the speed between the segment ends. The samples are `(position, velocity, time)` tuples, with the
+/// position in meters and the time in seconds from the start of the segment: the initial state, then the state
+/// after every accepted integration step, the last sample being the end of the segment. Recording is opt-in
+/// through this function since a long course takes many steps.
+///
+/// # Example
+///
+/// ```
+/// let mut trajectory_vec = Vec::new();
+/// let (time, final_velocity) = compute_time_and_final_velocity_with_trajectory(
+///     5.0,
+///     300.0,
+///     &road_segment,
+///     &resistance_model,
+///     &mut trajectory_vec,
+/// );
+/// ```
+pub fn compute_time_and_final_velocity_with_trajectory(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    trajectory_vec: &mut Vec<(f64, f64, f64)>,
+) -> (f64, f64) {
+    trajectory_vec.push((0.0, initial_velocity, 0.0));
+    let trajectory_vec = RefCell::new(trajectory_vec);
+    let record_step = |step: &IntegrationStep| {
+        trajectory_vec.borrow_mut().push((step.position, step.velocity, step.time));
+    };
+    compute_time_and_final_velocity_with_observer(
+        initial_velocity,
+        input_power,
+        road_segment,
+        resistance_model,
+        Some(&record_step),
+    )
+}
+
+/// Same as `compute_time_and_final_velocity`, with a relative wind speed varying within the segment, e.g. gusts
+/// from an anemometer log. `wind(position, time)` gives the relative wind speed in meters per second (positive
+/// for a headwind) at `position` meters from the start of the segment, `time` seconds after entering it. It
+/// replaces `road_segment.relative_wind_speed`. Since drag is quadratic in the air speed, a varying wind costs
+/// more time than its mean. See `WindSeries` for a sampled wind.
+///
+/// # Example
+///
+/// ```
+/// let wind_series = WindSeries { sample_interval: 1.0, wind_speed_vec: vec![2.0, 5.0, 1.0, 3.0] };
+/// let (time, final_velocity) = compute_time_and_final_velocity_with_wind(
+///     5.0,
+///     300.0,
+///     &road_segment,
+///     &resistance_model,
+///     &|_, time| wind_series.wind_speed_at(time),
+/// );
+/// ```
+pub fn compute_time_and_final_velocity_with_wind(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    wind: &dyn Fn(f64, f64) -> f64,
+) -> (f64, f64) {
+    let outcome = ride_segment_in_wind(
+        initial_velocity,
+        input_power,
+        road_segment,
+        resistance_model,
+        full_battery_energy(resistance_model),
+        f64::INFINITY,
+        SegmentRideOptions { step_strategy: StepStrategy::Energy, wind: Some(wind), observer: None },
+    );
+    (outcome.time, outcome.final_velocity)
+}
+
+/// A relative wind speed sampled at regular intervals, e.g. of time or distance.
+///
+/// # Fields
+///
+/// * `sample_interval` - The interval between two samples, e.g. in seconds.
+/// * `wind_speed_vec` - The relative wind speed of each sample in meters per second, positive for a headwind.
+pub struct WindSeries {
+    pub sample_interval: f64,
+    pub wind_speed_vec: Vec<f64>,
+}
+
+impl WindSeries {
+    /// Returns the wind speed at `t`, linearly interpolated between the samples, which are usually coarser than
+    /// the integration steps. The first and last samples hold before and after the series, and an empty series is
+    /// still air.
+    pub fn wind_speed_at(&self, t: f64) -> f64 {
+        let n_samples = self.wind_speed_vec.len();
+        if n_samples == 0 {
+            return 0.0;
         }
-        time += step_size / (0.5 * (new_velocity + current_velocity));
-        position += step_size;
-        current_velocity = new_velocity;
+        let sample_position = f64::clamp(t / self.sample_interval, 0.0, (n_samples - 1) as f64);
+        let index = usize::min(sample_position as usize, n_samples.saturating_sub(2));
+        let Some(&next_wind_speed) = self.wind_speed_vec.get(index + 1) else {
+            return self.wind_speed_vec[index];
+        };
+        let weight = sample_position - index as f64;
+        (1.0 - weight) * self.wind_speed_vec[index] + weight * next_wind_speed
     }
-    return (time, current_velocity);
 }
 
+/// The outcome of riding a single segment.
+///
+/// # Fields
+///
+/// * `time` - The time to ride the segment in seconds.
+/// * `final_velocity` - The velocity at the end of the segment in meters per second.
+/// * `battery_energy` - The motor assist battery energy left at the end of the segment in watt-hours.
+/// * `aero_energy` - The work done against air resistance over the segment in joules.
+/// * `wind_energy` - The part of `aero_energy` due to the wind, i.e. the difference with the work that would
+///   have been done in still air at the same speeds. Negative when the wind helped (tailwind).
+/// * `braking_energy` - The kinetic energy dissipated by braking over the segment in joules.
+/// * `step_count` - The number of integration steps tried on the segment, including the ones rejected by the step
+///   size controller, e.g. to find the segments that are slow to simulate.
+pub struct SegmentOutcome {
+    pub time: f64,
+    pub final_velocity: f64,
+    pub battery_energy: f64,
+    pub aero_energy: f64,
+    pub wind_energy: f64,
+    pub braking_energy: f64,
+    pub step_count: usize,
+}
 
+/// Rides a segment at the given input power, starting with the given battery energy in watt-hours.
+///
+/// While the battery has energy left, the motor assist power is added to the rider's power at the wheel and
+/// drained from the battery, scaled down on the step that empties it. Once empty, the rider continues on their
+/// own power for the rest of the segment.
+///
+/// The rider brakes at `BRAKING_DECELERATION` when needed to end the segment no faster than
+/// `exit_velocity_limit`, which can be `f64::INFINITY` for no limit. On a fixed gear, the rider also holds their
+/// speed under `FixedGear::max_velocity`, and the kinetic energy scrubbed counts as braking.
+pub fn ride_segment(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    battery_energy: f64,
+    exit_velocity_limit: f64,
+) -> SegmentOutcome {
+    ride_segment_with_observer(
+        initial_velocity,
+        input_power,
+        road_segment,
+        resistance_model,
+        battery_energy,
+        exit_velocity_limit,
+        None,
+    )
+}
 
-pub fn compute_all_times(
+/// Same as `ride_segment`, calling `observer` after every accepted integration step.
+pub fn ride_segment_with_observer(
     initial_velocity: f64,
-    initial_anaerobic_reserve: f64,
-    input_power_vec: &Vec<f64>,
-    road_segment_vec: &Vec<RoadSegment>,
+    input_power: f64,
+    road_segment: &RoadSegment,
     resistance_model: &BicycleResistanceModel,
-    rider_model: &morton::RiderModel,
-    out_duration_vec: &mut Vec<f64>,
-    out_power_vec: &mut Vec<f64>,
-    out_anaerobic_reserve: &mut Vec<f64>,
-) -> f64 {
-    let n_segments = input_power_vec.len();
-    let mut velocity = initial_velocity;
-    let mut current_anaerobic_reserve = initial_anaerobic_reserve;
+    battery_energy: f64,
+    exit_velocity_limit: f64,
+    observer: Option<&dyn Fn(&IntegrationStep)>,
+) -> SegmentOutcome {
+    ride_segment_in_wind(
+        initial_velocity,
+        input_power,
+        road_segment,
+        resistance_model,
+        battery_energy,
+        exit_velocity_limit,
+        SegmentRideOptions { step_strategy: StepStrategy::Energy, wind: None, observer },
+    )
+}
 
-    out_duration_vec.resize(n_segments, 0.0);
-    out_anaerobic_reserve.resize(n_segments, 0.0);
-    out_power_vec.copy_from_slice(input_power_vec);
+/// How `ride_segment_in_wind` integrates a segment.
+///
+/// # Fields
+///
+/// * `step_strategy` - How the integrator steps along the segment.
+/// * `wind` - The relative wind speed `wind(position, time)` within the segment, replacing
+///   `road_segment.relative_wind_speed`, if any.
+/// * `observer` - Called after every accepted integration step, if any.
+struct SegmentRideOptions<'a> {
+    step_strategy: StepStrategy,
+    wind: Option<&'a dyn Fn(f64, f64) -> f64>,
+    observer: Option<&'a dyn Fn(&IntegrationStep)>,
+}
 
-    let mut total_duration = 0.0;
-    for i in 0..n_segments {
-        let time_and_velocity = |input_power| {
-            compute_time_and_final_velocity(
-                velocity,
-                input_power,
-                &road_segment_vec[i],
-                resistance_model,
-            )
+/// Same as `ride_segment_with_observer`, with the steps, wind and observer given by `ride_options`.
+fn ride_segment_in_wind(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    battery_energy: f64,
+    exit_velocity_limit: f64,
+    ride_options: SegmentRideOptions,
+) -> SegmentOutcome {
+    let SegmentRideOptions { step_strategy, wind, observer } = ride_options;
+    let mut battery_energy = battery_energy;
+    let mut aero_energy = 0.0;
+    let mut wind_energy = 0.0;
+    let mut braking_energy = 0.0;
+    let mut step_count = 0;
+    let mut time: f64 = 0.0;
+    let mut position = 0.0;
+    let mut current_velocity = initial_velocity;
+    let mut previous_error = STEP_ERROR_TOL;
+    // Constant within the segment, so computed once rather than at every step.
+    let density = segment_air_density(road_segment, resistance_model);
+    let viscosity = kinematics::air_viscosity(road_segment.temperature);
+    let wheel_power = segment_wheel_power(input_power, road_segment, resistance_model);
+    let rolling_resistance_force = kinematics::get_rolling_resistance_force(
+        rolling_resistance_coef(road_segment, resistance_model),
+        resistance_model.total_mass(),
+    );
+    let gravity_force = kinematics::get_gravity_force(road_segment.slope, resistance_model.total_mass());
+    let cadence_velocity_limit =
+        if resistance_model.can_coast { f64::INFINITY } else { resistance_model.fixed_gear.max_velocity() };
+    let wind_speed_at = |position: f64, time: f64| {
+        match wind {
+            Some(wind) => wind(position, time),
+            None => road_segment.relative_wind_speed,
+        }
+    };
+    let air_resistance_coef_at = |velocity: f64, wind_speed: f64| {
+        air_resistance_coef_at(velocity, wind_speed, density, viscosity, resistance_model)
+    };
+    let force_at = |velocity: f64, assist_power: f64, wind_speed: f64| {
+        kinematics::get_total_force_with_constant_forces(
+            kinematics::kinetic_energy(velocity, resistance_model.total_mass()),
+            wheel_power + assist_power,
+            air_resistance_coef_at(velocity, wind_speed),
+            wind_speed,
+            rolling_resistance_force + roughness_loss_force(velocity, road_segment, resistance_model),
+            gravity_force,
+            resistance_model.total_mass(),
+        )
+    };
+    let velocity_of = |kinetic_energy: f64| {
+        f64::max(MIN_VELOCITY, kinematics::velocity(kinetic_energy, resistance_model.total_mass()))
+    };
+
+    // The first step only changes the kinetic energy by about `KINETIC_ENERGY_TOL`, then the controller adapts.
+    let mut step_size = match step_strategy {
+        StepStrategy::Energy => {
+            KINETIC_ENERGY_TOL / (0.001 + f64::abs(force_at(current_velocity, 0.0, wind_speed_at(0.0, 0.0))))
+        }
+        StepStrategy::Distance { step_length } => step_length,
+    };
+    // The end of the current distance increment, only used by `StepStrategy::Distance`.
+    let mut increment_end = step_size;
+    while position < road_segment.length {
+        step_count += 1;
+        // The last step is cut to end exactly at the end of the segment.
+        let remaining_length = road_segment.length - position;
+        let is_last_step = step_size >= remaining_length;
+        if is_last_step {
+            step_size = remaining_length;
+        }
+        // On the step that empties the battery, the assist is scaled down to spread the energy left over the step.
+        let assist_power = match resistance_model.motor_assist {
+            Some(motor_assist) if battery_energy > 0.0 => f64::min(
+                motor_assist.max_assist_power,
+                battery_energy * JOULES_PER_WATT_HOUR * current_velocity / step_size,
+            ),
+            _ => 0.0,
         };
+        let wind_speed = wind_speed_at(position, time);
+        let air_resistance_coef = air_resistance_coef_at(current_velocity, wind_speed);
+        let kinetic_energy = kinematics::kinetic_energy(current_velocity, resistance_model.total_mass());
+        let force = force_at(current_velocity, assist_power, wind_speed);
+        let air_force = kinematics::get_air_resistance_force(current_velocity, wind_speed, air_resistance_coef);
+        let still_air_force = kinematics::get_air_resistance_force(current_velocity, 0.0, air_resistance_coef);
 
-        let (mut new_time, mut new_velocity) = time_and_velocity( out_power_vec[i]);
-        let tau = morton::time_to_exhaustion(rider_model,  out_power_vec[i], current_anaerobic_reserve);
-        println!("tau = {:?}s", tau);
-        if tau < new_time {
-            for j in i..n_segments {
-                if out_power_vec[i] < rider_model.critical_power {
-                    break;
+        // Step doubling: the difference between one full Euler step and two half steps estimates the local error.
+        let full_step_kinetic_energy = kinetic_energy + force * step_size;
+        let half_step_kinetic_energy = kinetic_energy + 0.5 * force * step_size;
+        let half_step_wind_speed =
+            wind_speed_at(position + 0.5 * step_size, time + 0.5 * step_size / current_velocity);
+        let new_kinetic_energy = half_step_kinetic_energy
+            + 0.5 * force_at(velocity_of(half_step_kinetic_energy), assist_power, half_step_wind_speed) * step_size;
+        let error = f64::abs(new_kinetic_energy - full_step_kinetic_energy);
+        if error > STEP_ERROR_TOL && step_size > MIN_STEP_SIZE {
+            step_size *= match step_strategy {
+                StepStrategy::Energy => {
+                    f64::max(MIN_STEP_FACTOR, STEP_SAFETY_FACTOR * f64::sqrt(STEP_ERROR_TOL / error))
                 }
-                out_power_vec[j] =  rider_model.critical_power;
-            }
-            (new_time, new_velocity) = time_and_velocity( out_power_vec[i]);
+                StepStrategy::Distance { .. } => 0.5,
+            };
+            continue;
         }
-        
-        current_anaerobic_reserve = morton::update_anaerobic_reserve(rider_model,  out_power_vec[i], new_time, current_anaerobic_reserve);
-        println!(
-        "{:?}W for {:?}s > {:?}J",
-        out_power_vec[i],
-        new_time,
-        current_anaerobic_reserve
-        );
-        out_anaerobic_reserve[i] = current_anaerobic_reserve;
-        out_duration_vec[i] = new_time;
-        total_duration += new_time;
-        velocity = new_velocity;
-    }
-    return  total_duration;
-}
\ No newline at end of file
+
+        // Without a freewheel, the rider holds the speed at the maximal cadence with their legs, like braking.
+        let braking_velocity = f64::min(
+            braking_velocity_limit(exit_velocity_limit, remaining_length - step_size),
+            cadence_velocity_limit,
+        );
+        let free_velocity = velocity_of(new_kinetic_energy);
+        let new_velocity = f64::min(braking_velocity, free_velocity);
+        if new_velocity < free_velocity {
+            braking_energy += kinematics::kinetic_energy(free_velocity, resistance_model.total_mass())
+                - kinematics::kinetic_energy(new_velocity, resistance_model.total_mass());
+        }
+        let step_time = step_size / (0.5 * (new_velocity + current_velocity));
+        time += step_time;
+        battery_energy = f64::max(0.0, battery_energy - assist_power * step_time / JOULES_PER_WATT_HOUR);
+        aero_energy += air_force * step_size;
+        wind_energy += (air_force - still_air_force) * step_size;
+        position = if is_last_step { road_segment.length } else { position + step_size };
+        current_velocity = new_velocity;
+        if let Some(observer) = observer {
+            observer(&IntegrationStep {
+                position,
+                time,
+                velocity: current_velocity,
+                force,
+                step_size,
+            });
+        }
+
+        let StepStrategy::Distance { step_length } = step_strategy else {
+            // PI controller for a first order method: the integral term drives the error to the tolerance and the
+            // proportional term damps the oscillations of the step size.
+            let error = f64::max(error, 1e-12 * STEP_ERROR_TOL);
+            let step_factor = STEP_SAFETY_FACTOR
+                * f64::powf(STEP_ERROR_TOL / error, STEP_INTEGRAL_GAIN)
+                * f64::powf(previous_error / STEP_ERROR_TOL, STEP_PROPORTIONAL_GAIN);
+            step_size *= f64::clamp(step_factor, MIN_STEP_FACTOR, MAX_STEP_FACTOR);
+            previous_error = error;
+            continue;
+        };
+        // The sub-steps keep their size until the end of the current increment, then the next one is tried whole.
+        if position >= increment_end - MIN_STEP_SIZE {
+            increment_end += step_length;
+            step_size = step_length;
+        }
+        step_size = f64::min(step_size, increment_end - position);
+    }
+    SegmentOutcome {
+        time,
+        final_velocity: current_velocity,
+        battery_energy,
+        aero_energy,
+        wind_energy,
+        braking_energy,
+        step_count,
+    }
+}
+
+
+
+/// Computes the time to ride a segment and the final velocity by quadrature rather than stepping.
+///
+/// With constant power, slope and wind, the velocity relaxes monotonically towards the terminal velocity `v*`
+/// where the net force `F(v)` vanishes, and `dt = m dv / F(v)`, `dx = m v dv / F(v)`. `v*` is found by
+/// bisection to `tolerance`, and both integrals are taken over `s` with `v = v* - (v* - v0) exp(-s)`, which removes the
+/// singularity at `v*` so that Simpson's rule stays accurate on segments ending near terminal velocity. The end
+/// of the segment is then found by bisection on `s`. Motor assist, if any, is assumed to last the whole segment.
+/// The CdA is resolved as by the stepping integrator, from the speed bands or curve and with the Reynolds number
+/// correction, if any.
+///
+/// Falls back to `compute_time_and_final_velocity` when the rider cannot hold a positive velocity.
+pub fn compute_time_and_final_velocity_analytic(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    tolerance: &root_finding::Tolerance,
+) -> (f64, f64) {
+    let total_mass = resistance_model.total_mass();
+    let density = segment_air_density(road_segment, resistance_model);
+    let viscosity = kinematics::air_viscosity(road_segment.temperature);
+    let assist_power = match resistance_model.motor_assist {
+        Some(motor_assist) => motor_assist.max_assist_power,
+        None => 0.0,
+    };
+    let wheel_power = segment_wheel_power(input_power, road_segment, resistance_model) + assist_power;
+    let constant_force = kinematics::get_rolling_resistance_force(
+        rolling_resistance_coef(road_segment, resistance_model),
+        total_mass,
+    ) + kinematics::get_gravity_force(road_segment.slope, total_mass);
+    let wind_speed = road_segment.relative_wind_speed;
+    let force = |velocity: f64| {
+        kinematics::get_propulsive_force(wheel_power, velocity)
+            - kinematics::get_air_resistance_force(
+                velocity,
+                wind_speed,
+                air_resistance_coef_at(velocity, wind_speed, density, viscosity, resistance_model),
+            )
+            - constant_force
+            - roughness_loss_force(velocity, road_segment, resistance_model)
+    };
+    let fallback = || compute_time_and_final_velocity(initial_velocity, input_power, road_segment, resistance_model);
+
+    if force(0.0) <= 0.0 {
+        return fallback();
+    }
+    let mut high = 1.0;
+    while force(high) > 0.0 {
+        high *= 2.0;
+        if high > 1e4 {
+            return fallback();
+        }
+    }
+    let terminal_velocity = root_finding::bisect(force, 0.0, high, tolerance).value;
+    let initial_velocity = f64::max(0.0, initial_velocity);
+    let velocity_gap = terminal_velocity - initial_velocity;
+
+    let max_relaxation =
+        f64::ln(f64::abs(velocity_gap) / (ANALYTIC_TERMINAL_VELOCITY_TOL * terminal_velocity));
+    if max_relaxation <= 0.0 {
+        return (road_segment.length / terminal_velocity, terminal_velocity);
+    }
+    let velocity_at = |relaxation: f64| terminal_velocity - velocity_gap * f64::exp(-relaxation);
+    let distance_and_time = |relaxation: f64| {
+        let step = relaxation / ANALYTIC_QUADRATURE_INTERVALS as f64;
+        let mut distance = 0.0;
+        let mut time = 0.0;
+        for k in 0..=ANALYTIC_QUADRATURE_INTERVALS {
+            let weight = if k == 0 || k == ANALYTIC_QUADRATURE_INTERVALS {
+                1.0
+            } else if k % 2 == 1 {
+                4.0
+            } else {
+                2.0
+            };
+            let velocity = velocity_at(k as f64 * step);
+            let dt_ds = total_mass * (terminal_velocity - velocity) / force(velocity);
+            time += weight * dt_ds;
+            distance += weight * dt_ds * velocity;
+        }
+        (distance * step / 3.0, time * step / 3.0)
+    };
+
+    let (full_distance, full_time) = distance_and_time(max_relaxation);
+    if full_distance <= road_segment.length {
+        let remaining_time = (road_segment.length - full_distance) / terminal_velocity;
+        return (full_time + remaining_time, terminal_velocity);
+    }
+    let distance_gap = |relaxation: f64| distance_and_time(relaxation).0 - road_segment.length;
+    let relaxation = root_finding::bisect(distance_gap, 0.0, max_relaxation, tolerance).value;
+    (distance_and_time(relaxation).1, velocity_at(relaxation))
+}
+
+/// Simulates a coast-down: the rider stops pedaling at `initial_velocity` and freewheels along the segment.
+///
+/// The velocity is integrated in time with a fourth-order Runge-Kutta scheme, with no input power. Quadratic
+/// air resistance dominates at high speed and the constant rolling resistance at low speed, giving the
+/// characteristic nonlinear decay used to measure CdA and rolling resistance.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the coast-down in meters per second.
+/// * `road_segment` - The segment coasted on.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `dt` - The time step in seconds.
+///
+/// # Returns
+///
+/// * `Result<Vec<(f64, f64)>, String>` - The `(time, velocity)` samples every `dt` seconds, starting at
+///   `(0, initial_velocity)` and ending when the rider is down to the minimal velocity or reaches the end of the
+///   segment, or an error if `dt` is not a positive number.
+pub fn coast_down(
+    initial_velocity: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    dt: f64,
+) -> Result<Vec<(f64, f64)>, String> {
+    if dt.is_nan() || dt <= 0.0 {
+        return Err(format!("time step of {} s, expected a positive number", dt));
+    }
+    let total_mass = resistance_model.total_mass();
+    let density = segment_air_density(road_segment, resistance_model);
+    let constant_force = kinematics::get_rolling_resistance_force(
+        rolling_resistance_coef(road_segment, resistance_model),
+        total_mass,
+    ) + kinematics::get_gravity_force(road_segment.slope, total_mass);
+    let acceleration = |velocity: f64| {
+        -(kinematics::get_air_resistance_force(
+            velocity,
+            road_segment.relative_wind_speed,
+            0.5 * cda_at(velocity, resistance_model) * density,
+        ) + constant_force
+            + roughness_loss_force(velocity, road_segment, resistance_model))
+            / total_mass
+    };
+
+    let mut samples = vec![(0.0, initial_velocity)];
+    let mut time = 0.0;
+    let mut position = 0.0;
+    let mut velocity = initial_velocity;
+    while velocity > MIN_VELOCITY && position < road_segment.length {
+        let k1 = acceleration(velocity);
+        let k2 = acceleration(velocity + 0.5 * dt * k1);
+        let k3 = acceleration(velocity + 0.5 * dt * k2);
+        let k4 = acceleration(velocity + dt * k3);
+        let new_velocity = f64::max(0.0, velocity + dt / 6.0 * (k1 + 2.0 * k2 + 2.0 * k3 + k4));
+        position += 0.5 * (velocity + new_velocity) * dt;
+        time += dt;
+        velocity = new_velocity;
+        samples.push((time, velocity));
+    }
+    Ok(samples)
+}
+
+/// Returns the highest velocity from which the rider can brake down to `velocity_limit` within `distance`
+/// meters.
+fn braking_velocity_limit(velocity_limit: f64, distance: f64) -> f64 {
+    f64::sqrt(velocity_limit * velocity_limit + 2.0 * BRAKING_DECELERATION * f64::max(0.0, distance))
+}
+
+/// Returns the velocity limit at the end of each segment such that the rider can brake down to
+/// `final_velocity` by the finish, or `f64::INFINITY` everywhere without a final velocity.
+pub fn exit_velocity_limits(road_segment_vec: &Vec<RoadSegment>, final_velocity: Option<f64>) -> Vec<f64> {
+    let mut options = default_simulation_options();
+    options.final_velocity = final_velocity;
+    let mut exit_velocity_limit_vec = Vec::new();
+    fill_exit_velocity_limits(road_segment_vec, &options, &mut exit_velocity_limit_vec);
+    exit_velocity_limit_vec
+}
+
+/// Writes into `exit_velocity_limit_vec` the speed limit of every corner of `options.corner_vec` at the end of its
+/// segment, and `f64::INFINITY` elsewhere.
+fn fill_corner_velocity_limits(
+    road_segment_vec: &Vec<RoadSegment>,
+    options: &SimulationOptions,
+    exit_velocity_limit_vec: &mut Vec<f64>,
+) {
+    let n_segments = road_segment_vec.len();
+    exit_velocity_limit_vec.clear();
+    exit_velocity_limit_vec.resize(n_segments, f64::INFINITY);
+    for corner in options.corner_vec.iter().filter(|corner| corner.segment_index < n_segments) {
+        let radius = match options.road_width {
+            Some(road_width) => kinematics::racing_line_radius(corner.radius, corner.turn_angle, road_width),
+            None => corner.radius,
+        };
+        let corner_speed = kinematics::corner_speed_limit(radius, options.cornering_friction);
+        let exit_velocity_limit = &mut exit_velocity_limit_vec[corner.segment_index];
+        *exit_velocity_limit = f64::min(*exit_velocity_limit, corner_speed);
+    }
+}
+
+/// Writes into `exit_velocity_limit_vec` the velocity limit at the end of each segment such that the rider can
+/// brake down to `options.final_velocity` by the finish and to the speed limit of every corner of
+/// `options.corner_vec` when reaching it.
+fn fill_exit_velocity_limits(
+    road_segment_vec: &Vec<RoadSegment>,
+    options: &SimulationOptions,
+    exit_velocity_limit_vec: &mut Vec<f64>,
+) {
+    let n_segments = road_segment_vec.len();
+    fill_corner_velocity_limits(road_segment_vec, options, exit_velocity_limit_vec);
+    if options.final_velocity.is_none() && options.corner_vec.is_empty() {
+        return;
+    }
+    let mut velocity_limit = options.final_velocity.unwrap_or(f64::INFINITY);
+    for i in (0..n_segments).rev() {
+        velocity_limit = f64::min(velocity_limit, exit_velocity_limit_vec[i]);
+        exit_velocity_limit_vec[i] = velocity_limit;
+        velocity_limit = braking_velocity_limit(velocity_limit, road_segment_vec[i].length);
+    }
+}
+
+/// Returns the velocity at the end of a segment the rider freewheels along from `initial_velocity`, zero if they
+/// come to a stop before its end. See `coast_down`.
+fn coasting_exit_velocity(
+    initial_velocity: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+) -> f64 {
+    // COASTING_TIME_STEP is positive, so the coast-down cannot fail.
+    let sample_vec = coast_down(initial_velocity, road_segment, resistance_model, COASTING_TIME_STEP).unwrap();
+    let (_, final_velocity) = sample_vec[sample_vec.len() - 1];
+    if final_velocity > MIN_VELOCITY { final_velocity } else { 0.0 }
+}
+
+/// Same as `fill_exit_velocity_limits` for a rider who never brakes, so they can only slow down by freewheeling:
+/// the velocity limit at the end of each segment is the highest velocity from which freewheeling along the next
+/// segment ends it no faster than its own limit.
+///
+/// # Returns
+///
+/// * `Option<usize>` - The first segment too steep to keep under its limit even when entered at a crawl, where
+///   braking cannot be avoided, if any.
+fn fill_coasting_velocity_limits(
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    options: &SimulationOptions,
+    exit_velocity_limit_vec: &mut Vec<f64>,
+) -> Option<usize> {
+    let n_segments = road_segment_vec.len();
+    fill_corner_velocity_limits(road_segment_vec, options, exit_velocity_limit_vec);
+    let mut braking_segment_index = None;
+    let mut velocity_limit = options.final_velocity.unwrap_or(f64::INFINITY);
+    for i in (0..n_segments).rev() {
+        velocity_limit = f64::min(velocity_limit, exit_velocity_limit_vec[i]);
+        exit_velocity_limit_vec[i] = velocity_limit;
+        if velocity_limit == f64::INFINITY {
+            continue;
+        }
+        let overspeed = |initial_velocity: f64| {
+            coasting_exit_velocity(initial_velocity, &road_segment_vec[i], resistance_model) - velocity_limit
+        };
+        let crawl_velocity = 2.0 * MIN_VELOCITY;
+        if overspeed(crawl_velocity) > 0.0 {
+            braking_segment_index = Some(i);
+            velocity_limit = crawl_velocity;
+        } else if overspeed(MAX_STEADY_VELOCITY) <= 0.0 {
+            velocity_limit = f64::INFINITY;
+        } else {
+            velocity_limit =
+                root_finding::bisect(overspeed, crawl_velocity, MAX_STEADY_VELOCITY, &options.tolerance).value;
+        }
+    }
+    braking_segment_index
+}
+
+/// A corner at the end of a segment, which limits the speed the rider can take it at.
+///
+/// # Fields
+///
+/// * `segment_index` - The index of the segment at the end of which the corner is.
+/// * `radius` - The radius of the corner in meters, as followed without a racing line.
+/// * `turn_angle` - The change of direction through the corner in degrees, e.g. 180 for a hairpin.
+#[derive(Clone, Copy, Debug)]
+pub struct Corner {
+    pub segment_index: usize,
+    pub radius: f64,
+    pub turn_angle: f64,
+}
+
+/// A neutralized section of a course, e.g. behind the race director's car at the start of a gran fondo, ridden at
+/// a speed set externally rather than by the rider's pacing.
+///
+/// # Fields
+///
+/// * `segment_index` - The index of the neutralized segment.
+/// * `speed` - The speed the segment is ridden at in meters per second, positive. The simulation stops on a
+///   segment with another speed, see `InfeasibilityReason::InvalidNeutralSpeed`.
+#[derive(Clone, Copy, Debug)]
+pub struct NeutralSection {
+    pub segment_index: usize,
+    pub speed: f64,
+}
+
+/// Options of a simulation run.
+///
+/// # Fields
+///
+/// * `final_velocity` - The velocity in meters per second the rider must not exceed when crossing the finish
+///   line, e.g. to stop at the line. The rider brakes on the last segments as needed. `None` lets the rider
+///   finish at any speed.
+/// * `power_floor` - The lowest power in watts the rider pushes, e.g. a small soft-pedaling wattage on
+///   descents. Planned powers below it, including negative ones, are raised to it. Slowing down is not done
+///   through negative power but by braking, see `SimulationResult::braking_energy_vec`.
+/// * `power_cap` - An absolute power ceiling in watts enforced by a device, e.g. the ERG limit of a smart
+///   trainer. Every planned power is clamped to it, independently of the rider's physiological max power.
+///   With a cap below critical power, the anaerobic reserve only ever recovers. `None` applies no cap.
+/// * `corner_vec` - The corners of the course. The rider brakes as needed to take each one no faster than
+///   `kinematics::corner_speed_limit`.
+/// * `road_width` - The road width in meters the rider uses to take the corners on a racing line, which widens
+///   their radius, see `kinematics::racing_line_radius`. `None` keeps the radii of the corners.
+/// * `cornering_friction` - The lateral friction coefficient of the tyres in the corners.
+/// * `step_strategy` - How the integrator steps along each segment.
+/// * `altitude_acclimatization` - The acclimatization of the rider to altitude, e.g. on a given day of a stage
+///   race. The planned powers are then efforts in sea level watts, of which the rider delivers the
+///   `AltitudeAcclimatization::power_factor` of the segment altitude. `None` ignores the loss of power at
+///   altitude.
+/// * `neutral_section_vec` - The neutralized segments of the course, ridden at exactly their speed whatever the
+///   plan, see `NeutralSection`. The rider then pushes the power holding that speed, clamped between zero and the
+///   highest power they can hold over the segment (and `power_cap`), being carried along above it, e.g. in the
+///   bunch; any excess force, e.g. downhill, is braked away.
+/// * `forbid_braking` - Whether the rider must respect the corners and `final_velocity` without ever braking, only
+///   by easing off earlier: the power of a segment is then lowered as needed to end it under its speed limit,
+///   and the limits are found by freewheeling rather than braking back from the corners. A segment that cannot
+///   be kept under its limit even freewheeling makes the simulation stop with `BrakingRequired`.
+/// * `position_cda_vec` - The CdA in square meters of the rider's position on each segment, e.g. tucked on the
+///   flat and standing on steep ramps, replacing the CdA of the resistance model (including its speed curve or
+///   bands). Segments past its end, or all of them when it is empty, keep the model's CdA.
+/// * `position_change_penalty` - The time in seconds lost changing position, added to every segment whose CdA in
+///   `position_cda_vec` differs from the previous segment's, so frequent changes on a rolling course add up.
+/// * `regenerative_braking_efficiency` - The fraction of the braking energy captured, e.g. by an e-bike motor
+///   used as a generator, see `SimulationResult::recovered_energy_vec`. With motor assist, the captured energy
+///   recharges the battery, up to its capacity, at the end of each segment; without, it is only reported. Zero
+///   captures nothing.
+/// * `thermal_model` - The thermoregulation of the rider. The planned powers are then efforts in thermoneutral
+///   watts, of which the rider delivers the `ThermalModel::power_factor` of the heat strain built up from the
+///   segment temperatures. `None` ignores the heat.
+/// * `tolerance` - The stopping criterion of the root finding within the simulation, e.g. of the power holding a
+///   neutralized speed or of the velocity limits when braking is forbidden.
+pub struct SimulationOptions {
+    pub final_velocity: Option<f64>,
+    pub power_floor: f64,
+    pub power_cap: Option<f64>,
+    pub corner_vec: Vec<Corner>,
+    pub road_width: Option<f64>,
+    pub cornering_friction: f64,
+    pub step_strategy: StepStrategy,
+    pub altitude_acclimatization: Option<altitude::AltitudeAcclimatization>,
+    pub thermal_model: Option<thermal::ThermalModel>,
+    pub neutral_section_vec: Vec<NeutralSection>,
+    pub forbid_braking: bool,
+    pub position_cda_vec: Vec<f64>,
+    pub position_change_penalty: f64,
+    pub regenerative_braking_efficiency: f64,
+    pub tolerance: root_finding::Tolerance,
+}
+
+/// Returns the default simulation options: no constraint on the finish velocity, a power floor of 0 W, no
+/// power cap, no corners, adaptive steps on the kinetic energy error, no loss of power at altitude or in the
+/// heat, no neutralized section, braking allowed, a single riding position, no regenerative braking and the
+/// default root finding tolerance.
+pub const fn default_simulation_options() -> SimulationOptions {
+    SimulationOptions {
+        final_velocity: None,
+        power_floor: 0.0,
+        power_cap: None,
+        corner_vec: Vec::new(),
+        road_width: None,
+        cornering_friction: 0.8,
+        step_strategy: StepStrategy::Energy,
+        altitude_acclimatization: None,
+        thermal_model: None,
+        neutral_section_vec: Vec::new(),
+        forbid_braking: false,
+        position_cda_vec: Vec::new(),
+        position_change_penalty: 0.0,
+        regenerative_braking_efficiency: 0.0,
+        tolerance: root_finding::default_tolerance(),
+    }
+}
+
+/// Rides a neutralized segment at exactly `speed`, the change of speed on entering it being instantaneous.
+///
+/// Returns the power in planned watts holding the speed, i.e. delivered watts divided by `power_factor`, clamped
+/// between zero and `max_power`, and the outcome of the segment. When even coasting is too fast, the excess force
+/// counts as braking. The motor assist, if any, helps as long as the battery lasts and drains it; when the battery
+/// runs out within the segment, the assist is averaged over the segment. `speed` must be positive.
+fn ride_neutralized(
+    speed: f64,
+    power_factor: f64,
+    max_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    battery_energy: f64,
+    tolerance: &root_finding::Tolerance,
+) -> (f64, SegmentOutcome) {
+    let time = road_segment.length / speed;
+    let max_assist_power = match resistance_model.motor_assist {
+        Some(motor_assist) => motor_assist.max_assist_power,
+        None => 0.0,
+    };
+    let assisted_time = if max_assist_power > 0.0 {
+        f64::min(time, battery_energy * JOULES_PER_WATT_HOUR / max_assist_power)
+    } else {
+        0.0
+    };
+    let assist_power = max_assist_power * assisted_time / time;
+    let net_force_at = |input_power: f64| {
+        net_force_with_assist(speed, input_power * power_factor, assist_power, road_segment, resistance_model)
+    };
+    let (power, braking_force) = if net_force_at(0.0) >= 0.0 {
+        (0.0, net_force_at(0.0))
+    } else if net_force_at(max_power) <= 0.0 {
+        (max_power, 0.0)
+    } else {
+        (root_finding::bisect(net_force_at, 0.0, max_power, tolerance).value, 0.0)
+    };
+    let density = segment_air_density(road_segment, resistance_model);
+    let viscosity = kinematics::air_viscosity(road_segment.temperature);
+    let air_resistance_coef =
+        air_resistance_coef_at(speed, road_segment.relative_wind_speed, density, viscosity, resistance_model);
+    let aero_energy = kinematics::get_air_resistance_force(speed, road_segment.relative_wind_speed, air_resistance_coef)
+        * road_segment.length;
+    let still_air_energy = kinematics::get_air_resistance_force(speed, 0.0, air_resistance_coef) * road_segment.length;
+    let outcome = SegmentOutcome {
+        time,
+        final_velocity: speed,
+        battery_energy: f64::max(0.0, battery_energy - max_assist_power * assisted_time / JOULES_PER_WATT_HOUR),
+        aero_energy,
+        wind_energy: aero_energy - still_air_energy,
+        braking_energy: braking_force * road_segment.length,
+        step_count: 0,
+    };
+    (power, outcome)
+}
+
+/// The per-segment outputs of `compute_all_times`, with the same meaning as the fields of `SimulationResult`.
+///
+/// # Fields
+///
+/// * `duration_vec` - The time spent on each segment in seconds.
+/// * `power_vec` - The power actually ridden on each segment in watts.
+/// * `anaerobic_reserve_vec` - The anaerobic reserve at the end of each segment in joules.
+pub struct SimulationOutputs {
+    pub duration_vec: Vec<f64>,
+    pub power_vec: Vec<f64>,
+    pub anaerobic_reserve_vec: Vec<f64>,
+}
+
+impl SimulationOutputs {
+    /// Returns empty outputs, filled by `compute_all_times`.
+    pub fn new() -> SimulationOutputs {
+        SimulationOutputs {
+            duration_vec: Vec::new(),
+            power_vec: Vec::new(),
+            anaerobic_reserve_vec: Vec::new(),
+        }
+    }
+}
+
+impl Default for SimulationOutputs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Simulates a power plan over a course, writing the per-segment outputs into `outputs`.
+///
+/// # Returns
+///
+/// * `f64` - The total time in seconds.
+pub fn compute_all_times<M: PowerDurationModel>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    outputs: &mut SimulationOutputs,
+) -> f64 {
+    // The outputs lend their buffers to the scratch, so repeated calls reuse their allocations.
+    let mut scratch = SimulationScratch {
+        duration_vec: std::mem::take(&mut outputs.duration_vec),
+        power_vec: std::mem::take(&mut outputs.power_vec),
+        anaerobic_reserve_vec: std::mem::take(&mut outputs.anaerobic_reserve_vec),
+        ..SimulationScratch::new()
+    };
+    let (total_time, _) = simulate_into(
+        &SimulationInputs {
+            initial_velocity,
+            initial_anaerobic_reserve,
+            input_power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+        },
+        &default_simulation_options(),
+        &mut scratch,
+    );
+    outputs.duration_vec = scratch.duration_vec;
+    outputs.power_vec = scratch.power_vec;
+    outputs.anaerobic_reserve_vec = scratch.anaerobic_reserve_vec;
+    total_time
+}
+
+/// Reusable buffers for repeated simulations, e.g. in an optimizer loop, see `compute_all_times_with_scratch`.
+///
+/// The buffers hold the per-segment outputs of the last simulation, with the same meaning as the fields of
+/// `SimulationResult`. They are resized to every course, so one scratch can serve courses of different lengths,
+/// and only allocate when the course is longer than any before.
+pub struct SimulationScratch {
+    pub distance_vec: Vec<f64>,
+    pub duration_vec: Vec<f64>,
+    pub velocity_vec: Vec<f64>,
+    pub air_speed_vec: Vec<f64>,
+    pub power_vec: Vec<f64>,
+    pub anaerobic_reserve_vec: Vec<f64>,
+    pub battery_energy_vec: Vec<f64>,
+    pub aero_energy_vec: Vec<f64>,
+    pub wind_energy_vec: Vec<f64>,
+    pub braking_energy_vec: Vec<f64>,
+    pub recovered_energy_vec: Vec<f64>,
+    pub gravity_energy_vec: Vec<f64>,
+    pub step_count_vec: Vec<usize>,
+    exit_velocity_limit_vec: Vec<f64>,
+}
+
+impl SimulationScratch {
+    /// Returns empty buffers, which grow with the first simulation.
+    pub fn new() -> SimulationScratch {
+        SimulationScratch {
+            distance_vec: Vec::new(),
+            duration_vec: Vec::new(),
+            velocity_vec: Vec::new(),
+            air_speed_vec: Vec::new(),
+            power_vec: Vec::new(),
+            anaerobic_reserve_vec: Vec::new(),
+            battery_energy_vec: Vec::new(),
+            aero_energy_vec: Vec::new(),
+            wind_energy_vec: Vec::new(),
+            braking_energy_vec: Vec::new(),
+            recovered_energy_vec: Vec::new(),
+            gravity_energy_vec: Vec::new(),
+            step_count_vec: Vec::new(),
+            exit_velocity_limit_vec: Vec::new(),
+        }
+    }
+
+    /// Resizes every buffer to `n_segments` zeros, keeping the allocations.
+    pub fn resize(&mut self, n_segments: usize) {
+        for buffer in [
+            &mut self.distance_vec,
+            &mut self.duration_vec,
+            &mut self.velocity_vec,
+            &mut self.air_speed_vec,
+            &mut self.power_vec,
+            &mut self.anaerobic_reserve_vec,
+            &mut self.battery_energy_vec,
+            &mut self.aero_energy_vec,
+            &mut self.wind_energy_vec,
+            &mut self.braking_energy_vec,
+            &mut self.recovered_energy_vec,
+            &mut self.gravity_energy_vec,
+        ] {
+            buffer.clear();
+            buffer.resize(n_segments, 0.0);
+        }
+        self.step_count_vec.clear();
+        self.step_count_vec.resize(n_segments, 0);
+    }
+
+    /// Moves the per-segment outputs into a `SimulationResult` with the given total time and feasibility.
+    pub fn into_result(self, total_time: f64, feasibility: Feasibility) -> SimulationResult {
+        SimulationResult {
+            total_time,
+            distance_vec: self.distance_vec,
+            duration_vec: self.duration_vec,
+            velocity_vec: self.velocity_vec,
+            air_speed_vec: self.air_speed_vec,
+            power_vec: self.power_vec,
+            anaerobic_reserve_vec: self.anaerobic_reserve_vec,
+            battery_energy_vec: self.battery_energy_vec,
+            aero_energy_vec: self.aero_energy_vec,
+            wind_energy_vec: self.wind_energy_vec,
+            braking_energy_vec: self.braking_energy_vec,
+            recovered_energy_vec: self.recovered_energy_vec,
+            gravity_energy_vec: self.gravity_energy_vec,
+            step_count_vec: self.step_count_vec,
+            feasibility,
+        }
+    }
+}
+
+impl Default for SimulationScratch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Same as `compute_all_times`, writing the per-segment outputs into `scratch` instead of allocating them, so
+/// that repeated evaluations in an optimizer reuse the same memory. The results are the same as
+/// `simulate`'s.
+///
+/// # Returns
+///
+/// * `f64` - The total time in seconds.
+///
+/// # Example
+///
+/// ```
+/// let mut scratch = SimulationScratch::new();
+/// for input_power_vec in candidate_plans.iter() {
+///     let total_time = compute_all_times_with_scratch(
+///         0.0,
+///         rider_model.anaerobic_work_capacity,
+///         input_power_vec,
+///         &road_segment_vec,
+///         &resistance_model,
+///         &rider_model,
+///         &mut scratch,
+///     );
+///     println!("{}s, final reserve {:?}J", total_time, scratch.anaerobic_reserve_vec.last());
+/// }
+/// ```
+pub fn compute_all_times_with_scratch<M: PowerDurationModel>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    scratch: &mut SimulationScratch,
+) -> f64 {
+    let (total_time, _) = simulate_into(
+        &SimulationInputs {
+            initial_velocity,
+            initial_anaerobic_reserve,
+            input_power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+        },
+        &default_simulation_options(),
+        scratch,
+    );
+    total_time
+}
+
+/// Why a course cannot be completed.
+///
+/// * `InsufficientForce` - Even at their max power with a full anaerobic reserve, or at the power cap of the
+///   simulation options, the rider cannot produce enough force to move up the segment, with the motor assist
+///   the battery has left.
+/// * `AnaerobicReserveExhausted` - The rider could move up the segment when fresh, but the anaerobic reserve
+///   left when reaching it no longer allows enough power.
+/// * `BrakingRequired` - Braking is forbidden, see `SimulationOptions::forbid_braking`, but the segment cannot be
+///   kept under its speed limit without it, even freewheeling, e.g. a steep descent into a hairpin.
+/// * `InvalidNeutralSpeed` - The segment is neutralized at a speed that is not a positive number, see
+///   `NeutralSection`, so it is never ridden through.
+/// * `InvalidStepLength` - The step strategy of the simulation options is `StepStrategy::Distance` with a
+///   `step_length` that is not a positive number, so no segment is ever ridden through. Reported at the first
+///   segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InfeasibilityReason {
+    InsufficientForce,
+    AnaerobicReserveExhausted,
+    BrakingRequired,
+    InvalidNeutralSpeed,
+    InvalidStepLength,
+}
+
+/// Whether a simulation reached the end of the course, or the segment at which it stopped and why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Feasibility {
+    Feasible,
+    Infeasible {
+        segment_index: usize,
+        reason: InfeasibilityReason,
+    },
+}
+
+/// The outcome of simulating a power plan over a course.
+///
+/// # Fields
+///
+/// * `total_time` - The total time in seconds.
+/// * `distance_vec` - The distance from the start at the end of each segment in meters.
+/// * `duration_vec` - The time spent on each segment in seconds.
+/// * `velocity_vec` - The ground speed at the end of each segment in meters per second.
+/// * `air_speed_vec` - The apparent air speed at the end of each segment in meters per second, i.e. the ground
+///   speed plus the headwind component `relative_wind_speed`. It is below the ground speed with a tailwind.
+/// * `power_vec` - The power actually ridden on each segment in watts, after clamping to what the rider can sustain.
+/// * `anaerobic_reserve_vec` - The anaerobic reserve at the end of each segment in joules.
+/// * `battery_energy_vec` - The motor assist battery energy at the end of each segment in watt-hours, zero
+///   without assist.
+/// * `aero_energy_vec` - The work done against air resistance on each segment in joules.
+/// * `wind_energy_vec` - The part of the aero work on each segment due to the wind in joules, negative where
+///   the wind helped.
+/// * `braking_energy_vec` - The kinetic energy dissipated by braking on each segment in joules.
+/// * `recovered_energy_vec` - The part of the braking energy captured on each segment in joules, see
+///   `SimulationOptions::regenerative_braking_efficiency`.
+/// * `gravity_energy_vec` - The work done against gravity on each segment in joules, i.e. the gain of
+///   potential energy `m·g·Δh`. It is negative on descents, so its sum over a loop is zero.
+/// * `step_count_vec` - The number of integration steps tried on each segment, see `SegmentOutcome::step_count`.
+/// * `feasibility` - Whether the course was completed. When it was not, the entries of the vectors from the
+///   blocking segment onwards are zero and `total_time` only covers the segments before it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimulationResult {
+    pub total_time: f64,
+    pub distance_vec: Vec<f64>,
+    pub duration_vec: Vec<f64>,
+    pub velocity_vec: Vec<f64>,
+    pub air_speed_vec: Vec<f64>,
+    pub power_vec: Vec<f64>,
+    pub anaerobic_reserve_vec: Vec<f64>,
+    pub battery_energy_vec: Vec<f64>,
+    pub aero_energy_vec: Vec<f64>,
+    pub wind_energy_vec: Vec<f64>,
+    pub braking_energy_vec: Vec<f64>,
+    pub recovered_energy_vec: Vec<f64>,
+    pub gravity_energy_vec: Vec<f64>,
+    pub step_count_vec: Vec<usize>,
+    pub feasibility: Feasibility,
+}
+
+impl SimulationResult {
+    /// Returns the number of segments the rider completed.
+    pub fn completed_segments(&self) -> usize {
+        match self.feasibility {
+            Feasibility::Feasible => self.duration_vec.len(),
+            Feasibility::Infeasible { segment_index, .. } => segment_index,
+        }
+    }
+
+    /// Returns the elapsed time in seconds when the rider reaches `distance` meters from the start, linearly
+    /// interpolated within segments, or `None` if the rider did not reach it.
+    pub fn elapsed_time_at(&self, distance: f64) -> Option<f64> {
+        let mut previous_distance = 0.0;
+        let mut previous_time = 0.0;
+        for i in 0..self.completed_segments() {
+            let time = previous_time + self.duration_vec[i];
+            if distance <= self.distance_vec[i] {
+                let fraction = (distance - previous_distance) / (self.distance_vec[i] - previous_distance);
+                return Some(previous_time + f64::max(0.0, fraction) * (time - previous_time));
+            }
+            previous_distance = self.distance_vec[i];
+            previous_time = time;
+        }
+        None
+    }
+
+    /// Returns the splits at the labeled segments, e.g. from `data::segment_labels`, in course order. Segments
+    /// the rider did not reach are left out.
+    pub fn labeled_splits(&self, label_vec: &Vec<Option<String>>) -> Vec<LabeledSplit> {
+        let mut time = 0.0;
+        let mut split_vec = Vec::new();
+        for i in 0..self.completed_segments() {
+            time += self.duration_vec[i];
+            if let Some(Some(label)) = label_vec.get(i) {
+                split_vec.push(LabeledSplit {
+                    segment_index: i,
+                    label: label.clone(),
+                    distance: self.distance_vec[i],
+                    time,
+                });
+            }
+        }
+        split_vec
+    }
+
+    /// Returns whether the course was completed without the anaerobic reserve going negative.
+    pub fn is_feasible(&self) -> bool {
+        self.feasibility == Feasibility::Feasible
+            && self.anaerobic_reserve_vec.iter().all(|&reserve| reserve >= 0.0)
+    }
+
+    /// Returns the per-segment series used for charting, keeping at most `max_points` points.
+    ///
+    /// Every `k`-th segment is kept, with `k` the smallest stride giving at most `max_points` points, and the
+    /// last segment is always kept so the series ends at the finish.
+    pub fn chart_series(&self, max_points: usize) -> ChartSeries {
+        let n_segments = self.duration_vec.len();
+        let stride = usize::max(1, n_segments.div_ceil(usize::max(1, max_points)));
+        let mut indices: Vec<usize> = (0..n_segments).step_by(stride).collect();
+        if n_segments > 0 && indices.last() != Some(&(n_segments - 1)) {
+            if indices.len() == max_points {
+                indices.pop();
+            }
+            indices.push(n_segments - 1);
+        }
+        let mut elapsed_time_vec = Vec::with_capacity(n_segments);
+        let mut elapsed_time = 0.0;
+        for duration in self.duration_vec.iter() {
+            elapsed_time += duration;
+            elapsed_time_vec.push(elapsed_time);
+        }
+        ChartSeries {
+            distance: indices.iter().map(|&i| self.distance_vec[i]).collect(),
+            time: indices.iter().map(|&i| elapsed_time_vec[i]).collect(),
+            speed: indices.iter().map(|&i| self.velocity_vec[i]).collect(),
+            power: indices.iter().map(|&i| self.power_vec[i]).collect(),
+            anaerobic_reserve: indices.iter().map(|&i| self.anaerobic_reserve_vec[i]).collect(),
+        }
+    }
+
+    /// Serializes the full result to JSON.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Deserializes a result serialized with `to_json`.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> serde_json::Result<SimulationResult> {
+        serde_json::from_str(json)
+    }
+
+    /// Serializes the chart series of the result to JSON, keeping at most `max_points` points so that large
+    /// courses give small payloads. See `chart_series`.
+    #[cfg(feature = "serde")]
+    pub fn to_json_downsampled(&self, max_points: usize) -> serde_json::Result<String> {
+        serde_json::to_string(&self.chart_series(max_points))
+    }
+
+    /// Returns the normalized power in watts of the completed segments: the fourth root of the mean fourth
+    /// power of the 30 s rolling average of the power, sampled every second. It weighs surges more than the
+    /// average power does, so a steady plan has a lower normalized power than a variable one with the same
+    /// average. Rides shorter than the rolling window give their average power.
+    pub fn normalized_power(&self) -> f64 {
+        let mut power_sample_vec = Vec::new();
+        let mut segment_end_time = 0.0;
+        for i in 0..self.completed_segments() {
+            segment_end_time += self.duration_vec[i];
+            while (power_sample_vec.len() as f64) + 0.5 < segment_end_time {
+                power_sample_vec.push(self.power_vec[i]);
+            }
+        }
+        let n_samples = power_sample_vec.len();
+        if n_samples == 0 {
+            return 0.0;
+        }
+        if n_samples < NORMALIZED_POWER_WINDOW {
+            return power_sample_vec.iter().sum::<f64>() / n_samples as f64;
+        }
+        let mut window_sum: f64 = power_sample_vec[..NORMALIZED_POWER_WINDOW].iter().sum();
+        let mut fourth_power_sum = f64::powi(window_sum / NORMALIZED_POWER_WINDOW as f64, 4);
+        for k in NORMALIZED_POWER_WINDOW..n_samples {
+            window_sum += power_sample_vec[k] - power_sample_vec[k - NORMALIZED_POWER_WINDOW];
+            fourth_power_sum += f64::powi(window_sum / NORMALIZED_POWER_WINDOW as f64, 4);
+        }
+        let n_windows = n_samples - NORMALIZED_POWER_WINDOW + 1;
+        f64::powf(fourth_power_sum / n_windows as f64, 0.25)
+    }
+
+    /// Returns the effort zone of each segment.
+    pub fn effort_zones(&self, rider_model: &morton::RiderModel) -> Vec<morton::EffortZone> {
+        self
+            .power_vec
+            .iter()
+            .map(|&power| morton::effort_zone(rider_model, power))
+            .collect()
+    }
+
+    /// Returns the indices of the segments ridden in the given effort zone.
+    pub fn segments_in_zone(&self, rider_model: &morton::RiderModel, zone: morton::EffortZone) -> Vec<usize> {
+        (0..self.power_vec.len())
+            .filter(|&i| morton::effort_zone(rider_model, self.power_vec[i]) == zone)
+            .collect()
+    }
+
+    /// Returns the total time in seconds spent in the given effort zone.
+    pub fn time_in_zone(&self, rider_model: &morton::RiderModel, zone: morton::EffortZone) -> f64 {
+        self
+            .segments_in_zone(rider_model, zone)
+            .iter()
+            .fold(0.0, |time, &i| time + self.duration_vec[i])
+    }
+}
+
+/// The time at which a labeled segment is reached, e.g. the top of a named climb.
+///
+/// # Fields
+///
+/// * `segment_index` - The index of the segment.
+/// * `label` - The label of the segment.
+/// * `distance` - The distance from the start at the end of the segment in meters.
+/// * `time` - The elapsed time at the end of the segment in seconds.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LabeledSplit {
+    pub segment_index: usize,
+    pub label: String,
+    pub distance: f64,
+    pub time: f64,
+}
+
+/// Per-segment arrays of a result, suitable for charting.
+///
+/// # Fields
+///
+/// * `distance` - The distance from the start in meters.
+/// * `time` - The elapsed time in seconds.
+/// * `speed` - The velocity in meters per second.
+/// * `power` - The power in watts.
+/// * `anaerobic_reserve` - The anaerobic reserve (W'bal) in joules.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChartSeries {
+    pub distance: Vec<f64>,
+    pub time: Vec<f64>,
+    pub speed: Vec<f64>,
+    pub power: Vec<f64>,
+    pub anaerobic_reserve: Vec<f64>,
+}
+
+/// Simulates a power plan over a course with the default options, see `simulate_with_options`.
+pub fn simulate<M: PowerDurationModel>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> SimulationResult {
+    simulate_with_options(
+        initial_velocity,
+        initial_anaerobic_reserve,
+        input_power_vec,
+        road_segment_vec,
+        resistance_model,
+        rider_model,
+        &default_simulation_options(),
+    )
+}
+
+/// Converts a plan given in percent of critical power, as prescribed by coaches, into powers in watts.
+///
+/// Targets above the rider's max power with a full anaerobic reserve cannot be ridden by anybody, so they are
+/// clamped to it with a warning logged through the `log` crate.
+///
+/// # Arguments
+///
+/// * `percent_cp_vec` - The target of each segment in percent of critical power, e.g. 110 for 110% CP.
+/// * `rider_model` - The rider model giving the critical power and the max power.
+///
+/// # Returns
+///
+/// * `Vec<f64>` - The power of each segment in watts.
+///
+/// # Example
+///
+/// ```
+/// let power_vec = powers_from_percent_cp(&vec![90.0, 90.0, 110.0, 90.0], &morton::default_rider_model());
+/// ```
+pub fn powers_from_percent_cp<M: PowerDurationModel>(percent_cp_vec: &Vec<f64>, rider_model: &M) -> Vec<f64> {
+    let power_ceiling = rider_model.max_power(rider_model.anaerobic_work_capacity());
+    let mut power_vec = Vec::with_capacity(percent_cp_vec.len());
+    for (i, percent_cp) in percent_cp_vec.iter().enumerate() {
+        let power = 0.01 * percent_cp * rider_model.critical_power();
+        if power > power_ceiling {
+            log::warn!(
+                "segment {}: {}% CP is {} W, above the max power of {} W, clamped",
+                i,
+                percent_cp,
+                power,
+                power_ceiling
+            );
+        }
+        power_vec.push(f64::min(power, power_ceiling));
+    }
+    power_vec
+}
+
+/// Same as `simulate_with_options`, with the plan given in percent of critical power, see
+/// `powers_from_percent_cp`.
+pub fn simulate_percent_cp<M: PowerDurationModel>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    percent_cp_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    options: &SimulationOptions,
+) -> SimulationResult {
+    simulate_with_options(
+        initial_velocity,
+        initial_anaerobic_reserve,
+        &powers_from_percent_cp(percent_cp_vec, rider_model),
+        road_segment_vec,
+        resistance_model,
+        rider_model,
+        options,
+    )
+}
+
+/// Converts a plan given in watts per kilogram of rider mass, as in many training prescriptions, into powers in
+/// watts.
+///
+/// # Arguments
+///
+/// * `watts_per_kg_vec` - The target of each segment in watts per kilogram.
+/// * `rider_mass_vec` - The rider mass on each segment in kilograms, e.g. decreasing over a long ride as the rider
+///   burns their fuel, or constant at `BicycleResistanceModel::rider_mass`.
+///
+/// # Returns
+///
+/// * `Result<Vec<f64>, String>` - The power of each segment in watts, or an error if the two vectors do not have
+///   the same length.
+///
+/// # Example
+///
+/// ```
+/// let power_vec = powers_from_watts_per_kg(&vec![4.0, 4.0, 5.5], &vec![72.0, 71.8, 71.6]).unwrap();
+/// ```
+pub fn powers_from_watts_per_kg(watts_per_kg_vec: &Vec<f64>, rider_mass_vec: &Vec<f64>) -> Result<Vec<f64>, String> {
+    if watts_per_kg_vec.len() != rider_mass_vec.len() {
+        return Err(format!(
+            "{} targets in watts per kilogram for {} rider masses",
+            watts_per_kg_vec.len(),
+            rider_mass_vec.len()
+        ));
+    }
+    let power_vec = watts_per_kg_vec
+        .iter()
+        .zip(rider_mass_vec.iter())
+        .map(|(watts_per_kg, rider_mass)| watts_per_kg * rider_mass)
+        .collect();
+    Ok(power_vec)
+}
+
+/// Converts a power plan in watts, e.g. from an optimizer, into watts per kilogram of rider mass, the inverse of
+/// `powers_from_watts_per_kg`, with the same error on vectors of different lengths.
+pub fn watts_per_kg_from_powers(power_vec: &Vec<f64>, rider_mass_vec: &Vec<f64>) -> Result<Vec<f64>, String> {
+    if power_vec.len() != rider_mass_vec.len() {
+        return Err(format!("{} powers for {} rider masses", power_vec.len(), rider_mass_vec.len()));
+    }
+    let watts_per_kg_vec = power_vec
+        .iter()
+        .zip(rider_mass_vec.iter())
+        .map(|(power, rider_mass)| power / rider_mass)
+        .collect();
+    Ok(watts_per_kg_vec)
+}
+
+/// Same as `simulate_with_options`, with the plan given in watts per kilogram of the rider mass on each segment,
+/// see `powers_from_watts_per_kg`. Returns an error if the plan and `rider_mass_vec` do not have the same length.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_watts_per_kg<M: PowerDurationModel>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    watts_per_kg_vec: &Vec<f64>,
+    rider_mass_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    options: &SimulationOptions,
+) -> Result<SimulationResult, String> {
+    let result = simulate_with_options(
+        initial_velocity,
+        initial_anaerobic_reserve,
+        &powers_from_watts_per_kg(watts_per_kg_vec, rider_mass_vec)?,
+        road_segment_vec,
+        resistance_model,
+        rider_model,
+        options,
+    );
+    Ok(result)
+}
+
+/// Simulates a power plan over a course, starting at `initial_velocity`.
+///
+/// Segments are ridden one after the other at the planned power. When the rider would be exhausted before the
+/// end of a segment, the power of that segment and all following ones is dropped to critical power. On
+/// segments too steep to move up at the planned power, the rider pushes `minimum_power_to_move` instead. The
+/// simulation stops at the first segment the rider cannot get up, see `Feasibility`. When
+/// `options.final_velocity` is set, the rider brakes on the last segments as needed to respect it. When
+/// `options.power_cap` is set, the planned powers are clamped to it before riding. Planned powers below
+/// `options.power_floor` are raised to it, the cap taking precedence. When `options.altitude_acclimatization` is
+/// set, the rider delivers less than the planned power at altitude but is fatigued as if at sea level, and
+/// likewise in the heat when `options.thermal_model` is set.
+///
+/// Nothing is printed: each segment is logged through the `log` crate at debug and trace levels, which is
+/// silent unless the caller installs a logger.
+pub fn simulate_with_options<M: PowerDurationModel>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    options: &SimulationOptions,
+) -> SimulationResult {
+    let mut scratch = SimulationScratch::new();
+    let (total_time, feasibility) = simulate_into(
+        &SimulationInputs {
+            initial_velocity,
+            initial_anaerobic_reserve,
+            input_power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+        },
+        options,
+        &mut scratch,
+    );
+    scratch.into_result(total_time, feasibility)
+}
+
+/// The arguments of `simulate_with_options` besides the options, see there.
+struct SimulationInputs<'a, M> {
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    input_power_vec: &'a Vec<f64>,
+    road_segment_vec: &'a Vec<RoadSegment>,
+    resistance_model: &'a BicycleResistanceModel,
+    rider_model: &'a M,
+}
+
+/// Same as `simulate_with_options`, writing the per-segment outputs into `scratch` and returning the total time
+/// and the feasibility.
+fn simulate_into<M: PowerDurationModel>(
+    inputs: &SimulationInputs<M>,
+    options: &SimulationOptions,
+    scratch: &mut SimulationScratch,
+) -> (f64, Feasibility) {
+    let SimulationInputs {
+        initial_velocity,
+        initial_anaerobic_reserve,
+        input_power_vec,
+        road_segment_vec,
+        resistance_model,
+        rider_model,
+    } = *inputs;
+    let n_segments = input_power_vec.len();
+    scratch.resize(n_segments);
+    let SimulationScratch {
+        distance_vec,
+        duration_vec,
+        velocity_vec,
+        air_speed_vec,
+        power_vec,
+        anaerobic_reserve_vec,
+        battery_energy_vec,
+        aero_energy_vec,
+        wind_energy_vec,
+        braking_energy_vec,
+        recovered_energy_vec,
+        gravity_energy_vec,
+        step_count_vec,
+        exit_velocity_limit_vec,
+    } = scratch;
+    let braking_segment_index = if options.forbid_braking {
+        fill_coasting_velocity_limits(road_segment_vec, resistance_model, options, exit_velocity_limit_vec)
+    } else {
+        fill_exit_velocity_limits(road_segment_vec, options, exit_velocity_limit_vec);
+        None
+    };
+    let mut velocity = initial_velocity;
+    let mut current_anaerobic_reserve = initial_anaerobic_reserve;
+    let mut battery_energy = full_battery_energy(resistance_model);
+
+    power_vec.copy_from_slice(input_power_vec);
+    for power in power_vec.iter_mut() {
+        *power = f64::max(*power, options.power_floor);
+        if let Some(power_cap) = options.power_cap {
+            *power = f64::min(*power, power_cap);
+        }
+    }
+    let mut feasibility = Feasibility::Feasible;
+    let is_valid_step_strategy = match options.step_strategy {
+        StepStrategy::Energy => true,
+        StepStrategy::Distance { step_length } => step_length.is_finite() && step_length > 0.0,
+    };
+
+    let mut total_duration = 0.0;
+    let mut total_distance = 0.0;
+    let mut heat_strain = 0.0;
+    for i in 0..n_segments {
+        if !is_valid_step_strategy {
+            feasibility = Feasibility::Infeasible {
+                segment_index: i,
+                reason: InfeasibilityReason::InvalidStepLength,
+            };
+            break;
+        }
+        // The riding position of the segment, if any, replaces the CdA of the model.
+        let position_resistance_model;
+        let resistance_model = match options.position_cda_vec.get(i) {
+            Some(&cda) => {
+                position_resistance_model = BicycleResistanceModel {
+                    cda_surface: cda,
+                    cda_speed_curve: None,
+                    cda_speed_bands: None,
+                    ..resistance_model.clone()
+                };
+                &position_resistance_model
+            }
+            None => resistance_model,
+        };
+        let previous_cda = i.checked_sub(1).and_then(|k| options.position_cda_vec.get(k));
+        let position_change_time = match (previous_cda, options.position_cda_vec.get(i)) {
+            (Some(previous_cda), Some(cda)) if cda != previous_cda => options.position_change_penalty,
+            _ => 0.0,
+        };
+        // The plan and the anaerobic reserve are in sea level, thermoneutral watts, so the power delivered to move
+        // is scaled back.
+        let altitude_power_factor = match options.altitude_acclimatization {
+            Some(acclimatization) => acclimatization.power_factor(road_segment_vec[i].altitude),
+            None => 1.0,
+        };
+        let thermal_power_factor = match options.thermal_model {
+            Some(thermal_model) => thermal_model.power_factor(heat_strain),
+            None => 1.0,
+        };
+        let power_factor = altitude_power_factor * thermal_power_factor;
+        if braking_segment_index == Some(i) {
+            feasibility = Feasibility::Infeasible {
+                segment_index: i,
+                reason: InfeasibilityReason::BrakingRequired,
+            };
+            break;
+        }
+        let neutral_section =
+            options.neutral_section_vec.iter().find(|neutral_section| neutral_section.segment_index == i);
+        let outcome = match neutral_section {
+            Some(neutral_section) => {
+                if neutral_section.speed.is_nan() || neutral_section.speed <= 0.0 {
+                    feasibility = Feasibility::Infeasible {
+                        segment_index: i,
+                        reason: InfeasibilityReason::InvalidNeutralSpeed,
+                    };
+                    break;
+                }
+                // The rider pushes no harder than they can hold over the whole segment.
+                let duration = road_segment_vec[i].length / neutral_section.speed;
+                let mut max_power = rider_model.max_power(current_anaerobic_reserve);
+                if rider_model.time_to_exhaustion(max_power, current_anaerobic_reserve) < duration {
+                    let endurance_margin =
+                        |power: f64| rider_model.time_to_exhaustion(power, current_anaerobic_reserve) - duration;
+                    let critical_power = rider_model.critical_power();
+                    max_power =
+                        root_finding::bisect(endurance_margin, critical_power, max_power, &options.tolerance).value;
+                }
+                if let Some(power_cap) = options.power_cap {
+                    max_power = f64::min(max_power, power_cap);
+                }
+                let (power, outcome) = ride_neutralized(
+                    neutral_section.speed,
+                    power_factor,
+                    max_power,
+                    &road_segment_vec[i],
+                    resistance_model,
+                    battery_energy,
+                    &options.tolerance,
+                );
+                power_vec[i] = power;
+                outcome
+            }
+            None => {
+                let required_power =
+                    minimum_power_to_move_with_battery(&road_segment_vec[i], resistance_model, battery_energy)
+                        / power_factor;
+                if required_power > rider_model.max_power(rider_model.anaerobic_work_capacity())
+                    || options.power_cap.is_some_and(|power_cap| required_power > power_cap)
+                {
+                    feasibility = Feasibility::Infeasible {
+                        segment_index: i,
+                        reason: InfeasibilityReason::InsufficientForce,
+                    };
+                    break;
+                }
+                if required_power > rider_model.max_power(current_anaerobic_reserve) {
+                    feasibility = Feasibility::Infeasible {
+                        segment_index: i,
+                        reason: InfeasibilityReason::AnaerobicReserveExhausted,
+                    };
+                    break;
+                }
+
+                // Without braking, the speed limit is respected below by lowering the power instead.
+                let ride_exit_velocity_limit =
+                    if options.forbid_braking { f64::INFINITY } else { exit_velocity_limit_vec[i] };
+                let ride = |input_power| {
+                    ride_segment_in_wind(
+                        velocity,
+                        input_power * power_factor,
+                        &road_segment_vec[i],
+                        resistance_model,
+                        battery_energy,
+                        ride_exit_velocity_limit,
+                        SegmentRideOptions { step_strategy: options.step_strategy, wind: None, observer: None },
+                    )
+                };
+
+                let mut outcome = ride( power_vec[i]);
+                let tau = rider_model.time_to_exhaustion(power_vec[i], current_anaerobic_reserve);
+                log::trace!("tau = {:?}s", tau);
+                if tau < outcome.time {
+                    for j in i..n_segments {
+                        if power_vec[i] < rider_model.critical_power() {
+                            break;
+                        }
+                        power_vec[j] =  rider_model.critical_power();
+                    }
+                    outcome = ride( power_vec[i]);
+                }
+                if power_vec[i] < required_power {
+                    // The rider has to push at least this hard to get up the segment, and fails if they cannot
+                    // hold it.
+                    power_vec[i] = required_power;
+                    outcome = ride(power_vec[i]);
+                    if rider_model.time_to_exhaustion(power_vec[i], current_anaerobic_reserve) < outcome.time {
+                        feasibility = Feasibility::Infeasible {
+                            segment_index: i,
+                            reason: InfeasibilityReason::AnaerobicReserveExhausted,
+                        };
+                        break;
+                    }
+                }
+                if options.forbid_braking && outcome.final_velocity > exit_velocity_limit_vec[i] {
+                    let overspeed = |input_power: f64| ride(input_power).final_velocity - exit_velocity_limit_vec[i];
+                    if overspeed(options.power_floor) > 0.0 {
+                        feasibility = Feasibility::Infeasible {
+                            segment_index: i,
+                            reason: InfeasibilityReason::BrakingRequired,
+                        };
+                        break;
+                    }
+                    power_vec[i] =
+                        root_finding::bisect(overspeed, options.power_floor, power_vec[i], &options.tolerance).value;
+                    outcome = ride(power_vec[i]);
+                }
+                outcome
+            }
+        };
+        
+        current_anaerobic_reserve = rider_model.update_anaerobic_reserve(power_vec[i], outcome.time, current_anaerobic_reserve);
+        log::debug!("{:?}W for {:?}s > {:?}J", power_vec[i], outcome.time, current_anaerobic_reserve);
+        anaerobic_reserve_vec[i] = current_anaerobic_reserve;
+        // Without motor assist the battery is empty and stays so, the captured energy only being reported.
+        let recovered_energy = options.regenerative_braking_efficiency * outcome.braking_energy;
+        battery_energy = f64::min(
+            full_battery_energy(resistance_model),
+            outcome.battery_energy + recovered_energy / JOULES_PER_WATT_HOUR,
+        );
+        battery_energy_vec[i] = battery_energy;
+        aero_energy_vec[i] = outcome.aero_energy;
+        wind_energy_vec[i] = outcome.wind_energy;
+        braking_energy_vec[i] = outcome.braking_energy;
+        recovered_energy_vec[i] = recovered_energy;
+        gravity_energy_vec[i] = kinematics::get_gravity_force(road_segment_vec[i].slope, resistance_model.total_mass())
+            * road_segment_vec[i].length;
+        step_count_vec[i] = outcome.step_count;
+        duration_vec[i] = outcome.time + position_change_time;
+        velocity_vec[i] = outcome.final_velocity;
+        air_speed_vec[i] = outcome.final_velocity + road_segment_vec[i].relative_wind_speed;
+        total_duration += outcome.time + position_change_time;
+        total_distance += road_segment_vec[i].length;
+        distance_vec[i] = total_distance;
+        velocity = outcome.final_velocity;
+        if let Some(thermal_model) = options.thermal_model {
+            heat_strain = thermal_model.update_heat_strain(heat_strain, road_segment_vec[i].temperature, outcome.time);
+        }
+    }
+    if let Feasibility::Infeasible { segment_index, .. } = feasibility {
+        power_vec[segment_index..].fill(0.0);
+    }
+    (total_duration, feasibility)
+}
+
+/// Computes where one run gains or loses time on another along the course, e.g. two pacing plans or two riders.
+///
+/// The runs are aligned by distance, so they can come from the same route split into different segments: the
+/// checkpoints are the segment ends of `result_a`, and the elapsed time of `result_b` at each of them is
+/// interpolated within its own segments. Checkpoints that one of the runs did not reach are left out.
+///
+/// # Returns
+///
+/// * `Vec<f64>` - The gap in seconds at each checkpoint, positive where `result_a` is ahead of `result_b`.
+pub fn time_gap(result_a: &SimulationResult, result_b: &SimulationResult) -> Vec<f64> {
+    let mut time_a = 0.0;
+    let mut gap_vec = Vec::new();
+    for i in 0..result_a.completed_segments() {
+        time_a += result_a.duration_vec[i];
+        let Some(time_b) = result_b.elapsed_time_at(result_a.distance_vec[i]) else {
+            break;
+        };
+        gap_vec.push(time_b - time_a);
+    }
+    gap_vec
+}
+
+/// Simulates the same power plan, course and rider with several resistance models, e.g. to compare wheels or
+/// tires. The models are simulated in parallel.
+///
+/// # Returns
+///
+/// * `Vec<SimulationResult>` - One result per model, in the order of `resistance_models`. Models with which the
+///   plan cannot be completed still get a result, flagged by `is_feasible`.
+pub fn compare_models<M: PowerDurationModel + Sync>(
+    initial_velocity: f64,
+    initial_anaerobic_reserve: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<RoadSegment>,
+    rider_model: &M,
+    resistance_models: &[BicycleResistanceModel],
+) -> Vec<SimulationResult> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = resistance_models
+            .iter()
+            .map(|resistance_model| {
+                scope.spawn(move || {
+                    simulate(
+                        initial_velocity,
+                        initial_anaerobic_reserve,
+                        input_power_vec,
+                        road_segment_vec,
+                        resistance_model,
+                        rider_model,
+                    )
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Computes the equivalent flat distance of a course: the distance the rider covers on a flat road in the time
+/// they need to ride the course at critical power.
+///
+/// The reference flat road is at sea level and 20°C, without wind, on smooth road. Dividing by the actual
+/// length of the course gives a difficulty score comparable across courses. It exceeds 1 on hilly courses and
+/// can be below 1 on net-downhill courses.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+/// * `tolerance` - The stopping criterion of the bisection on the flat distance.
+///
+/// # Returns
+///
+/// * `f64` - The equivalent flat distance in meters, or `f64::INFINITY` if the rider cannot complete the
+///   course.
+pub fn equivalent_flat_distance<M: PowerDurationModel>(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    tolerance: &root_finding::Tolerance,
+) -> f64 {
+    let critical_power = rider_model.critical_power();
+    let result = simulate(
+        initial_velocity,
+        rider_model.anaerobic_work_capacity(),
+        &vec![critical_power; road_segment_vec.len()],
+        road_segment_vec,
+        resistance_model,
+        rider_model,
+    );
+    if !result.is_feasible() {
+        return f64::INFINITY;
+    }
+
+    let flat_time = |length: f64| {
+        let flat_segment = RoadSegment {
+            length,
+            altitude: 0.0,
+            slope: 0.0,
+            temperature: 20.0,
+            relative_wind_speed: 0.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        };
+        let (time, _) =
+            compute_time_and_final_velocity(initial_velocity, critical_power, &flat_segment, resistance_model);
+        time - result.total_time
+    };
+    let mut upper_length = f64::max(1.0, road_segment_vec.iter().map(|segment| segment.length).sum());
+    while flat_time(upper_length) < 0.0 {
+        upper_length *= 2.0;
+    }
+    root_finding::bisect(flat_time, 0.0, upper_length, tolerance).value
+}
+
+/// Computes the marginal time cost of extra mass: how many seconds each additional kilogram costs on a course
+/// ridden at critical power.
+///
+/// The total time is differentiated numerically with respect to the mass by a central difference of
+/// `MASS_DIFFERENCE_STEP` kilograms, added to the bicycle. It is small on flat courses, where mass only adds
+/// rolling resistance, and large on climbs. Divide by 1000 for the cost of a gram.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+///
+/// # Returns
+///
+/// * `f64` - The time cost in seconds per kilogram, or `f64::INFINITY` if the rider cannot complete the course
+///   with the extra mass.
+///
+/// # Example
+///
+/// ```
+/// let cost = seconds_per_kilogram(0.0, &road_segment_vec, &default_resistance_model(), &default_rider_model());
+/// println!("Each kilogram costs {:.1}s", cost);
+/// ```
+pub fn seconds_per_kilogram<M: PowerDurationModel>(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> f64 {
+    let input_power_vec = vec![rider_model.critical_power(); road_segment_vec.len()];
+    let total_time_with = |mass_difference: f64| {
+        let mut perturbed_model = resistance_model.clone();
+        perturbed_model.bike_mass += mass_difference;
+        let result = simulate(
+            initial_velocity,
+            rider_model.anaerobic_work_capacity(),
+            &input_power_vec,
+            road_segment_vec,
+            &perturbed_model,
+            rider_model,
+        );
+        if result.is_feasible() { result.total_time } else { f64::INFINITY }
+    };
+    let heavier_time = total_time_with(MASS_DIFFERENCE_STEP);
+    if heavier_time == f64::INFINITY {
+        return f64::INFINITY;
+    }
+    (heavier_time - total_time_with(-MASS_DIFFERENCE_STEP)) / (2.0 * MASS_DIFFERENCE_STEP)
+}
+
+/// Computes the marginal time gain of a higher critical power: how many seconds each additional watt of critical
+/// power saves on a course ridden at critical power, e.g. to decide whether a training block is worth it.
+///
+/// The total time is differentiated numerically with respect to the critical power by a central difference of
+/// `CRITICAL_POWER_DIFFERENCE_STEP` watts, the rider riding at the perturbed critical power. The sensitivity is
+/// negative, and large on climbs, where the time is roughly inversely proportional to the power. It is small on
+/// easy courses where the speed is set by gravity rather than by the rider, e.g. on descents, where the rider is
+/// fast and the extra power is spent against the aero drag.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model, of which the critical power is perturbed with `with_critical_power`.
+///
+/// # Returns
+///
+/// * `f64` - The time change in seconds per watt of critical power, or `f64::NEG_INFINITY` if the rider can
+///   only complete the course with the extra power.
+///
+/// # Example
+///
+/// ```
+/// let gain = watts_to_seconds(0.0, &road_segment_vec, &default_resistance_model(), &default_rider_model());
+/// println!("Each watt of critical power saves {:.1}s", -gain);
+/// ```
+pub fn watts_to_seconds<M: PowerDurationModel>(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> f64 {
+    let total_time_with = |power_difference: f64| {
+        let perturbed_rider_model = rider_model.with_critical_power(rider_model.critical_power() + power_difference);
+        let input_power_vec = vec![perturbed_rider_model.critical_power(); road_segment_vec.len()];
+        let result = simulate(
+            initial_velocity,
+            perturbed_rider_model.anaerobic_work_capacity(),
+            &input_power_vec,
+            road_segment_vec,
+            resistance_model,
+            &perturbed_rider_model,
+        );
+        if result.is_feasible() { result.total_time } else { f64::INFINITY }
+    };
+    let weaker_time = total_time_with(-CRITICAL_POWER_DIFFERENCE_STEP);
+    if weaker_time == f64::INFINITY {
+        return f64::NEG_INFINITY;
+    }
+    (total_time_with(CRITICAL_POWER_DIFFERENCE_STEP) - weaker_time) / (2.0 * CRITICAL_POWER_DIFFERENCE_STEP)
+}
+
+/// Finds the equipment with the best tradeoff between CdA and mass on a course ridden at critical power, e.g. deep
+/// wheels against climbing wheels.
+///
+/// Each option replaces the CdA of the resistance model (including its speed curve or bands) and the mass of the
+/// bicycle. Flat courses favor the options with a low CdA and climbs the light ones, so on a tradeoff curve sampled
+/// from the aero to the light end, the best option moves towards the light end as the course gets hillier.
+///
+/// # Arguments
+///
+/// * `tradeoff_vec` - The `(cda, bike_mass)` options in square meters and kilograms, e.g. sampled along a curve.
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle, of which the other parameters are kept.
+/// * `rider_model` - The rider model.
+///
+/// # Returns
+///
+/// * `Option<(usize, f64)>` - The index of the fastest option and its total time in seconds, or `None` if there
+///   is no option or the rider cannot complete the course with any of them.
+///
+/// # Example
+///
+/// ```
+/// let tradeoff_vec = vec![(0.30, 8.0), (0.29, 8.4), (0.28, 8.9)];
+/// let (best_index, time) = optimal_cda_mass_tradeoff(
+///     &tradeoff_vec,
+///     0.0,
+///     &road_segment_vec,
+///     &default_resistance_model(),
+///     &default_rider_model(),
+/// )
+/// .unwrap();
+/// ```
+pub fn optimal_cda_mass_tradeoff<M: PowerDurationModel>(
+    tradeoff_vec: &Vec<(f64, f64)>,
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> Option<(usize, f64)> {
+    let input_power_vec = vec![rider_model.critical_power(); road_segment_vec.len()];
+    let mut best: Option<(usize, f64)> = None;
+    for (i, &(cda, bike_mass)) in tradeoff_vec.iter().enumerate() {
+        let equipment_model = BicycleResistanceModel {
+            cda_surface: cda,
+            cda_speed_curve: None,
+            cda_speed_bands: None,
+            bike_mass,
+            ..resistance_model.clone()
+        };
+        let result = simulate(
+            initial_velocity,
+            rider_model.anaerobic_work_capacity(),
+            &input_power_vec,
+            road_segment_vec,
+            &equipment_model,
+            rider_model,
+        );
+        if result.is_feasible() && best.is_none_or(|(_, best_time)| result.total_time < best_time) {
+            best = Some((i, result.total_time));
+        }
+    }
+    best
+}
+
+/// A source of resistance to the rider's motion, see `time_attributed_to`.
+///
+/// * `Aero` - The air resistance, including the wind.
+/// * `Rolling` - The rolling resistance of the tyres, scaled by the road roughness.
+/// * `Roughness` - The extra losses of rough roads: the `roughness` multiplier of rolling resistance and the
+///   speed-dependent `roughness_speed_loss`.
+/// * `Gravity` - The component of gravity along the road.
+/// * `Drivetrain` - The proportional and fixed drivetrain losses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResistanceSource {
+    Aero,
+    Rolling,
+    Roughness,
+    Gravity,
+    Drivetrain,
+}
+
+/// Computes the time a resistance source costs over a course ridden at critical power: the difference between
+/// the total time and the one of a counterfactual ride with that source switched off, all else equal.
+///
+/// Switching off gravity flattens every segment. It is not physical, since the rider no longer gains or loses
+/// altitude, but shows how much of the time the climbs cost; on a course with long descents it can be negative,
+/// the descents having saved more time than the climbs cost.
+///
+/// # Arguments
+///
+/// * `source` - The resistance switched off.
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+///
+/// # Returns
+///
+/// * `f64` - The time in seconds the source costs, or `f64::INFINITY` if the rider can only complete the course
+///   without it.
+///
+/// # Example
+///
+/// ```
+/// let rolling_time = time_attributed_to(
+///     ResistanceSource::Rolling,
+///     0.0,
+///     &road_segment_vec,
+///     &default_resistance_model(),
+///     &default_rider_model(),
+/// );
+/// println!("Rolling resistance costs {:.0}s", rolling_time);
+/// ```
+pub fn time_attributed_to<M: PowerDurationModel>(
+    source: ResistanceSource,
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> f64 {
+    let mut counterfactual_model = resistance_model.clone();
+    let mut counterfactual_segment_vec: Vec<RoadSegment> =
+        road_segment_vec.iter().map(|road_segment| RoadSegment { ..*road_segment }).collect();
+    match source {
+        ResistanceSource::Aero => {
+            counterfactual_model.cda_surface = 0.0;
+            counterfactual_model.cda_speed_curve = None;
+            counterfactual_model.cda_speed_bands = None;
+        }
+        ResistanceSource::Rolling => {
+            counterfactual_model.rolling_resistance = 0.0;
+            for road_segment in counterfactual_segment_vec.iter_mut() {
+                road_segment.rolling_resistance = None;
+            }
+        }
+        ResistanceSource::Roughness => {
+            counterfactual_model.roughness_speed_loss = 0.0;
+            for road_segment in counterfactual_segment_vec.iter_mut() {
+                road_segment.roughness = 1.0;
+            }
+        }
+        ResistanceSource::Gravity => {
+            for road_segment in counterfactual_segment_vec.iter_mut() {
+                road_segment.slope = 0.0;
+            }
+        }
+        ResistanceSource::Drivetrain => {
+            counterfactual_model.drivetrain_efficiency = 1.0;
+            counterfactual_model.drivetrain_fixed_loss = 0.0;
+            for road_segment in counterfactual_segment_vec.iter_mut() {
+                road_segment.drivetrain_efficiency = None;
+            }
+        }
+    }
+    let input_power_vec = vec![rider_model.critical_power(); road_segment_vec.len()];
+    let total_time = |road_segment_vec: &Vec<RoadSegment>, resistance_model: &BicycleResistanceModel| {
+        let result = simulate(
+            initial_velocity,
+            rider_model.anaerobic_work_capacity(),
+            &input_power_vec,
+            road_segment_vec,
+            resistance_model,
+            rider_model,
+        );
+        if result.is_feasible() { result.total_time } else { f64::INFINITY }
+    };
+    total_time(road_segment_vec, resistance_model)
+        - total_time(&counterfactual_segment_vec, &counterfactual_model)
+}
+
+/// Computes a lower bound on the time to ride a course: the time at the rider's max instantaneous power on every
+/// segment, as if the anaerobic reserve never depleted.
+///
+/// This is not a pacing a rider can hold, only a bound for validating optimizers: riding faster needs more
+/// power somewhere, and the rider never has more than `max_power` with a full reserve, so no plan simulated
+/// with `simulate` beats it.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model.
+///
+/// # Returns
+///
+/// * `f64` - The lower bound on the total time in seconds.
+pub fn fastest_unconstrained_time<M: PowerDurationModel>(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> f64 {
+    let max_power = rider_model.max_power(rider_model.anaerobic_work_capacity());
+    let mut time = 0.0;
+    let mut velocity = initial_velocity;
+    let mut battery_energy = full_battery_energy(resistance_model);
+    for road_segment in road_segment_vec.iter() {
+        let outcome =
+            ride_segment(velocity, max_power, road_segment, resistance_model, battery_energy, f64::INFINITY);
+        time += outcome.time;
+        velocity = outcome.final_velocity;
+        battery_energy = outcome.battery_energy;
+    }
+    time
+}
+
+/// Finds the constant power needed to ride a course in a target time, ignoring whether the rider can sustain it.
+///
+/// The power is found by bisection between 0 and `MAX_CONSTANT_POWER` watts, stopping at `tolerance` on the
+/// power. A tighter tolerance gives a time closer to the target, at the cost of more simulations.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `target_time` - The time to ride the course in seconds.
+/// * `tolerance` - The stopping criterion on the power in watts.
+///
+/// # Returns
+///
+/// * `root_finding::Solution` - The power in watts, not converged when the target time cannot be reached
+///   between 0 and `MAX_CONSTANT_POWER` watts.
+///
+/// # Example
+///
+/// ```
+/// let solution =
+///     constant_power_for_time(0.0, &road_segment_vec, &resistance_model, 3600.0, &default_tolerance());
+/// if solution.converged {
+///     println!("Ride at {:.0}W", solution.value);
+/// }
+/// ```
+pub fn constant_power_for_time(
+    initial_velocity: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    target_time: f64,
+    tolerance: &root_finding::Tolerance,
+) -> root_finding::Solution {
+    let time_difference = |input_power: f64| {
+        let mut time = 0.0;
+        let mut velocity = initial_velocity;
+        for road_segment in road_segment_vec.iter() {
+            let (duration, final_velocity) =
+                compute_time_and_final_velocity(velocity, input_power, road_segment, resistance_model);
+            time += duration;
+            velocity = final_velocity;
+        }
+        time - target_time
+    };
+    root_finding::bisect(time_difference, 0.0, MAX_CONSTANT_POWER, tolerance)
+}
+
+/// The outcome of riding a course at a fixed power until the rider fails, see `ride_to_failure`.
+///
+/// * `CompletesCourse` - The rider reaches the finish in `total_time` seconds, e.g. always at or below critical
+///   power.
+/// * `Fails` - The rider fails `distance` meters from the start, `time` seconds after it, on the segment
+///   `segment_index`. The `reason` is `AnaerobicReserveExhausted` when the anaerobic reserve hits zero, and
+///   `InsufficientForce` when the power cannot move the rider up the segment, who then stops at its start.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RideToFailure {
+    CompletesCourse {
+        total_time: f64,
+    },
+    Fails {
+        distance: f64,
+        time: f64,
+        segment_index: usize,
+        reason: InfeasibilityReason,
+    },
+}
+
+/// Rides a course at a fixed power, starting with a full anaerobic reserve, and reports how far the rider gets
+/// before the reserve is exhausted, instead of dropping to critical power as `simulate` does.
+///
+/// The failure point within a segment is interpolated in time between the integration steps.
+///
+/// # Example
+///
+/// ```
+/// match ride_to_failure(0.0, 450.0, &road_segment_vec, &resistance_model, &rider_model) {
+///     RideToFailure::CompletesCourse { total_time } => println!("Finished in {:.0}s", total_time),
+///     RideToFailure::Fails { distance, time, .. } => println!("Cracked after {:.0}m in {:.0}s", distance, time),
+/// }
+/// ```
+pub fn ride_to_failure<M: PowerDurationModel>(
+    initial_velocity: f64,
+    input_power: f64,
+    road_segment_vec: &Vec<RoadSegment>,
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+) -> RideToFailure {
+    let mut velocity = initial_velocity;
+    let mut battery_energy = full_battery_energy(resistance_model);
+    let mut anaerobic_reserve = rider_model.anaerobic_work_capacity();
+    let mut total_time = 0.0;
+    let mut total_distance = 0.0;
+    for (i, road_segment) in road_segment_vec.iter().enumerate() {
+        if input_power < minimum_power_to_move_with_battery(road_segment, resistance_model, battery_energy) {
+            return RideToFailure::Fails {
+                distance: total_distance,
+                time: total_time,
+                segment_index: i,
+                reason: InfeasibilityReason::InsufficientForce,
+            };
+        }
+        let trajectory_vec = RefCell::new(vec![(0.0, 0.0)]);
+        let record_step = |step: &IntegrationStep| {
+            trajectory_vec.borrow_mut().push((step.position, step.time));
+        };
+        let outcome = ride_segment_with_observer(
+            velocity,
+            input_power,
+            road_segment,
+            resistance_model,
+            battery_energy,
+            f64::INFINITY,
+            Some(&record_step),
+        );
+        let time_to_exhaustion = rider_model.time_to_exhaustion(input_power, anaerobic_reserve);
+        if input_power > rider_model.critical_power() && time_to_exhaustion < outcome.time {
+            let trajectory_vec = trajectory_vec.into_inner();
+            let k = trajectory_vec.partition_point(|&(_, time)| time < time_to_exhaustion).max(1);
+            let (start_position, start_time) = trajectory_vec[k - 1];
+            let (end_position, end_time) = trajectory_vec[usize::min(k, trajectory_vec.len() - 1)];
+            let weight = if end_time > start_time {
+                (time_to_exhaustion - start_time) / (end_time - start_time)
+            } else {
+                0.0
+            };
+            return RideToFailure::Fails {
+                distance: total_distance + start_position + weight * (end_position - start_position),
+                time: total_time + time_to_exhaustion,
+                segment_index: i,
+                reason: InfeasibilityReason::AnaerobicReserveExhausted,
+            };
+        }
+        anaerobic_reserve = rider_model.update_anaerobic_reserve(input_power, outcome.time, anaerobic_reserve);
+        velocity = outcome.final_velocity;
+        battery_energy = outcome.battery_energy;
+        total_time += outcome.time;
+        total_distance += road_segment.length;
+    }
+    RideToFailure::CompletesCourse { total_time }
+}
+
+/// An alternative route between two points, with how much of the wind reaches the rider on it.
+///
+/// # Fields
+///
+/// * `road_segment_vec` - The segments of the route.
+/// * `wind_exposure` - The fraction of the wind felt on the route, e.g. 1 on an open road and 0.3 on a road
+///   sheltered by trees or hills.
+pub struct RouteAlternative {
+    pub road_segment_vec: Vec<RoadSegment>,
+    pub wind_exposure: f64,
+}
+
+/// Returns the time in seconds to ride a route at constant power with the given headwind.
+fn route_time(
+    initial_velocity: f64,
+    input_power: f64,
+    route: &RouteAlternative,
+    resistance_model: &BicycleResistanceModel,
+    wind_speed: f64,
+) -> f64 {
+    let mut time = 0.0;
+    let mut velocity = initial_velocity;
+    for road_segment in route.road_segment_vec.iter() {
+        let windy_segment = RoadSegment {
+            relative_wind_speed: road_segment.relative_wind_speed + route.wind_exposure * wind_speed,
+            ..*road_segment
+        };
+        let (duration, final_velocity) =
+            compute_time_and_final_velocity(velocity, input_power, &windy_segment, resistance_model);
+        time += duration;
+        velocity = final_velocity;
+    }
+    time
+}
+
+/// Finds the headwind speed at which two alternative routes take the same time at constant power, e.g. an
+/// exposed flat road and a sheltered hillier one.
+///
+/// The headwind is swept over `wind_speed_range` and the first change of the faster route is refined by
+/// bisection. The wind is added to the `relative_wind_speed` of every segment, scaled by the exposure of each route.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the routes in meters per second.
+/// * `input_power` - The constant power in watts.
+/// * `route_a` - The first route.
+/// * `route_b` - The second route.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `wind_speed_range` - The lowest and highest headwind speeds tried in meters per second, negative for a
+///   tailwind.
+/// * `tolerance` - The stopping criterion of the bisection on the wind speed.
+///
+/// # Returns
+///
+/// * `Option<f64>` - The break-even headwind speed in meters per second, or `None` if the same route is faster
+///   over the whole range or the bisection does not converge.
+pub fn break_even_wind_speed(
+    initial_velocity: f64,
+    input_power: f64,
+    route_a: &RouteAlternative,
+    route_b: &RouteAlternative,
+    resistance_model: &BicycleResistanceModel,
+    wind_speed_range: (f64, f64),
+    tolerance: &root_finding::Tolerance,
+) -> Option<f64> {
+    let time_difference = |wind_speed: f64| {
+        route_time(initial_velocity, input_power, route_a, resistance_model, wind_speed)
+            - route_time(initial_velocity, input_power, route_b, resistance_model, wind_speed)
+    };
+    let (min_wind_speed, max_wind_speed) = wind_speed_range;
+    let wind_step = (max_wind_speed - min_wind_speed) / WIND_SWEEP_STEPS as f64;
+    let mut low = min_wind_speed;
+    let mut low_difference = time_difference(low);
+    for k in 1..=WIND_SWEEP_STEPS {
+        let high = min_wind_speed + k as f64 * wind_step;
+        let high_difference = time_difference(high);
+        if low_difference == 0.0 {
+            return Some(low);
+        }
+        if low_difference * high_difference <= 0.0 {
+            let solution = root_finding::bisect(time_difference, low, high, tolerance);
+            return solution.converged.then_some(solution.value);
+        }
+        low = high;
+        low_difference = high_difference;
+    }
+    None
+}
+
+/// Computes the steady velocity of a rider holding `input_power` on a segment, where the propulsive force balances
+/// the resistances. The segment is assumed long enough to reach it, e.g. for a virtual flat road.
+///
+/// # Arguments
+///
+/// * `input_power` - The power in watts.
+/// * `road_segment` - The segment, whose length is ignored.
+/// * `resistance_model` - The resistance model of the bicycle. Motor assist, if any, is assumed to last.
+/// * `tolerance` - The stopping criterion of the bisection on the velocity.
+///
+/// # Returns
+///
+/// * `f64` - The steady velocity in meters per second, at least `MIN_VELOCITY`.
+pub fn steady_velocity(
+    input_power: f64,
+    road_segment: &RoadSegment,
+    resistance_model: &BicycleResistanceModel,
+    tolerance: &root_finding::Tolerance,
+) -> f64 {
+    let net_force = |velocity: f64| {
+        net_force(velocity, input_power, road_segment, resistance_model)
+    };
+    root_finding::bisect(net_force, MIN_VELOCITY, MAX_STEADY_VELOCITY, tolerance).value
+}
+
+/// Computes the steady velocity at a fixed power over a grid of gradients and wind speeds, e.g. for a pacing chart,
+/// at sea level and 20 degrees Celsius. See `steady_velocity`.
+///
+/// # Arguments
+///
+/// * `input_power` - The power in watts.
+/// * `slope_vec` - The gradients of the rows, e.g. 0.08 for 8%.
+/// * `wind_speed_vec` - The relative wind speeds of the columns in meters per second, positive for a headwind.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `tolerance` - The stopping criterion of the bisection on the velocity.
+///
+/// # Returns
+///
+/// * `Vec<Vec<Option<f64>>>` - The steady velocity in meters per second for each gradient (row) and wind speed
+///   (column), `None` where the power cannot move the rider, i.e. the net force is not positive at `MIN_VELOCITY`.
+///
+/// # Example
+///
+/// ```
+/// let table = isopower_table(
+///     250.0,
+///     &vec![0.0, 0.04, 0.08],
+///     &vec![-5.0, 0.0, 5.0],
+///     &default_resistance_model(),
+///     &root_finding::default_tolerance(),
+/// );
+/// println!("{:?} m/s up 8% into a 5 m/s headwind", table[2][2]);
+/// ```
+pub fn isopower_table(
+    input_power: f64,
+    slope_vec: &Vec<f64>,
+    wind_speed_vec: &Vec<f64>,
+    resistance_model: &BicycleResistanceModel,
+    tolerance: &root_finding::Tolerance,
+) -> Vec<Vec<Option<f64>>> {
+    slope_vec
+        .iter()
+        .map(|&slope| {
+            wind_speed_vec
+                .iter()
+                .map(|&wind_speed| {
+                    let road_segment = RoadSegment {
+                        length: 1.0,
+                        altitude: 0.0,
+                        slope,
+                        temperature: 20.0,
+                        relative_wind_speed: wind_speed,
+                        roughness: 1.0,
+                        rolling_resistance: None,
+                        drivetrain_efficiency: None,
+                    };
+                    if net_force(MIN_VELOCITY, input_power, &road_segment, resistance_model) <= 0.0 {
+                        return None;
+                    }
+                    Some(steady_velocity(input_power, &road_segment, resistance_model, tolerance))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes the share of air resistance in the total resistance when riding steadily at `input_power` up a
+/// gradient, in still air at sea level and 20 degrees Celsius.
+///
+/// # Arguments
+///
+/// * `input_power` - The power in watts.
+/// * `slope` - The gradient, e.g. 0.08 for 8%. Not negative, since gravity then propels the rider.
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `tolerance` - The stopping criterion of the bisection on the steady velocity.
+///
+/// # Returns
+///
+/// * `f64` - The air resistance divided by the sum of the air, rolling, roughness and gravity resistances, between
+///   0 and 1.
+pub fn aero_share_of_resistance(
+    input_power: f64,
+    slope: f64,
+    resistance_model: &BicycleResistanceModel,
+    tolerance: &root_finding::Tolerance,
+) -> f64 {
+    let road_segment = RoadSegment {
+        length: 1.0,
+        altitude: 0.0,
+        slope,
+        temperature: 20.0,
+        relative_wind_speed: 0.0,
+        roughness: 1.0,
+        rolling_resistance: None,
+        drivetrain_efficiency: None,
+    };
+    let total_mass = resistance_model.total_mass();
+    let velocity = steady_velocity(input_power, &road_segment, resistance_model, tolerance);
+    let density = segment_air_density(&road_segment, resistance_model);
+    let air_force =
+        kinematics::get_air_resistance_force(velocity, 0.0, 0.5 * cda_at(velocity, resistance_model) * density);
+    let other_force = kinematics::get_rolling_resistance_force(
+        rolling_resistance_coef(&road_segment, resistance_model),
+        total_mass,
+    ) + roughness_loss_force(velocity, &road_segment, resistance_model)
+        + kinematics::get_gravity_force(slope, total_mass);
+    air_force / (air_force + other_force)
+}
+
+/// Computes the critical gradient of a rider: the gradient above which, riding at critical power, air resistance
+/// is less than `aero_share` of the total resistance, so aerodynamics stop mattering and climbing ability
+/// dominates. See `aero_share_of_resistance`.
+///
+/// # Arguments
+///
+/// * `resistance_model` - The resistance model of the bicycle.
+/// * `rider_model` - The rider model giving the critical power.
+/// * `aero_share` - The share of air resistance considered negligible, e.g. 0.05 for 5%.
+/// * `tolerance` - The stopping criterion of the bisections on the gradient and on the steady velocity.
+///
+/// # Returns
+///
+/// * `Option<f64>` - The critical gradient, e.g. 0.06 for 6%, or `None` if air resistance is already negligible
+///   on the flat, e.g. with a very low CdA, or still matters on a `MAX_CRITICAL_GRADIENT` climb.
+///
+/// # Example
+///
+/// ```
+/// let gradient = critical_gradient(
+///     &default_resistance_model(),
+///     &morton::default_rider_model(),
+///     0.05,
+///     &root_finding::default_tolerance(),
+/// );
+/// ```
+pub fn critical_gradient<M: PowerDurationModel>(
+    resistance_model: &BicycleResistanceModel,
+    rider_model: &M,
+    aero_share: f64,
+    tolerance: &root_finding::Tolerance,
+) -> Option<f64> {
+    let excess_aero_share = |slope: f64| {
+        aero_share_of_resistance(rider_model.critical_power(), slope, resistance_model, tolerance) - aero_share
+    };
+    if excess_aero_share(0.0) <= 0.0 {
+        return None;
+    }
+    let solution = root_finding::bisect(excess_aero_share, 0.0, MAX_CRITICAL_GRADIENT, tolerance);
+    if !solution.converged {
+        return None;
+    }
+    Some(solution.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::omni_domain;
+    use crate::sim::pacing;
+    use crate::testutil::{count_allocations, make_course, CourseProfile};
+
+    #[test]
+    fn air_drag_slows_a_flat_segment_in_still_air() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 1, segment_length: 2000.0 });
+        let slippery = BicycleResistanceModel { cda_surface: 0.15, ..default_resistance_model() };
+        let (time, _) = compute_time_and_final_velocity(10.0, 250.0, &road_segment_vec[0], &default_resistance_model());
+        let (slippery_time, _) = compute_time_and_final_velocity(10.0, 250.0, &road_segment_vec[0], &slippery);
+        assert!(slippery_time < 0.9 * time, "{} vs {}", slippery_time, time);
+    }
+
+    #[test]
+    fn air_density_table_matches_the_exact_density_within_its_error() {
+        // Climbing from sea level to 1600 m.
+        let road_segment_vec =
+            make_course(CourseProfile::ConstantClimb { n_segments: 40, segment_length: 500.0, slope: 0.08 });
+        let rider_model = morton::default_rider_model();
+        let tabulated = BicycleResistanceModel {
+            air_density_table: Some(kinematics::air_density_table(-500.0, 5000.0, 56).unwrap()),
+            ..default_resistance_model()
+        };
+        let ride = |resistance_model: &BicycleResistanceModel| {
+            simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![250.0; road_segment_vec.len()],
+                &road_segment_vec,
+                resistance_model,
+                &rider_model,
+            )
+        };
+
+        let exact_result = ride(&default_resistance_model());
+        let tabulated_result = ride(&tabulated);
+        // The table interpolates the density within 2e-5, which bounds the relative error on the times.
+        let relative_error = (tabulated_result.total_time - exact_result.total_time).abs() / exact_result.total_time;
+        assert!(relative_error < 2e-5, "relative error {}", relative_error);
+        for (tabulated, exact) in tabulated_result.duration_vec.iter().zip(&exact_result.duration_vec) {
+            assert!((tabulated - exact).abs() < 2e-5 * exact);
+        }
+    }
+
+    #[test]
+    fn time_in_zones_sums_to_total_time() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 6, segment_length: 300.0 });
+        let rider_model = morton::default_rider_model();
+        let input_power_vec = vec![150.0, 250.0, 280.0, 350.0, 150.0, 280.0];
+        let result = simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &input_power_vec,
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        let zones = [
+            morton::EffortZone::Endurance,
+            morton::EffortZone::Tempo,
+            morton::EffortZone::Threshold,
+            morton::EffortZone::AboveCriticalPower,
+        ];
+        let zone_time: f64 = zones.iter().map(|&zone| result.time_in_zone(&rider_model, zone)).sum();
+        assert!((zone_time - result.total_time).abs() < 1e-9 * result.total_time);
+        assert_eq!(result.segments_in_zone(&rider_model, morton::EffortZone::Endurance), vec![0, 4]);
+        assert_eq!(result.segments_in_zone(&rider_model, morton::EffortZone::Tempo), vec![1]);
+        assert_eq!(result.segments_in_zone(&rider_model, morton::EffortZone::Threshold), vec![2, 5]);
+        assert_eq!(result.segments_in_zone(&rider_model, morton::EffortZone::AboveCriticalPower), vec![3]);
+    }
+
+    #[test]
+    fn anaerobic_reserve_stays_constant_at_exactly_critical_power() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 300.0,
+            max_slope: 0.05,
+            period: 8,
+        });
+        let rider_model = morton::default_rider_model();
+        // Neither full nor empty, so that a drift either way would show.
+        let initial_anaerobic_reserve = 0.5 * rider_model.anaerobic_work_capacity;
+        let result = simulate(
+            5.0,
+            initial_anaerobic_reserve,
+            &vec![rider_model.critical_power; road_segment_vec.len()],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        assert!(result.is_feasible());
+        assert!(result.anaerobic_reserve_vec.iter().all(|&reserve| reserve == initial_anaerobic_reserve));
+        assert_eq!(
+            result.segments_in_zone(&rider_model, morton::EffortZone::Threshold),
+            (0..road_segment_vec.len()).collect::<Vec<usize>>()
+        );
+    }
+
+    #[test]
+    fn lower_cda_is_faster_on_the_flat() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 10, segment_length: 500.0 });
+        let rider_model = morton::default_rider_model();
+        let input_power_vec = vec![250.0; road_segment_vec.len()];
+        let resistance_models = [
+            default_resistance_model(),
+            BicycleResistanceModel {
+                cda_surface: 0.25,
+                ..default_resistance_model()
+            },
+        ];
+        let result_vec = compare_models(
+            0.0,
+            rider_model.anaerobic_work_capacity,
+            &input_power_vec,
+            &road_segment_vec,
+            &rider_model,
+            &resistance_models,
+        );
+
+        assert_eq!(result_vec.len(), 2);
+        assert!(result_vec.iter().all(|result| result.is_feasible()));
+        assert!(result_vec[1].total_time < result_vec[0].total_time);
+    }
+
+    #[test]
+    fn assisted_climb_is_faster_and_drains_the_delivered_energy() {
+        let road_segment_vec =
+            make_course(CourseProfile::ConstantClimb { n_segments: 5, segment_length: 200.0, slope: 0.06 });
+        let rider_model = morton::default_rider_model();
+        let input_power_vec = vec![250.0; road_segment_vec.len()];
+        let motor_assist = MotorAssist {
+            max_assist_power: 250.0,
+            battery_capacity: 500.0,
+        };
+        let assisted_resistance_model = BicycleResistanceModel {
+            motor_assist: Some(motor_assist),
+            ..default_resistance_model()
+        };
+        let ride = |resistance_model: &BicycleResistanceModel| {
+            simulate(
+                3.0,
+                rider_model.anaerobic_work_capacity,
+                &input_power_vec,
+                &road_segment_vec,
+                resistance_model,
+                &rider_model,
+            )
+        };
+
+        let unassisted = ride(&default_resistance_model());
+        let assisted = ride(&assisted_resistance_model);
+
+        assert!(assisted.total_time < unassisted.total_time);
+        let drained_energy = motor_assist.battery_capacity - assisted.battery_energy_vec.last().unwrap();
+        let delivered_energy = motor_assist.max_assist_power * assisted.total_time / JOULES_PER_WATT_HOUR;
+        assert!((drained_energy - delivered_energy).abs() < 1e-6 * delivered_energy);
+    }
+
+    #[test]
+    fn battery_running_out_partway_up_a_climb_only_delivers_its_energy() {
+        let motor_assist = MotorAssist {
+            max_assist_power: 250.0,
+            battery_capacity: 10.0,
+        };
+        let e_bike = BicycleResistanceModel {
+            motor_assist: Some(motor_assist),
+            ..default_resistance_model()
+        };
+        let climb = make_course(CourseProfile::ConstantClimb { n_segments: 1, segment_length: 2000.0, slope: 0.06 });
+        let ride = |step_strategy: StepStrategy| {
+            ride_segment_in_wind(
+                5.0,
+                250.0,
+                &climb[0],
+                &e_bike,
+                motor_assist.battery_capacity,
+                f64::INFINITY,
+                SegmentRideOptions { step_strategy, wind: None, observer: None },
+            )
+        };
+
+        // The battery runs out about 1100 m up, where the adaptive steps have grown to tens of meters.
+        let adaptive_outcome = ride(StepStrategy::Energy);
+        let short_step_outcome = ride(StepStrategy::Distance { step_length: 1.0 });
+        assert!(adaptive_outcome.battery_energy < 1e-9);
+        assert!(short_step_outcome.battery_energy < 1e-9);
+        assert!(
+            f64::abs(adaptive_outcome.time - short_step_outcome.time) < 1e-4 * short_step_outcome.time,
+            "{} vs {}",
+            adaptive_outcome.time,
+            short_step_outcome.time
+        );
+    }
+
+    /// Returns a flat segment followed by a segment of the given slope.
+    fn flat_then_slope(slope: f64) -> Vec<RoadSegment> {
+        let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 2, segment_length: 100.0 });
+        road_segment_vec[1].slope = slope;
+        road_segment_vec
+    }
+
+    #[test]
+    fn infeasible_courses_report_the_limiting_segment_and_reason() {
+        let rider_model = morton::default_rider_model();
+        let resistance_model = default_resistance_model();
+        let input_power_vec = vec![250.0; 2];
+        let infeasibility = |road_segment_vec: &Vec<RoadSegment>, anaerobic_reserve: f64, options: &SimulationOptions| {
+            simulate_with_options(
+                5.0,
+                anaerobic_reserve,
+                &input_power_vec,
+                road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                options,
+            )
+            .feasibility
+        };
+        let options = default_simulation_options();
+
+        // Steeper than the rider can move up even at max power.
+        assert_eq!(
+            infeasibility(&flat_then_slope(1.5), rider_model.anaerobic_work_capacity, &options),
+            Feasibility::Infeasible { segment_index: 1, reason: InfeasibilityReason::InsufficientForce }
+        );
+        // Within the rider's max power when fresh, but not at critical power with an empty reserve.
+        assert_eq!(
+            infeasibility(&flat_then_slope(0.5), rider_model.anaerobic_work_capacity, &options),
+            Feasibility::Feasible
+        );
+        assert_eq!(
+            infeasibility(&flat_then_slope(0.5), 0.0, &options),
+            Feasibility::Infeasible { segment_index: 1, reason: InfeasibilityReason::AnaerobicReserveExhausted }
+        );
+        // A steep descent to a slow finish cannot be ridden without braking.
+        let descent = make_course(CourseProfile::Descent { n_segments: 2, segment_length: 100.0, slope: 0.15 });
+        let no_braking_options = SimulationOptions {
+            final_velocity: Some(1.0),
+            forbid_braking: true,
+            ..default_simulation_options()
+        };
+        assert_eq!(
+            infeasibility(&descent, rider_model.anaerobic_work_capacity, &no_braking_options),
+            Feasibility::Infeasible { segment_index: 0, reason: InfeasibilityReason::BrakingRequired }
+        );
+    }
+
+    #[test]
+    fn motor_assist_only_gets_up_a_wall_while_the_battery_lasts() {
+        let rider_model = morton::default_rider_model();
+        let motor_assist = MotorAssist {
+            max_assist_power: 1000.0,
+            battery_capacity: 40.0,
+        };
+        let e_bike = BicycleResistanceModel {
+            motor_assist: Some(motor_assist),
+            ..default_resistance_model()
+        };
+        // Steeper than the rider can move up on their own, but not with the motor.
+        let wall = flat_then_slope(1.5).remove(1);
+        assert!(minimum_power_to_move(&wall, &default_resistance_model()) > rider_model.max_power);
+        assert!(minimum_power_to_move(&wall, &e_bike) < 250.0);
+        assert_eq!(
+            minimum_power_to_move_with_battery(&wall, &e_bike, 0.0),
+            minimum_power_to_move(&wall, &default_resistance_model())
+        );
+
+        let feasibility = |road_segment_vec: &Vec<RoadSegment>| {
+            simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![250.0; road_segment_vec.len()],
+                road_segment_vec,
+                &e_bike,
+                &rider_model,
+            )
+            .feasibility
+        };
+        assert_eq!(feasibility(&vec![RoadSegment { ..wall }]), Feasibility::Feasible);
+        // The battery lasts about 2.4 km of flat at full assist, so it is empty at the foot of the wall.
+        let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 5, segment_length: 1000.0 });
+        road_segment_vec.push(wall);
+        assert_eq!(
+            feasibility(&road_segment_vec),
+            Feasibility::Infeasible { segment_index: 5, reason: InfeasibilityReason::InsufficientForce }
+        );
+    }
+
+    #[test]
+    fn headwind_costs_energy_and_tailwind_saves_it() {
+        let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 3, segment_length: 500.0 });
+        road_segment_vec[0].relative_wind_speed = 4.0;
+        road_segment_vec[2].relative_wind_speed = -4.0;
+        let rider_model = morton::default_rider_model();
+        let result = simulate(
+            8.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![250.0; 3],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        assert!(result.wind_energy_vec[0] > 0.0);
+        assert_eq!(result.wind_energy_vec[1], 0.0);
+        assert!(result.wind_energy_vec[2] < 0.0);
+    }
+
+    #[test]
+    fn analytic_solver_agrees_with_stepping_on_the_flat() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 1, segment_length: 2000.0 });
+        let reynolds_resistance_model = BicycleResistanceModel {
+            reynolds_correction: Some(ReynoldsCorrection {
+                characteristic_length: 1.7,
+                reference_reynolds_number: 1e6,
+                sensitivity: 0.2,
+            }),
+            ..default_resistance_model()
+        };
+        for resistance_model in [default_resistance_model(), reynolds_resistance_model] {
+            for initial_velocity in [5.0, 15.0] {
+                let (time, final_velocity) =
+                    compute_time_and_final_velocity(initial_velocity, 250.0, &road_segment_vec[0], &resistance_model);
+                let (analytic_time, analytic_final_velocity) = compute_time_and_final_velocity_analytic(
+                    initial_velocity,
+                    250.0,
+                    &road_segment_vec[0],
+                    &resistance_model,
+                    &root_finding::default_tolerance(),
+                );
+                assert!((analytic_time - time).abs() < 1e-4 * time, "{} vs {}", analytic_time, time);
+                assert!((analytic_final_velocity - final_velocity).abs() < 1e-4 * final_velocity);
+            }
+        }
+    }
+
+    #[test]
+    fn flying_start_is_faster_than_standing_start() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 4, segment_length: 250.0 });
+        let rider_model = morton::default_rider_model();
+        let ride = |initial_velocity: f64, options: &SimulationOptions| {
+            simulate_with_options(
+                initial_velocity,
+                rider_model.anaerobic_work_capacity,
+                &vec![280.0; 4],
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                options,
+            )
+        };
+
+        let standing = ride(0.0, &default_simulation_options());
+        let flying = ride(12.0, &default_simulation_options());
+        assert!(flying.total_time < standing.total_time);
+
+        let stopping_options = SimulationOptions {
+            final_velocity: Some(2.0),
+            ..default_simulation_options()
+        };
+        let stopping = ride(12.0, &stopping_options);
+        assert!(*stopping.velocity_vec.last().unwrap() <= 2.0 + 1e-6);
+        assert!(stopping.total_time > flying.total_time);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trips_to_an_equal_result() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 6,
+            segment_length: 200.0,
+            max_slope: 0.05,
+            period: 6,
+        });
+        let rider_model = morton::default_rider_model();
+        let result = simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![320.0; 6],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        let json = result.to_json().unwrap();
+        assert_eq!(SimulationResult::from_json(&json).unwrap(), result);
+    }
+
+    #[test]
+    fn coast_down_decelerates_monotonically_to_a_stop() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 1, segment_length: 5000.0 });
+        let sample_vec = coast_down(12.0, &road_segment_vec[0], &default_resistance_model(), 0.5).unwrap();
+
+        assert_eq!(sample_vec[0], (0.0, 12.0));
+        for pair in sample_vec.windows(2) {
+            assert!(pair[1].0 > pair[0].0);
+            assert!(pair[1].1 < pair[0].1);
+        }
+        let (_, final_velocity) = *sample_vec.last().unwrap();
+        assert!(final_velocity < 2.0 * MIN_VELOCITY, "{}", final_velocity);
+    }
+
+    #[test]
+    fn coast_down_rejects_a_step_that_is_not_positive() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 1, segment_length: 5000.0 });
+        for dt in [0.0, -0.5, f64::NAN] {
+            assert!(coast_down(12.0, &road_segment_vec[0], &default_resistance_model(), dt).is_err(), "{}", dt);
+        }
+    }
+
+    #[test]
+    fn power_cap_slows_the_plan_down() {
+        let road_segment_vec =
+            make_course(CourseProfile::ConstantClimb { n_segments: 5, segment_length: 200.0, slope: 0.05 });
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let mut input_power_vec = Vec::new();
+        pacing::optimize_dynamic_programming(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &pacing::default_dynamic_pacer_settings(),
+            &mut input_power_vec,
+        );
+        let ride = |options: &SimulationOptions| {
+            simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &input_power_vec,
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                options,
+            )
+        };
+
+        let uncapped = ride(&default_simulation_options());
+        assert!(uncapped.power_vec.iter().any(|&power| power > 330.0));
+        let capped = ride(&SimulationOptions {
+            power_cap: Some(330.0),
+            ..default_simulation_options()
+        });
+
+        assert!(uncapped.is_feasible() && capped.is_feasible());
+        assert!(capped.power_vec.iter().all(|&power| power <= 330.0));
+        assert!(capped.total_time > uncapped.total_time);
+    }
+
+    #[test]
+    fn hilly_course_is_longer_than_its_flat_equivalent() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 20,
+            segment_length: 250.0,
+            max_slope: 0.06,
+            period: 10,
+        });
+        let length: f64 = road_segment_vec.iter().map(|road_segment| road_segment.length).sum();
+        let flat_distance = equivalent_flat_distance(
+            5.0,
+            &road_segment_vec,
+            &default_resistance_model(),
+            &morton::default_rider_model(),
+            &root_finding::default_tolerance(),
+        );
+
+        assert!(flat_distance > length, "{} <= {}", flat_distance, length);
+    }
+
+    #[test]
+    fn gravity_work_cancels_on_a_closed_loop() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 12,
+            segment_length: 200.0,
+            max_slope: 0.05,
+            period: 12,
+        });
+        let rider_model = morton::default_rider_model();
+        let result = simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![280.0; 12],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        let net_gravity_energy: f64 = result.gravity_energy_vec.iter().sum();
+        let climbing_energy: f64 = result.gravity_energy_vec.iter().filter(|&&energy| energy > 0.0).sum();
+        assert!(climbing_energy > 0.0);
+        assert!(net_gravity_energy.abs() < 1e-9 * climbing_energy, "{}", net_gravity_energy);
+    }
+
+    #[test]
+    fn tailwind_air_speed_is_below_ground_speed() {
+        let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 2, segment_length: 500.0 });
+        road_segment_vec[1].relative_wind_speed = -3.0;
+        let rider_model = morton::default_rider_model();
+        let result = simulate(
+            8.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![250.0; 2],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        assert_eq!(result.air_speed_vec[0], result.velocity_vec[0]);
+        assert!((result.velocity_vec[1] - result.air_speed_vec[1] - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn power_floor_is_respected_on_descents() {
+        let road_segment_vec = make_course(CourseProfile::Descent {
+            n_segments: 4,
+            segment_length: 300.0,
+            slope: 0.06,
+        });
+        let rider_model = morton::default_rider_model();
+        let options = SimulationOptions {
+            power_floor: 50.0,
+            ..default_simulation_options()
+        };
+        let result = simulate_with_options(
+            10.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![0.0, -20.0, 30.0, 200.0],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+            &options,
+        );
+
+        assert_eq!(result.power_vec, vec![50.0, 50.0, 50.0, 200.0]);
+    }
+
+    #[test]
+    fn descent_too_steep_to_coast_under_the_speed_limit_is_braked() {
+        let road_segment_vec = make_course(CourseProfile::Descent {
+            n_segments: 4,
+            segment_length: 300.0,
+            slope: 0.1,
+        });
+        let rider_model = morton::default_rider_model();
+        let options = SimulationOptions {
+            final_velocity: Some(8.0),
+            ..default_simulation_options()
+        };
+        let result = simulate_with_options(
+            10.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![0.0; 4],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+            &options,
+        );
+
+        // Coasting, the rider reaches about 20 m/s, so they must brake down to the limit at the end.
+        assert!(result.is_feasible());
+        assert_eq!(result.power_vec, vec![0.0; 4]);
+        assert!(result.braking_energy_vec.iter().sum::<f64>() > 0.0);
+        assert!(*result.velocity_vec.last().unwrap() <= 8.0 + 1e-6, "{:?}", result.velocity_vec);
+    }
+
+    #[test]
+    fn labeled_climb_name_appears_in_its_result_segment() {
+        let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 2, segment_length: 400.0 });
+        road_segment_vec.extend(make_course(CourseProfile::ConstantClimb {
+            n_segments: 2,
+            segment_length: 400.0,
+            slope: 0.07,
+        }));
+        let distance_vec = vec![0.0, 400.0, 800.0, 1200.0, 1600.0];
+        let waypoint_vec = vec![
+            crate::data::Waypoint { distance: 1500.0, name: "Col de la Croix".to_string() },
+            crate::data::Waypoint { distance: 820.0, name: "Foot of the climb".to_string() },
+            crate::data::Waypoint { distance: 1550.0, name: "Summit cafe".to_string() },
+        ];
+        let label_vec = crate::data::segment_labels(&distance_vec, &waypoint_vec);
+        let rider_model = morton::default_rider_model();
+        let result = simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![250.0; 4],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        let split_vec = result.labeled_splits(&label_vec);
+        assert_eq!(split_vec.len(), 2);
+        assert_eq!(split_vec[0].segment_index, 2);
+        assert_eq!(split_vec[0].label, "Foot of the climb");
+        assert_eq!(split_vec[1].segment_index, 3);
+        assert_eq!(split_vec[1].label, "Col de la Croix / Summit cafe");
+        assert!((split_vec[1].time - result.total_time).abs() < 1e-9);
+        assert!((split_vec[1].distance - 1600.0).abs() < 1e-9);
+    }
+
+    /// Time and number of steps to ride `road_segment` from `initial_velocity` with fixed Euler steps of
+    /// `step_size` meters, in still air on smooth road.
+    fn fixed_step_ride(
+        initial_velocity: f64,
+        input_power: f64,
+        road_segment: &RoadSegment,
+        resistance_model: &BicycleResistanceModel,
+        step_size: f64,
+    ) -> (f64, f64, usize) {
+        let total_mass = resistance_model.total_mass();
+        let density = kinematics::air_density(road_segment.altitude, road_segment.temperature);
+        let viscosity = kinematics::air_viscosity(road_segment.temperature);
+        let rolling_resistance_force = kinematics::get_rolling_resistance_force(
+            rolling_resistance_coef(road_segment, resistance_model),
+            total_mass,
+        );
+        let gravity_force = kinematics::get_gravity_force(road_segment.slope, total_mass);
+        let wheel_power = segment_wheel_power(input_power, road_segment, resistance_model);
+        let mut time = 0.0;
+        let mut position = 0.0;
+        let mut velocity = initial_velocity;
+        let mut step_count = 0;
+        while position < road_segment.length {
+            let step = f64::min(step_size, road_segment.length - position);
+            let kinetic_energy = kinematics::kinetic_energy(velocity, total_mass);
+            let force = kinematics::get_total_force_with_constant_forces(
+                kinetic_energy,
+                wheel_power,
+                air_resistance_coef_at(velocity, 0.0, density, viscosity, resistance_model),
+                0.0,
+                rolling_resistance_force,
+                gravity_force,
+                total_mass,
+            );
+            let new_velocity = f64::max(MIN_VELOCITY, kinematics::velocity(kinetic_energy + force * step, total_mass));
+            time += step / (0.5 * (velocity + new_velocity));
+            position += step;
+            velocity = new_velocity;
+            step_count += 1;
+        }
+        (time, velocity, step_count)
+    }
+
+    #[test]
+    fn step_controller_meets_the_accuracy_with_fewer_steps_than_a_fixed_small_step() {
+        // The slope jumps at the boundary between the segments, where the controller shrinks its step.
+        let road_segment_vec = flat_then_slope(0.08);
+        let resistance_model = default_resistance_model();
+        let ride_fixed = |step_size: f64| {
+            let mut velocity = 3.0;
+            let mut total_time = 0.0;
+            let mut total_steps = 0;
+            for road_segment in road_segment_vec.iter() {
+                let (time, final_velocity, step_count) =
+                    fixed_step_ride(velocity, 300.0, road_segment, &resistance_model, step_size);
+                total_time += time;
+                total_steps += step_count;
+                velocity = final_velocity;
+            }
+            (total_time, total_steps)
+        };
+        let (reference_time, _) = ride_fixed(0.001);
+
+        let mut velocity = 3.0;
+        let mut controlled_time = 0.0;
+        let mut controlled_steps = 0;
+        for road_segment in road_segment_vec.iter() {
+            let outcome = ride_segment(velocity, 300.0, road_segment, &resistance_model, 0.0, f64::INFINITY);
+            controlled_time += outcome.time;
+            controlled_steps += outcome.step_count;
+            velocity = outcome.final_velocity;
+        }
+        let tolerance = 0.002;
+        assert!((controlled_time - reference_time).abs() < tolerance, "{} s vs {} s", controlled_time, reference_time);
+
+        // The largest fixed step meeting the same accuracy needs more steps.
+        let (_, fixed_steps) = [5.0, 2.0, 1.0, 0.5, 0.2, 0.1, 0.05, 0.02, 0.01]
+            .into_iter()
+            .map(ride_fixed)
+            .find(|&(time, _)| (time - reference_time).abs() < tolerance)
+            .unwrap();
+        assert!(controlled_steps < fixed_steps, "{} steps vs {} steps", controlled_steps, fixed_steps);
+    }
+
+    #[test]
+    fn step_observer_sees_increasing_positions_without_changing_the_ride() {
+        let road_segment = &make_course(CourseProfile::ConstantClimb {
+            n_segments: 1,
+            segment_length: 500.0,
+            slope: 0.05,
+        })[0];
+        let resistance_model = default_resistance_model();
+        let step_vec = std::cell::RefCell::new(Vec::new());
+        let log_step = |step: &IntegrationStep| step_vec.borrow_mut().push(*step);
+
+        let observed = compute_time_and_final_velocity_with_observer(
+            4.0,
+            280.0,
+            road_segment,
+            &resistance_model,
+            Some(&log_step),
+        );
+        let unobserved = compute_time_and_final_velocity(4.0, 280.0, road_segment, &resistance_model);
+        assert_eq!(observed, unobserved);
+
+        let step_vec = step_vec.into_inner();
+        let step_count = ride_segment(4.0, 280.0, road_segment, &resistance_model, 0.0, f64::INFINITY).step_count;
+        assert!(step_vec.len() > 10 && step_vec.len() <= step_count, "{} steps", step_vec.len());
+        let mut position = 0.0;
+        for step in step_vec.iter() {
+            assert!(step.position > position);
+            assert!((step.position - position - step.step_size).abs() < 1e-9);
+            position = step.position;
+        }
+        assert!((position - road_segment.length).abs() < 1e-9);
+    }
+
+    #[test]
+    fn time_gap_of_a_run_to_itself_is_zero() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 250.0,
+            max_slope: 0.05,
+            period: 4,
+        });
+        let rider_model = morton::default_rider_model();
+        let result = simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![200.0, 350.0, 250.0, 300.0, 200.0, 350.0, 250.0, 300.0],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+        );
+
+        let gap_vec = time_gap(&result, &result);
+        assert_eq!(gap_vec.len(), road_segment_vec.len());
+        assert!(gap_vec.iter().all(|&gap| gap.abs() < 1e-9), "{:?}", gap_vec);
+    }
+
+    #[test]
+    fn time_gap_aligns_runs_split_into_different_segments() {
+        // The same 2 km flat route, in 250 m segments for one run and 500 m segments for the other, so that half of
+        // the checkpoints fall within a segment of the other run.
+        let coarse_segment_vec = make_course(CourseProfile::Flat { n_segments: 4, segment_length: 500.0 });
+        let fine_segment_vec = make_course(CourseProfile::Flat { n_segments: 8, segment_length: 250.0 });
+        let rider_model = morton::default_rider_model();
+        let resistance_model = default_resistance_model();
+        // Each run starts at its steady speed, so the speeds stay constant and the gaps are known exactly.
+        let ride = |input_power: f64, road_segment_vec: &Vec<RoadSegment>| {
+            let tolerance = root_finding::default_tolerance();
+            let velocity = steady_velocity(input_power, &road_segment_vec[0], &resistance_model, &tolerance);
+            let result = simulate(
+                velocity,
+                rider_model.anaerobic_work_capacity,
+                &vec![input_power; road_segment_vec.len()],
+                road_segment_vec,
+                &resistance_model,
+                &rider_model,
+            );
+            (velocity, result)
+        };
+        let (fast_velocity, fast_result) = ride(300.0, &fine_segment_vec);
+        let (slow_velocity, slow_result) = ride(200.0, &coarse_segment_vec);
+
+        let gap_vec = time_gap(&fast_result, &slow_result);
+        assert_eq!(gap_vec.len(), fine_segment_vec.len());
+        for (i, gap) in gap_vec.iter().enumerate() {
+            let distance = 250.0 * (i + 1) as f64;
+            let expected_gap = distance / slow_velocity - distance / fast_velocity;
+            assert!((gap - expected_gap).abs() < 1e-3, "{} s vs {} s at {} m", gap, expected_gap, distance);
+        }
+    }
+
+    #[test]
+    fn integrated_distance_equals_the_segment_length() {
+        let road_segment = &make_course(CourseProfile::ConstantClimb {
+            n_segments: 1,
+            segment_length: 500.0,
+            slope: 0.03,
+        })[0];
+        let resistance_model = default_resistance_model();
+        // The segment is an exact multiple of the distance steps, of 0.1 m only up to rounding.
+        for step_strategy in [
+            StepStrategy::Energy,
+            StepStrategy::Distance { step_length: 5.0 },
+            StepStrategy::Distance { step_length: 0.1 },
+        ] {
+            let step_vec = std::cell::RefCell::new(Vec::new());
+            let log_step = |step: &IntegrationStep| step_vec.borrow_mut().push(*step);
+            ride_segment_in_wind(
+                5.0,
+                250.0,
+                road_segment,
+                &resistance_model,
+                0.0,
+                f64::INFINITY,
+                SegmentRideOptions { step_strategy, wind: None, observer: Some(&log_step) },
+            );
+
+            let step_vec = step_vec.into_inner();
+            assert_eq!(step_vec.last().unwrap().position, road_segment.length, "{:?}", step_strategy);
+            let integrated_length: f64 = step_vec.iter().map(|step| step.step_size).sum();
+            assert!((integrated_length - road_segment.length).abs() < 1e-9, "{:?}", step_strategy);
+            assert!(step_vec.iter().all(|step| step.step_size > 0.0), "{:?}", step_strategy);
+        }
+    }
+
+    #[test]
+    fn affine_drivetrain_loss_dominates_at_low_power_only() {
+        // Both models lose 9 W at 300 W.
+        let affine_model = BicycleResistanceModel {
+            drivetrain_efficiency: 0.98,
+            drivetrain_fixed_loss: 3.0,
+            ..default_resistance_model()
+        };
+        let multiplicative_model = BicycleResistanceModel {
+            drivetrain_efficiency: 0.97,
+            drivetrain_fixed_loss: 0.0,
+            ..default_resistance_model()
+        };
+        let loss = |input_power: f64, resistance_model: &BicycleResistanceModel| {
+            input_power - wheel_power(input_power, resistance_model)
+        };
+        assert!((loss(300.0, &affine_model) - loss(300.0, &multiplicative_model)).abs() < 1e-9);
+        assert!(loss(60.0, &affine_model) > loss(60.0, &multiplicative_model));
+        assert!(loss(600.0, &affine_model) < loss(600.0, &multiplicative_model));
+        // The fixed loss never takes more than what is pedaled.
+        assert_eq!(wheel_power(2.0, &affine_model), 0.0);
+
+        let road_segment = &make_course(CourseProfile::Flat { n_segments: 1, segment_length: 500.0 })[0];
+        let time = |input_power: f64, resistance_model: &BicycleResistanceModel| {
+            compute_time_and_final_velocity(5.0, input_power, road_segment, resistance_model).0
+        };
+        assert!(time(60.0, &affine_model) > time(60.0, &multiplicative_model));
+        assert!(time(600.0, &affine_model) < time(600.0, &multiplicative_model));
+    }
+
+    #[test]
+    fn sheltered_hilly_route_wins_above_a_break_even_headwind() {
+        let exposed_route = RouteAlternative {
+            road_segment_vec: make_course(CourseProfile::Flat { n_segments: 10, segment_length: 500.0 }),
+            wind_exposure: 1.0,
+        };
+        let sheltered_route = RouteAlternative {
+            road_segment_vec: make_course(CourseProfile::RollingSine {
+                n_segments: 10,
+                segment_length: 500.0,
+                max_slope: 0.05,
+                period: 10,
+            }),
+            wind_exposure: 0.3,
+        };
+        let resistance_model = default_resistance_model();
+        let time = |route: &RouteAlternative, wind_speed: f64| {
+            route_time(8.0, 250.0, route, &resistance_model, wind_speed)
+        };
+
+        let tolerance = root_finding::default_tolerance();
+        let break_even = |wind_speed_range: (f64, f64)| {
+            break_even_wind_speed(
+                8.0,
+                250.0,
+                &exposed_route,
+                &sheltered_route,
+                &resistance_model,
+                wind_speed_range,
+                &tolerance,
+            )
+        };
+        let wind_speed = break_even((0.0, 15.0)).unwrap();
+        // In still air the flat road is faster, so the hills only pay off in a real headwind.
+        assert!(wind_speed > 0.5 && wind_speed < 15.0, "{} m/s", wind_speed);
+        assert!(time(&exposed_route, wind_speed - 0.5) < time(&sheltered_route, wind_speed - 0.5));
+        assert!(time(&exposed_route, wind_speed + 0.5) > time(&sheltered_route, wind_speed + 0.5));
+        assert!(break_even((0.0, 0.5 * wind_speed)).is_none());
+    }
+
+    #[test]
+    fn tucking_cda_curve_raises_terminal_velocity_on_a_descent() {
+        let road_segment_vec = make_course(CourseProfile::Descent {
+            n_segments: 10,
+            segment_length: 300.0,
+            slope: 0.07,
+        });
+        let constant_model = default_resistance_model();
+        let cda = constant_model.cda_surface;
+        let final_velocity = |cda_speed_curve: Option<Vec<(f64, f64)>>| {
+            let resistance_model = BicycleResistanceModel { cda_speed_curve, ..default_resistance_model() };
+            let mut velocity = 5.0;
+            for road_segment in road_segment_vec.iter() {
+                velocity = compute_time_and_final_velocity(velocity, 0.0, road_segment, &resistance_model).1;
+            }
+            velocity
+        };
+
+        let constant_velocity = final_velocity(None);
+        let tucked_velocity = final_velocity(Some(vec![(8.0, cda), (18.0, 0.75 * cda)]));
+        assert!(tucked_velocity > constant_velocity + 1.0, "{} vs {} m/s", tucked_velocity, constant_velocity);
+        // A constant curve reproduces the scalar CdA, and an empty one falls back to it.
+        assert_eq!(final_velocity(Some(vec![(5.0, cda), (20.0, cda)])), constant_velocity);
+        assert_eq!(final_velocity(Some(Vec::new())), constant_velocity);
+    }
+
+    #[test]
+    fn total_mass_is_the_sum_and_only_the_total_drives_the_physics() {
+        let light_bike_model = BicycleResistanceModel {
+            rider_mass: 75.0,
+            bike_mass: 6.0,
+            ..default_resistance_model()
+        };
+        let heavy_bike_model = BicycleResistanceModel {
+            rider_mass: 70.0,
+            bike_mass: 11.0,
+            ..default_resistance_model()
+        };
+        assert_eq!(light_bike_model.total_mass(), 81.0);
+        assert_eq!(heavy_bike_model.total_mass(), 81.0);
+        let total_mass_model = resistance_model_with_total_mass(81.0).unwrap();
+        assert!((total_mass_model.total_mass() - 81.0).abs() < 1e-12);
+        assert_eq!(total_mass_model.bike_mass, DEFAULT_BIKE_MASS);
+        assert!(resistance_model_with_total_mass(DEFAULT_BIKE_MASS).is_err());
+        assert!(resistance_model_with_total_mass(5.0).is_err());
+        assert!(resistance_model_with_total_mass(f64::NAN).is_err());
+
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 6,
+            segment_length: 300.0,
+            max_slope: 0.06,
+            period: 6,
+        });
+        let rider_model = morton::default_rider_model();
+        let total_time = |resistance_model: &BicycleResistanceModel| {
+            simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![280.0; 6],
+                &road_segment_vec,
+                resistance_model,
+                &rider_model,
+            )
+            .total_time
+        };
+        let reference_time = total_time(&light_bike_model);
+        assert_eq!(total_time(&heavy_bike_model), reference_time);
+        assert!((total_time(&total_mass_model) - reference_time).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sinusoidal_wind_costs_more_time_than_its_mean() {
+        let road_segment = &make_course(CourseProfile::Flat { n_segments: 1, segment_length: 2000.0 })[0];
+        let resistance_model = default_resistance_model();
+        let mean_wind_speed = 3.0;
+        let gust = |position: f64| mean_wind_speed + 5.0 * f64::sin(2.0 * std::f64::consts::PI * position / 200.0);
+
+        let steady_segment = RoadSegment { relative_wind_speed: mean_wind_speed, ..*road_segment };
+        let (steady_time, _) = compute_time_and_final_velocity(8.0, 250.0, &steady_segment, &resistance_model);
+        let (gusty_time, _) =
+            compute_time_and_final_velocity_with_wind(8.0, 250.0, road_segment, &resistance_model, &|position, _| {
+                gust(position)
+            });
+        assert!(gusty_time > steady_time + 0.5, "{} s vs {} s", gusty_time, steady_time);
+
+        // The same wind sampled every 25 m, coarser than the integration steps, is interpolated between samples.
+        let wind_series = WindSeries {
+            sample_interval: 25.0,
+            wind_speed_vec: (0..=80).map(|k| gust(25.0 * k as f64)).collect(),
+        };
+        let (sampled_time, _) =
+            compute_time_and_final_velocity_with_wind(8.0, 250.0, road_segment, &resistance_model, &|position, _| {
+                wind_series.wind_speed_at(position)
+            });
+        assert!((sampled_time - gusty_time).abs() < 0.1 * (gusty_time - steady_time));
+    }
+
+    #[test]
+    fn steep_segments_take_more_integration_steps_than_a_flat_one() {
+        // A long flat lead-in brings the rider to cruising speed before the segment of interest.
+        let step_count = |slope: f64| {
+            let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 2, segment_length: 2000.0 });
+            road_segment_vec[1].length = 500.0;
+            road_segment_vec[1].slope = slope;
+            let rider_model = morton::default_rider_model();
+            simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![250.0, 250.0],
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+            )
+            .step_count_vec[1]
+        };
+        let flat_step_count = step_count(0.0);
+        for slope in [0.15, -0.15] {
+            let steep_step_count = step_count(slope);
+            assert!(steep_step_count > 2 * flat_step_count, "{} steps vs {} steps", steep_step_count, flat_step_count);
+        }
+    }
+
+    #[test]
+    fn extra_mass_costs_much_more_on_a_climb_than_on_the_flat() {
+        let flat_segment_vec = make_course(CourseProfile::Flat { n_segments: 20, segment_length: 250.0 });
+        let climb_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 20,
+            segment_length: 250.0,
+            slope: 0.07,
+        });
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+
+        let flat_cost = seconds_per_kilogram(5.0, &flat_segment_vec, &resistance_model, &rider_model);
+        let climb_cost = seconds_per_kilogram(5.0, &climb_segment_vec, &resistance_model, &rider_model);
+        assert!(flat_cost > 0.0);
+        assert!(climb_cost > 10.0 * flat_cost, "{} s/kg vs {} s/kg", climb_cost, flat_cost);
+    }
+
+    #[test]
+    fn fixed_gear_descent_is_capped_by_cadence() {
+        let road_segment_vec = make_course(CourseProfile::Descent {
+            n_segments: 6,
+            segment_length: 300.0,
+            slope: 0.07,
+        });
+        let freewheel_model = default_resistance_model();
+        let fixed_gear_model = BicycleResistanceModel {
+            can_coast: false,
+            ..default_resistance_model()
+        };
+        let rider_model = morton::default_rider_model();
+        let ride = |resistance_model: &BicycleResistanceModel| {
+            simulate(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![100.0; 6],
+                &road_segment_vec,
+                resistance_model,
+                &rider_model,
+            )
+        };
+
+        let freewheel_result = ride(&freewheel_model);
+        let fixed_gear_result = ride(&fixed_gear_model);
+        let max_velocity = fixed_gear_model.fixed_gear.max_velocity();
+        let top_speed = |result: &SimulationResult| result.velocity_vec.iter().copied().fold(0.0, f64::max);
+        assert!(top_speed(&freewheel_result) > max_velocity + 1.0);
+        assert!(top_speed(&fixed_gear_result) <= max_velocity + 1e-9);
+        assert!(fixed_gear_result.total_time > freewheel_result.total_time);
+        assert!(fixed_gear_result.braking_energy_vec.iter().sum::<f64>() > 0.0);
+    }
+
+    #[test]
+    fn reused_scratch_allocates_nothing_and_matches_fresh_runs() {
+        let long_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 30,
+            segment_length: 200.0,
+            max_slope: 0.06,
+            period: 10,
+        });
+        let short_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 10,
+            segment_length: 200.0,
+            slope: 0.04,
+        });
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let long_power_vec = vec![320.0; long_segment_vec.len()];
+        let short_power_vec = vec![280.0; short_segment_vec.len()];
+        let fresh_long = simulate(5.0, 20000.0, &long_power_vec, &long_segment_vec, &resistance_model, &rider_model);
+        let fresh_short =
+            simulate(5.0, 20000.0, &short_power_vec, &short_segment_vec, &resistance_model, &rider_model);
+        let run = |scratch: &mut SimulationScratch, input_power_vec: &Vec<f64>, road_segment_vec: &Vec<RoadSegment>| {
+            compute_all_times_with_scratch(
+                5.0,
+                20000.0,
+                input_power_vec,
+                road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                scratch,
+            )
+        };
+
+        // The first run on the longest course sizes the buffers.
+        let mut scratch = SimulationScratch::new();
+        run(&mut scratch, &long_power_vec, &long_segment_vec);
+        let (total_times, allocation_count) = count_allocations(|| {
+            let mut total_times = [0.0; 100];
+            for (k, total_time) in total_times.iter_mut().enumerate() {
+                *total_time = if k % 2 == 0 {
+                    run(&mut scratch, &short_power_vec, &short_segment_vec)
+                } else {
+                    run(&mut scratch, &long_power_vec, &long_segment_vec)
+                };
+            }
+            total_times
+        });
+        assert_eq!(allocation_count, 0);
+        for (k, &total_time) in total_times.iter().enumerate() {
+            let fresh = if k % 2 == 0 { &fresh_short } else { &fresh_long };
+            assert_eq!(total_time, fresh.total_time);
+        }
+        assert_eq!(scratch.velocity_vec, fresh_long.velocity_vec);
+        assert_eq!(scratch.anaerobic_reserve_vec, fresh_long.anaerobic_reserve_vec);
+        assert_eq!(scratch.power_vec, fresh_long.power_vec);
+
+        // A scratch sized for the long course is shrunk safely for the short one.
+        run(&mut scratch, &short_power_vec, &short_segment_vec);
+        assert_eq!(scratch.duration_vec, fresh_short.duration_vec);
+
+        let (_, fresh_allocation_count) = count_allocations(|| {
+            simulate(5.0, 20000.0, &short_power_vec, &short_segment_vec, &resistance_model, &rider_model)
+        });
+        assert!(fresh_allocation_count > 0);
+    }
+
+    #[test]
+    fn gravel_segment_is_slower_than_an_identical_paved_one() {
+        let paved_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 4,
+            segment_length: 500.0,
+            slope: 0.01,
+        });
+        let mut gravel_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 4,
+            segment_length: 500.0,
+            slope: 0.01,
+        });
+        gravel_segment_vec[2].rolling_resistance = Some(0.012);
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let ride = |road_segment_vec: &Vec<RoadSegment>| {
+            simulate(5.0, 20000.0, &vec![250.0; 4], road_segment_vec, &resistance_model, &rider_model)
+        };
+
+        let paved_result = ride(&paved_segment_vec);
+        let gravel_result = ride(&gravel_segment_vec);
+        assert!(gravel_result.duration_vec[2] > paved_result.duration_vec[2] + 1.0);
+        // Unset segments fall back to the model's rolling resistance.
+        assert_eq!(gravel_result.duration_vec[..2], paved_result.duration_vec[..2]);
+        let paved_model_segment = RoadSegment {
+            rolling_resistance: Some(resistance_model.rolling_resistance),
+            ..paved_segment_vec[0]
+        };
+        assert_eq!(
+            compute_time_and_final_velocity(5.0, 250.0, &paved_model_segment, &resistance_model),
+            compute_time_and_final_velocity(5.0, 250.0, &paved_segment_vec[0], &resistance_model)
+        );
+    }
+
+    #[test]
+    fn tighter_tolerance_gives_a_more_accurate_constant_power() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 10,
+            segment_length: 500.0,
+            max_slope: 0.05,
+            period: 5,
+        });
+        let resistance_model = default_resistance_model();
+        let ride_time = |input_power: f64| {
+            let mut time = 0.0;
+            let mut velocity = 5.0;
+            for road_segment in road_segment_vec.iter() {
+                let (duration, final_velocity) =
+                    compute_time_and_final_velocity(velocity, input_power, road_segment, &resistance_model);
+                time += duration;
+                velocity = final_velocity;
+            }
+            time
+        };
+        let target_time = ride_time(263.0);
+        let solve = |abs: f64| {
+            let tolerance = root_finding::Tolerance { abs, rel: 0.0, max_iter: 200 };
+            constant_power_for_time(5.0, &road_segment_vec, &resistance_model, target_time, &tolerance)
+        };
+
+        let loose_solution = solve(10.0);
+        let tight_solution = solve(1e-8);
+        assert!(loose_solution.converged && tight_solution.converged);
+        assert!(tight_solution.iterations > loose_solution.iterations);
+        let loose_error = (ride_time(loose_solution.value) - target_time).abs();
+        let tight_error = (ride_time(tight_solution.value) - target_time).abs();
+        assert!(tight_error < loose_error, "{} s vs {} s", tight_error, loose_error);
+        assert!((tight_solution.value - 263.0).abs() < 1e-6);
+
+        // A target faster than any power allows cannot be bracketed.
+        let unreachable_solution =
+            constant_power_for_time(5.0, &road_segment_vec, &resistance_model, 1.0, &root_finding::default_tolerance());
+        assert!(!unreachable_solution.converged);
+    }
+
+    #[test]
+    fn optimized_time_is_never_below_the_unconstrained_bound() {
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        for profile in [
+            CourseProfile::Flat { n_segments: 8, segment_length: 500.0 },
+            CourseProfile::ConstantClimb { n_segments: 8, segment_length: 500.0, slope: 0.06 },
+            CourseProfile::RollingSine { n_segments: 8, segment_length: 500.0, max_slope: 0.05, period: 4 },
+        ] {
+            let road_segment_vec = make_course(profile);
+            let bound = fastest_unconstrained_time(5.0, &road_segment_vec, &resistance_model, &rider_model);
+            let mut power_vec = Vec::new();
+            let optimized_time = pacing::optimize_dynamic_programming(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &road_segment_vec,
+                &resistance_model,
+                &rider_model,
+                &pacing::default_dynamic_pacer_settings(),
+                &mut power_vec,
+            );
+            assert!(optimized_time >= bound, "{} s < {} s", optimized_time, bound);
+        }
+    }
+
+    /// Marks the start and end of the simulation in `simulation_writes_nothing_between_markers`.
+    const STDOUT_START_MARKER: &str = "<<simulation start>>";
+    const STDOUT_END_MARKER: &str = "<<simulation end>>";
+
+    #[test]
+    #[ignore = "run in a child process by simulation_prints_nothing_to_stdout"]
+    fn simulation_writes_nothing_between_markers() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 20,
+            segment_length: 200.0,
+            max_slope: 0.08,
+            period: 10,
+        });
+        let rider_model = morton::default_rider_model();
+        let resistance_model = default_resistance_model();
+        let input_power_vec = vec![450.0; road_segment_vec.len()];
+        print!("{}", STDOUT_START_MARKER);
+        let result = simulate(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &input_power_vec,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+        let mut outputs = SimulationOutputs::new();
+        compute_all_times(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &input_power_vec,
+            &road_segment_vec,
+            &resistance_model,
+            &rider_model,
+            &mut outputs,
+        );
+        print!("{}", STDOUT_END_MARKER);
+        assert!(result.total_time > 0.0);
+    }
+
+    #[test]
+    fn simulation_prints_nothing_to_stdout() {
+        // The test harness captures the output of the tests, so the simulation runs in a child test process
+        // writing straight to its stdout.
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "sim::simulation::tests::simulation_writes_nothing_between_markers",
+                "--exact",
+                "--ignored",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let start = stdout.find(STDOUT_START_MARKER).unwrap() + STDOUT_START_MARKER.len();
+        let end = stdout.find(STDOUT_END_MARKER).unwrap();
+        assert_eq!(&stdout[start..end], "");
+    }
+
+    #[test]
+    fn racing_line_raises_corner_speeds_and_shortens_the_descent() {
+        let road_segment_vec = make_course(CourseProfile::Descent {
+            n_segments: 20,
+            segment_length: 100.0,
+            slope: 0.06,
+        });
+        let rider_model = morton::default_rider_model();
+        let (hairpin_index, bend_index) = (9, 14);
+        let ride = |road_width: Option<f64>| {
+            let options = SimulationOptions {
+                corner_vec: vec![
+                    Corner {
+                        segment_index: hairpin_index,
+                        radius: 10.0,
+                        turn_angle: 180.0,
+                    },
+                    Corner {
+                        segment_index: bend_index,
+                        radius: 30.0,
+                        turn_angle: 30.0,
+                    },
+                ],
+                road_width,
+                ..default_simulation_options()
+            };
+            simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![200.0; 20],
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                &options,
+            )
+        };
+
+        let tight = ride(None);
+        let racing = ride(Some(4.0));
+        assert!(racing.total_time < tight.total_time);
+        assert!(racing.velocity_vec[hairpin_index] > tight.velocity_vec[hairpin_index]);
+        assert!(racing.velocity_vec[bend_index] > tight.velocity_vec[bend_index]);
+        // The rider still brakes for the hairpin on the racing line, but no longer for the bend.
+        assert!(racing.velocity_vec[hairpin_index] < racing.velocity_vec[hairpin_index - 1]);
+        assert!(tight.velocity_vec[bend_index] < tight.velocity_vec[bend_index - 1]);
+        assert!(racing.velocity_vec[bend_index] > racing.velocity_vec[bend_index - 1]);
+        // A hairpin only gains the road width while a sweeping bend gains many times it.
+        assert!((kinematics::racing_line_radius(10.0, 180.0, 4.0) - 14.0).abs() < 1e-9);
+        assert!(kinematics::racing_line_radius(30.0, 30.0, 4.0) > 30.0 + 20.0 * 4.0);
+    }
+
+    #[test]
+    fn distance_and_energy_step_strategies_agree_on_total_time() {
+        let rider_model = morton::default_rider_model();
+        let ride = |road_segment_vec: &Vec<RoadSegment>, step_strategy: StepStrategy| {
+            let options = SimulationOptions {
+                step_strategy,
+                ..default_simulation_options()
+            };
+            let input_power_vec = vec![250.0; road_segment_vec.len()];
+            let result = simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &input_power_vec,
+                road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                &options,
+            );
+            result.total_time
+        };
+
+        let steady = make_course(CourseProfile::Flat { n_segments: 5, segment_length: 1000.0 });
+        let rolling = make_course(CourseProfile::RollingSine {
+            n_segments: 12,
+            segment_length: 250.0,
+            max_slope: 0.06,
+            period: 6,
+        });
+        for road_segment_vec in [steady, rolling] {
+            let energy_time = ride(&road_segment_vec, StepStrategy::Energy);
+            for step_length in [1.0, 10.0, 100.0, 1000.0] {
+                let distance_time = ride(&road_segment_vec, StepStrategy::Distance { step_length });
+                assert!(f64::abs(distance_time - energy_time) < 1e-4 * energy_time);
+            }
+        }
+    }
+
+    #[test]
+    fn step_length_that_is_not_positive_is_infeasible() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 3, segment_length: 100.0 });
+        let rider_model = morton::default_rider_model();
+        for step_length in [0.0, -10.0, f64::NAN, f64::INFINITY] {
+            let options = SimulationOptions {
+                step_strategy: StepStrategy::Distance { step_length },
+                ..default_simulation_options()
+            };
+            let result = simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![250.0; 3],
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                &options,
+            );
+            assert_eq!(
+                result.feasibility,
+                Feasibility::Infeasible { segment_index: 0, reason: InfeasibilityReason::InvalidStepLength },
+                "{}",
+                step_length
+            );
+        }
+    }
+
+    #[test]
+    fn full_cp_plan_equals_raw_cp_plan() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 8,
+            segment_length: 300.0,
+            max_slope: 0.05,
+            period: 4,
+        });
+        let rider_model = morton::default_rider_model();
+        let options = default_simulation_options();
+        let percent_cp = simulate_percent_cp(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![100.0; 8],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+            &options,
+        );
+        let raw = simulate_with_options(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![rider_model.critical_power; 8],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+            &options,
+        );
+        assert_eq!(percent_cp.total_time, raw.total_time);
+        assert_eq!(percent_cp.power_vec, raw.power_vec);
+
+        // Targets beyond the max power at a full reserve are clamped to it.
+        let power_ceiling = rider_model.max_power(rider_model.anaerobic_work_capacity);
+        let power_vec = powers_from_percent_cp(&vec![110.0, 1000.0], &rider_model);
+        assert!((power_vec[0] - 1.1 * rider_model.critical_power).abs() < 1e-9);
+        assert_eq!(power_vec[1], power_ceiling);
+    }
+
+    #[test]
+    fn aero_share_is_larger_below_the_critical_gradient() {
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let tolerance = root_finding::default_tolerance();
+        let gradient = critical_gradient(&resistance_model, &rider_model, 0.05, &tolerance).unwrap();
+        assert!(gradient > 0.0);
+        let share_at =
+            |slope: f64| aero_share_of_resistance(rider_model.critical_power, slope, &resistance_model, &tolerance);
+        assert!((share_at(gradient) - 0.05).abs() < 1e-3);
+        assert!(share_at(0.0) > share_at(0.5 * gradient));
+        assert!(share_at(0.5 * gradient) > share_at(0.9 * gradient));
+        assert!(share_at(0.9 * gradient) > 0.05);
+        assert!(share_at(1.1 * gradient) < 0.05);
+
+        // With a vanishing CdA, air resistance is negligible even on the flat.
+        let slippery = BicycleResistanceModel {
+            cda_surface: 1e-6,
+            ..default_resistance_model()
+        };
+        assert!(critical_gradient(&slippery, &rider_model, 0.05, &tolerance).is_none());
+    }
+
+    #[test]
+    fn net_force_vanishes_at_steady_velocity() {
+        let road_segment = RoadSegment {
+            length: 5000.0,
+            altitude: 0.0,
+            slope: 0.03,
+            temperature: 20.0,
+            relative_wind_speed: 3.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        };
+        let corrected = BicycleResistanceModel {
+            reynolds_correction: Some(ReynoldsCorrection {
+                characteristic_length: 1.7,
+                reference_reynolds_number: 1e6,
+                sensitivity: 0.2,
+            }),
+            ..default_resistance_model()
+        };
+        for resistance_model in [default_resistance_model(), corrected] {
+            let velocity = steady_velocity(250.0, &road_segment, &resistance_model, &root_finding::default_tolerance());
+            assert!(net_force(velocity, 250.0, &road_segment, &resistance_model).abs() < 1e-4);
+            assert!(net_force(0.9 * velocity, 250.0, &road_segment, &resistance_model) > 0.0);
+            assert!(net_force(1.1 * velocity, 250.0, &road_segment, &resistance_model) < 0.0);
+        }
+
+        // Standing still, the force is finite.
+        let standing_force = net_force(0.0, 250.0, &road_segment, &default_resistance_model());
+        assert!(standing_force.is_finite());
+        assert!(standing_force > 0.0);
+    }
+
+    #[test]
+    fn muddy_drivetrain_is_slower_at_the_same_power() {
+        let clean = RoadSegment {
+            length: 500.0,
+            altitude: 0.0,
+            slope: 0.02,
+            temperature: 20.0,
+            relative_wind_speed: 0.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        };
+        let resistance_model = default_resistance_model();
+        let time_on = |road_segment: &RoadSegment| {
+            compute_time_and_final_velocity(8.0, 250.0, road_segment, &resistance_model).0
+        };
+
+        let model_value = RoadSegment {
+            drivetrain_efficiency: Some(resistance_model.drivetrain_efficiency),
+            ..clean
+        };
+        let muddy = RoadSegment {
+            drivetrain_efficiency: Some(0.85),
+            ..clean
+        };
+        assert_eq!(time_on(&model_value), time_on(&clean));
+        assert!(time_on(&muddy) > time_on(&clean) + 1.0);
+    }
+
+    #[test]
+    fn acceleration_vanishes_at_steady_velocity_and_is_negative_above() {
+        let road_segment = RoadSegment {
+            length: 1000.0,
+            altitude: 0.0,
+            slope: 0.01,
+            temperature: 20.0,
+            relative_wind_speed: 2.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        };
+        let resistance_model = default_resistance_model();
+        let acceleration_at =
+            |velocity: f64| instantaneous_acceleration(velocity, 250.0, &road_segment, &resistance_model);
+        let velocity = steady_velocity(250.0, &road_segment, &resistance_model, &root_finding::default_tolerance());
+        assert!(acceleration_at(velocity).abs() < 1e-6);
+        assert!(acceleration_at(velocity - 1.0) > 0.0);
+        assert!(acceleration_at(velocity + 1.0) < 0.0);
+
+        // Near a standstill the torque limit keeps the acceleration finite.
+        assert!(acceleration_at(0.0).is_finite());
+        assert!((acceleration_at(0.0) - acceleration_at(1e-9)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn trajectory_ends_at_the_returned_time_and_velocity() {
+        let road_segment = RoadSegment {
+            length: 1234.0,
+            altitude: 0.0,
+            slope: 0.04,
+            temperature: 20.0,
+            relative_wind_speed: 0.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        };
+        let resistance_model = default_resistance_model();
+        let mut trajectory_vec = Vec::new();
+        let (time, final_velocity) = compute_time_and_final_velocity_with_trajectory(
+            6.0,
+            280.0,
+            &road_segment,
+            &resistance_model,
+            &mut trajectory_vec,
+        );
+        assert_eq!(
+            (time, final_velocity),
+            compute_time_and_final_velocity(6.0, 280.0, &road_segment, &resistance_model)
+        );
+        assert_eq!(trajectory_vec[0], (0.0, 6.0, 0.0));
+        let &(position, velocity, end_time) = trajectory_vec.last().unwrap();
+        assert!((position - road_segment.length).abs() < 1e-9);
+        assert_eq!(velocity, final_velocity);
+        assert_eq!(end_time, time);
+        assert!(trajectory_vec.windows(2).all(|pair| pair[1].0 > pair[0].0 && pair[1].2 > pair[0].2));
+    }
+
+    #[test]
+    fn constant_watts_per_kg_with_decreasing_mass_gives_decreasing_watts() {
+        let rider_mass_vec = vec![72.0, 71.8, 71.6, 71.4];
+        let power_vec = powers_from_watts_per_kg(&vec![4.0; 4], &rider_mass_vec).unwrap();
+        assert!(power_vec.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!((power_vec[0] - 288.0).abs() < 1e-9);
+        let watts_per_kg_vec = watts_per_kg_from_powers(&power_vec, &rider_mass_vec).unwrap();
+        assert!(watts_per_kg_vec.iter().all(|watts_per_kg| (watts_per_kg - 4.0).abs() < 1e-12));
+
+        // The plan is not truncated to the shorter of the two vectors.
+        assert!(powers_from_watts_per_kg(&vec![4.0; 4], &rider_mass_vec[..3].to_vec()).is_err());
+        assert!(watts_per_kg_from_powers(&power_vec[..3].to_vec(), &rider_mass_vec).is_err());
+
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 4, segment_length: 500.0 });
+        let rider_model = morton::default_rider_model();
+        let ride = |rider_mass_vec: &Vec<f64>| {
+            simulate_watts_per_kg(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![4.0; 4],
+                rider_mass_vec,
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                &default_simulation_options(),
+            )
+        };
+        assert_eq!(ride(&rider_mass_vec).unwrap().power_vec, power_vec);
+        assert!(ride(&rider_mass_vec[..2].to_vec()).is_err());
+    }
+
+    #[test]
+    fn high_fixed_power_fails_before_the_finish_of_a_hard_course() {
+        let road_segment_vec = make_course(CourseProfile::ConstantClimb {
+            n_segments: 20,
+            segment_length: 500.0,
+            slope: 0.06,
+        });
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+
+        let RideToFailure::Fails {
+            distance,
+            time,
+            segment_index,
+            reason,
+        } = ride_to_failure(0.0, 450.0, &road_segment_vec, &resistance_model, &rider_model)
+        else {
+            panic!("the rider should fail at 450 W");
+        };
+        assert_eq!(reason, InfeasibilityReason::AnaerobicReserveExhausted);
+        assert!(distance > 0.0 && distance < 10000.0);
+        assert_eq!(segment_index, (distance / 500.0) as usize);
+        // The rider fails once their max power, falling with the reserve, drops to 450 W.
+        let delta_p = 450.0 - rider_model.critical_power;
+        let failure_reserve =
+            rider_model.anaerobic_work_capacity * delta_p / (rider_model.max_power - rider_model.critical_power);
+        let expected_time = (rider_model.anaerobic_work_capacity - failure_reserve) / delta_p;
+        assert!((time - expected_time).abs() < 1e-6);
+
+        // At critical power the reserve never runs out.
+        assert!(matches!(
+            ride_to_failure(0.0, rider_model.critical_power, &road_segment_vec, &resistance_model, &rider_model),
+            RideToFailure::CompletesCourse { .. }
+        ));
+    }
+
+    #[test]
+    fn isopower_speed_decreases_as_the_gradient_increases() {
+        let slope_vec = vec![-0.04, 0.0, 0.02, 0.04, 0.08, 0.12, 0.5];
+        let wind_speed_vec = vec![-5.0, 0.0, 5.0];
+        let table = isopower_table(
+            250.0,
+            &slope_vec,
+            &wind_speed_vec,
+            &default_resistance_model(),
+            &root_finding::default_tolerance(),
+        );
+        assert_eq!(table.len(), slope_vec.len());
+        for j in 0..wind_speed_vec.len() {
+            let velocity_vec: Vec<f64> = table.iter().take(6).map(|row| row[j].unwrap()).collect();
+            assert!(velocity_vec.windows(2).all(|pair| pair[1] < pair[0]));
+        }
+        // A headwind slows the rider at every gradient.
+        for row in table.iter().take(6) {
+            assert!(row[0].unwrap() > row[1].unwrap() && row[1].unwrap() > row[2].unwrap());
+        }
+        // Nobody moves up a 50% wall at 250 W.
+        assert!(table[6].iter().all(|velocity| velocity.is_none()));
+    }
+
+    #[test]
+    fn neutralized_segment_takes_its_length_over_the_neutral_speed() {
+        let flat_course = make_course(CourseProfile::Flat { n_segments: 5, segment_length: 500.0 });
+        let road_segment_vec: Vec<RoadSegment> = flat_course
+            .iter()
+            .enumerate()
+            .map(|(i, road_segment)| RoadSegment {
+                slope: if i == 3 { -0.08 } else { 0.0 },
+                ..*road_segment
+            })
+            .collect();
+        let rider_model = morton::default_rider_model();
+        let options = SimulationOptions {
+            neutral_section_vec: vec![
+                NeutralSection {
+                    segment_index: 1,
+                    speed: 8.0,
+                },
+                NeutralSection {
+                    segment_index: 3,
+                    speed: 5.0,
+                },
+            ],
+            ..default_simulation_options()
+        };
+        let result = simulate_with_options(
+            5.0,
+            rider_model.anaerobic_work_capacity,
+            &vec![300.0; 5],
+            &road_segment_vec,
+            &default_resistance_model(),
+            &rider_model,
+            &options,
+        );
+        assert_eq!(result.duration_vec[1], 500.0 / 8.0);
+        assert_eq!(result.velocity_vec[1], 8.0);
+        assert!(result.power_vec[1] > 0.0 && result.power_vec[1] < 300.0);
+        // Behind the car down an 8% descent, the rider coasts and brakes.
+        assert_eq!(result.duration_vec[3], 500.0 / 5.0);
+        assert_eq!(result.power_vec[3], 0.0);
+        assert!(result.braking_energy_vec[3] > 0.0);
+        assert_eq!(result.power_vec[4], 300.0);
+    }
+
+    #[test]
+    fn neutralized_segment_uses_and_drains_the_motor_assist() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 3, segment_length: 500.0 });
+        let rider_model = morton::default_rider_model();
+        let motor_assist = MotorAssist { max_assist_power: 100.0, battery_capacity: 20.0 };
+        let e_bike = BicycleResistanceModel { motor_assist: Some(motor_assist), ..default_resistance_model() };
+        let simulate_neutralized = |speed: f64, resistance_model: &BicycleResistanceModel| {
+            let options = SimulationOptions {
+                neutral_section_vec: vec![NeutralSection { segment_index: 1, speed }],
+                ..default_simulation_options()
+            };
+            simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![250.0; 3],
+                &road_segment_vec,
+                resistance_model,
+                &rider_model,
+                &options,
+            )
+        };
+        let unassisted = simulate_neutralized(8.0, &default_resistance_model());
+        let assisted = simulate_neutralized(8.0, &e_bike);
+        assert!(assisted.power_vec[1] < unassisted.power_vec[1]);
+        let drained_energy = assisted.battery_energy_vec[0] - assisted.battery_energy_vec[1];
+        let delivered_energy = motor_assist.max_assist_power * assisted.duration_vec[1] / JOULES_PER_WATT_HOUR;
+        assert!((drained_energy - delivered_energy).abs() < 1e-9, "{} {}", drained_energy, delivered_energy);
+
+        for speed in [0.0, -2.0, f64::NAN] {
+            assert_eq!(
+                simulate_neutralized(speed, &e_bike).feasibility,
+                Feasibility::Infeasible { segment_index: 1, reason: InfeasibilityReason::InvalidNeutralSpeed }
+            );
+        }
+    }
+
+    #[test]
+    fn zeroing_rolling_resistance_saves_time() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 20,
+            segment_length: 500.0,
+            max_slope: 0.05,
+            period: 10,
+        });
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let time_of = |source: ResistanceSource| {
+            time_attributed_to(source, 5.0, &road_segment_vec, &resistance_model, &rider_model)
+        };
+        let rolling_time = time_of(ResistanceSource::Rolling);
+        let aero_time = time_of(ResistanceSource::Aero);
+        assert!(rolling_time > 0.0);
+        assert!(time_of(ResistanceSource::Drivetrain) > 0.0);
+        // At these speeds, air resistance costs more than rolling resistance.
+        assert!(aero_time > rolling_time);
+        assert!(aero_time.is_finite());
+    }
+
+    #[test]
+    fn forbidding_braking_eases_off_before_a_corner() {
+        let lead_in = make_course(CourseProfile::Flat { n_segments: 4, segment_length: 500.0 });
+        let ride = |slope: f64, radius: f64, forbid_braking: bool| {
+            let descent = make_course(CourseProfile::Descent {
+                n_segments: 4,
+                segment_length: 250.0,
+                slope,
+            });
+            let road_segment_vec: Vec<RoadSegment> = lead_in
+                .iter()
+                .chain(descent.iter())
+                .map(|road_segment| RoadSegment { ..*road_segment })
+                .collect();
+            let rider_model = morton::default_rider_model();
+            let options = SimulationOptions {
+                corner_vec: vec![Corner {
+                    segment_index: 6,
+                    radius,
+                    turn_angle: 90.0,
+                }],
+                forbid_braking,
+                ..default_simulation_options()
+            };
+            simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![300.0; 8],
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                &options,
+            )
+        };
+
+        let braking = ride(0.02, 15.0, false);
+        let easing = ride(0.02, 15.0, true);
+        assert!(braking.is_feasible() && easing.is_feasible());
+        let corner_speed = kinematics::corner_speed_limit(15.0, 0.8);
+        assert!(braking.velocity_vec[6] <= corner_speed + 1e-6);
+        assert!(easing.velocity_vec[6] <= corner_speed + 1e-6);
+        assert!(braking.braking_energy_vec.iter().sum::<f64>() > 0.0);
+        assert_eq!(easing.braking_energy_vec.iter().sum::<f64>(), 0.0);
+        assert!(braking.power_vec.iter().all(|&power| power == 300.0));
+        assert!(easing.power_vec[4..7].iter().any(|&power| power < 300.0));
+        assert!(easing.total_time > braking.total_time);
+
+        // A steep descent into a tight corner cannot be slowed enough by freewheeling.
+        let steep = ride(0.08, 8.0, true);
+        assert!(matches!(
+            steep.feasibility,
+            Feasibility::Infeasible {
+                reason: InfeasibilityReason::BrakingRequired,
+                ..
+            }
+        ));
+        assert!(ride(0.08, 8.0, false).is_feasible());
+    }
+
+    #[test]
+    fn frequent_position_changes_add_up_to_a_measurable_penalty() {
+        let road_segment_vec = make_course(CourseProfile::RollingSine {
+            n_segments: 12,
+            segment_length: 250.0,
+            max_slope: 0.06,
+            period: 2,
+        });
+        let rider_model = morton::default_rider_model();
+        let ride = |position_cda_vec: Vec<f64>, position_change_penalty: f64| {
+            let options = SimulationOptions {
+                position_cda_vec,
+                position_change_penalty,
+                ..default_simulation_options()
+            };
+            simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![300.0; 12],
+                &road_segment_vec,
+                &default_resistance_model(),
+                &rider_model,
+                &options,
+            )
+            .total_time
+        };
+        // Tucked on every descent and sitting up on every climb.
+        let alternating_cda_vec: Vec<f64> = (0..12).map(|i| if i % 2 == 0 { 0.35 } else { 0.25 }).collect();
+        let changing = ride(alternating_cda_vec.clone(), 2.0);
+        let free_changes = ride(alternating_cda_vec, 0.0);
+        assert!((changing - free_changes - 11.0 * 2.0).abs() < 1e-9);
+
+        let steady = ride(vec![0.3; 12], 2.0);
+        assert_eq!(steady, ride(vec![0.3; 12], 0.0));
+    }
+
+    #[test]
+    fn raising_critical_power_saves_time_per_watt() {
+        let climb = make_course(CourseProfile::ConstantClimb { n_segments: 10, segment_length: 500.0, slope: 0.08 });
+        let descent = make_course(CourseProfile::Descent { n_segments: 10, segment_length: 500.0, slope: 0.06 });
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+
+        let time_at_critical_power = |critical_power: f64| {
+            let stronger_rider_model = rider_model.with_critical_power(critical_power);
+            simulate(
+                0.0,
+                stronger_rider_model.anaerobic_work_capacity,
+                &vec![critical_power; climb.len()],
+                &climb,
+                &resistance_model,
+                &stronger_rider_model,
+            )
+            .total_time
+        };
+        let critical_power = rider_model.critical_power;
+        assert!(time_at_critical_power(critical_power + 10.0) < time_at_critical_power(critical_power));
+
+        let climb_gain = watts_to_seconds(0.0, &climb, &resistance_model, &rider_model);
+        assert!(climb_gain < 0.0);
+        // On a descent gravity sets the speed, so each watt saves much less time.
+        let descent_gain = watts_to_seconds(0.0, &descent, &resistance_model, &rider_model);
+        assert!(descent_gain < 0.0);
+        assert!(descent_gain.abs() < 0.1 * climb_gain.abs());
+
+        // Any power-duration model can be perturbed.
+        let omni_domain_model = omni_domain::default_omni_domain_model();
+        let omni_domain_gain = watts_to_seconds(0.0, &climb, &resistance_model, &omni_domain_model);
+        assert!(omni_domain_gain < 0.0);
+    }
+
+    #[test]
+    fn optimal_equipment_moves_towards_lower_mass_as_the_course_gets_hillier() {
+        // From the aero end, deep wheels and an aero frame, to the light end of the tradeoff curve.
+        let tradeoff_vec: Vec<(f64, f64)> = (0..=8).map(|k| (0.26 + 0.01 * k as f64, 10.0 - 0.5 * k as f64)).collect();
+        let resistance_model = default_resistance_model();
+        let rider_model = morton::default_rider_model();
+        let best_index_vec: Vec<usize> = [0.0, 0.02, 0.05, 0.1]
+            .iter()
+            .map(|&slope| {
+                let road_segment_vec = make_course(CourseProfile::ConstantClimb {
+                    n_segments: 10,
+                    segment_length: 500.0,
+                    slope,
+                });
+                optimal_cda_mass_tradeoff(&tradeoff_vec, 5.0, &road_segment_vec, &resistance_model, &rider_model)
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        assert!(best_index_vec.windows(2).all(|pair| pair[1] >= pair[0]));
+        // The flat course favors the lowest CdA and the steep climb the lowest mass.
+        assert_eq!(best_index_vec[0], 0);
+        assert_eq!(*best_index_vec.last().unwrap(), tradeoff_vec.len() - 1);
+
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 10, segment_length: 500.0 });
+        assert!(optimal_cda_mass_tradeoff(&vec![], 5.0, &road_segment_vec, &resistance_model, &rider_model).is_none());
+    }
+
+    #[test]
+    fn braking_heavy_descent_recovers_energy_and_recharges_the_battery() {
+        let road_segment_vec = make_course(CourseProfile::Descent {
+            n_segments: 20,
+            segment_length: 100.0,
+            slope: 0.08,
+        });
+        let rider_model = morton::default_rider_model();
+        let corner_vec = || -> Vec<Corner> {
+            (3..20).step_by(4).map(|segment_index| Corner { segment_index, radius: 10.0, turn_angle: 180.0 }).collect()
+        };
+        let ride = |resistance_model: &BicycleResistanceModel, regenerative_braking_efficiency: f64| {
+            let options = SimulationOptions {
+                corner_vec: corner_vec(),
+                regenerative_braking_efficiency,
+                ..default_simulation_options()
+            };
+            simulate_with_options(
+                5.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![150.0; 20],
+                &road_segment_vec,
+                resistance_model,
+                &rider_model,
+                &options,
+            )
+        };
+
+        // Without regeneration the braking energy is only dissipated.
+        let bicycle = default_resistance_model();
+        let dissipated = ride(&bicycle, 0.0);
+        assert!(dissipated.braking_energy_vec.iter().sum::<f64>() > 10000.0);
+        assert!(dissipated.recovered_energy_vec.iter().all(|&energy| energy == 0.0));
+
+        // The analysis reports the captured fraction of the braking energy on every segment.
+        let analyzed = ride(&bicycle, 0.6);
+        assert_eq!(analyzed.braking_energy_vec, dissipated.braking_energy_vec);
+        for (recovered, braking) in analyzed.recovered_energy_vec.iter().zip(&analyzed.braking_energy_vec) {
+            assert!((recovered - 0.6 * braking).abs() < 1e-9);
+        }
+        assert!(analyzed.battery_energy_vec.iter().all(|&energy| energy == 0.0));
+
+        // On an e-bike, the captured energy recharges the battery, up to its capacity.
+        let motor_assist = MotorAssist { max_assist_power: 250.0, battery_capacity: 20.0 };
+        let e_bike = BicycleResistanceModel { motor_assist: Some(motor_assist), ..default_resistance_model() };
+        let without_regeneration = ride(&e_bike, 0.0);
+        let with_regeneration = ride(&e_bike, 0.6);
+        let final_energy = |result: &SimulationResult| *result.battery_energy_vec.last().unwrap();
+        let battery_gain = final_energy(&with_regeneration) - final_energy(&without_regeneration);
+        let recovered_energy = with_regeneration.recovered_energy_vec.iter().sum::<f64>() / JOULES_PER_WATT_HOUR;
+        assert!(battery_gain > 0.0);
+        assert!(battery_gain <= recovered_energy + 1e-9);
+        assert!(with_regeneration.battery_energy_vec.iter().all(|&energy| energy <= motor_assist.battery_capacity));
+    }
+}
@@ -0,0 +1,672 @@
+// Team time trial
+// Several riders taking turns at the front, the others drafting behind the leader, a rider following a wheel, or
+// riding a tandem
+
+use crate::sim::power_duration::PowerDurationModel;
+use crate::sim::simulation;
+
+/// The CdA of a tandem relative to a single rider's, the stoker sitting mostly in the captain's wake.
+const TANDEM_CDA_FACTOR: f64 = 1.15;
+
+/// Longest distance in meters a following rider rides before the gap to the rider ahead, and so their draft, is
+/// updated.
+const DRAFT_STEP_LENGTH: f64 = 5.0;
+
+/// The rotation of the riders at the front of the group.
+///
+/// # Fields
+///
+/// * `leader_vec` - The index of the rider leading each segment. When that rider has been dropped, the next
+///   rider still in the group (in index order, wrapping around) leads instead.
+/// * `draft_cda_factor` - The fraction of their CdA the riders behind the leader are exposed to, e.g. 0.7.
+pub struct TeamRotation {
+    pub leader_vec: Vec<usize>,
+    pub draft_cda_factor: f64,
+}
+
+/// The outcome of a team simulation.
+///
+/// # Fields
+///
+/// * `total_time` - The total time of the group in seconds.
+/// * `duration_vec` - The time spent on each segment in seconds.
+/// * `velocity_vec` - The velocity at the end of each segment in meters per second.
+/// * `leader_vec` - The index of the rider who actually led each segment.
+/// * `anaerobic_reserve_vecs` - For each rider, the anaerobic reserve at the end of each segment in joules,
+///   zero from the segment where they were dropped.
+/// * `dropped_segment_vec` - For each rider, the segment on which they were dropped, if any.
+/// * `completed` - Whether the group reached the finish with at least one rider. When it did not, the entries
+///   of the vectors from the last segment onwards are zero and `total_time` only covers the segments before.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TeamResult {
+    pub total_time: f64,
+    pub duration_vec: Vec<f64>,
+    pub velocity_vec: Vec<f64>,
+    pub leader_vec: Vec<usize>,
+    pub anaerobic_reserve_vecs: Vec<Vec<f64>>,
+    pub dropped_segment_vec: Vec<Option<usize>>,
+    pub completed: bool,
+}
+
+/// Power in watts of a rider drafting behind a leader riding at `leader_power`, at the same speed.
+///
+/// The drafting rider does the same work at the wheel as the leader except for the part of the aero work they are
+/// sheltered from, and loses the same to the drivetrain for it as a solo rider.
+fn drafting_power(
+    leader_power: f64,
+    road_segment: &simulation::RoadSegment,
+    outcome: &simulation::SegmentOutcome,
+    resistance_model: &simulation::BicycleResistanceModel,
+    draft_cda_factor: f64,
+) -> f64 {
+    let leader_wheel_power = simulation::segment_wheel_power(leader_power, road_segment, resistance_model);
+    let saved_energy = (1.0 - draft_cda_factor) * outcome.aero_energy;
+    simulation::segment_input_power(leader_wheel_power - saved_energy / outcome.time, road_segment, resistance_model)
+}
+
+/// Simulates a group of riders sharing the work at the front, e.g. in a team time trial.
+///
+/// The group rides each segment at the planned power of the leader, with the full CdA. The other riders keep
+/// the same speed, sheltered by the leader, which needs less power. Each rider has their own anaerobic reserve,
+/// starting full. A rider who cannot hold their power until the end of a segment is dropped there and the group
+/// continues without them; if it was the leader, the next rider in the group takes over the segment. All
+/// riders share the same resistance model, without motor assist.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `input_power_vec` - The power of the leader on each segment in watts.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of each rider.
+/// * `rider_models` - The riders of the team.
+/// * `rotation` - Who leads each segment and how much drafting helps.
+///
+/// # Returns
+///
+/// * `Result<TeamResult, String>` - The times of the group and the reserve of each rider, or an error if
+///   `rotation.leader_vec` does not have one entry per segment.
+pub fn simulate_team<M: PowerDurationModel>(
+    initial_velocity: f64,
+    input_power_vec: &Vec<f64>,
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_models: &[M],
+    rotation: &TeamRotation,
+) -> Result<TeamResult, String> {
+    let n_segments = input_power_vec.len();
+    if rotation.leader_vec.len() != n_segments {
+        return Err(format!(
+            "expected a leader for each of the {} segments, got {}",
+            n_segments,
+            rotation.leader_vec.len()
+        ));
+    }
+    let n_riders = rider_models.len();
+    let mut velocity = initial_velocity;
+    let mut anaerobic_reserve_vec: Vec<f64> =
+        rider_models.iter().map(|rider| rider.anaerobic_work_capacity()).collect();
+    let mut is_in_group = vec![true; n_riders];
+
+    let mut duration_vec = vec![0.0; n_segments];
+    let mut velocity_vec = vec![0.0; n_segments];
+    let mut leader_vec = vec![0; n_segments];
+    let mut anaerobic_reserve_vecs = vec![vec![0.0; n_segments]; n_riders];
+    let mut dropped_segment_vec = vec![None; n_riders];
+    let mut completed = true;
+
+    let mut total_duration = 0.0;
+    for i in 0..n_segments {
+        // Every rider rides at the speed of the group, whoever leads.
+        let outcome = simulation::ride_segment(
+            velocity,
+            input_power_vec[i],
+            &road_segment_vec[i],
+            resistance_model,
+            0.0,
+            f64::INFINITY,
+        );
+        let drafting_power = drafting_power(
+            input_power_vec[i],
+            &road_segment_vec[i],
+            &outcome,
+            resistance_model,
+            rotation.draft_cda_factor,
+        );
+        let leader = loop {
+            let Some(leader) = (0..n_riders)
+                .map(|k| (rotation.leader_vec[i] + k) % n_riders)
+                .find(|&rider| is_in_group[rider])
+            else {
+                break None;
+            };
+            let power_of = |rider: usize| if rider == leader { input_power_vec[i] } else { drafting_power };
+            for rider in 0..n_riders {
+                if is_in_group[rider]
+                    && rider_models[rider].time_to_exhaustion(power_of(rider), anaerobic_reserve_vec[rider])
+                        < outcome.time
+                {
+                    is_in_group[rider] = false;
+                    dropped_segment_vec[rider] = Some(i);
+                }
+            }
+            if is_in_group[leader] {
+                break Some(leader);
+            }
+        };
+        let Some(leader) = leader else {
+            completed = false;
+            break;
+        };
+
+        for rider in 0..n_riders {
+            if is_in_group[rider] {
+                let power = if rider == leader { input_power_vec[i] } else { drafting_power };
+                anaerobic_reserve_vec[rider] =
+                    rider_models[rider].update_anaerobic_reserve(power, outcome.time, anaerobic_reserve_vec[rider]);
+            }
+        }
+        for rider in 0..n_riders {
+            if is_in_group[rider] {
+                anaerobic_reserve_vecs[rider][i] = anaerobic_reserve_vec[rider];
+            }
+        }
+        duration_vec[i] = outcome.time;
+        velocity_vec[i] = outcome.final_velocity;
+        leader_vec[i] = leader;
+        total_duration += outcome.time;
+        velocity = outcome.final_velocity;
+    }
+    Ok(TeamResult {
+        total_time: total_duration,
+        duration_vec,
+        velocity_vec,
+        leader_vec,
+        anaerobic_reserve_vecs,
+        dropped_segment_vec,
+        completed,
+    })
+}
+
+/// How the shelter behind a rider falls off with the gap to their rear wheel.
+///
+/// # Fields
+///
+/// * `min_cda_factor` - The fraction of their CdA a rider right on the wheel is exposed to, e.g. 0.6.
+/// * `draft_range` - The gap in meters beyond which the rider gets no draft, having lost the wheel.
+pub struct DraftModel {
+    pub min_cda_factor: f64,
+    pub draft_range: f64,
+}
+
+/// Returns a draft model where a rider right on the wheel is exposed to 60% of their CdA, and the draft is lost
+/// 5 meters behind.
+pub const fn default_draft_model() -> DraftModel {
+    DraftModel {
+        min_cda_factor: 0.6,
+        draft_range: 5.0,
+    }
+}
+
+impl DraftModel {
+    /// Returns the fraction of their CdA a rider `gap` meters behind the rear wheel of the rider ahead is exposed
+    /// to: `min_cda_factor` on the wheel, rising linearly to 1 at `draft_range`. Beyond it, or with a negative gap
+    /// when the rider has come past, there is no draft and the factor is 1.
+    pub fn draft_cda_factor(&self, gap: f64) -> f64 {
+        if gap < 0.0 || gap >= self.draft_range {
+            return 1.0;
+        }
+        self.min_cda_factor + (1.0 - self.min_cda_factor) * gap / self.draft_range
+    }
+}
+
+/// Returns the resistance model of a rider exposed to `draft_cda_factor` of their CdA, scaling the CdA speed
+/// curve or bands too.
+fn drafted_resistance_model(
+    resistance_model: &simulation::BicycleResistanceModel,
+    draft_cda_factor: f64,
+) -> simulation::BicycleResistanceModel {
+    simulation::BicycleResistanceModel {
+        cda_surface: draft_cda_factor * resistance_model.cda_surface,
+        cda_speed_curve: resistance_model
+            .cda_speed_curve
+            .as_ref()
+            .map(|curve| curve.iter().map(|&(velocity, cda)| (velocity, draft_cda_factor * cda)).collect()),
+        cda_speed_bands: resistance_model.cda_speed_bands.as_ref().map(|bands| bands.scaled(draft_cda_factor)),
+        ..resistance_model.clone()
+    }
+}
+
+/// The outcome of a follower simulation, sampled every `DRAFT_STEP_LENGTH` meters or less.
+///
+/// # Fields
+///
+/// * `total_time` - The time of the following rider in seconds, from when they cross the start line.
+/// * `distance_vec` - The distance from the start at the end of each step in meters.
+/// * `velocity_vec` - The velocity of the following rider at the end of each step in meters per second.
+/// * `gap_vec` - The gap to the rider ahead at the start of each step in meters, negative once the following
+///   rider has come past.
+/// * `draft_cda_factor_vec` - The fraction of their CdA the following rider was exposed to on each step.
+pub struct FollowerResult {
+    pub total_time: f64,
+    pub distance_vec: Vec<f64>,
+    pub velocity_vec: Vec<f64>,
+    pub gap_vec: Vec<f64>,
+    pub draft_cda_factor_vec: Vec<f64>,
+}
+
+/// Simulates a rider following another, with a draft depending on the gap between them.
+///
+/// Both riders follow their own power plan from the same start line, the follower `start_delay` seconds after
+/// the leader, who is not affected by the follower. The course is ridden in steps of at most `DRAFT_STEP_LENGTH`
+/// meters. At the start of each step, the gap is the time the leader passed that point before the follower times
+/// the follower's speed, and the follower rides the step exposed to the `DraftModel::draft_cda_factor` of that
+/// gap. A follower riding too easily drifts back and, once past `draft_range`, rides with their full CdA, so
+/// losing the wheel costs even more time. The riders' anaerobic reserves are not modeled.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity of both riders at the start line in meters per second.
+/// * `start_delay` - The time in seconds between the leader and the follower crossing the start line.
+/// * `input_power_vecs` - For the leader and the follower, the power on each segment in watts.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_models` - The resistance models of the leader and of the follower, with their full CdA.
+/// * `draft_model` - How the draft falls off with the gap.
+///
+/// # Returns
+///
+/// * `FollowerResult` - The time of the follower and the gap and draft along the course.
+///
+/// # Example
+///
+/// ```
+/// let result = simulate_follower(
+///     10.0,
+///     0.2,
+///     [&leader_power_vec, &follower_power_vec],
+///     &road_segment_vec,
+///     [&resistance_model, &resistance_model],
+///     &default_draft_model(),
+/// );
+/// println!("Follower finished in {:.0}s", result.total_time);
+/// ```
+pub fn simulate_follower(
+    initial_velocity: f64,
+    start_delay: f64,
+    input_power_vecs: [&Vec<f64>; 2],
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_models: [&simulation::BicycleResistanceModel; 2],
+    draft_model: &DraftModel,
+) -> FollowerResult {
+    let [leader_power_vec, follower_power_vec] = input_power_vecs;
+    let [leader_resistance_model, follower_resistance_model] = resistance_models;
+    let mut distance_vec = Vec::new();
+    let mut velocity_vec = Vec::new();
+    let mut gap_vec = Vec::new();
+    let mut draft_cda_factor_vec = Vec::new();
+
+    let mut distance = 0.0;
+    let mut leader_time = 0.0;
+    let mut leader_velocity = initial_velocity;
+    let mut follower_time = start_delay;
+    let mut follower_velocity = initial_velocity;
+    for (i, road_segment) in road_segment_vec.iter().enumerate() {
+        let n_steps = f64::ceil(road_segment.length / DRAFT_STEP_LENGTH).max(1.0) as usize;
+        let step_segment = simulation::RoadSegment {
+            length: road_segment.length / n_steps as f64,
+            ..*road_segment
+        };
+        for _ in 0..n_steps {
+            let gap = (follower_time - leader_time) * follower_velocity;
+            let draft_cda_factor = draft_model.draft_cda_factor(gap);
+            let (leader_duration, leader_final_velocity) = simulation::compute_time_and_final_velocity(
+                leader_velocity,
+                leader_power_vec[i],
+                &step_segment,
+                leader_resistance_model,
+            );
+            let (follower_duration, follower_final_velocity) = simulation::compute_time_and_final_velocity(
+                follower_velocity,
+                follower_power_vec[i],
+                &step_segment,
+                &drafted_resistance_model(follower_resistance_model, draft_cda_factor),
+            );
+            leader_time += leader_duration;
+            leader_velocity = leader_final_velocity;
+            follower_time += follower_duration;
+            follower_velocity = follower_final_velocity;
+            distance += step_segment.length;
+
+            distance_vec.push(distance);
+            velocity_vec.push(follower_velocity);
+            gap_vec.push(gap);
+            draft_cda_factor_vec.push(draft_cda_factor);
+        }
+    }
+    FollowerResult {
+        total_time: follower_time - start_delay,
+        distance_vec,
+        velocity_vec,
+        gap_vec,
+        draft_cda_factor_vec,
+    }
+}
+
+/// Returns the resistance model of a tandem from the one of its captain on a solo bicycle: the `cda_surface` is
+/// `TANDEM_CDA_FACTOR` times the captain's, and the mass is the one of both riders on a tandem bicycle.
+///
+/// # Arguments
+///
+/// * `captain_resistance_model` - The resistance model of the captain (front rider) on a solo bicycle.
+/// * `stoker_mass` - The mass of the stoker (rear rider) with their equipment in kilograms.
+/// * `tandem_bike_mass` - The mass of the tandem bicycle in kilograms.
+pub fn tandem_resistance_model(
+    captain_resistance_model: &simulation::BicycleResistanceModel,
+    stoker_mass: f64,
+    tandem_bike_mass: f64,
+) -> simulation::BicycleResistanceModel {
+    let mut resistance_model = captain_resistance_model.clone();
+    resistance_model.rider_mass += stoker_mass;
+    resistance_model.bike_mass = tandem_bike_mass;
+    resistance_model.cda_surface *= TANDEM_CDA_FACTOR;
+    resistance_model
+}
+
+/// The outcome of a tandem simulation.
+///
+/// # Fields
+///
+/// * `total_time` - The total time in seconds.
+/// * `duration_vec` - The time spent on each segment in seconds.
+/// * `velocity_vec` - The velocity at the end of each segment in meters per second.
+/// * `power_vecs` - For each rider, the power actually ridden on each segment in watts.
+/// * `anaerobic_reserve_vecs` - For each rider, the anaerobic reserve at the end of each segment in joules.
+pub struct TandemResult {
+    pub total_time: f64,
+    pub duration_vec: Vec<f64>,
+    pub velocity_vec: Vec<f64>,
+    pub power_vecs: [Vec<f64>; 2],
+    pub anaerobic_reserve_vecs: [Vec<f64>; 2],
+}
+
+/// Simulates a tandem: two riders pedaling the same bicycle, so their powers add up against a single frontal
+/// area and the combined mass, see `tandem_resistance_model`.
+///
+/// Each rider follows their own power plan and has their own anaerobic reserve, starting full. When a rider
+/// cannot hold their power until the end of a segment, they drop to their critical power for that segment and
+/// all following ones, as in `simulation::simulate`, so the combined power drops while the other rider carries
+/// on with their plan.
+///
+/// # Arguments
+///
+/// * `initial_velocity` - The velocity at the start of the course in meters per second.
+/// * `input_power_vecs` - For each rider, the power on each segment in watts.
+/// * `road_segment_vec` - The segments of the course.
+/// * `resistance_model` - The resistance model of the tandem with both riders.
+/// * `rider_models` - The captain and the stoker.
+///
+/// # Returns
+///
+/// * `TandemResult` - The times of the tandem and the power and reserve of each rider.
+pub fn simulate_tandem<M: PowerDurationModel>(
+    initial_velocity: f64,
+    input_power_vecs: [&Vec<f64>; 2],
+    road_segment_vec: &Vec<simulation::RoadSegment>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    rider_models: &[M; 2],
+) -> TandemResult {
+    let n_segments = road_segment_vec.len();
+    let mut velocity = initial_velocity;
+    let mut battery_energy = simulation::full_battery_energy(resistance_model);
+    let mut anaerobic_reserves = [rider_models[0].anaerobic_work_capacity(), rider_models[1].anaerobic_work_capacity()];
+    let mut is_exhausted = [false; 2];
+    let mut power_vecs = [vec![0.0; n_segments], vec![0.0; n_segments]];
+    let mut anaerobic_reserve_vecs = [vec![0.0; n_segments], vec![0.0; n_segments]];
+    let mut duration_vec = vec![0.0; n_segments];
+    let mut velocity_vec = vec![0.0; n_segments];
+
+    let mut total_duration = 0.0;
+    for i in 0..n_segments {
+        let power_of = |rider: usize, is_exhausted: &[bool; 2]| {
+            let planned_power = input_power_vecs[rider][i];
+            if is_exhausted[rider] {
+                return f64::min(planned_power, rider_models[rider].critical_power());
+            }
+            planned_power
+        };
+        let ride = |is_exhausted: &[bool; 2]| {
+            simulation::ride_segment(
+                velocity,
+                power_of(0, is_exhausted) + power_of(1, is_exhausted),
+                &road_segment_vec[i],
+                resistance_model,
+                battery_energy,
+                f64::INFINITY,
+            )
+        };
+        let mut outcome = ride(&is_exhausted);
+        // A rider giving up slows the tandem, which can make the other one give up in turn.
+        loop {
+            let newly_exhausted = (0..2).find(|&rider| {
+                !is_exhausted[rider]
+                    && rider_models[rider].time_to_exhaustion(power_of(rider, &is_exhausted), anaerobic_reserves[rider])
+                        < outcome.time
+            });
+            let Some(rider) = newly_exhausted else {
+                break;
+            };
+            is_exhausted[rider] = true;
+            outcome = ride(&is_exhausted);
+        }
+
+        for rider in 0..2 {
+            let power = power_of(rider, &is_exhausted);
+            anaerobic_reserves[rider] =
+                rider_models[rider].update_anaerobic_reserve(power, outcome.time, anaerobic_reserves[rider]);
+            power_vecs[rider][i] = power;
+            anaerobic_reserve_vecs[rider][i] = anaerobic_reserves[rider];
+        }
+        duration_vec[i] = outcome.time;
+        velocity_vec[i] = outcome.final_velocity;
+        total_duration += outcome.time;
+        velocity = outcome.final_velocity;
+        battery_energy = outcome.battery_energy;
+    }
+    TandemResult {
+        total_time: total_duration,
+        duration_vec,
+        velocity_vec,
+        power_vecs,
+        anaerobic_reserve_vecs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton::{self, RiderModel};
+    use crate::testutil::{make_course, CourseProfile};
+
+    /// Highest constant leader power, on a 10 W grid, at which the whole team finishes together.
+    fn sustainable_power(rider_models: &[RiderModel], road_segment_vec: &Vec<simulation::RoadSegment>) -> f64 {
+        let rotation = TeamRotation {
+            leader_vec: (0..road_segment_vec.len()).map(|i| i % rider_models.len()).collect(),
+            draft_cda_factor: 0.7,
+        };
+        let resistance_model = simulation::default_resistance_model();
+        (15..=80)
+            .map(|k| 10.0 * k as f64)
+            .take_while(|&power| {
+                let result = simulate_team(
+                    10.0,
+                    &vec![power; road_segment_vec.len()],
+                    road_segment_vec,
+                    &resistance_model,
+                    rider_models,
+                    &rotation,
+                )
+                .unwrap();
+                result.completed && result.dropped_segment_vec.iter().all(Option::is_none)
+            })
+            .last()
+            .unwrap()
+    }
+
+    #[test]
+    fn slowest_rider_limits_the_sustainable_speed() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 20, segment_length: 500.0 });
+        let even_team: Vec<RiderModel> = (0..4).map(|_| morton::default_rider_model()).collect();
+        let mut uneven_team: Vec<RiderModel> = (0..3).map(|_| morton::default_rider_model()).collect();
+        uneven_team.push(RiderModel {
+            critical_power: 220.0,
+            anaerobic_work_capacity: 15000.0,
+            ..morton::default_rider_model()
+        });
+
+        let even_power = sustainable_power(&even_team, &road_segment_vec);
+        let uneven_power = sustainable_power(&uneven_team, &road_segment_vec);
+        assert!(uneven_power < even_power, "{} W vs {} W", uneven_power, even_power);
+
+        // Just above the pace of the uneven team, only the weak rider is dropped and the others finish.
+        let rotation = TeamRotation {
+            leader_vec: (0..road_segment_vec.len()).map(|i| i % 4).collect(),
+            draft_cda_factor: 0.7,
+        };
+        let result = simulate_team(
+            10.0,
+            &vec![uneven_power + 10.0; road_segment_vec.len()],
+            &road_segment_vec,
+            &simulation::default_resistance_model(),
+            &uneven_team,
+            &rotation,
+        )
+        .unwrap();
+        assert!(result.completed);
+        assert!(result.dropped_segment_vec[..3].iter().all(Option::is_none));
+        assert!(result.dropped_segment_vec[3].is_some());
+    }
+
+    #[test]
+    fn drafting_rider_saves_the_sheltered_aero_work_through_the_same_drivetrain() {
+        let mut road_segment = make_course(CourseProfile::Flat { n_segments: 1, segment_length: 1000.0 }).remove(0);
+        road_segment.drivetrain_efficiency = Some(0.9);
+        let resistance_model = simulation::BicycleResistanceModel {
+            drivetrain_fixed_loss: 5.0,
+            ..simulation::default_resistance_model()
+        };
+        let outcome = simulation::ride_segment(10.0, 300.0, &road_segment, &resistance_model, 0.0, f64::INFINITY);
+        let power = drafting_power(300.0, &road_segment, &outcome, &resistance_model, 0.7);
+
+        let saved_power = 0.3 * outcome.aero_energy / outcome.time;
+        let leader_wheel_power = simulation::segment_wheel_power(300.0, &road_segment, &resistance_model);
+        let follower_wheel_power = simulation::segment_wheel_power(power, &road_segment, &resistance_model);
+        assert!((follower_wheel_power - (leader_wheel_power - saved_power)).abs() < 1e-9);
+
+        // Soft-pedaling at speed, the leader puts less through to the wheel than the follower is sheltered from.
+        road_segment.length = 100.0;
+        let easy_outcome = simulation::ride_segment(12.0, 20.0, &road_segment, &resistance_model, 0.0, f64::INFINITY);
+        assert_eq!(drafting_power(20.0, &road_segment, &easy_outcome, &resistance_model, 0.7), 0.0);
+    }
+
+    #[test]
+    fn rotation_without_a_leader_for_each_segment_is_rejected() {
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 4, segment_length: 500.0 });
+        let team: Vec<RiderModel> = (0..2).map(|_| morton::default_rider_model()).collect();
+        for leader_vec in [vec![0, 1, 0], vec![0, 1, 0, 1, 0]] {
+            let rotation = TeamRotation { leader_vec, draft_cda_factor: 0.7 };
+            let result = simulate_team(
+                10.0,
+                &vec![300.0; 4],
+                &road_segment_vec,
+                &simulation::default_resistance_model(),
+                &team,
+                &rotation,
+            );
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn tandem_is_faster_than_either_rider_solo() {
+        let road_segment_vec = make_course(CourseProfile::Flat {
+            n_segments: 40,
+            segment_length: 500.0,
+        });
+        let solo_resistance_model = simulation::default_resistance_model();
+        let rider_models = [morton::default_rider_model(), morton::rider_preset(morton::RiderPreset::Amateur)];
+        let solo_time = |rider_model: &RiderModel| {
+            simulation::simulate(
+                0.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![rider_model.critical_power; 40],
+                &road_segment_vec,
+                &solo_resistance_model,
+                rider_model,
+            )
+            .total_time
+        };
+        let tandem_resistance_model =
+            tandem_resistance_model(&solo_resistance_model, solo_resistance_model.rider_mass, 16.0);
+        let captain_power_vec = vec![rider_models[0].critical_power; 40];
+        let stoker_power_vec = vec![rider_models[1].critical_power; 40];
+        let tandem = simulate_tandem(
+            0.0,
+            [&captain_power_vec, &stoker_power_vec],
+            &road_segment_vec,
+            &tandem_resistance_model,
+            &rider_models,
+        );
+        assert!(tandem.total_time < solo_time(&rider_models[0]));
+        assert!(tandem.total_time < solo_time(&rider_models[1]));
+
+        // A stoker riding above their critical power fatigues first, and the combined power drops.
+        let hard_stoker_power_vec = vec![rider_models[0].critical_power; 40];
+        let tandem = simulate_tandem(
+            0.0,
+            [&captain_power_vec, &hard_stoker_power_vec],
+            &road_segment_vec,
+            &tandem_resistance_model,
+            &rider_models,
+        );
+        assert_eq!(tandem.power_vecs[1][0], rider_models[0].critical_power);
+        assert_eq!(*tandem.power_vecs[1].last().unwrap(), rider_models[1].critical_power);
+        assert!(tandem.power_vecs[0].iter().all(|&power| power == rider_models[0].critical_power));
+        let captain_capacity = rider_models[0].anaerobic_work_capacity;
+        assert!(tandem.anaerobic_reserve_vecs[0].iter().all(|&reserve| reserve == captain_capacity));
+    }
+
+    #[test]
+    fn follower_drifting_past_the_draft_range_reverts_to_full_cda() {
+        let draft_model = default_draft_model();
+        assert_eq!(draft_model.draft_cda_factor(0.0), draft_model.min_cda_factor);
+        assert_eq!(draft_model.draft_cda_factor(draft_model.draft_range), 1.0);
+        assert_eq!(draft_model.draft_cda_factor(-1.0), 1.0);
+
+        let road_segment_vec = make_course(CourseProfile::Flat { n_segments: 10, segment_length: 200.0 });
+        let resistance_model = simulation::default_resistance_model();
+        let result = simulate_follower(
+            10.0,
+            0.2,
+            [&vec![300.0; 10], &vec![150.0; 10]],
+            &road_segment_vec,
+            [&resistance_model, &resistance_model],
+            &draft_model,
+        );
+
+        // The follower starts on the wheel, then drifts back for good.
+        assert!(result.draft_cda_factor_vec[0] < 1.0);
+        let lost_index = result.gap_vec.iter().position(|&gap| gap >= draft_model.draft_range).unwrap();
+        assert!(lost_index > 0);
+        assert!(result.gap_vec[lost_index..].windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(result.draft_cda_factor_vec[lost_index..].iter().all(|&factor| factor == 1.0));
+
+        // Without the draft the follower settles at their solo speed.
+        let solo_velocity = simulation::compute_time_and_final_velocity(
+            *result.velocity_vec.last().unwrap(),
+            150.0,
+            &simulation::RoadSegment { length: 2000.0, ..road_segment_vec[0] },
+            &resistance_model,
+        )
+        .1;
+        assert!((result.velocity_vec.last().unwrap() - solo_velocity).abs() < 0.05);
+    }
+}
@@ -0,0 +1,131 @@
+// Thermoregulation
+// Loss of sustainable power as heat strain builds up over a long effort in hot conditions
+
+use crate::sim::simulation;
+
+/// The thermoregulation of a rider in the heat.
+///
+/// Above `neutral_temperature`, heat strain builds up: it relaxes exponentially towards the excess of the ambient
+/// temperature over the neutral one, so it integrates the heat load of the previous segments, and decays back
+/// when the road gets cooler. The rider then delivers only a fraction of their power, see `power_factor`, which
+/// lowers their effective critical power alike. In cool conditions there is no strain and no loss.
+///
+/// # Fields
+///
+/// * `neutral_temperature` - The ambient temperature in °C up to which there is no heat strain.
+/// * `time_constant` - The time in seconds over which the heat strain closes the gap to the ambient excess by a
+///   factor e.
+/// * `derate_per_degree` - The fraction of power lost per °C of heat strain.
+/// * `max_derate` - The largest fraction of power lost, however hot.
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalModel {
+    pub neutral_temperature: f64,
+    pub time_constant: f64,
+    pub derate_per_degree: f64,
+    pub max_derate: f64,
+}
+
+/// Returns a thermal model losing 1% of power per °C above 20°C once the heat strain has built up over about
+/// 20 minutes, up to 25%.
+pub const fn default_thermal_model() -> ThermalModel {
+    ThermalModel {
+        neutral_temperature: 20.0,
+        time_constant: 1200.0,
+        derate_per_degree: 0.01,
+        max_derate: 0.25,
+    }
+}
+
+impl ThermalModel {
+    /// Returns the heat strain in °C after riding `duration` seconds at the ambient `temperature` in °C,
+    /// starting from `heat_strain`.
+    pub fn update_heat_strain(&self, heat_strain: f64, temperature: f64, duration: f64) -> f64 {
+        let target_strain = f64::max(0.0, temperature - self.neutral_temperature);
+        target_strain + (heat_strain - target_strain) * f64::exp(-duration / self.time_constant)
+    }
+
+    /// Returns the fraction of their thermoneutral power the rider delivers under the given heat strain in °C.
+    pub fn power_factor(&self, heat_strain: f64) -> f64 {
+        1.0 - f64::min(self.max_derate, self.derate_per_degree * heat_strain)
+    }
+
+    /// Returns the heat strain at the start of each segment of a ride, starting without strain, from the time
+    /// spent on each segment, e.g. `SimulationResult::duration_vec`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let thermal_model = default_thermal_model();
+    /// let heat_strain_vec = thermal_model.heat_strain_vec(&road_segment_vec, &result.duration_vec);
+    /// let final_critical_power = rider_model.critical_power * thermal_model.power_factor(heat_strain_vec[n - 1]);
+    /// ```
+    pub fn heat_strain_vec(
+        &self,
+        road_segment_vec: &Vec<simulation::RoadSegment>,
+        duration_vec: &Vec<f64>,
+    ) -> Vec<f64> {
+        let mut heat_strain = 0.0;
+        let mut heat_strain_vec = Vec::with_capacity(duration_vec.len());
+        for (road_segment, &duration) in road_segment_vec.iter().zip(duration_vec.iter()) {
+            heat_strain_vec.push(heat_strain);
+            heat_strain = self.update_heat_strain(heat_strain, road_segment.temperature, duration);
+        }
+        heat_strain_vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+    use crate::sim::simulation::{RoadSegment, SimulationOptions};
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn long_effort_in_the_heat_lowers_effective_cp_and_slows_the_rider() {
+        let rider_model = morton::default_rider_model();
+        let thermal_model = default_thermal_model();
+        let course_at = |temperature: f64| -> Vec<RoadSegment> {
+            make_course(CourseProfile::Flat {
+                n_segments: 40,
+                segment_length: 1000.0,
+            })
+            .iter()
+            .map(|road_segment| RoadSegment {
+                temperature,
+                ..*road_segment
+            })
+            .collect()
+        };
+        let ride = |road_segment_vec: &Vec<RoadSegment>, thermal_model: Option<ThermalModel>| {
+            let options = SimulationOptions {
+                thermal_model,
+                ..simulation::default_simulation_options()
+            };
+            simulation::simulate_with_options(
+                8.0,
+                rider_model.anaerobic_work_capacity,
+                &vec![rider_model.critical_power; 40],
+                road_segment_vec,
+                &simulation::default_resistance_model(),
+                &rider_model,
+                &options,
+            )
+        };
+        let effective_cp = |road_segment_vec: &Vec<RoadSegment>, duration_vec: &Vec<f64>| {
+            let heat_strain_vec = thermal_model.heat_strain_vec(road_segment_vec, duration_vec);
+            rider_model.critical_power * thermal_model.power_factor(*heat_strain_vec.last().unwrap())
+        };
+
+        let hot_course = course_at(35.0);
+        let cool_course = course_at(15.0);
+        let hot = ride(&hot_course, Some(thermal_model));
+        let cool = ride(&cool_course, Some(thermal_model));
+        assert!(hot.total_time > cool.total_time);
+        assert!(effective_cp(&hot_course, &hot.duration_vec) < rider_model.critical_power);
+        assert_eq!(effective_cp(&cool_course, &cool.duration_vec), rider_model.critical_power);
+
+        // In cool conditions the thermal model changes nothing.
+        assert_eq!(cool.total_time, ride(&cool_course, None).total_time);
+    }
+}
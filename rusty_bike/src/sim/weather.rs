@@ -0,0 +1,102 @@
+// Weather forecast
+// Maps a forecast of temperature and wind onto the segments of a course
+
+use crate::sim::simulation;
+
+/// A forecast of the weather from a given time.
+///
+/// # Fields
+///
+/// * `time` - The time from which the forecast applies, in seconds on the same clock as the pass times.
+/// * `temperature` - The temperature in degrees Celsius.
+/// * `wind_speed` - The wind speed in meters per second.
+/// * `wind_direction` - The direction the wind blows from in degrees, clockwise from north, e.g. 270 for a
+///   westerly wind.
+pub struct Weather {
+    pub time: f64,
+    pub temperature: f64,
+    pub wind_speed: f64,
+    pub wind_direction: f64,
+}
+
+/// Returns the headwind component in meters per second of a wind blowing from `wind_direction` for a rider
+/// heading towards `heading`, both in degrees clockwise from north. It is negative with a tailwind.
+pub fn headwind_component(wind_speed: f64, wind_direction: f64, heading: f64) -> f64 {
+    wind_speed * f64::cos(f64::to_radians(wind_direction - heading))
+}
+
+/// Returns the forecast in effect at `time`: the last one from before `time`, or the first one if `time` is
+/// before all of them.
+fn forecast_at(forecast_vec: &[Weather], time: f64) -> &Weather {
+    let index = forecast_vec.partition_point(|weather| weather.time <= time);
+    &forecast_vec[index.saturating_sub(1)]
+}
+
+/// Sets the temperature and the relative wind of every segment from a weather forecast.
+///
+/// Each segment gets the forecast in effect when the rider enters it. Segments passed before the first forecast
+/// use the first one and segments passed after the last forecast use the last one. The pass times can be
+/// estimated from a previous simulation of the course, e.g. the start time plus the cumulated
+/// `SimulationResult::duration_vec`. The course holds no positions, so the forecast is assumed uniform over it.
+///
+/// # Arguments
+///
+/// * `road_segment_vec` - The segments of the course, updated in place.
+/// * `heading_vec` - The heading of each segment in degrees clockwise from north.
+/// * `pass_time_vec` - The time at which the rider enters each segment, on the clock of the forecast.
+/// * `forecast_vec` - The forecasts, in increasing order of time. When empty, the segments are left unchanged.
+///
+/// # Example
+///
+/// ```
+/// let forecast_vec = vec![
+///     Weather { time: 0.0, temperature: 12.0, wind_speed: 5.0, wind_direction: 270.0 },
+///     Weather { time: 3600.0, temperature: 15.0, wind_speed: 3.0, wind_direction: 250.0 },
+/// ];
+/// apply_weather(&mut road_segment_vec, &heading_vec, &pass_time_vec, &forecast_vec);
+/// ```
+pub fn apply_weather(
+    road_segment_vec: &mut [simulation::RoadSegment],
+    heading_vec: &[f64],
+    pass_time_vec: &[f64],
+    forecast_vec: &[Weather],
+) {
+    if forecast_vec.is_empty() {
+        return;
+    }
+    for (i, road_segment) in road_segment_vec.iter_mut().enumerate() {
+        let weather = forecast_at(forecast_vec, pass_time_vec[i]);
+        road_segment.temperature = weather.temperature;
+        road_segment.relative_wind_speed =
+            headwind_component(weather.wind_speed, weather.wind_direction, heading_vec[i]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{make_course, CourseProfile};
+
+    #[test]
+    fn headwind_then_tailwind_forecast_sets_the_relative_wind_of_each_half() {
+        let mut road_segment_vec = make_course(CourseProfile::Flat { n_segments: 6, segment_length: 1000.0 });
+        // Heading east all along, entering a segment every 100 s from 50 s before the first forecast.
+        let heading_vec = vec![90.0; 6];
+        let pass_time_vec: Vec<f64> = (0..6).map(|i| 100.0 * i as f64 - 50.0).collect();
+        let forecast_vec = vec![
+            Weather { time: 0.0, temperature: 12.0, wind_speed: 5.0, wind_direction: 90.0 },
+            Weather { time: 250.0, temperature: 16.0, wind_speed: 4.0, wind_direction: 270.0 },
+        ];
+
+        apply_weather(&mut road_segment_vec, &heading_vec, &pass_time_vec, &forecast_vec);
+
+        for road_segment in road_segment_vec[..3].iter() {
+            assert!((road_segment.relative_wind_speed - 5.0).abs() < 1e-9);
+            assert_eq!(road_segment.temperature, 12.0);
+        }
+        for road_segment in road_segment_vec[3..].iter() {
+            assert!((road_segment.relative_wind_speed + 4.0).abs() < 1e-9);
+            assert_eq!(road_segment.temperature, 16.0);
+        }
+    }
+}
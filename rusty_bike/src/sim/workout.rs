@@ -0,0 +1,177 @@
+// Workout
+// Interval workouts ridden on a flat virtual road, e.g. on a smart trainer in ERG mode
+
+use crate::sim::root_finding;
+use crate::sim::simulation;
+use crate::sim::simulation::RoadSegment;
+
+/// The longest duration in seconds of a segment of a virtual course, so that the anaerobic reserve is tracked
+/// within each interval and not only at its end.
+const WORKOUT_SEGMENT_DURATION: f64 = 10.0;
+
+/// A block of a workout held at constant power.
+///
+/// # Fields
+///
+/// * `duration` - The duration in seconds.
+/// * `power` - The power in watts, see `simulation::powers_from_percent_cp` for targets in percent of CP.
+#[derive(Clone, Copy, Debug)]
+pub struct Interval {
+    pub duration: f64,
+    pub power: f64,
+}
+
+/// Returns `repetitions` efforts at `work`, each followed by `recovery`, e.g. 5×3 min at 110% CP with 3 min
+/// recovery.
+///
+/// # Example
+///
+/// ```
+/// let work = Interval { duration: 180.0, power: 330.0 };
+/// let recovery = Interval { duration: 180.0, power: 150.0 };
+/// let interval_vec = repeat_intervals(work, recovery, 5);
+/// ```
+pub fn repeat_intervals(work: Interval, recovery: Interval, repetitions: usize) -> Vec<Interval> {
+    let mut interval_vec = Vec::with_capacity(2 * repetitions);
+    for _ in 0..repetitions {
+        interval_vec.push(work);
+        interval_vec.push(recovery);
+    }
+    interval_vec
+}
+
+/// A flat virtual road on which a workout is ridden, to be passed to `simulation::simulate`.
+///
+/// # Fields
+///
+/// * `road_segment_vec` - The segments of the road, at most `WORKOUT_SEGMENT_DURATION` seconds long each.
+/// * `power_vec` - The power of each segment in watts.
+/// * `initial_velocity` - The steady velocity at the power of the first interval in meters per second, so the
+///   rider does not spend the first interval accelerating from a standstill.
+pub struct VirtualCourse {
+    pub road_segment_vec: Vec<RoadSegment>,
+    pub power_vec: Vec<f64>,
+    pub initial_velocity: f64,
+}
+
+/// Synthesizes a flat virtual road from a workout, so the anaerobic reserve of the rider can be simulated
+/// without terrain.
+///
+/// Each interval becomes a stretch of road as long as the rider covers at the steady velocity of its power
+/// in its duration. The simulated durations match the workout up to the few seconds spent accelerating or
+/// slowing down at each change of power. Intervals without a positive duration are skipped.
+///
+/// # Arguments
+///
+/// * `interval_vec` - The intervals of the workout, in order.
+/// * `resistance_model` - The resistance model of the bicycle, or of the trainer emulating it.
+/// * `tolerance` - The stopping criterion of the bisection on the steady velocity of each interval.
+///
+/// # Returns
+///
+/// * `VirtualCourse` - The virtual road with the power of each of its segments.
+///
+/// # Example
+///
+/// ```
+/// let course =
+///     workout_to_course(&interval_vec, &simulation::default_resistance_model(), &root_finding::default_tolerance());
+/// let result = simulation::simulate(
+///     course.initial_velocity,
+///     rider_model.anaerobic_work_capacity,
+///     &course.power_vec,
+///     &course.road_segment_vec,
+///     &resistance_model,
+///     &rider_model,
+/// );
+/// ```
+pub fn workout_to_course(
+    interval_vec: &Vec<Interval>,
+    resistance_model: &simulation::BicycleResistanceModel,
+    tolerance: &root_finding::Tolerance,
+) -> VirtualCourse {
+    let flat_segment = RoadSegment {
+        length: 0.0,
+        altitude: 0.0,
+        slope: 0.0,
+        temperature: 20.0,
+        relative_wind_speed: 0.0,
+        roughness: 1.0,
+        rolling_resistance: None,
+        drivetrain_efficiency: None,
+    };
+    let mut road_segment_vec = Vec::new();
+    let mut power_vec = Vec::new();
+    let mut initial_velocity = None;
+    for interval in interval_vec.iter().filter(|interval| interval.duration > 0.0) {
+        let velocity = simulation::steady_velocity(interval.power, &flat_segment, resistance_model, tolerance);
+        initial_velocity = initial_velocity.or(Some(velocity));
+        let n_segments = f64::ceil(interval.duration / WORKOUT_SEGMENT_DURATION) as usize;
+        let segment_length = velocity * interval.duration / n_segments as f64;
+        for _ in 0..n_segments {
+            road_segment_vec.push(RoadSegment {
+                length: segment_length,
+                ..flat_segment
+            });
+            power_vec.push(interval.power);
+        }
+    }
+    VirtualCourse {
+        road_segment_vec,
+        power_vec,
+        initial_velocity: initial_velocity.unwrap_or(0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::morton;
+
+    #[test]
+    fn hard_intervals_deplete_and_partially_recover_the_reserve() {
+        let rider_model = morton::default_rider_model();
+        let resistance_model = simulation::default_resistance_model();
+        let work = Interval {
+            duration: 180.0,
+            power: 1.1 * rider_model.critical_power,
+        };
+        let recovery = Interval {
+            duration: 180.0,
+            power: 150.0,
+        };
+        let repetitions = 5;
+        let course = workout_to_course(
+            &repeat_intervals(work, recovery, repetitions),
+            &resistance_model,
+            &root_finding::default_tolerance(),
+        );
+        let result = simulation::simulate(
+            course.initial_velocity,
+            rider_model.anaerobic_work_capacity,
+            &course.power_vec,
+            &course.road_segment_vec,
+            &resistance_model,
+            &rider_model,
+        );
+        assert_eq!(result.completed_segments(), course.road_segment_vec.len());
+
+        // Each interval is 18 segments of 10 s, so the reserve is read at the end of every work and recovery.
+        let segments_per_interval = 18;
+        let reserve_at = |n_intervals: usize| result.anaerobic_reserve_vec[n_intervals * segments_per_interval - 1];
+        let first_depletion = rider_model.anaerobic_work_capacity - reserve_at(1);
+        let planned_depletion = (work.power - rider_model.critical_power) * work.duration;
+        assert!((first_depletion - planned_depletion).abs() < 0.05 * planned_depletion);
+
+        let mut previous_trough = rider_model.anaerobic_work_capacity;
+        for rep in 0..repetitions {
+            let trough = reserve_at(2 * rep + 1);
+            let recovered = reserve_at(2 * rep + 2);
+            assert!(trough < previous_trough);
+            assert!(recovered > trough);
+            assert!(recovered < rider_model.anaerobic_work_capacity);
+            previous_trough = trough;
+        }
+        assert!(previous_trough > 0.0);
+    }
+}
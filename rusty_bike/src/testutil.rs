@@ -0,0 +1,157 @@
+use crate::sim::simulation::RoadSegment;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Canonical synthetic course profiles.
+///
+/// Every profile is made of `n_segments` segments of exactly `segment_length` meters, at 20°C, without wind,
+/// on smooth road (`roughness` of 1), starting at an altitude of 0 m. The altitude of each segment is the
+/// altitude at its start, accumulated from the slopes of the previous segments.
+///
+/// * `Flat` - Every segment has a slope of 0.
+/// * `ConstantClimb` - Every segment has the given `slope` (e.g. 0.08 for 8%).
+/// * `RollingSine` - Segment `i` has a slope of `max_slope * sin(2π (i + 0.5) / period)`, so the course
+///   alternates climbs and descents every `period / 2` segments and whole periods end at the starting altitude.
+/// * `Descent` - Every segment has a slope of `-slope`, i.e. `slope` is the positive steepness of the descent.
+pub enum CourseProfile {
+    Flat {
+        n_segments: usize,
+        segment_length: f64,
+    },
+    ConstantClimb {
+        n_segments: usize,
+        segment_length: f64,
+        slope: f64,
+    },
+    RollingSine {
+        n_segments: usize,
+        segment_length: f64,
+        max_slope: f64,
+        period: usize,
+    },
+    Descent {
+        n_segments: usize,
+        segment_length: f64,
+        slope: f64,
+    },
+}
+
+fn make_segments(n_segments: usize, segment_length: f64, slope_of: impl Fn(usize) -> f64) -> Vec<RoadSegment> {
+    let mut road_segment_vec = Vec::with_capacity(n_segments);
+    let mut altitude = 0.0;
+    for i in 0..n_segments {
+        let slope = slope_of(i);
+        road_segment_vec.push(RoadSegment {
+            length: segment_length,
+            altitude,
+            slope,
+            temperature: 20.0,
+            relative_wind_speed: 0.0,
+            roughness: 1.0,
+            rolling_resistance: None,
+            drivetrain_efficiency: None,
+        });
+        altitude += slope * segment_length;
+    }
+    road_segment_vec
+}
+
+/// Generates the segments of a synthetic course, so that tests do not depend on data files.
+///
+/// # Example
+///
+/// ```
+/// let course = make_course(CourseProfile::ConstantClimb { n_segments: 10, segment_length: 100.0, slope: 0.08 });
+/// ```
+pub fn make_course(profile: CourseProfile) -> Vec<RoadSegment> {
+    match profile {
+        CourseProfile::Flat {
+            n_segments,
+            segment_length,
+        } => make_segments(n_segments, segment_length, |_| 0.0),
+        CourseProfile::ConstantClimb {
+            n_segments,
+            segment_length,
+            slope,
+        } => make_segments(n_segments, segment_length, |_| slope),
+        CourseProfile::RollingSine {
+            n_segments,
+            segment_length,
+            max_slope,
+            period,
+        } => make_segments(n_segments, segment_length, |i| {
+            max_slope * f64::sin(2.0 * std::f64::consts::PI * (i as f64 + 0.5) / period as f64)
+        }),
+        CourseProfile::Descent {
+            n_segments,
+            segment_length,
+            slope,
+        } => make_segments(n_segments, segment_length, |_| -slope),
+    }
+}
+
+/// Counts the heap allocations of each thread, so that tests running in parallel can check that a code path does
+/// not allocate, see `count_allocations`.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATION_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The count is gone while the thread is torn down, when allocations no longer matter.
+        let _ = ALLOCATION_COUNT.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATION_COUNT.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f` and returns its result with the number of heap allocations and reallocations it made on the current
+/// thread.
+pub fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let start_count = ALLOCATION_COUNT.with(Cell::get);
+    let result = f();
+    (result, ALLOCATION_COUNT.with(Cell::get) - start_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn courses_follow_their_documented_profiles() {
+        let climb = make_course(CourseProfile::ConstantClimb { n_segments: 10, segment_length: 100.0, slope: 0.08 });
+        assert_eq!(climb.len(), 10);
+        for (i, road_segment) in climb.iter().enumerate() {
+            assert_eq!(road_segment.length, 100.0);
+            assert_eq!(road_segment.slope, 0.08);
+            assert!((road_segment.altitude - 8.0 * i as f64).abs() < 1e-9);
+        }
+
+        let descent = make_course(CourseProfile::Descent { n_segments: 4, segment_length: 50.0, slope: 0.05 });
+        assert!(descent.iter().all(|road_segment| road_segment.slope == -0.05));
+
+        let rolling = make_course(CourseProfile::RollingSine {
+            n_segments: 24,
+            segment_length: 100.0,
+            max_slope: 0.04,
+            period: 12,
+        });
+        let end_altitude: f64 = rolling.iter().map(|road_segment| road_segment.slope * road_segment.length).sum();
+        assert!(end_altitude.abs() < 1e-9);
+        assert!(rolling[..6].iter().all(|road_segment| road_segment.slope > 0.0));
+        assert!(rolling[6..12].iter().all(|road_segment| road_segment.slope < 0.0));
+    }
+}